@@ -0,0 +1,81 @@
+//! Compares the per-address execution index against scanning the change log
+//! on a loop-heavy trace. Run with `cargo bench -p koradar-core --bench executions`.
+
+use koradar_core::{Address, Change, ChangeFlags, Clnum, TraceDB, REG_COUNT};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::Instant;
+
+const BIAS: i64 = 0x40_0000;
+const LOOP_BODY: u64 = 64;
+const ITERATIONS: u64 = 20_000;
+const QUERIES: usize = 200;
+
+fn main() {
+    let db = TraceDB::new(REG_COUNT);
+    db.set_bias(BIAS);
+    db.add_symbol(0x1000, LOOP_BODY * 4, "hot_loop".to_string());
+
+    // A tight loop dominates the trace, as in most real workloads
+    let mut log = Vec::new();
+    let mut clnum: Clnum = 1;
+    for _ in 0..ITERATIONS {
+        for offset in 0..LOOP_BODY {
+            let change = Change {
+                address: BIAS as u64 + 0x1000 + offset * 4,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            };
+            db.add_change(change);
+            log.push(change);
+            clnum += 1;
+        }
+    }
+    println!("{} instructions over {} addresses", log.len(), LOOP_BODY);
+
+    let targets: Vec<Address> = (0..QUERIES as u64).map(|i| BIAS as u64 + 0x1000 + (i % LOOP_BODY) * 4).collect();
+
+    let scan = |address: Address| -> Vec<Clnum> {
+        log.iter()
+            .filter(|c| c.address == address && ChangeFlags::from_bits_truncate(c.flags).contains(ChangeFlags::IS_START))
+            .map(|c| c.clnum)
+            .collect()
+    };
+
+    let start = Instant::now();
+    let scanned: Vec<Vec<Clnum>> = targets.iter().map(|&a| black_box(scan(a))).collect();
+    let scan_time = start.elapsed();
+
+    let start = Instant::now();
+    let indexed: Vec<Vec<Clnum>> = targets.iter().map(|&a| black_box(db.executions_of(a))).collect();
+    let index_time = start.elapsed();
+
+    assert_eq!(scanned, indexed, "index disagrees with the scan");
+    report("executions_of", scan_time, index_time);
+
+    let range = BIAS as u64 + 0x1000..BIAS as u64 + 0x1000 + LOOP_BODY * 4;
+    let start = Instant::now();
+    let mut scanned_first: HashMap<Address, Clnum> = HashMap::new();
+    for change in log.iter().filter(|c| range.contains(&c.address)) {
+        scanned_first.entry(change.address).or_insert(change.clnum);
+    }
+    let scan_time = start.elapsed();
+
+    let start = Instant::now();
+    let indexed_first = black_box(db.first_executions(range));
+    let index_time = start.elapsed();
+
+    assert_eq!(scanned_first, indexed_first, "first executions disagree");
+    report("first_executions", scan_time, index_time);
+}
+
+fn report(name: &str, scan: std::time::Duration, index: std::time::Duration) {
+    println!(
+        "{:<18} scan {:>10.3?}  index {:>10.3?}  ({:.0}x)",
+        name,
+        scan,
+        index,
+        scan.as_secs_f64() / index.as_secs_f64().max(1e-9)
+    );
+}
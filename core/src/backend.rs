@@ -0,0 +1,69 @@
+// The queries that reconstruct the traced program's state, behind a trait so
+// the server doesn't depend on where the trace is kept: in memory (`TraceDB`,
+// the default) or, with the `sqlite` feature, in a SQLite file
+// (`sqlite::SqliteTrace`).
+
+use crate::db::{Address, Clnum, TraceDB, TraceLogOptions, TraceLogPage};
+
+pub trait TraceBackend: Send + Sync {
+    /// Last clnum of the trace, 0 if it's empty.
+    fn max_clnum(&self) -> Clnum;
+
+    /// Registers of the vcpu that executed `clnum`, before it ran.
+    fn get_registers_at(&self, clnum: Clnum) -> Vec<u64>;
+
+    /// `size` bytes at `addr` as of `clnum`, unknown bytes as zero.
+    fn get_memory_at(&self, clnum: Clnum, addr: Address, size: usize) -> Vec<u8>;
+
+    /// The instruction at `clnum` as the state view shows it.
+    fn annotated_disassembly(&self, clnum: Clnum) -> String;
+
+    /// Whether `name` is a known symbol, for the timeline's function filter.
+    fn has_symbol(&self, name: &str) -> bool;
+
+    /// Whether the trace is held in memory, rather than queried where it's
+    /// stored. Only an in-memory trace collapses library calls in
+    /// `get_trace_log`.
+    fn is_in_memory(&self) -> bool {
+        false
+    }
+
+    /// Up to `count` timeline entries from `start` on (see
+    /// `TraceDB::get_trace_log`).
+    fn get_trace_log(&self, start: Clnum, count: u32, options: TraceLogOptions) -> TraceLogPage;
+
+    /// `get_trace_log` for the `radius` clnums on either side of `center`.
+    fn get_trace_log_around(&self, center: Clnum, radius: u32, options: TraceLogOptions) -> TraceLogPage {
+        self.get_trace_log(center.saturating_sub(radius), radius.saturating_mul(2), options)
+    }
+}
+
+impl TraceBackend for TraceDB {
+    fn max_clnum(&self) -> Clnum {
+        self.changes.read().last().map_or(0, |ch| ch.clnum)
+    }
+
+    fn get_registers_at(&self, clnum: Clnum) -> Vec<u64> {
+        TraceDB::get_registers_at(self, clnum)
+    }
+
+    fn get_memory_at(&self, clnum: Clnum, addr: Address, size: usize) -> Vec<u8> {
+        TraceDB::get_memory_at(self, clnum, addr, size)
+    }
+
+    fn annotated_disassembly(&self, clnum: Clnum) -> String {
+        self.annotate_operands(clnum)
+    }
+
+    fn has_symbol(&self, name: &str) -> bool {
+        TraceDB::has_symbol(self, name)
+    }
+
+    fn is_in_memory(&self) -> bool {
+        true
+    }
+
+    fn get_trace_log(&self, start: Clnum, count: u32, options: TraceLogOptions) -> TraceLogPage {
+        TraceDB::get_trace_log(self, start, count, options)
+    }
+}
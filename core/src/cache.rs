@@ -0,0 +1,136 @@
+// Size-capped cache for derived data that can be recomputed (disassembly
+// text). Entries carry the tick of their last use; once the cache is full the
+// least recently used eighth is evicted in one pass, so the cost of eviction
+// stays amortized over many inserts.
+
+use dashmap::DashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Hit, miss and eviction counts of a cache, with its current size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+pub(crate) struct LruCache<K: Hash + Eq> {
+    // Key -> (value, tick of last use)
+    map: DashMap<K, (String, u64)>,
+    // Maximum number of entries; 0 disables caching
+    capacity: AtomicUsize,
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<K: Hash + Eq> LruCache<K> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            map: DashMap::new(),
+            capacity: AtomicUsize::new(capacity),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<String> {
+        match self.map.get_mut(key) {
+            Some(mut entry) => {
+                entry.1 = self.tick();
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.0.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&self, key: K, value: String) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            return;
+        }
+        if self.map.len() >= capacity {
+            self.evict_to(capacity - capacity / 8 - 1);
+        }
+        self.map.insert(key, (value, self.tick()));
+    }
+
+    pub(crate) fn clear(&self) {
+        self.map.clear();
+    }
+
+    /// Changes the capacity, evicting least recently used entries if the
+    /// cache is now over it.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        if self.map.len() > capacity {
+            self.evict_to(capacity);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.map.len(),
+            capacity: self.capacity.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Drops the least recently used entries until at most `keep` remain
+    fn evict_to(&self, keep: usize) {
+        let mut ticks: Vec<u64> = self.map.iter().map(|entry| entry.1).collect();
+        if ticks.len() <= keep {
+            return;
+        }
+        let excess = ticks.len() - keep;
+        // Ticks are unique, so exactly the `excess` oldest are at or below this
+        let (_, &mut cutoff, _) = ticks.select_nth_unstable(excess - 1);
+        let before = self.map.len();
+        self.map.retain(|_, entry| entry.1 > cutoff);
+        let evicted = before.saturating_sub(self.map.len());
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_when_full() {
+        let cache = LruCache::new(8);
+        for i in 0..8 {
+            cache.insert(i, i.to_string());
+        }
+        // Touch 0 so 1 becomes the oldest
+        assert_eq!(cache.get(&0).as_deref(), Some("0"));
+        cache.insert(8, "8".to_string());
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&0).as_deref(), Some("0"));
+        assert_eq!(cache.get(&8).as_deref(), Some("8"));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.evictions), (7, 2));
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+
+        cache.set_capacity(3);
+        assert_eq!(cache.stats().entries, 3);
+        assert_eq!(cache.get(&8).as_deref(), Some("8"));
+    }
+}
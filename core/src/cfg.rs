@@ -1,9 +1,22 @@
-use crate::db::{TraceDB, ChangeFlags};
-use crate::il::{ControlFlowGraph, BasicBlock, Edge, Instruction, Operation};
-use std::collections::{HashMap, HashSet};
+use crate::db::{Address, Change, Clnum, DisasmView, TraceDB, ChangeFlags};
+use crate::disasm::{InsnGroup, OperandDetail};
+use crate::error::QueryError;
+use crate::il::{CfgDiff, ControlFlowGraph, BasicBlock, Edge, EdgeKind, Instruction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// An edge of a merged graph as found in trace A and in trace B
+type EdgePair<'a> = (Option<&'a Edge>, Option<&'a Edge>);
 
 impl TraceDB {
-    pub fn analyze_cfg(&self, only_user_code: bool, start_from_main: bool) -> ControlFlowGraph {
+    /// Builds the CFG of the trace. With `collapse_stubs`, calls through a
+    /// PLT stub lead straight to the function it resolved to, instead of
+    /// through a node for the stub (see `skip_stubs`).
+    pub fn analyze_cfg(
+        &self,
+        only_user_code: bool,
+        start_from_main: bool,
+        collapse_stubs: bool,
+    ) -> Result<ControlFlowGraph, QueryError> {
         let changes = self.changes.read();
         
         // Pass 1: Identify leaders and edges from trace
@@ -18,34 +31,22 @@ impl TraceDB {
                 let run_addr = (static_addr as i128 + bias as i128) as u64;
                 
                 // Find first execution of main
-                if let Some(first_exec) = changes.iter().find(|c| {
-                    c.address == run_addr && ChangeFlags::from_bits_truncate(c.flags).contains(ChangeFlags::IS_START)
-                }) {
-                    min_clnum = first_exec.clnum;
-                    println!("[DEBUG] Found main at run_addr={:x}, clnum={}", run_addr, min_clnum);
+                if let Some(first_exec) = self.first_execution_at(run_addr) {
+                    min_clnum = first_exec;
+                    tracing::debug!(run_addr = format_args!("{:x}", run_addr), clnum = min_clnum, "found main");
                 } else {
-                    println!("[DEBUG] 'main' symbol found at static {:x} (run {:x}), but no execution trace found.", static_addr, run_addr);
+                    tracing::debug!(
+                        static_addr = format_args!("{:x}", static_addr),
+                        run_addr = format_args!("{:x}", run_addr),
+                        "main symbol found but never executed"
+                    );
                 }
             } else {
-                println!("[DEBUG] 'main' symbol not found in symbol table.");
+                return Err(QueryError::SymbolNotFound("main".to_string()));
             }
         }
 
-        // #region agent log
-        {
-            use std::fs::OpenOptions;
-            use std::io::Write;
-            let path = "/Users/shinta/git/github.com/geohot/qira/.cursor/debug.log";
-            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
-                let _ = writeln!(file, "{{\"id\":\"log_cfg_filter\",\"timestamp\":{},\"location\":\"core/cfg.rs:analyze_cfg\",\"message\":\"Filter Params\",\"data\":{{\"start_from_main\":{}, \"min_clnum\":{}, \"bias\":{}}},\"sessionId\":\"debug-session\",\"runId\":\"debug-run\",\"hypothesisId\":\"filter-logic\"}}", 
-                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                    start_from_main,
-                    min_clnum,
-                    self.get_bias()
-                );
-            }
-        }
-        // #endregion
+        tracing::debug!(start_from_main, min_clnum, bias = self.get_bias(), "analyze_cfg filter");
 
         // Filter changes to get only PC changes (instructions)
         let total_pc_changes = changes.iter()
@@ -58,13 +59,14 @@ impl TraceDB {
             .filter(|c| ChangeFlags::from_bits_truncate(c.flags).contains(ChangeFlags::IS_START))
             .filter(|c| !only_user_code || self.is_user_code(c.address))
             .collect();
+        let pc_changes = if collapse_stubs { self.skip_stubs(&changes, pc_changes) } else { pc_changes };
 
         if only_user_code {
-             println!("[DEBUG] analyze_cfg: Total PC changes: {}, After user_code filter: {}", total_pc_changes, pc_changes.len());
+            tracing::debug!(total = total_pc_changes, user = pc_changes.len(), "analyze_cfg user code filter");
         }
 
         if pc_changes.is_empty() {
-            return ControlFlowGraph { blocks: vec![], edges: vec![] };
+            return Ok(ControlFlowGraph { blocks: vec![], edges: vec![] });
         }
 
         block_starts.insert(pc_changes[0].address);
@@ -79,33 +81,51 @@ impl TraceDB {
         let mut block_first_clnum: HashMap<u64, u32> = HashMap::new();
         block_first_clnum.insert(pc_changes[0].address, pc_changes[0].clnum);
         
-        for i in 0..pc_changes.len()-1 {
-            let curr = pc_changes[i];
+        // Signal/interrupt entries and returns get edges of their own
+        let is_async = |c: &Change| ChangeFlags::from_bits_truncate(c.flags).contains(ChangeFlags::IS_ASYNC);
+
+        // jumps[i]: control left pc_changes[i] other than by falling through.
+        // Instructions whose length can't be determined count as jumps.
+        let jumps: Vec<bool> = pc_changes
+            .windows(2)
+            .map(|pair| {
+                is_async(pair[1])
+                    || self.insn_len(pair[0].clnum, pair[0].address)
+                        .is_none_or(|len| pair[0].address + len != pair[1].address)
+            })
+            .collect();
+
+        for (i, &is_jump) in jumps.iter().enumerate() {
             let next = pc_changes[i+1];
-            
-            let curr_addr = curr.address;
-            let next_addr = next.address;
-            
-            let mut is_jump = true;
-            if let Some(bytes) = self.instructions.get(&curr.clnum) {
-                if !bytes.is_empty() {
-                    let size = bytes.len() as u64;
-                    if curr_addr + size == next_addr {
-                        is_jump = false;
-                    }
-                }
-            }
-            
             if is_jump {
-                block_starts.insert(next_addr);
-                block_first_clnum.entry(next_addr).or_insert(next.clnum);
+                block_starts.insert(next.address);
+                block_first_clnum.entry(next.address).or_insert(next.clnum);
             }
         }
         
         // Pass 2: Build Blocks
         
         let mut final_blocks = HashMap::new();
-        let mut final_edges = HashSet::new();
+        // (src block, dst block, asynchronous) -> (kind, clnums at which the
+        // transition happened). Ordered so serialized graphs are identical
+        // between runs.
+        let mut final_edges: BTreeMap<(u64, u64, bool), (EdgeKind, Vec<u32>)> = BTreeMap::new();
+        // Classified once per edge, from the instruction that first took it
+        let mut add_edge = |src: u64, from: &Change, to: &Change| {
+            let asynchronous = is_async(to);
+            final_edges
+                .entry((src, to.address, asynchronous))
+                .or_insert_with(|| {
+                    let kind = if asynchronous {
+                        EdgeKind::FallThrough
+                    } else {
+                        self.edge_kind(from.clnum, from.address, to.address)
+                    };
+                    (kind, Vec::new())
+                })
+                .1
+                .push(to.clnum);
+        };
         
         let mut current_start = pc_changes[0].address;
         let mut current_insns = Vec::new();
@@ -120,7 +140,7 @@ impl TraceDB {
                 
                 // Add edge from previous instruction to this one
                 if i > 0 {
-                    final_edges.insert((current_start, curr.address)); 
+                    add_edge(current_start, pc_changes[i - 1], curr);
                 }
                 
                 // Start new
@@ -128,25 +148,15 @@ impl TraceDB {
                 current_insns.clear();
             }
             
-            let disassembly = self.get_disassembly_at(curr.clnum);
+            let disassembly = self.disassembly_at(curr.clnum, DisasmView::Cfg);
             let mnemonic = disassembly.split_whitespace().next().unwrap_or("???").to_string();
             let operands = disassembly[mnemonic.len()..].trim().to_string();
             
-            let mut is_jump = false;
-            if i < pc_changes.len() - 1 {
-                let next = pc_changes[i+1];
-                if let Some(bytes) = self.instructions.get(&curr.clnum) {
-                     if !bytes.is_empty() {
-                        if curr.address + bytes.len() as u64 != next.address {
-                            is_jump = true;
-                        }
-                     }
-                }
-            }
+            let is_jump = jumps.get(i).copied().unwrap_or(false);
             
             if !current_insns.iter().any(|insn: &Instruction| insn.address == curr.address) {
                  current_insns.push(Instruction {
-                    operation: Operation::Nop, 
+                    operation: self.lift_instruction(curr.clnum, curr.address),
                     address: curr.address,
                     mnemonic,
                     operands,
@@ -156,8 +166,9 @@ impl TraceDB {
             if is_jump {
                 // Record edge
                 if i < pc_changes.len() - 1 {
-                    let next_addr = pc_changes[i+1].address;
-                    final_edges.insert((current_start, next_addr));
+                    let next = pc_changes[i+1];
+                    let next_addr = next.address;
+                    add_edge(current_start, curr, next);
                     
                     // Finish block
                     final_blocks.insert(current_start, current_insns.clone());
@@ -183,9 +194,7 @@ impl TraceDB {
             node_indices.insert(*start, i);
             let instructions = final_blocks.get(start).unwrap().clone();
             
-            let bias = self.get_bias();
-            let static_addr = (*start as i128 - bias as i128) as u64;
-            let symbol = self.find_symbol(static_addr).map(|(name, _)| name);
+            let symbol = self.symbolize(*start).map(|(name, _)| name);
             let clnum = *block_first_clnum.get(start).unwrap_or(&0);
 
             nodes.push(BasicBlock {
@@ -193,23 +202,473 @@ impl TraceDB {
                 instructions,
                 symbol,
                 clnum,
+                diff: None,
             });
         }
         
         let mut graph_edges = Vec::new();
-        for (src, dst) in final_edges {
+        for ((src, dst, asynchronous), (kind, clnums)) in final_edges {
             if let (Some(&head), Some(&tail)) = (node_indices.get(&src), node_indices.get(&dst)) {
                 graph_edges.push(Edge {
                     head, 
                     tail, 
                     condition: None,
+                    clnums,
+                    asynchronous,
+                    kind,
+                    diff: None,
                 });
             }
         }
         
-        ControlFlowGraph {
+        graph_edges.sort_by_key(|e| (e.head, e.tail, e.asynchronous));
+
+        Ok(ControlFlowGraph {
             blocks: nodes,
             edges: graph_edges,
+        })
+    }
+
+    /// Merges this trace's CFG (A) with `other`'s (B), matching blocks and
+    /// edges by static address so runs loaded at different bases line up.
+    /// Every block and edge is tagged with where it was seen; a block in both
+    /// whose executed bytes differ (e.g. patched at runtime) is `Changed`.
+    /// Blocks and edges keep A's clnums, so those only in B have none.
+    pub fn diff_cfg(
+        &self,
+        other: &TraceDB,
+        only_user_code: bool,
+        start_from_main: bool,
+        collapse_stubs: bool,
+    ) -> Result<ControlFlowGraph, QueryError> {
+        let cfg_a = self.analyze_cfg(only_user_code, start_from_main, collapse_stubs)?;
+        let cfg_b = other.analyze_cfg(only_user_code, start_from_main, collapse_stubs)?;
+        // Static start address of each block, by index
+        let starts = |db: &TraceDB, cfg: &ControlFlowGraph| -> Vec<Option<Address>> {
+            cfg.blocks
+                .iter()
+                .map(|b| b.instructions.first().map(|i| db.static_memory_address(i.address)))
+                .collect()
+        };
+        let (starts_a, starts_b) = (starts(self, &cfg_a), starts(other, &cfg_b));
+
+        let mut merged: BTreeMap<Address, (Option<&BasicBlock>, Option<&BasicBlock>)> = BTreeMap::new();
+        for (block, start) in cfg_a.blocks.iter().zip(&starts_a) {
+            if let Some(start) = start {
+                merged.entry(*start).or_default().0 = Some(block);
+            }
+        }
+        for (block, start) in cfg_b.blocks.iter().zip(&starts_b) {
+            if let Some(start) = start {
+                merged.entry(*start).or_default().1 = Some(block);
+            }
+        }
+
+        let mut blocks = Vec::with_capacity(merged.len());
+        let mut indices = HashMap::new();
+        for (&start, &(block_a, block_b)) in &merged {
+            let (block, diff) = match (block_a, block_b) {
+                (Some(a), Some(b)) if self.block_code(a) == other.block_code(b) => (a, CfgDiff::Common),
+                (Some(a), Some(_)) => (a, CfgDiff::Changed),
+                (Some(a), None) => (a, CfgDiff::OnlyA),
+                (None, Some(b)) => (b, CfgDiff::OnlyB),
+                (None, None) => continue,
+            };
+            indices.insert(start, blocks.len());
+            blocks.push(BasicBlock {
+                index: blocks.len(),
+                instructions: block.instructions.clone(),
+                symbol: block.symbol.clone(),
+                clnum: if diff == CfgDiff::OnlyB { 0 } else { block.clnum },
+                diff: Some(diff),
+            });
+        }
+
+        // (head, tail, asynchronous) in merged indices -> (A's edge, B's edge)
+        let mut edges: BTreeMap<(usize, usize, bool), EdgePair> = BTreeMap::new();
+        let merged_key = |starts: &[Option<Address>], edge: &Edge| {
+            let head = indices.get(&starts[edge.head]?)?;
+            let tail = indices.get(&starts[edge.tail]?)?;
+            Some((*head, *tail, edge.asynchronous))
+        };
+        for edge in &cfg_a.edges {
+            if let Some(key) = merged_key(&starts_a, edge) {
+                edges.entry(key).or_default().0 = Some(edge);
+            }
+        }
+        for edge in &cfg_b.edges {
+            if let Some(key) = merged_key(&starts_b, edge) {
+                edges.entry(key).or_default().1 = Some(edge);
+            }
+        }
+
+        let edges = edges
+            .into_iter()
+            .map(|((head, tail, asynchronous), (edge_a, edge_b))| {
+                let diff = match (edge_a.is_some(), edge_b.is_some()) {
+                    (true, true) => CfgDiff::Common,
+                    (true, false) => CfgDiff::OnlyA,
+                    _ => CfgDiff::OnlyB,
+                };
+                Edge {
+                    head,
+                    tail,
+                    condition: None,
+                    clnums: edge_a.map(|e| e.clnums.clone()).unwrap_or_default(),
+                    asynchronous,
+                    kind: edge_a.or(edge_b).map(|e| e.kind).unwrap_or_default(),
+                    diff: Some(diff),
+                }
+            })
+            .collect();
+
+        Ok(ControlFlowGraph { blocks, edges })
+    }
+
+    // Drops each run of stub instructions that execution left straight for
+    // the stub's target, so the call before it leads to the function itself.
+    // A run followed by anything else stays, as the only sign of the call:
+    // the target was filtered out (a library with `only_user_code`), or the
+    // trace ended in the stub.
+    fn skip_stubs<'a>(&self, changes: &[Change], pc_changes: Vec<&'a Change>) -> Vec<&'a Change> {
+        // A loop through a stub would otherwise look it up on every pass
+        let mut stubs: HashMap<Address, bool> = HashMap::new();
+        let is_stub: Vec<bool> = pc_changes
+            .iter()
+            .map(|c| *stubs.entry(c.address).or_insert_with(|| self.is_stub(c.address)))
+            .collect();
+        let mut kept = Vec::with_capacity(pc_changes.len());
+        let mut i = 0;
+        while i < pc_changes.len() {
+            if !is_stub[i] {
+                kept.push(pc_changes[i]);
+                i += 1;
+                continue;
+            }
+            let run_end = is_stub[i..].iter().position(|&stub| !stub).map_or(pc_changes.len(), |n| i + n);
+            // The instruction the stub jumped to, whether or not it was kept
+            let last = pc_changes[run_end - 1];
+            let vcpu = self.vcpu_at(last.clnum);
+            let after = changes.partition_point(|ch| ch.clnum <= last.clnum);
+            let target = changes[after..].iter().find(|ch| {
+                ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START)
+                    && self.vcpu_at(ch.clnum) == vcpu
+            });
+            let reached = matches!(
+                (target, pc_changes.get(run_end)),
+                (Some(target), Some(next)) if target.clnum == next.clnum
+            );
+            if !reached {
+                kept.extend_from_slice(&pc_changes[i..run_end]);
+            }
+            i = run_end;
+        }
+        kept
+    }
+
+    // Classifies the transition from the instruction executed at `clnum`
+    // (located at runtime `from`) to the one at `to`
+    fn edge_kind(&self, clnum: Clnum, from: Address, to: Address) -> EdgeKind {
+        let falls_through = self.insn_len(clnum, from).is_some_and(|len| from + len == to);
+        let otherwise = if falls_through { EdgeKind::FallThrough } else { EdgeKind::UncondJump };
+        let Some(detail) = self.insn_detail(clnum, from) else {
+            return otherwise;
+        };
+        let groups = self.insn_groups(clnum, from);
+        // `call rax` and `jmp qword ptr [rip + 0x2fe2]` name no target
+        let direct = !detail.operands.is_empty()
+            && detail.operands.iter().all(|op| matches!(op, OperandDetail::Imm { .. }));
+
+        if groups.contains(&InsnGroup::Ret) {
+            EdgeKind::Ret
+        } else if groups.contains(&InsnGroup::Call) {
+            if direct { EdgeKind::Call } else { EdgeKind::Indirect }
+        } else if groups.contains(&InsnGroup::Jump) {
+            if !direct {
+                EdgeKind::Indirect
+            } else if !detail.mnemonic.ends_with("jmp") {
+                // jcc, loop and jrcxz
+                if falls_through { EdgeKind::FallThrough } else { EdgeKind::CondTaken }
+            } else if self.is_tail_call(from, to) {
+                EdgeKind::TailCall
+            } else {
+                EdgeKind::UncondJump
+            }
+        } else {
+            otherwise
+        }
+    }
+
+    // A jump from `from` lands on the first instruction of another function
+    fn is_tail_call(&self, from: Address, to: Address) -> bool {
+        match (self.symbolize(from), self.symbolize(to)) {
+            (Some((caller, _)), Some((callee, 0))) => caller != callee,
+            (None, Some((_, 0))) => true,
+            _ => false,
+        }
+    }
+
+    // (static address, bytes first executed there) for each instruction of
+    // `block`, to tell whether two traces ran the same code
+    fn block_code(&self, block: &BasicBlock) -> Vec<(Address, Vec<u8>)> {
+        block
+            .instructions
+            .iter()
+            .map(|insn| {
+                let bytes = self
+                    .first_execution_at(insn.address)
+                    .map(|clnum| {
+                        let mut bytes = self.insn_bytes(clnum, insn.address);
+                        if let Some(len) = self.insn_len(clnum, insn.address) {
+                            bytes.truncate(len as usize);
+                        }
+                        bytes
+                    })
+                    .unwrap_or_default();
+                (self.static_memory_address(insn.address), bytes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Change, ChangeFlags, TraceDB, REG_COUNT};
+    use crate::il::{CfgDiff, EdgeKind};
+    use crate::testing::TraceBuilder;
+
+    #[test]
+    fn mermaid_output_is_deterministic() {
+        let trace = || {
+            let db = TraceDB::new(REG_COUNT);
+            db.add_symbol(0x1000, 0x10, "main".to_string());
+            db.add_symbol(0x2000, 0x10, "helper".to_string());
+            db.add_symbol(0x3000, 0x10, "other".to_string());
+            // main calls helper and other in turn, looping twice
+            let pcs = [0x1000, 0x2000, 0x1005, 0x3000, 0x1000, 0x2000, 0x1005, 0x3000, 0x100a];
+            for (i, &pc) in pcs.iter().enumerate() {
+                let clnum = i as u32 + 1;
+                db.add_instruction(clnum, vec![0x90; 5]);
+                db.add_change(Change {
+                    address: pc,
+                    data: 0,
+                    clnum,
+                    flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+                });
+            }
+            db.analyze_cfg(false, false, false).unwrap().to_mermaid()
+        };
+
+        let first = trace();
+        for _ in 0..8 {
+            assert_eq!(trace(), first);
+        }
+    }
+
+    #[test]
+    fn signal_transitions_get_asynchronous_edges() {
+        let db = TraceDB::new(REG_COUNT);
+        // A signal interrupts the nops at 0x1000 with the handler at 0x2000,
+        // which returns to where it left off
+        let pcs = [(0x1000, false), (0x2000, true), (0x2001, false), (0x1001, true), (0x1002, false)];
+        for (i, &(pc, asynchronous)) in pcs.iter().enumerate() {
+            let clnum = i as u32 + 1;
+            db.add_instruction(clnum, vec![0x90]);
+            let mut flags = ChangeFlags::IS_VALID | ChangeFlags::IS_START;
+            if asynchronous {
+                flags |= ChangeFlags::IS_ASYNC;
+            }
+            db.add_change(Change { address: pc, data: 0, clnum, flags: flags.bits() });
+        }
+
+        assert!(db.is_async_entry(2) && db.is_async_entry(4));
+        assert!(!db.is_async_entry(3));
+        let cfg = db.analyze_cfg(false, false, false).unwrap();
+        let edges: Vec<_> = cfg.edges.iter().map(|e| (e.clnums.clone(), e.asynchronous)).collect();
+        assert_eq!(edges, vec![(vec![2], true), (vec![4], true)]);
+        assert_eq!(cfg.to_mermaid().matches("-.->|\"async\"|").count(), 2);
+    }
+
+    #[test]
+    fn diff_matches_blocks_by_static_address() {
+        // Each run is loaded at its own base. Both start with two
+        // instructions at static 0x1000 and end at 0x4000, but A goes through
+        // 0x2000 and B through 0x3000, and B's second instruction is patched
+        let trace = |bias: u64, via: u64, second: u8| {
+            let db = TraceDB::new(REG_COUNT);
+            db.set_bias(bias as i64);
+            let insns = [(0x1000, 0x90), (0x1001, second), (via, 0x90), (0x4000, 0x90)];
+            for (i, &(pc, byte)) in insns.iter().enumerate() {
+                let clnum = i as u32 + 1;
+                db.add_instruction(clnum, vec![byte]);
+                db.add_change(Change {
+                    address: bias + pc,
+                    data: 0,
+                    clnum,
+                    flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+                });
+            }
+            db
+        };
+        let a = trace(0x10000, 0x2000, 0x90);
+        let b = trace(0x20000, 0x3000, 0xcc);
+
+        let cfg = a.diff_cfg(&b, false, false, false).unwrap();
+        let blocks: Vec<_> = cfg.blocks.iter().map(|b| (b.instructions[0].address, b.diff)).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                (0x11000, Some(CfgDiff::Changed)),
+                (0x12000, Some(CfgDiff::OnlyA)),
+                (0x23000, Some(CfgDiff::OnlyB)),
+                (0x14000, Some(CfgDiff::Common)),
+            ]
+        );
+        let edges: Vec<_> = cfg.edges.iter().map(|e| (e.head, e.tail, e.diff, e.clnums.clone())).collect();
+        assert_eq!(
+            edges,
+            vec![
+                (0, 1, Some(CfgDiff::OnlyA), vec![3]),
+                (0, 2, Some(CfgDiff::OnlyB), vec![]),
+                (1, 3, Some(CfgDiff::OnlyA), vec![4]),
+                (2, 3, Some(CfgDiff::OnlyB), vec![]),
+            ]
+        );
+
+        let mermaid = cfg.to_mermaid();
+        assert!(mermaid.contains("\"]:::onlyB;"));
+        // B-only blocks have nothing to jump to in trace A
+        assert!(!mermaid.contains("click block2 "));
+        assert_eq!(mermaid.matches("linkStyle").count(), 4);
+        assert!(cfg.to_dot().contains("fillcolor"));
+    }
+
+    #[test]
+    fn edges_are_classified_by_their_terminating_instruction() {
+        let db = TraceDB::new(REG_COUNT);
+        db.add_symbol(0x1000, 0x20, "main".to_string());
+        db.add_symbol(0x2000, 0x10, "helper".to_string());
+        db.add_symbol(0x3000, 0x10, "other".to_string());
+        let insns: [(u64, &[u8]); 10] = [
+            (0x1000, &[0xe8, 0xfb, 0x0f, 0x00, 0x00]), // call 0x2000
+            (0x2000, &[0x31, 0xc0]),                   // xor eax, eax
+            (0x2002, &[0x74, 0x02]),                   // je 0x2006, taken
+            (0x2006, &[0xc3]),                         // ret
+            (0x1005, &[0xff, 0xd0]),                   // call rax
+            (0x2004, &[0x75, 0x0a]),                   // jne 0x2010, not taken
+            (0x2006, &[0xc3]),                         // ret
+            (0x1007, &[0xeb, 0x02]),                   // jmp 0x100b
+            (0x100b, &[0xe9, 0xf0, 0x1f, 0x00, 0x00]), // jmp 0x3000
+            (0x3000, &[0x90]),                         // nop
+        ];
+        for (i, &(pc, bytes)) in insns.iter().enumerate() {
+            let clnum = i as u32 + 1;
+            db.add_instruction(clnum, bytes.to_vec());
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
         }
+
+        let cfg = db.analyze_cfg(false, false, false).unwrap();
+        let start = |index: usize| cfg.blocks[index].instructions[0].address;
+        let edges: Vec<_> = cfg.edges.iter().map(|e| (start(e.head), start(e.tail), e.kind)).collect();
+        assert_eq!(
+            edges,
+            vec![
+                (0x1000, 0x2000, EdgeKind::Call),
+                (0x1005, 0x2004, EdgeKind::Indirect),
+                (0x1007, 0x100b, EdgeKind::UncondJump),
+                (0x100b, 0x3000, EdgeKind::TailCall),
+                (0x2000, 0x2006, EdgeKind::CondTaken),
+                // 0x2006 is a block start since the je jumped there
+                (0x2004, 0x2006, EdgeKind::FallThrough),
+                (0x2006, 0x1005, EdgeKind::Ret),
+                (0x2006, 0x1007, EdgeKind::Ret),
+            ]
+        );
+
+        let mermaid = cfg.to_mermaid();
+        assert!(mermaid.contains("    block0 ==>|\"call\"| block4;"));
+        assert!(mermaid.contains("    block3 ==>|\"tail call\"| block7;"));
+        assert!(mermaid.contains("    block5 --> block6;"));
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    block4 -> block6 [label=\"taken\", href=\"javascript:onCfgNodeClick(4)\"];"));
+        assert!(dot.contains("    block1 -> block5 [style=dotted, label=\"indirect\""));
+    }
+
+    #[test]
+    fn missing_bytes_fall_back_to_memory_lengths() {
+        let db = TraceDB::new(REG_COUNT);
+        // push rbp; mov rbp, rsp; nop; jmp 0x1000 -- loaded in memory
+        let code = [0x55, 0x48, 0x89, 0xe5, 0x90, 0xeb, 0xf9];
+        db.load_static_memory(0x1000, &code);
+        // Two passes through the loop; only the jmp's bytes were captured
+        let pcs = [0x1000, 0x1001, 0x1004, 0x1005, 0x1000, 0x1001, 0x1004, 0x1005];
+        for (i, &pc) in pcs.iter().enumerate() {
+            let clnum = i as u32 + 1;
+            if pc == 0x1005 {
+                db.add_instruction(clnum, vec![0xeb, 0xf9]);
+            }
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        let cfg = db.analyze_cfg(false, false, false).unwrap();
+        assert_eq!(cfg.blocks.len(), 1);
+        assert_eq!(cfg.blocks[0].instructions.len(), 4);
+        assert_eq!(cfg.edges.len(), 1);
+        assert_eq!(cfg.edges[0].clnums, vec![5]);
+    }
+
+    #[test]
+    fn all_zero_bytes_are_a_captured_instruction() {
+        let db = TraceDB::new(REG_COUNT);
+        // nops in memory, but what ran was add byte ptr [rax], al
+        db.load_static_memory(0x1000, &[0x90, 0x90]);
+        db.add_instruction(1, vec![0x00, 0x00]);
+        assert_eq!(db.insn_len(1, 0x1000), Some(2));
+    }
+
+    #[test]
+    fn calls_through_plt_stubs_collapse_into_their_target() {
+        let mut trace = TraceBuilder::new();
+        trace.insn(0x401000, &[0xe8, 0x1b, 0x00, 0x00, 0x00]); // call puts@plt
+        trace.insn(0x401020, &[0xff, 0x25, 0xe2, 0x2f, 0x00, 0x00]); // jmp [rip + 0x2fe2]
+        trace.insn(0x500000, &[0xc3]); // puts: ret
+        trace.insn(0x401005, &[0x90]);
+        let db = trace.build();
+        db.register_code_range(0x401000, 0x100);
+        db.register_stub_range(0x401020, 0x10);
+        db.add_symbol(0x401000, 0x20, "main".to_string());
+        db.add_symbol(0x401020, 0x10, "puts@plt".to_string());
+        db.add_symbol(0x500000, 0x10, "puts".to_string());
+
+        let edges = |only_user_code: bool, collapse_stubs: bool| {
+            let cfg = db.analyze_cfg(only_user_code, false, collapse_stubs).unwrap();
+            let start = |index: usize| cfg.blocks[index].instructions[0].address;
+            cfg.edges.iter().map(|e| (start(e.head), start(e.tail), e.kind)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            edges(false, false),
+            vec![
+                (0x401000, 0x401020, EdgeKind::Call),
+                (0x401020, 0x500000, EdgeKind::Indirect),
+                (0x500000, 0x401005, EdgeKind::Ret),
+            ]
+        );
+        assert_eq!(
+            edges(false, true),
+            vec![(0x401000, 0x500000, EdgeKind::Call), (0x500000, 0x401005, EdgeKind::Ret)]
+        );
+        // Without the library, the stub is all there is of the call
+        assert_eq!(edges(true, true), edges(true, false));
+        assert_eq!(edges(true, true)[0], (0x401000, 0x401020, EdgeKind::Call));
     }
 }
@@ -0,0 +1,180 @@
+use crate::backend::TraceBackend;
+use crate::db::{changes_at, split_effects, Address, ChangeFlags, Clnum, TraceDB, TraceLogOptions, REG_NAMES};
+use crate::disasm::InsnGroup;
+
+// Rough character budget for the AI context (~4 characters per token)
+const CONTEXT_BUDGET: usize = 8000;
+// How many instructions (of any vcpu) to walk back looking for call frames
+// and syscalls. Each one is disassembled, so frames further back than this
+// are left out rather than making the request slow.
+const BACKTRACE_SCAN_LIMIT: usize = 5_000;
+const MAX_FRAMES: usize = 8;
+const MAX_SYSCALLS: usize = 5;
+
+// An instruction in the trace, as (clnum, pc)
+type Site = (Clnum, Address);
+
+impl TraceDB {
+    /// Builds the execution context sent along with an AskAI request: the
+    /// current instruction and function, registers, memory it touches, the
+    /// call stack, recent syscalls and `context_lines` instructions of
+    /// surrounding user code on either side. Sections are ordered by
+    /// importance and the result is cut off at a fixed budget.
+    pub fn ai_context(&self, clnum: Clnum, context_lines: u32) -> String {
+        let (pc, mem_accesses) = {
+            let changes = self.changes.read();
+            let effects = changes_at(&changes, clnum);
+            let pc = effects
+                .iter()
+                .find(|ch| ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START))
+                .map(|ch| ch.address);
+            (pc, split_effects(effects).1)
+        };
+        let label = |address: Address| {
+            self.symbol_label(address)
+                .map(|s| format!(" <{}>", s))
+                .unwrap_or_default()
+        };
+
+        let mut sections = Vec::new();
+
+        let function = pc
+            .and_then(|pc| self.symbolize(pc))
+            .map(|(name, _)| name)
+            .unwrap_or_else(|| "unknown".to_string());
+        sections.push(format!(
+            "Instruction (clnum {}): {:x}: {}\nFunction: {}",
+            clnum,
+            pc.unwrap_or(0),
+            self.get_disassembly_at(clnum),
+            function
+        ));
+
+        let regs = self.get_registers_at(clnum);
+        let regs_str = regs
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{}={:x}", REG_NAMES.get(i).unwrap_or(&"reg"), v))
+            .collect::<Vec<_>>()
+            .join(", ");
+        sections.push(format!("Registers: {}", regs_str));
+
+        if !mem_accesses.is_empty() {
+            let lines = mem_accesses
+                .iter()
+                .map(|&(addr, value, is_write)| {
+                    let op = if is_write { "write" } else { "read" };
+                    let value = value.map_or_else(|| "unknown".to_string(), |value| format!("{:x}", value));
+                    format!("  {} [{:x}]{} = {}", op, addr, label(addr), value)
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            sections.push(format!("Memory accessed:\n{}", lines));
+        }
+
+        let (frames, syscalls) = self.backtrace(clnum);
+        let mut stack = vec![format!("  #0 {}", function)];
+        stack.extend(frames.iter().enumerate().map(|(i, &(c, call_pc))| {
+            format!("  #{} {:x}{} (call at clnum {})", i + 1, call_pc, label(call_pc), c)
+        }));
+        sections.push(format!("Call stack:\n{}", stack.join("\n")));
+
+        if !syscalls.is_empty() {
+            let lines = syscalls
+                .iter()
+                .map(|&(c, sys_pc)| {
+                    let nr = self.get_registers_at(c).first().copied().unwrap_or(0);
+                    format!("  clnum {}: syscall {} at {:x}{}", c, nr, sys_pc, label(sys_pc))
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            sections.push(format!("Recent syscalls:\n{}", lines));
+        }
+
+        let user_code = TraceLogOptions { only_user_code: true, ..Default::default() };
+        let log = self.get_trace_log_around(clnum, context_lines, user_code).entries;
+        let code = log
+            .iter()
+            .map(|e| {
+                let marker = if e.clnum == clnum { "=>" } else { "  " };
+                format!("{} {:x}: {}", marker, e.address, e.disassembly)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        sections.push(format!("Surrounding Code:\n{}", code));
+
+        let mut context = sections.join("\n\n");
+        if context.len() > CONTEXT_BUDGET {
+            let mut cut = CONTEXT_BUDGET;
+            while !context.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            context.truncate(cut);
+            context.push_str("\n...(truncated)");
+        }
+        context
+    }
+
+    // Walks backwards from `clnum` through the instructions of the vcpu that
+    // executed it, returning the call sites still on that thread's stack
+    // (innermost first) and its most recent syscalls
+    fn backtrace(&self, clnum: Clnum) -> (Vec<Site>, Vec<Site>) {
+        let vcpu = self.vcpu_at(clnum);
+        // Copied out first so the change log isn't locked while disassembling
+        let instructions: Vec<Site> = {
+            let changes = self.changes.read();
+            let end = changes.partition_point(|ch| ch.clnum < clnum);
+            changes[..end]
+                .iter()
+                .rev()
+                .filter(|ch| ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START))
+                .take(BACKTRACE_SCAN_LIMIT)
+                .map(|ch| (ch.clnum, ch.address))
+                .collect()
+        };
+
+        let mut frames = Vec::new();
+        let mut syscalls = Vec::new();
+        // Number of returns seen whose matching call hasn't been reached yet
+        let mut pending_returns = 0usize;
+        for (c, pc) in instructions.into_iter().filter(|&(c, _)| self.vcpu_at(c) == vcpu) {
+            let groups = self.insn_groups(c, pc);
+            if groups.contains(&InsnGroup::Ret) {
+                pending_returns += 1;
+            } else if groups.contains(&InsnGroup::Call) {
+                if pending_returns == 0 {
+                    if frames.len() < MAX_FRAMES {
+                        frames.push((c, pc));
+                    }
+                } else {
+                    pending_returns -= 1;
+                }
+            } else if groups.contains(&InsnGroup::Syscall) && syscalls.len() < MAX_SYSCALLS {
+                syscalls.push((c, pc));
+            }
+            if frames.len() == MAX_FRAMES && syscalls.len() == MAX_SYSCALLS {
+                break;
+            }
+        }
+        (frames, syscalls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::call_and_return;
+
+    #[test]
+    fn context_shows_the_open_call_frame_and_surrounding_code() {
+        let db = call_and_return().build();
+        let context = db.ai_context(2, 10);
+        assert!(context.starts_with("Instruction (clnum 2): 401010: mov eax, 1\n"), "{}", context);
+        assert!(context.contains("Call stack:\n  #0 unknown\n  #1 401000 (call at clnum 1)\n"), "{}", context);
+        assert!(context.contains("\n=> 401010: mov eax, 1\n"), "{}", context);
+        assert!(context.contains("   401015: ret"), "{}", context);
+
+        // Once the callee returned its frame is gone
+        let context = db.ai_context(4, 10);
+        assert!(context.contains("Call stack:\n  #0 unknown\n\n"), "{}", context);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::db::{changes_at, is_start, Address, ChangeFlags, Clnum, DisasmView, TraceDB, REG_NAMES};
+use crate::error::QueryError;
+use crate::il::{DataFlowEdge, DataFlowGraph, DataFlowNode};
+use std::collections::{BTreeSet, HashMap};
+
+impl TraceDB {
+    /// The backward slice of `target` from `start_clnum` (see `get_slice`)
+    /// as a data-flow graph. For each instruction in the slice, every
+    /// register it reads and every memory byte it loads is traced to the
+    /// instruction that last wrote it; those writers in the slice get an
+    /// edge to it. Values from before the trace (or written outside the
+    /// slice) have no producer.
+    pub fn data_flow_graph(&self, start_clnum: Clnum, target: String) -> Result<DataFlowGraph, QueryError> {
+        let slice = self.get_slice(start_clnum, target.clone())?;
+        let index: HashMap<Clnum, usize> = slice.iter().enumerate().map(|(i, &clnum)| (clnum, i)).collect();
+
+        // Each instruction's pc and loads (by the address they start at, with
+        // their size), copied out under one lock since the writer queries
+        // below take it again
+        let effects: Vec<(Address, Vec<(u64, u64)>)> = {
+            let changes = self.changes.read();
+            slice
+                .iter()
+                .map(|&clnum| {
+                    let group = changes_at(&changes, clnum);
+                    let address = group.iter().find(|ch| is_start(ch)).map_or(0, |ch| ch.address);
+                    let loads = group
+                        .iter()
+                        .filter(|ch| {
+                            let flags = ChangeFlags::from_bits_truncate(ch.flags);
+                            flags.contains(ChangeFlags::IS_MEM) && !flags.contains(ChangeFlags::IS_WRITE)
+                        })
+                        .map(|ch| (ch.address, (ch.flags & ChangeFlags::SIZE_MASK.bits()) as u64 / 8))
+                        .collect();
+                    (address, loads)
+                })
+                .collect()
+        };
+
+        let mut nodes = Vec::with_capacity(slice.len());
+        // (producer, consumer, location), ordered and deduplicated
+        let mut edges: BTreeSet<(usize, usize, String)> = BTreeSet::new();
+        for (consumer, (&clnum, (address, loads))) in slice.iter().zip(effects).enumerate() {
+            nodes.push(DataFlowNode {
+                clnum,
+                address,
+                text: self.disassembly_at(clnum, DisasmView::Timeline),
+                symbol: self.symbol_label(address),
+            });
+
+            let mut add_edge = |writer: Option<Clnum>, location: String| {
+                if let Some(&producer) = writer.and_then(|writer| index.get(&writer)) {
+                    edges.insert((producer, consumer, location));
+                }
+            };
+
+            for reg in self.insn_read_registers(clnum, address).into_iter().filter(|&r| r < 16) {
+                add_edge(self.last_writer_reg(clnum, reg), REG_NAMES[reg].to_string());
+            }
+            // Each byte of a load can have its own writer
+            for (load, size) in loads {
+                let location = self.address_label(load).unwrap_or_else(|| format!("0x{:x}", load));
+                for byte in load..load + size.max(1) {
+                    add_edge(self.last_writer(clnum.saturating_sub(1), byte), location.clone());
+                }
+            }
+        }
+
+        let edges = edges
+            .into_iter()
+            .map(|(producer, consumer, location)| DataFlowEdge { producer, consumer, location })
+            .collect();
+        Ok(DataFlowGraph { target, nodes, edges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::memory_write;
+
+    #[test]
+    fn edges_follow_the_value_through_registers_and_memory() {
+        let db = memory_write().build();
+        let graph = db.data_flow_graph(3, "rbx".to_string()).unwrap();
+
+        let clnums: Vec<_> = graph.nodes.iter().map(|n| n.clnum).collect();
+        assert_eq!(clnums, vec![1, 2, 3]);
+        let edges: Vec<_> = graph.edges.iter().map(|e| (e.producer, e.consumer, e.location.as_str())).collect();
+        assert_eq!(edges, vec![(0, 1, "rax"), (1, 2, "0x7ffdffd8")]);
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.contains("    node0 -->|\"rax\"| node1;\n"));
+        assert!(mermaid.contains("    click node2 call onCfgNodeClick(3)\n"));
+        // Only the load into rbx produced the target
+        assert!(mermaid.contains("    node2 ==> target;\n"));
+        assert!(!mermaid.contains("node1 ==> target"));
+    }
+}
@@ -1,14 +1,143 @@
-use crate::disasm::Disassembler;
-use crate::protocol::TraceEntry;
+use crate::cache::{CacheStats, LruCache};
+use crate::disasm::{
+    is_truncated, BackendKind, Disassembler, InsnDetail, InsnGroup, OperandDetail, RegPart, Truncated,
+};
+use crate::error::QueryError;
+use crate::il::Operation;
+use crate::protocol::{
+    BreakCondition, ChangeRecord, CondOperand, DisasmRequestOptions, DisasmSyntax, ExportRecord, ImmediateBase, NamedRegion, RegisterWriter, SessionConfig, SymbolStats, TraceEntry,
+    WriteOrigin,
+};
+use crate::store::{ChangeStore, DEFAULT_SPILL_THRESHOLD};
+use crate::syscall;
 use dashmap::DashMap;
 use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub type Address = u64;
 pub type Clnum = u32; // Change Line Number (Logical Time)
 pub type Data = u64;
 
+pub const REG_RSP: usize = 7;
+// Register indices beyond the 16 GPRs (RAX..R15) captured by the tracer
+pub const REG_FS_BASE: usize = 16;
+pub const REG_GS_BASE: usize = 17;
+pub const REG_COUNT: usize = 18;
+pub const REG_NAMES: [&str; REG_COUNT] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "fs_base", "gs_base",
+];
+
+// Immediates within this distance below 2^64 (or 2^32 for 32-bit operands)
+// are shown as negative numbers by `normalize_operands`
+const NEAR_WRAP: i64 = 0x10000;
+// The same for 16-bit operands, whose whole upper half NEAR_WRAP would cover
+const NEAR_WRAP_16: i64 = 0x100;
+
+// Longest possible x86 instruction
+const MAX_INSN_LEN: usize = 15;
+// Fewest printable bytes an immediate is annotated with as text for; shorter
+// ones are mostly sizes and offsets (`sub rsp, 0x28` would read "(")
+const MIN_TEXT_IMMEDIATE: usize = 4;
+// Entries kept in `insn_cache` unless configured otherwise
+pub const DEFAULT_INSN_CACHE_CAPACITY: usize = 1 << 16;
+// Cap on a function listing, for symbols without a size that never return
+const MAX_LISTING_INSNS: usize = 4096;
+// Bytes of a syscall's output buffer seeded as tainted
+const MAX_TAINT_BUFFER: u64 = 1 << 20;
+// Instructions a syscall's taint is followed through
+const MAX_TAINT_INSNS: usize = 1_000_000;
+
+// Bytes below rsp that leaf functions may use without moving it (SysV red zone)
+const STACK_RED_ZONE: u64 = 128;
+// rsp moves further than this from a known stack (stack pivots, sigaltstack)
+// are not folded into its extent
+const STACK_MAX_SPAN: u64 = 64 << 20;
+
+/// Address range a stack pointer has covered, as of `clnum`.
+#[derive(Clone, Copy, Debug)]
+struct StackExtent {
+    clnum: Clnum,
+    // Lowest rsp seen, minus the red zone
+    low: u64,
+    // Highest rsp seen, rounded up to the end of its page (argv/envp live above)
+    high: u64,
+}
+
+// (register index, value) and (address, value if captured, is_write), as in TraceEntry
+type RegDiff = (usize, u64);
+type MemAccess = (u64, Option<u64>, bool);
+// Where `find_return` left off for a call: the clnum its return site
+// executed at, or, until it's found, the last clnum scanned and the call
+// depth there, so a live trace resumes the scan instead of repeating it
+#[derive(Clone, Copy, Debug)]
+enum CallScan {
+    Returned(Clnum),
+    Pending { scanned: Clnum, depth: i64 },
+}
+
+// (first clnum, bytes) of code the tracer saw replaced at an address
+type CodeVersion = (Clnum, Vec<u8>);
+// (address, instruction bytes, text options) -> disassembly
+type InsnCacheKey = (Address, Vec<u8>, DisasmOptions);
+
+/// Rewrites applied to disassembly text; part of the text cache key, so
+/// views formatting the same instruction differently don't evict each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DisasmOptions {
+    pub normalize: bool,
+    pub immediate_base: ImmediateBase,
+    pub ascii_immediates: bool,
+    // Stack variables, branch targets and absolute operands named
+    pub symbolize: bool,
+}
+
+impl DisasmOptions {
+    /// Text as the disassembler prints it.
+    pub fn raw() -> Self {
+        Self { normalize: false, immediate_base: ImmediateBase::Hex, ascii_immediates: false, symbolize: false }
+    }
+}
+
+impl From<&SessionConfig> for DisasmOptions {
+    fn from(config: &SessionConfig) -> Self {
+        Self {
+            normalize: config.normalize_immediates,
+            immediate_base: config.immediate_base,
+            ascii_immediates: config.ascii_immediates,
+            symbolize: config.symbolize,
+        }
+    }
+}
+
+impl From<&DisasmRequestOptions> for DisasmOptions {
+    fn from(options: &DisasmRequestOptions) -> Self {
+        Self {
+            normalize: options.normalize_immediates,
+            immediate_base: options.immediate_base,
+            ascii_immediates: options.ascii_immediates,
+            symbolize: options.symbolize,
+        }
+    }
+}
+
+/// Where disassembly text is shown, which decides how it is formatted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisasmView {
+    // Also the current instruction, followed by its operand values
+    Timeline,
+    // CFG node labels: plain mnemonics and operands to keep nodes narrow,
+    // unless the session's `symbolize_cfg` is set
+    Cfg,
+}
+
+// Per register index: [(Clnum, Value)] whenever the value changed
+type RegisterHistory = Vec<Vec<(Clnum, u64)>>;
+
 bitflags::bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
     pub struct ChangeFlags: u32 {
@@ -17,6 +146,13 @@ bitflags::bitflags! {
         const IS_MEM     = 0x20000000;
         const IS_START   = 0x10000000;
         const IS_SYSCALL = 0x08000000;
+        // On an IS_START change: control arrived asynchronously (signal
+        // delivery or return, interrupt) rather than from the previous
+        // instruction
+        const IS_ASYNC   = 0x04000000;
+        // On an IS_MEM change: the tracer didn't capture the value, and
+        // `data` is meaningless
+        const NO_VALUE   = 0x02000000;
         const SIZE_MASK  = 0x000000FF;
     }
 }
@@ -30,80 +166,411 @@ pub struct Change {
     pub flags: u32,
 }
 
-#[derive(Debug, Default)]
+impl From<Change> for ChangeRecord {
+    fn from(ch: Change) -> Self {
+        ChangeRecord {
+            address: ch.address,
+            data: ch.data,
+            clnum: ch.clnum,
+            flags: ch.flags,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 struct MemoryCell {
     // Initial static value (from binary loader)
     static_value: Option<u8>,
-    // Dynamic history
-    history: Vec<(Clnum, u8)>,
+    // Dynamic history; None for a write whose value wasn't captured
+    history: Vec<(Clnum, Option<u8>)>,
+    // Writes before this clnum were dropped by the history limit (0 if none were)
+    truncated_before: Clnum,
 }
 
 impl MemoryCell {
+    // None before the first retained write once older ones were dropped,
+    // since the value then is unknown rather than the static one, and after
+    // a write whose value wasn't captured
     fn get_value_at(&self, clnum: Clnum) -> Option<u8> {
         let idx = self.history.partition_point(|&(c, _)| c <= clnum);
-        if idx == 0 {
+        if idx > 0 {
+            self.history[idx - 1].1
+        } else if self.truncated_before > 0 {
+            None
+        } else {
             self.static_value
+        }
+    }
+
+    // Whether the byte was overwritten by `clnum` with a value that wasn't
+    // captured, or its history from before then was dropped. A byte that
+    // was never written isn't unknown, it holds its static value (or 0).
+    fn is_unknown_at(&self, clnum: Clnum) -> bool {
+        let idx = self.history.partition_point(|&(c, _)| c <= clnum);
+        if idx > 0 {
+            self.history[idx - 1].1.is_none()
+        } else {
+            self.truncated_before > 0
+        }
+    }
+
+    fn enforce_limit(&mut self, limit: usize) {
+        if trim_history(&mut self.history, limit) {
+            self.truncated_before = self.history[0].0;
+        }
+    }
+
+    // Drops history older than `cutoff`, keeping the last earlier entry so the
+    // value at `cutoff` is still reconstructible
+    fn prune_before(&mut self, cutoff: Clnum) {
+        let idx = self.history.partition_point(|&(c, _)| c < cutoff);
+        if idx > 1 {
+            self.history.drain(..idx - 1);
+        }
+    }
+}
+
+/// One page of `get_trace_log` output.
+#[derive(Debug, Clone, Default)]
+pub struct TraceLogPage {
+    pub entries: Vec<TraceEntry>,
+    // Where the next page should start scanning (just past the last clnum
+    // examined), or None once the end of the trace was reached
+    pub next_start: Option<Clnum>,
+}
+
+/// Which instructions `get_trace_log` shows, and how it groups them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceLogOptions<'a> {
+    // Only instructions in user code (see `is_user_code`)
+    pub only_user_code: bool,
+    // Only instructions in the function with this name
+    pub function: Option<&'a str>,
+    // A call from user code into a library (or its PLT stub) is one entry
+    // counting the clnums skipped until the call returned
+    pub collapse_library_calls: bool,
+    // Consecutive executions of the same address are one entry counted in
+    // its `repeats`
+    pub fold: bool,
+}
+
+/// What `taint_from_syscall` seeded and found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallTaint {
+    pub nr: u64,
+    // rax once the syscall returned
+    pub result: u64,
+    // (address, length) of the buffer the syscall filled, if it fills one
+    pub buffer: Option<(Address, u64)>,
+    // Instructions after the syscall that used data derived from its result
+    pub slice: Vec<Clnum>,
+}
+
+/// Where a traced program that was killed, rather than exiting, stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crash {
+    // Last executed instruction: the faulting one, or the syscall that
+    // raised `signal`
+    pub clnum: Clnum,
+    pub address: Address,
+    pub signal: Option<i32>,
+}
+
+/// A secondary image (typically a shared library) loaded at a known runtime base.
+/// The main executable is not a `Module`: its bias is discovered while tracing
+/// and lives in `TraceDB` itself.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub path: String,
+    // RunAddr - StaticAddr for this module
+    pub bias: i64,
+    // Runtime range [start, end) covered by the module's loadable segments
+    pub start: u64,
+    pub end: u64,
+    // StaticAddress -> (Size, SymbolName)
+    pub symbols: BTreeMap<u64, (u64, String)>,
+}
+
+impl Module {
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.start && address < self.end
+    }
+
+    pub fn to_static(&self, address: u64) -> u64 {
+        (address as i128 - self.bias as i128) as u64
+    }
+
+    /// File name of the module's path, e.g. "libc.so.6".
+    pub fn file_name(&self) -> String {
+        Path::new(&self.path)
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.clone())
+    }
+
+    // Whether `name` is the module's path or its file name (see `find_module`)
+    fn is_named(&self, name: &str) -> bool {
+        self.path == name || Path::new(&self.path).file_name().is_some_and(|f| f == name)
+    }
+
+    fn find_symbol(&self, static_addr: u64) -> Option<(String, u64)> {
+        let (&start, (size, name)) = self.symbols.range(..=static_addr).next_back()?;
+        if static_addr < start + (*size).max(1) {
+            Some((name.clone(), static_addr - start))
         } else {
-            Some(self.history[idx - 1].1)
+            None
         }
     }
+
+    // Whether `static_addr` is in a `name@plt` symbol, as `find_symbol`
+    // would find it
+    fn is_stub_at(&self, static_addr: u64) -> bool {
+        self.symbols
+            .range(..=static_addr)
+            .next_back()
+            .is_some_and(|(&start, (size, name))| static_addr < start + (*size).max(1) && name.ends_with("@plt"))
+    }
 }
 
 pub struct TraceDB {
-    pub(crate) changes: RwLock<Vec<Change>>,
+    pub(crate) changes: RwLock<ChangeStore>,
     memory: DashMap<Address, MemoryCell>,
-    registers: RwLock<Vec<Vec<(Clnum, u64)>>>,
+    // Values loaded by memory reads: Address -> [(Clnum, Value)], kept apart
+    // from `memory` so reads never show up as write history
+    reads: DashMap<Address, Vec<(Clnum, u64)>>,
+    // Register history kept separately per vcpu, so interleaved threads
+    // don't overwrite each other's state
+    pub(crate) registers: RwLock<HashMap<u32, RegisterHistory>>,
+    pub(crate) reg_count: usize,
+    // Which vcpu executed each clnum, as runs: (first clnum, vcpu)
+    vcpus: RwLock<Vec<(Clnum, u32)>>,
     // Reverse index: (Address, AccessType ('R'|'W')) -> List of Clnums
     access_index: DashMap<(Address, u8), Vec<Clnum>>,
+    // Runtime address -> clnums that executed the instruction there, in
+    // order, so per-address execution queries don't scan the change log
+    executions: DashMap<Address, Vec<Clnum>>,
     // Disassembler instance
     disassembler: Mutex<Disassembler>,
-    // Instruction cache: (Address, Instruction Bytes) -> Disassembled String
-    insn_cache: DashMap<(Address, Vec<u8>), String>,
+    // Instruction cache: (Address, Instruction Bytes, Text Options) -> Disassembled String
+    // Only filled from the fast (no-detail) path; detailed queries bypass it.
+    // Keyed by the bytes, so rewritten code at an address gets its own entry.
+    // Size-capped (see `set_insn_cache_capacity`) since it can be rebuilt
+    pub(crate) insn_cache: LruCache<InsnCacheKey>,
+    // Display and analysis switches, see `configure`
+    config: RwLock<SessionConfig>,
     // Map from Clnum to instruction bytes
     pub(crate) instructions: DashMap<Clnum, Vec<u8>>,
     // Map from Clnum to disassembly string (fallback if bytes unavailable or disasm failed)
-    instructions_disasm: DashMap<Clnum, String>,
+    pub(crate) instructions_disasm: DashMap<Clnum, String>,
+    // Self-modifying code: runtime address -> each time the tracer saw
+    // different bytes translated there, in clnum order
+    pub(crate) code_versions: RwLock<BTreeMap<Address, Vec<CodeVersion>>>,
+    // Call clnum -> how far `find_return` got pairing it with its return
+    call_returns: DashMap<Clnum, CallScan>,
+    // Runtime pcs of executed blocks reported by a coverage-mode tracer
+    pub(crate) block_coverage: RwLock<BTreeSet<Address>>,
     // User code ranges (start, end) inclusive
-    user_code_ranges: RwLock<Vec<(u64, u64)>>,
+    pub(crate) user_code_ranges: RwLock<Vec<(u64, u64)>>,
+    // Explicit user code ranges (static, [start, end)); override the above when set
+    explicit_user_ranges: RwLock<Option<Vec<(u64, u64)>>>,
+    // PLT sections of the main binary (static, [start, end))
+    stub_ranges: RwLock<Vec<(u64, u64)>>,
     // Entry point of the binary (static address)
     entry_point: RwLock<Option<u64>>,
     // Execution bias (RunAddr - StaticAddr)
     bias: RwLock<i64>,
     // Symbol map (StaticAddress -> (Size, SymbolName))
     pub(crate) symbols: DashMap<u64, (u64, String)>,
+    // The `name@plt` entries of `symbols` by address (StaticAddress -> Size),
+    // for `is_stub`
+    stub_symbols: RwLock<BTreeMap<u64, u64>>,
+    // User-named memory regions: runtime start -> (len, name), never overlapping
+    regions: RwLock<BTreeMap<Address, (u64, String)>>,
+    // Shared libraries and other images with their own bias and symbols
+    modules: RwLock<Vec<Module>>,
+    // Per-vcpu history of stack extents, grown from observed rsp values
+    stacks: RwLock<HashMap<u32, Vec<StackExtent>>>,
+    // Set once the traced program ended without exiting
+    crash: RwLock<Option<Crash>>,
+    // Ring buffer mode: keep only the last N clnums of history (None = unbounded)
+    window: Option<usize>,
+    // Writes kept per memory byte, and per address in `access_index`
+    // (0 = unbounded)
+    memory_history_limit: AtomicUsize,
+    // Clnums below this have been evicted by the window
+    pruned_before: RwLock<Clnum>,
 }
 
 impl TraceDB {
     pub fn new(reg_count: usize) -> Self {
-        let mut regs = Vec::with_capacity(reg_count);
-        for _ in 0..reg_count {
-            regs.push(Vec::new());
-        }
+        Self::with_window(reg_count, None)
+    }
 
+    /// Creates a DB that retains only the last `window` clnums of changes,
+    /// memory history and register history. Older state is evicted and
+    /// queries for it are reported as pruned.
+    pub fn with_window(reg_count: usize, window: Option<usize>) -> Self {
         Self {
-            changes: RwLock::new(Vec::new()),
+            changes: RwLock::new(ChangeStore::new()),
             memory: DashMap::new(),
-            registers: RwLock::new(regs),
+            reads: DashMap::new(),
+            registers: RwLock::new(HashMap::new()),
+            reg_count,
+            vcpus: RwLock::new(Vec::new()),
             access_index: DashMap::new(),
+            executions: DashMap::new(),
             disassembler: Mutex::new(Disassembler::new().expect("Failed to init disassembler")),
-            insn_cache: DashMap::new(),
+            insn_cache: LruCache::new(DEFAULT_INSN_CACHE_CAPACITY),
+            config: RwLock::new(SessionConfig::default()),
             instructions: DashMap::new(),
             instructions_disasm: DashMap::new(),
+            code_versions: RwLock::new(BTreeMap::new()),
+            call_returns: DashMap::new(),
+            block_coverage: RwLock::new(BTreeSet::new()),
             user_code_ranges: RwLock::new(Vec::new()),
+            explicit_user_ranges: RwLock::new(None),
+            stub_ranges: RwLock::new(Vec::new()),
             entry_point: RwLock::new(None),
             bias: RwLock::new(0),
             symbols: DashMap::new(),
+            stub_symbols: RwLock::new(BTreeMap::new()),
+            regions: RwLock::new(BTreeMap::new()),
+            modules: RwLock::new(Vec::new()),
+            stacks: RwLock::new(HashMap::new()),
+            crash: RwLock::new(None),
+            window: window.filter(|&w| w > 0),
+            memory_history_limit: AtomicUsize::new(0),
+            pruned_before: RwLock::new(0),
+        }
+    }
+
+    /// Moves the change log into a memory-mapped file at `path` (created or
+    /// truncated) once it outgrows about a million changes. Queries read
+    /// through the mapping; without this, changes stay in memory.
+    pub fn with_spill_file(self, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        *self.changes.write() = ChangeStore::spilling(path.as_ref(), DEFAULT_SPILL_THRESHOLD)?;
+        Ok(self)
+    }
+
+    pub fn window(&self) -> Option<usize> {
+        self.window
+    }
+
+    /// Keeps only (about) the last `limit` writes of each memory byte, so a
+    /// hot address like a loop counter doesn't grow without bound. Older
+    /// values of a capped byte read as unknown and its `write_origin` as
+    /// `Truncated`; the writes indexed per address are capped alike. None,
+    /// the default, keeps every write; 0 is treated as None.
+    pub fn set_memory_history_limit(&self, limit: Option<usize>) {
+        self.memory_history_limit.store(limit.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    pub fn memory_history_limit(&self) -> Option<usize> {
+        Some(self.memory_history_limit.load(Ordering::Relaxed)).filter(|&n| n > 0)
+    }
+
+    /// Lowest clnum whose state is still retained (0 unless the window evicted history).
+    pub fn first_retained_clnum(&self) -> Clnum {
+        *self.pruned_before.read()
+    }
+
+    pub fn is_pruned(&self, clnum: Clnum) -> bool {
+        clnum < self.first_retained_clnum()
+    }
+
+    /// Drops all recorded execution (changes, memory and register history,
+    /// instructions) so a new run can start again from clnum 1. Loaded images,
+    /// symbols, named regions, static memory and user ranges are kept.
+    pub fn reset_trace(&self) {
+        let mut pruned_before = self.pruned_before.write();
+        self.changes.write().clear();
+        self.memory.retain(|_, cell| {
+            cell.history.clear();
+            cell.truncated_before = 0;
+            cell.static_value.is_some()
+        });
+        self.reads.clear();
+        self.registers.write().clear();
+        self.vcpus.write().clear();
+        self.access_index.clear();
+        self.executions.clear();
+        self.instructions.clear();
+        self.instructions_disasm.clear();
+        self.code_versions.write().clear();
+        self.call_returns.clear();
+        self.block_coverage.write().clear();
+        self.stacks.write().clear();
+        *self.crash.write() = None;
+        *pruned_before = 0;
+    }
+
+    // Evicts history that fell out of the window. Eviction runs in batches of
+    // window/8 clnums so the per-instruction cost stays amortized.
+    fn enforce_window(&self, clnum: Clnum) {
+        let window = match self.window {
+            Some(w) => w as Clnum,
+            None => return,
+        };
+        let cutoff = (clnum + 1).saturating_sub(window);
+        let slack = (window / 8).max(1);
+        if cutoff < *self.pruned_before.read() + slack {
+            return;
+        }
+
+        let mut pruned_before = self.pruned_before.write();
+        if cutoff < *pruned_before + slack {
+            return;
+        }
+
+        {
+            let mut changes = self.changes.write();
+            let idx = changes.partition_point(|c| c.clnum < cutoff);
+            changes.drain_front(idx);
+        }
+        for mut cell in self.memory.iter_mut() {
+            cell.prune_before(cutoff);
+        }
+        self.reads.retain(|_, history| {
+            history.retain(|&(c, _)| c >= cutoff);
+            !history.is_empty()
+        });
+        for history in self.registers.write().values_mut().flatten() {
+            let idx = history.partition_point(|&(c, _)| c < cutoff);
+            if idx > 1 {
+                history.drain(..idx - 1);
+            }
+        }
+        {
+            let mut vcpus = self.vcpus.write();
+            let idx = vcpus.partition_point(|&(c, _)| c < cutoff);
+            if idx > 1 {
+                vcpus.drain(..idx - 1);
+            }
         }
+        self.access_index.retain(|_, clnums| {
+            clnums.retain(|&c| c >= cutoff);
+            !clnums.is_empty()
+        });
+        self.executions.retain(|_, clnums| {
+            let idx = clnums.partition_point(|&c| c < cutoff);
+            clnums.drain(..idx);
+            !clnums.is_empty()
+        });
+        self.instructions.retain(|&c, _| c >= cutoff);
+        self.call_returns.retain(|&c, _| c >= cutoff);
+        self.instructions_disasm.retain(|&c, _| c >= cutoff);
+
+        *pruned_before = cutoff;
     }
 
     pub fn set_entry_point(&self, ep: u64) {
         *self.entry_point.write() = Some(ep);
-        println!("[DEBUG] TraceDB: Entry Point set to {:x}", ep);
+        tracing::debug!("entry point set to {:x}", ep);
     }
 
     pub fn set_bias(&self, bias: i64) {
         *self.bias.write() = bias;
-        println!("[DEBUG] TraceDB: Bias set to {:x} (RunAddr - StaticAddr)", bias);
+        tracing::debug!("bias set to {:x} (RunAddr - StaticAddr)", bias);
     }
 
     pub fn get_bias(&self) -> i64 {
@@ -115,6 +582,12 @@ impl TraceDB {
     }
 
     pub fn add_symbol(&self, start: u64, size: u64, name: String) {
+        let mut stubs = self.stub_symbols.write();
+        if name.ends_with("@plt") {
+            stubs.insert(start, size);
+        } else {
+            stubs.remove(&start);
+        }
         self.symbols.insert(start, (size, name));
     }
 
@@ -148,13 +621,427 @@ impl TraceDB {
         None
     }
 
+    /// Returns the first clnum at which the instruction at `static_addr` (in
+    /// the main binary) executed.
+    pub fn first_execution(&self, static_addr: u64) -> Option<Clnum> {
+        // StaticAddr = RunAddr - Bias  => RunAddr = StaticAddr + Bias
+        let run_addr = (static_addr as i128 + self.get_bias() as i128) as u64;
+        self.first_execution_at(run_addr)
+    }
+
+    /// First clnum at which the instruction at runtime `address` executed.
+    pub fn first_execution_at(&self, address: Address) -> Option<Clnum> {
+        self.executions.get(&address).and_then(|clnums| clnums.first().copied())
+    }
+
+    /// Every clnum that executed the instruction at runtime `address`, in order.
+    pub fn executions_of(&self, address: Address) -> Vec<Clnum> {
+        self.executions.get(&address).map(|clnums| clnums.clone()).unwrap_or_default()
+    }
+
+    /// How many times the instruction at runtime `address` executed.
+    pub fn execution_count(&self, address: Address) -> usize {
+        self.executions.get(&address).map_or(0, |clnums| clnums.len())
+    }
+
+    /// The registers `reg_indices` at each execution of the block whose leader
+    /// is at runtime `block_addr`, as seen on entry to the block. Successive
+    /// rows show how a loop body changes them per iteration.
+    pub fn block_iteration_states(&self, block_addr: u64, reg_indices: &[usize]) -> Vec<(Clnum, Vec<u64>)> {
+        self.executions_of(block_addr)
+            .into_iter()
+            .map(|clnum| {
+                let regs = self.get_registers_at(clnum);
+                (clnum, reg_indices.iter().map(|&i| regs.get(i).copied().unwrap_or(0)).collect())
+            })
+            .collect()
+    }
+
+    /// First clnum at which each runtime address in `range` executed, for the
+    /// addresses that did.
+    pub fn first_executions(&self, range: Range<Address>) -> HashMap<Address, Clnum> {
+        self.executions
+            .iter()
+            .filter(|entry| range.contains(entry.key()))
+            .filter_map(|entry| entry.value().first().map(|&clnum| (*entry.key(), clnum)))
+            .collect()
+    }
+
+    /// Records executed blocks reported by a coverage-mode tracer (runtime pcs).
+    pub fn add_block_coverage(&self, blocks: &[Address]) {
+        self.block_coverage.write().extend(blocks);
+    }
+
+    /// Function coverage of the main binary: for each symbol (in address
+    /// order), whether any instruction in it executed and how many executed
+    /// instructions fall in its range. See `execution_counts`.
+    pub fn coverage(&self) -> Vec<(String, bool, u32)> {
+        self.execution_counts()
+            .into_iter()
+            .map(|stats| (stats.name, stats.covered, stats.executions))
+            .collect()
+    }
+
+    /// Execution statistics of every symbol of the main binary, in address
+    /// order. Symbols without a size (e.g. PE exports) only count executions
+    /// of their exact address. Blocks from a coverage-mode tracer mark a
+    /// function covered without adding to its count.
+    pub fn execution_counts(&self) -> Vec<SymbolStats> {
+        let bias = self.get_bias();
+        let to_static = |address: Address| (address as i128 - bias as i128) as u64;
+        // Static address -> (executions, first clnum)
+        let mut hits: BTreeMap<u64, (u32, Clnum)> = BTreeMap::new();
+        let blocks: BTreeSet<u64> = {
+            let modules = self.modules.read();
+            let in_module = |address| modules.iter().any(|m| m.contains(address));
+            for entry in self.executions.iter().filter(|entry| !in_module(*entry.key())) {
+                let Some(&first) = entry.value().first() else { continue };
+                hits.insert(to_static(*entry.key()), (entry.value().len() as u32, first));
+            }
+            self.block_coverage
+                .read()
+                .iter()
+                .filter(|&&pc| !in_module(pc))
+                .map(|&pc| to_static(pc))
+                .collect()
+        };
+
+        let mut symbols: Vec<(u64, u64, String)> = self
+            .symbols
+            .iter()
+            .map(|r| (*r.key(), r.value().0, r.value().1.clone()))
+            .collect();
+        symbols.sort();
+        symbols
+            .into_iter()
+            .map(|(start, size, name)| {
+                let range = start..start.saturating_add(size.max(1));
+                let executions: u32 = hits.range(range.clone()).map(|(_, (n, _))| n).sum();
+                let first_clnum = hits.range(range.clone()).map(|(_, &(_, first))| first).min();
+                let block_hit = blocks.range(range).next().is_some();
+                SymbolStats {
+                    name,
+                    address: start,
+                    size,
+                    executions,
+                    covered: executions > 0 || block_hit,
+                    first_clnum,
+                }
+            })
+            .collect()
+    }
+
+    /// Clnum where user code starts: the first execution of `main`, or of the
+    /// entry point if the binary has no `main` symbol.
+    pub fn main_clnum(&self) -> Result<Clnum, QueryError> {
+        let (what, static_addr) = match self.find_symbol_by_name("main") {
+            Some(addr) => ("main", addr),
+            None => match self.get_entry_point() {
+                Some(ep) => ("entry point", ep),
+                None => return Err(QueryError::SymbolNotFound("main".to_string())),
+            },
+        };
+        self.first_execution(static_addr)
+            .ok_or_else(|| QueryError::NeverExecuted(what.to_string()))
+    }
+
+    /// Loads a shared library (or other ELF image) mapped at `runtime_base`.
+    /// Its symbols and code are resolved with the module's own bias.
+    pub fn add_module(&self, path: &Path, runtime_base: u64) -> anyhow::Result<()> {
+        crate::loader::BinaryLoader::load_module(self, path, runtime_base)
+    }
+
+    pub(crate) fn push_module(&self, module: Module) {
+        tracing::debug!(
+            "module {} at {:x} - {:x} (bias {:x})",
+            module.path,
+            module.start,
+            module.end,
+            module.bias
+        );
+        self.modules.write().push(module);
+    }
+
+    pub fn modules(&self) -> Vec<Module> {
+        self.modules.read().clone()
+    }
+
+    /// The loaded module whose path or file name is `name`.
+    pub fn find_module(&self, name: &str) -> Option<Module> {
+        self.modules
+            .read()
+            .iter()
+            .find(|m| m.is_named(name))
+            .cloned()
+    }
+
+    // Address at which static bytes for runtime `address` were loaded: modules
+    // are loaded at their runtime addresses, the main executable at static ones
+    pub(crate) fn static_memory_address(&self, address: u64) -> u64 {
+        if self.modules.read().iter().any(|m| m.contains(address)) {
+            address
+        } else {
+            (address as i128 - self.get_bias() as i128) as u64
+        }
+    }
+
+    /// Resolves a runtime address to its containing symbol and offset, using
+    /// the bias of whichever module (or the main executable) it belongs to.
+    pub fn symbolize(&self, address: u64) -> Option<(String, u64)> {
+        if let Some(module) = self.modules.read().iter().find(|m| m.contains(address)) {
+            return module.find_symbol(module.to_static(address));
+        }
+        self.find_symbol((address as i128 - self.get_bias() as i128) as u64)
+    }
+
+    /// `symbolize` formatted as "name" or "name+0xoff".
+    pub fn symbol_label(&self, address: u64) -> Option<String> {
+        self.symbolize(address).map(offset_label)
+    }
+
+    /// Records how the traced program ended (TraceEvent::ProcessEnd). Without
+    /// an exit status it was killed, and its last executed instruction is
+    /// kept as the crash point.
+    pub fn record_process_end(&self, exit_code: Option<i64>, signal: Option<i32>) {
+        let crash = match exit_code {
+            Some(_) => None,
+            None => {
+                let changes = self.changes.read();
+                changes.iter().rev().find(|ch| is_start(ch)).map(|ch| Crash {
+                    clnum: ch.clnum,
+                    address: ch.address,
+                    signal,
+                })
+            }
+        };
+        *self.crash.write() = crash;
+    }
+
+    /// The crash point, if the program was killed rather than exiting.
+    pub fn crash(&self) -> Option<Crash> {
+        *self.crash.read()
+    }
+
+    /// Names `len` bytes at runtime `address`, replacing any named regions
+    /// they overlap. Names are single words so they read as operands.
+    pub fn name_region(&self, address: Address, len: u64, name: &str) -> Result<(), QueryError> {
+        if len == 0 {
+            return Err(QueryError::InvalidRegion(format!("'{}' has no bytes", name)));
+        }
+        if name.is_empty() || name.chars().any(char::is_whitespace) {
+            return Err(QueryError::InvalidRegion(format!("'{}' is not a single word", name)));
+        }
+        let end = address.saturating_add(len);
+        let mut regions = self.regions.write();
+        regions.retain(|&start, (size, _)| start.saturating_add(*size) <= address || start >= end);
+        regions.insert(address, (end - address, name.to_string()));
+        // Cached text has branch targets and operands named
+        self.insn_cache.clear();
+        Ok(())
+    }
+
+    /// Removes the named region containing `address`, or all of them for None.
+    pub fn unname_region(&self, address: Option<Address>) {
+        let mut regions = self.regions.write();
+        match address {
+            Some(address) => {
+                let containing = regions
+                    .range(..=address)
+                    .next_back()
+                    .filter(|(&start, (len, _))| address - start < *len)
+                    .map(|(&start, _)| start);
+                if let Some(start) = containing {
+                    regions.remove(&start);
+                }
+            }
+            None => regions.clear(),
+        }
+        self.insn_cache.clear();
+    }
+
+    /// User-named regions in address order.
+    pub fn regions(&self) -> Vec<NamedRegion> {
+        self.regions
+            .read()
+            .iter()
+            .map(|(&address, (len, name))| NamedRegion { address, len: *len, name: name.clone() })
+            .collect()
+    }
+
+    /// The named region containing runtime `address`, and the offset into it.
+    pub fn region_at(&self, address: Address) -> Option<(String, u64)> {
+        let regions = self.regions.read();
+        let (&start, (len, name)) = regions.range(..=address).next_back()?;
+        (address - start < *len).then(|| (name.clone(), address - start))
+    }
+
+    /// What runtime `address` is called: the named region containing it, else
+    /// its symbol, with the offset into either.
+    pub fn name_address(&self, address: Address) -> Option<(String, u64)> {
+        self.region_at(address).or_else(|| self.symbolize(address))
+    }
+
+    /// `name_address` formatted as "name" or "name+0xoff".
+    pub fn address_label(&self, address: Address) -> Option<String> {
+        self.name_address(address).map(offset_label)
+    }
+
+    /// Best-effort description of what `address` points into: its region or
+    /// symbol, or the containing module as "file+0xoff" when neither covers it.
+    pub fn describe_address(&self, address: u64) -> Option<String> {
+        if let Some(label) = self.address_label(address) {
+            return Some(label);
+        }
+        let modules = self.modules.read();
+        let module = modules.iter().find(|m| m.contains(address))?;
+        Some(format!("{}+0x{:x}", module.file_name(), address - module.start))
+    }
+
+    /// Returns true if `name` is a symbol of the main executable or any module.
+    pub fn has_symbol(&self, name: &str) -> bool {
+        self.find_symbol_by_name(name).is_some()
+            || self
+                .modules
+                .read()
+                .iter()
+                .any(|m| m.symbols.values().any(|(_, n)| n == name))
+    }
+
     pub fn get_memory_writes(&self, address: Address) -> Vec<Clnum> {
+        let first = self.first_retained_clnum();
         self.memory
             .get(&address)
-            .map(|cell| cell.history.iter().map(|(c, _)| *c).collect())
+            .map(|cell| {
+                cell.history
+                    .iter()
+                    .map(|(c, _)| *c)
+                    .filter(|&c| c >= first)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the clnums of every load covering the byte at `address` (like
+    /// `get_memory_writes`, a wider load starting just below it counts).
+    pub fn get_memory_reads(&self, address: Address) -> Vec<Clnum> {
+        let changes = self.changes.read();
+        let mut clnums = Vec::new();
+        for offset in 0..8 {
+            let start = match address.checked_sub(offset) {
+                Some(a) => a,
+                None => break,
+            };
+            let Some(indexed) = self.access_index.get(&(start, b'R')) else {
+                continue;
+            };
+            clnums.extend(indexed.iter().copied().filter(|&clnum| {
+                changes_at(&changes, clnum).iter().any(|ch| {
+                    let flags = ChangeFlags::from_bits_truncate(ch.flags);
+                    let size = (ch.flags & ChangeFlags::SIZE_MASK.bits()) as u64 / 8;
+                    ch.address == start
+                        && flags.contains(ChangeFlags::IS_MEM)
+                        && !flags.contains(ChangeFlags::IS_WRITE)
+                        && offset < size.max(1)
+                })
+            }));
+        }
+        clnums.sort_unstable();
+        clnums.dedup();
+        clnums
+    }
+
+    /// The little-endian `width`-byte value at `address` (width is clamped to
+    /// 1..=8) after every write to any of its bytes, in clnum order. Each
+    /// point is rebuilt from the per-byte histories, so partial writes and
+    /// wider writes straddling the address both show up with the bytes they
+    /// left untouched. Unwritten bytes read as their static value, or 0.
+    pub fn value_history(&self, address: Address, width: usize) -> Vec<(Clnum, u64)> {
+        let width = width.clamp(1, 8);
+        let first = self.first_retained_clnum();
+        // Copied out one at a time rather than holding several map guards
+        let cells: Vec<Option<MemoryCell>> = (0..width as u64)
+            .map(|i| self.memory.get(&(address + i)).map(|cell| cell.clone()))
+            .collect();
+        let mut clnums: Vec<Clnum> = cells
+            .iter()
+            .flatten()
+            .flat_map(|cell| cell.history.iter().map(|&(c, _)| c))
+            .filter(|&c| c >= first)
+            .collect();
+        clnums.sort_unstable();
+        clnums.dedup();
+        clnums
+            .into_iter()
+            .map(|clnum| {
+                let value = cells.iter().enumerate().fold(0u64, |value, (i, cell)| {
+                    let byte = cell.as_ref().and_then(|cell| cell.get_value_at(clnum)).unwrap_or(0);
+                    value | (byte as u64) << (8 * i)
+                });
+                (clnum, value)
+            })
+            .collect()
+    }
+
+    /// Returns every (clnum, value) loaded from `address` by an access
+    /// starting there, in clnum order.
+    pub fn get_read_values(&self, address: Address) -> Vec<(Clnum, u64)> {
+        self.reads
+            .get(&address)
+            .map(|history| history.clone())
             .unwrap_or_default()
     }
 
+    /// Returns the value the instruction at `clnum` loaded from `address`, if it read it.
+    pub fn read_value_at(&self, clnum: Clnum, address: Address) -> Option<u64> {
+        let history = self.reads.get(&address)?;
+        let idx = history.partition_point(|&(c, _)| c < clnum);
+        history
+            .get(idx)
+            .filter(|&&(c, _)| c == clnum)
+            .map(|&(_, value)| value)
+    }
+
+    /// Returns the clnum of the last write to `address` at or before `clnum`.
+    pub fn last_writer(&self, clnum: Clnum, address: Address) -> Option<Clnum> {
+        let cell = self.memory.get(&address)?;
+        let idx = cell.history.partition_point(|&(c, _)| c <= clnum);
+        if idx == 0 {
+            None
+        } else {
+            Some(cell.history[idx - 1].0)
+        }
+    }
+
+    /// Like `last_writer`, but distinguishes a byte that was never written
+    /// and comes from the binary from one there is no data for at all.
+    pub fn write_origin(&self, clnum: Clnum, address: Address) -> WriteOrigin {
+        if let Some(writer) = self.last_writer(clnum, address) {
+            return WriteOrigin::Written { clnum: writer };
+        }
+        match self.memory.get(&address) {
+            Some(cell) if cell.truncated_before > 0 => WriteOrigin::Truncated { before: cell.truncated_before },
+            Some(cell) if cell.static_value.is_some() => WriteOrigin::Static,
+            _ => WriteOrigin::NoData,
+        }
+    }
+
+    // Every retained byte write as (address, clnum, value if captured), and
+    // the bytes loaded from images, both in address order
+    pub(crate) fn memory_contents(&self) -> (Vec<(Address, Clnum, Option<u8>)>, Vec<(Address, u8)>) {
+        let mut writes = Vec::new();
+        let mut loaded = Vec::new();
+        for cell in self.memory.iter() {
+            let address = *cell.key();
+            writes.extend(cell.history.iter().map(|&(clnum, value)| (address, clnum, value)));
+            if let Some(value) = cell.static_value {
+                loaded.push((address, value));
+            }
+        }
+        writes.sort_unstable();
+        loaded.sort_unstable();
+        (writes, loaded)
+    }
+
     pub fn load_static_memory(&self, start_addr: Address, data: &[u8]) {
         for (i, &byte) in data.iter().enumerate() {
             let addr = start_addr + i as u64;
@@ -163,21 +1050,74 @@ impl TraceDB {
     }
 
     pub fn register_code_range(&self, start: u64, size: u64) {
-        println!(
-            "[DEBUG] register_code_range: {:x} - {:x}",
-            start,
-            start + size
-        );
+        tracing::debug!("code range {:x} - {:x}", start, start + size);
         let mut ranges = self.user_code_ranges.write();
         ranges.push((start, start + size));
     }
 
+    /// Marks the static range `[start, start + size)` as linker stubs (the
+    /// PLT sections), which only forward calls to the function they import.
+    pub fn register_stub_range(&self, start: u64, size: u64) {
+        tracing::debug!("stub range {:x} - {:x}", start, start + size);
+        self.stub_ranges.write().push((start, start + size));
+    }
+
+    /// Whether runtime `address` is in a linker stub: a PLT section of the
+    /// main binary, or a `name@plt` entry of any loaded image.
+    pub fn is_stub(&self, address: u64) -> bool {
+        if let Some(module) = self.modules.read().iter().find(|m| m.contains(address)) {
+            return module.is_stub_at(module.to_static(address));
+        }
+        let static_addr = (address as i128 - self.get_bias() as i128) as u64;
+        let in_plt = self
+            .stub_ranges
+            .read()
+            .iter()
+            .any(|&(start, end)| static_addr >= start && static_addr < end);
+        in_plt
+            || self
+                .stub_symbols
+                .read()
+                .range(..=static_addr)
+                .next_back()
+                .is_some_and(|(&start, &size)| static_addr < start + size)
+    }
+
+    /// Restricts user code to the given static `[start, end)` ranges, replacing
+    /// the executable-segment ranges found by the loader (which also cover any
+    /// statically linked runtime). An empty list restores the segment ranges.
+    pub fn set_user_ranges(&self, ranges: Vec<(u64, u64)>) {
+        *self.explicit_user_ranges.write() = if ranges.is_empty() { None } else { Some(ranges) };
+    }
+
+    // The static `[start, end)` ranges `is_user_code` goes by for the main
+    // binary: the explicit ones if set, else the executable segments
+    pub(crate) fn user_ranges(&self) -> Vec<(u64, u64)> {
+        match &*self.explicit_user_ranges.read() {
+            Some(ranges) => ranges.clone(),
+            None => self.user_code_ranges.read().clone(),
+        }
+    }
+
     pub fn is_user_code(&self, address: u64) -> bool {
-        let ranges = self.user_code_ranges.read();
+        if let Some(name) = &self.config.read().active_module {
+            if let Some(contains) = self.modules.read().iter().find(|m| m.is_named(name)).map(|m| m.contains(address)) {
+                return contains;
+            }
+        }
+
+        let explicit = self.explicit_user_ranges.read();
+        let segments = self.user_code_ranges.read();
+        let ranges = explicit.as_ref().unwrap_or(&segments);
         // If no ranges registered, treat everything as user code
         if ranges.is_empty() {
             return true;
         }
+
+        // Shared libraries are never user code
+        if self.modules.read().iter().any(|m| m.contains(address)) {
+            return false;
+        }
         
         // Normalize address by removing bias
         // StaticAddr = RunAddr - Bias
@@ -191,18 +1131,56 @@ impl TraceDB {
     }
 
     pub fn add_instruction(&self, clnum: Clnum, bytes: Vec<u8>) {
+        self.enforce_window(clnum);
         if !bytes.is_empty() {
             self.instructions.insert(clnum, bytes);
         }
     }
 
+    // Whether `bytes` decode to one instruction of exactly their length
+    pub(crate) fn decodes_exactly(&self, bytes: &[u8], address: Address) -> bool {
+        self.disassembler
+            .lock()
+            .length(bytes, address)
+            .is_ok_and(|len| len == bytes.len())
+    }
+
     pub fn add_instruction_disasm(&self, clnum: Clnum, disasm: String) {
         if !disasm.is_empty() {
             self.instructions_disasm.insert(clnum, disasm);
         }
     }
 
+    /// Records that the code at runtime `address` was replaced by `bytes`
+    /// from `clnum` on, as reported by the tracer for self-modifying code.
+    pub fn invalidate_code(&self, clnum: Clnum, address: Address, bytes: Vec<u8>) {
+        tracing::debug!(clnum, address = format_args!("{:x}", address), "code modified");
+        let mut versions = self.code_versions.write();
+        let history = versions.entry(address).or_default();
+        let idx = history.partition_point(|&(c, _)| c <= clnum);
+        history.insert(idx, (clnum, bytes));
+    }
+
+    /// How many times the code at runtime `address` had been replaced by
+    /// `clnum` (0 while it still holds the originally translated bytes).
+    pub fn code_version(&self, address: Address, clnum: Clnum) -> usize {
+        self.code_versions
+            .read()
+            .get(&address)
+            .map_or(0, |history| history.partition_point(|&(c, _)| c <= clnum))
+    }
+
+    // Bytes the code at `address` was last replaced with at or before `clnum`
+    fn replaced_code(&self, address: Address, clnum: Clnum) -> Option<Vec<u8>> {
+        let versions = self.code_versions.read();
+        let history = versions.get(&address)?;
+        let idx = history.partition_point(|&(c, _)| c <= clnum);
+        idx.checked_sub(1).map(|i| history[i].1.clone())
+    }
+
     pub fn add_change(&self, change: Change) {
+        self.enforce_window(change.clnum);
+
         // 1. Add to raw log
         {
             let mut w = self.changes.write();
@@ -213,41 +1191,58 @@ impl TraceDB {
 
         // 2. Update Indices
         if flags.contains(ChangeFlags::IS_MEM) {
-            // Memory Access
+            // Memory Access. A write whose value wasn't captured still
+            // overwrites the bytes, which are unknown from then on; a read of
+            // an unknown value isn't recorded.
+            let known = !flags.contains(ChangeFlags::NO_VALUE);
             if flags.contains(ChangeFlags::IS_WRITE) {
                 let size = (change.flags & ChangeFlags::SIZE_MASK.bits()) as u64 / 8;
+                let limit = self.memory_history_limit();
                 let mut data = change.data;
                 for i in 0..size {
                     let addr = change.address + i;
                     let byte = (data & 0xFF) as u8;
                     data >>= 8;
 
-                    self.memory
-                        .entry(addr)
-                        .or_default()
-                        .history
-                        .push((change.clnum, byte));
+                    let mut cell = self.memory.entry(addr).or_default();
+                    cell.history.push((change.clnum, known.then_some(byte)));
+                    if let Some(limit) = limit {
+                        cell.enforce_limit(limit);
+                    }
                 }
+            } else if known {
+                self.reads
+                    .entry(change.address)
+                    .or_default()
+                    .push((change.clnum, change.data));
             }
         } else if flags.contains(ChangeFlags::IS_WRITE) {
             // Register Write
             let reg_idx = (change.address / 8) as usize;
+            let vcpu = self.vcpu_at(change.clnum);
             let mut regs = self.registers.write();
+            let regs = regs.entry(vcpu).or_insert_with(|| vec![Vec::new(); self.reg_count]);
             if reg_idx < regs.len() {
                 regs[reg_idx].push((change.clnum, change.data));
             }
         }
 
+        if flags.contains(ChangeFlags::IS_START) {
+            self.executions.entry(change.address).or_default().push(change.clnum);
+        }
+
         // 3. Update Reverse Index
         let type_char = if flags.contains(ChangeFlags::IS_WRITE) {
             b'W'
         } else {
             b'R'
         };
-        self.access_index
-            .entry((change.address, type_char))
-            .or_default()
-            .push(change.clnum);
+        let mut clnums = self.access_index.entry((change.address, type_char)).or_default();
+        clnums.push(change.clnum);
+        let capped = flags.contains(ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE);
+        if let Some(limit) = self.memory_history_limit().filter(|_| capped) {
+            trim_history(&mut *clnums, limit);
+        }
     }
 
     pub fn get_memory_at(&self, clnum: Clnum, addr: Address, size: usize) -> Vec<u8> {
@@ -264,8 +1259,59 @@ impl TraceDB {
         result
     }
 
-    pub fn get_registers_at(&self, clnum: Clnum) -> Vec<u64> {
-        let regs = self.registers.read();
+    /// The little-endian `size`-byte value at `addr` as of `clnum`, read as
+    /// `get_memory_at` does, or None if any of its bytes is unknown: last
+    /// written with a value the tracer didn't capture, or with its history
+    /// dropped by the limit.
+    pub fn get_known_value_at(&self, clnum: Clnum, addr: Address, size: usize) -> Option<u64> {
+        let unknown = (0..size as u64).any(|i| {
+            self.memory
+                .get(&(addr + i))
+                .is_some_and(|cell| cell.is_unknown_at(clnum))
+        });
+        if unknown {
+            return None;
+        }
+        Some(
+            self.get_memory_at(clnum, addr, size)
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | byte as u64),
+        )
+    }
+
+    /// Whether `condition` holds as the instruction at `clnum` is reached,
+    /// given the registers it started with (`regs`, as the tracer sends them).
+    /// Memory the trace knows nothing about reads as zero; memory whose value
+    /// is unknown (see `get_known_value_at`) never satisfies a condition.
+    pub fn condition_holds(&self, clnum: Clnum, regs: &[u64], condition: &BreakCondition) -> bool {
+        let actual = match condition.operand {
+            CondOperand::Reg { index } => match regs.get(index) {
+                Some(&value) => value,
+                None => return false,
+            },
+            CondOperand::Mem { address, size } => {
+                match self.get_known_value_at(clnum.saturating_sub(1), address, size.clamp(1, 8) as usize) {
+                    Some(value) => value,
+                    None => return false,
+                }
+            }
+        };
+        condition.op.holds(actual, condition.value)
+    }
+
+    /// Registers of the vcpu that executed `clnum`, before it ran.
+    pub fn get_registers_at(&self, clnum: Clnum) -> Vec<u64> {
+        self.get_registers_at_vcpu(clnum, self.vcpu_at(clnum))
+    }
+
+    /// Registers of `vcpu` as of `clnum`: its state before the instruction
+    /// at `clnum` if it executed that one, otherwise as it last left them.
+    pub fn get_registers_at_vcpu(&self, clnum: Clnum, vcpu: u32) -> Vec<u64> {
+        let regs = self.registers.read();
+        let Some(regs) = regs.get(&vcpu) else {
+            return vec![0; self.reg_count];
+        };
         regs.iter()
             .map(|history| {
                 let idx = history.partition_point(|&(c, _)| c <= clnum);
@@ -278,8 +1324,267 @@ impl TraceDB {
             .collect()
     }
 
+    /// Records that `vcpu` executed `clnum`. Clnums must arrive in order.
+    pub fn record_vcpu(&self, clnum: Clnum, vcpu: u32) {
+        let mut vcpus = self.vcpus.write();
+        if vcpus.last().is_none_or(|&(_, last)| last != vcpu) {
+            vcpus.push((clnum, vcpu));
+        }
+    }
+
+    /// The vcpu that executed `clnum` (0 for traces without vcpu records).
+    pub fn vcpu_at(&self, clnum: Clnum) -> u32 {
+        let vcpus = self.vcpus.read();
+        let idx = vcpus.partition_point(|&(c, _)| c <= clnum);
+        idx.checked_sub(1).map_or(0, |i| vcpus[i].1)
+    }
+
+    /// The instruction that wrote the value register `reg` holds as of
+    /// `clnum` (in the vcpu executing `clnum`). None if the register kept the
+    /// value it had when first seen, or isn't tracked.
+    pub fn last_writer_reg(&self, clnum: Clnum, reg: usize) -> Option<Clnum> {
+        let vcpu = self.vcpu_at(clnum);
+        let changed_at = {
+            let regs = self.registers.read();
+            let history = regs.get(&vcpu)?.get(reg)?;
+            let idx = history.partition_point(|&(c, _)| c <= clnum);
+            // The first entry is the value the register was first seen with
+            if idx < 2 {
+                return None;
+            }
+            history[idx - 1].0
+        };
+        // Registers are captured before each instruction, so the new value
+        // was written by the vcpu's instruction just before it showed up
+        self.previous_clnum_of(changed_at, vcpu)
+    }
+
+    /// Where register `reg` changed value within `range`, on any vcpu,
+    /// downsampled to at most `buckets` equal slices of it: (clnum of the
+    /// first writer in the slice, number of changes in it) for each slice
+    /// with any, in order. Only each slice's first change is resolved to its
+    /// writer (as `last_writer_reg` does); the others are counted by the
+    /// clnum before their new value showed up.
+    pub fn register_timeline(&self, reg: usize, range: RangeInclusive<Clnum>, buckets: u32) -> Vec<(Clnum, u32)> {
+        let (lo, hi) = (*range.start(), *range.end());
+        if buckets == 0 || lo > hi {
+            return Vec::new();
+        }
+        let span = u64::from(hi - lo) + 1;
+        let bucket_of = |clnum: Clnum| u64::from(clnum - lo) * u64::from(buckets) / span;
+        // Per slice: (first change, its vcpu, number of changes)
+        let mut slices: BTreeMap<u64, (Clnum, u32, u32)> = BTreeMap::new();
+        {
+            let regs = self.registers.read();
+            for (&vcpu, history) in regs.iter().filter_map(|(vcpu, regs)| Some((vcpu, regs.get(reg)?))) {
+                // The first entry is the value first seen, not a write. A
+                // value showing up at c was written before c, so c runs to hi + 1.
+                let history = history.get(1..).unwrap_or(&[]);
+                let start = history.partition_point(|&(c, _)| c <= lo);
+                let end = history.partition_point(|&(c, _)| c <= hi.saturating_add(1));
+                for &(c, _) in &history[start..end] {
+                    let slice = slices.entry(bucket_of(c - 1)).or_insert((c, vcpu, 0));
+                    if c < slice.0 {
+                        (slice.0, slice.1) = (c, vcpu);
+                    }
+                    slice.2 += 1;
+                }
+            }
+        }
+        slices
+            .into_values()
+            .filter_map(|(c, vcpu, count)| {
+                let writer = self.previous_clnum_of(c, vcpu).filter(|writer| range.contains(writer))?;
+                Some((writer, count))
+            })
+            .collect()
+    }
+
+    /// The nearest instruction after `clnum` (before it, unless `forward`)
+    /// that changed register `reg`'s value, on any vcpu. None if there is
+    /// no such change.
+    pub fn register_change_near(&self, reg: usize, clnum: Clnum, forward: bool) -> Option<Clnum> {
+        // A value shows up after its writer ran, so the change right after
+        // `clnum` may have been written at or before it: two candidates each
+        let candidates: Vec<(u32, Vec<Clnum>)> = {
+            let regs = self.registers.read();
+            regs.iter()
+                .filter_map(|(&vcpu, regs)| {
+                    let history = regs.get(reg)?.get(1..)?;
+                    let idx = history.partition_point(|&(c, _)| c <= clnum);
+                    let nearby: Vec<Clnum> = if forward {
+                        history.iter().skip(idx).take(2).map(|&(c, _)| c).collect()
+                    } else {
+                        history.iter().take(idx + 1).rev().take(2).map(|&(c, _)| c).collect()
+                    };
+                    Some((vcpu, nearby))
+                })
+                .collect()
+        };
+        let writers = candidates.into_iter().filter_map(|(vcpu, nearby)| {
+            nearby
+                .into_iter()
+                .filter_map(|c| self.previous_clnum_of(c, vcpu))
+                .find(|&writer| if forward { writer > clnum } else { writer < clnum })
+        });
+        if forward {
+            writers.min()
+        } else {
+            writers.max()
+        }
+    }
+
+    // Register operands in `text` (the instruction at `clnum`) it reads, by
+    // their name there, with the clnum that last wrote each
+    fn operand_defs(&self, clnum: Clnum, address: Address, text: &str) -> Vec<(String, Clnum)> {
+        let bytes = self.insn_bytes(clnum, address);
+        let reads = self
+            .disassembler
+            .lock()
+            .get_read_registers(&bytes, address)
+            .unwrap_or_default();
+        let mut defs: Vec<(String, Clnum)> = Vec::new();
+        if reads.is_empty() {
+            return defs;
+        }
+        for word in text.split(|c: char| !c.is_ascii_alphanumeric() && c != '_') {
+            let Some(part) = RegPart::from_name(word) else { continue };
+            if !reads.contains(&part.index) || defs.iter().any(|(name, _)| name == word) {
+                continue;
+            }
+            if let Some(writer) = self.last_writer_reg(clnum, part.index) {
+                defs.push((word.to_string(), writer));
+            }
+        }
+        defs
+    }
+
+    // The last clnum before `clnum` that `vcpu` executed
+    fn previous_clnum_of(&self, clnum: Clnum, vcpu: u32) -> Option<Clnum> {
+        let mut end = clnum.checked_sub(1)?;
+        let vcpus = self.vcpus.read();
+        if vcpus.is_empty() {
+            return Some(end);
+        }
+        let mut idx = vcpus.partition_point(|&(c, _)| c <= end);
+        while idx > 0 {
+            let (start, run_vcpu) = vcpus[idx - 1];
+            if run_vcpu == vcpu {
+                return Some(end);
+            }
+            end = start.checked_sub(1)?;
+            idx -= 1;
+        }
+        None
+    }
+
+    /// One step of provenance for register `reg` as of `clnum`: the
+    /// instruction that last wrote it (see `last_writer_reg`) with the
+    /// registers and memory it read.
+    pub fn register_writer(&self, clnum: Clnum, reg: usize) -> Option<RegisterWriter> {
+        let writer = self.last_writer_reg(clnum, reg)?;
+        let (address, mem_inputs) = {
+            let changes = self.changes.read();
+            let effects = changes_at(&changes, writer);
+            let address = effects.iter().find(|ch| is_start(ch))?.address;
+            let (_, accesses) = split_effects(effects);
+            let reads = accesses
+                .into_iter()
+                .filter(|&(_, _, is_write)| !is_write)
+                .map(|(addr, value, _)| (addr, value))
+                .collect::<Vec<_>>();
+            (address, reads)
+        };
+
+        let bytes = self.insn_bytes(writer, address);
+        let read_regs = self
+            .disassembler
+            .lock()
+            .get_read_registers(&bytes, address)
+            .unwrap_or_default();
+        let values = self.get_registers_at_vcpu(writer, self.vcpu_at(writer));
+        let reg_inputs = read_regs
+            .into_iter()
+            .filter_map(|r| values.get(r).map(|&value| (r, value)))
+            .collect();
+
+        Some(RegisterWriter {
+            clnum: writer,
+            address,
+            disassembly: self.get_disassembly_at(writer),
+            reg_inputs,
+            mem_inputs,
+        })
+    }
+
+    // The first clnum after `clnum` executed by `vcpu`
+    fn next_clnum_of(&self, clnum: Clnum, vcpu: u32) -> Option<Clnum> {
+        let next = clnum.checked_add(1)?;
+        let vcpus = self.vcpus.read();
+        if vcpus.is_empty() {
+            return Some(next);
+        }
+        let idx = vcpus.partition_point(|&(c, _)| c <= next);
+        if idx > 0 && vcpus[idx - 1].1 == vcpu {
+            return Some(next);
+        }
+        vcpus[idx..].iter().find(|&&(_, run_vcpu)| run_vcpu == vcpu).map(|&(start, _)| start)
+    }
+
+    /// Grows `vcpu`'s stack extent to cover `rsp` at `clnum`.
+    pub fn note_stack_pointer(&self, vcpu: u32, clnum: Clnum, rsp: u64) {
+        if rsp == 0 {
+            return;
+        }
+        let low = rsp.saturating_sub(STACK_RED_ZONE);
+        let high = rsp | 0xfff;
+        let mut stacks = self.stacks.write();
+        let history = stacks.entry(vcpu).or_default();
+        match history.last().copied() {
+            None => history.push(StackExtent { clnum, low, high }),
+            Some(cur) if low >= cur.low && high <= cur.high => {}
+            Some(cur) => {
+                let extent = StackExtent {
+                    clnum,
+                    low: cur.low.min(low),
+                    high: cur.high.max(high),
+                };
+                if extent.high - extent.low <= STACK_MAX_SPAN {
+                    history.push(extent);
+                }
+            }
+        }
+    }
+
+    /// Stack ranges [low, high] of every vcpu as known at `clnum`.
+    pub fn stack_regions_at(&self, clnum: Clnum) -> Vec<(u64, u64)> {
+        self.stacks
+            .read()
+            .values()
+            .filter_map(|history| {
+                let idx = history.partition_point(|e| e.clnum <= clnum);
+                idx.checked_sub(1).map(|i| (history[i].low, history[i].high))
+            })
+            .collect()
+    }
+
+    /// True if `addr` lies in a stack region observed up to `clnum`.
+    pub fn is_stack_address(&self, addr: Address, clnum: Clnum) -> bool {
+        self.stack_regions_at(clnum)
+            .iter()
+            .any(|&(low, high)| addr >= low && addr <= high)
+    }
+
+    /// Records the registers of the vcpu that executed `clnum` (see
+    /// `record_vcpu`).
     pub fn update_registers(&self, clnum: Clnum, new_regs: &[u64]) {
+        self.update_registers_vcpu(clnum, self.vcpu_at(clnum), new_regs);
+    }
+
+    pub fn update_registers_vcpu(&self, clnum: Clnum, vcpu: u32, new_regs: &[u64]) {
+        self.enforce_window(clnum);
         let mut regs = self.registers.write();
+        let regs = regs.entry(vcpu).or_insert_with(|| vec![Vec::new(); self.reg_count]);
         // Ensure enough space
         while regs.len() < new_regs.len() {
             regs.push(Vec::new());
@@ -301,29 +1606,292 @@ impl TraceDB {
         }
     }
 
+    /// Formatting used for `view` under the session configuration.
+    pub fn disasm_options(&self, view: DisasmView) -> DisasmOptions {
+        let config = self.config.read();
+        let options = DisasmOptions::from(&*config);
+        match view {
+            DisasmView::Timeline => options,
+            DisasmView::Cfg if config.symbolize_cfg => options,
+            DisasmView::Cfg => DisasmOptions { symbolize: false, ascii_immediates: false, ..options },
+        }
+    }
+
     pub fn disassemble(&self, address: Address, bytes: &[u8]) -> String {
+        self.disassemble_with(address, bytes, self.disasm_options(DisasmView::Timeline))
+    }
+
+    pub fn disassemble_with(&self, address: Address, bytes: &[u8], options: DisasmOptions) -> String {
         if bytes.is_empty() {
             return String::from("...");
         }
 
-        let key = (address, bytes.to_vec());
+        let key = (address, bytes.to_vec(), options);
         if let Some(s) = self.insn_cache.get(&key) {
-            return s.clone();
+            return s;
         }
 
-        let mut disasm = self
-            .disassembler
-            .lock()
-            .disassemble(bytes, address)
-            .unwrap_or_else(|_| "invalid".to_string());
-        
-        // Semantic Lifting: Stack Variables
-        disasm = self.resolve_stack_vars(&disasm);
+        let (result, rip_target, immediates) = {
+            let disassembler = self.disassembler.lock();
+            let immediates = if options.normalize { disassembler.immediates(bytes, address) } else { Vec::new() };
+            (
+                disassembler.disassemble(bytes, address),
+                disassembler.rip_relative_target(bytes, address),
+                immediates,
+            )
+        };
+        let disasm = match result {
+            Ok(text) => self.format_text(text, options, rip_target, &immediates),
+            Err(e) => match e.downcast_ref::<Truncated>() {
+                Some(truncated) => return format!("(truncated: {} bytes)", truncated.available),
+                None => self.format_text("invalid".to_string(), options, None, &[]),
+            },
+        };
 
         self.insn_cache.insert(key, disasm.clone());
         disasm
     }
 
+    /// Linearly disassembles `bytes` at `address` with a disassembler of its
+    /// own in `syntax`, leaving the session's disassembler, configuration and
+    /// text cache alone. Symbolization still names targets after this trace's
+    /// symbols and regions. Bytes that don't decode are listed as `.byte`, and
+    /// an instruction cut off by the end of `bytes` as truncated.
+    pub fn disassemble_detached(
+        &self,
+        address: Address,
+        bytes: &[u8],
+        syntax: DisasmSyntax,
+        options: DisasmOptions,
+    ) -> anyhow::Result<Vec<(Address, String)>> {
+        let mut disassembler = Disassembler::new()?;
+        disassembler.set_syntax(syntax)?;
+
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let rest = &bytes[offset..];
+            let address = address.wrapping_add(offset as u64);
+            match disassembler.length(rest, address) {
+                Ok(len) => {
+                    let insn = &rest[..len];
+                    let text = disassembler
+                        .disassemble(insn, address)
+                        .unwrap_or_else(|_| "invalid".to_string());
+                    let rip_target = disassembler.rip_relative_target(insn, address);
+                    let immediates = if options.normalize { disassembler.immediates(insn, address) } else { Vec::new() };
+                    lines.push((address, self.format_text(text, options, rip_target, &immediates)));
+                    offset += len;
+                }
+                Err(e) if is_truncated(&e) => {
+                    lines.push((address, format!("(truncated: {} bytes)", rest.len())));
+                    break;
+                }
+                Err(_) => {
+                    lines.push((address, format!(".byte 0x{:02x}", rest[0])));
+                    offset += 1;
+                }
+            }
+        }
+        Ok(lines)
+    }
+
+    // Applies the rewrites selected by `options` to decoded text.
+    // `rip_target` is the absolute address of its rip-relative operand, if any,
+    // and `immediates` its immediate operands (see `Disassembler::immediates`).
+    fn format_text(
+        &self,
+        mut disasm: String,
+        options: DisasmOptions,
+        rip_target: Option<Address>,
+        immediates: &[(i64, u8)],
+    ) -> String {
+        if options.normalize {
+            disasm = Self::normalize_operands(&disasm, immediates);
+        }
+        if options.immediate_base != ImmediateBase::Hex || options.ascii_immediates {
+            disasm = Self::format_immediates(&disasm, options.immediate_base, options.ascii_immediates);
+        }
+        if options.symbolize {
+            // Semantic Lifting: Stack Variables
+            disasm = self.resolve_stack_vars(&disasm);
+            // Symbolization: branch targets that start a known symbol (e.g. puts@plt)
+            disasm = self.resolve_branch_targets(&disasm);
+            disasm = self.resolve_absolute_operands(&disasm);
+            if let Some(target) = rip_target {
+                disasm = self.annotate_rip_target(&disasm, target);
+            }
+        }
+        disasm
+    }
+
+    /// Caps the disassembly text cache at `capacity` entries, evicting the
+    /// least recently used ones beyond it; 0 disables the cache.
+    pub fn set_insn_cache_capacity(&self, capacity: usize) {
+        self.insn_cache.set_capacity(capacity);
+    }
+
+    /// Size and hit/miss/eviction counts of the disassembly text cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.insn_cache.stats()
+    }
+
+    /// Enables or disables operand normalization (signed immediates, hex
+    /// displacements) in disassembly output.
+    pub fn set_normalize_disasm(&self, enabled: bool) {
+        self.config.write().normalize_immediates = enabled;
+    }
+
+    /// Switches the decoder used for instruction text, lengths and register
+    /// reads, keeping the configured syntax. Clears `insn_cache`.
+    pub fn set_disasm_backend(&self, kind: BackendKind) -> anyhow::Result<()> {
+        let mut disassembler = Disassembler::with_backend(kind)?;
+        disassembler.set_syntax(self.config.read().syntax)?;
+        *self.disassembler.lock() = disassembler;
+        self.insn_cache.clear();
+        Ok(())
+    }
+
+    pub fn config(&self) -> SessionConfig {
+        self.config.read().clone()
+    }
+
+    /// Applies a new session configuration. Changing the syntax clears
+    /// `insn_cache`; the text options are part of its key and the active
+    /// module only affects `is_user_code`, so those leave it intact. On an
+    /// error the previous configuration stays in place.
+    pub fn configure(&self, config: SessionConfig) -> Result<(), QueryError> {
+        if let Some(name) = &config.active_module {
+            if self.find_module(name).is_none() {
+                return Err(QueryError::UnknownModule(name.clone()));
+            }
+        }
+
+        let mut current = self.config.write();
+        if config.syntax != current.syntax {
+            self.disassembler
+                .lock()
+                .set_syntax(config.syntax)
+                .map_err(|e| QueryError::UnsupportedSyntax(e.to_string()))?;
+            self.insn_cache.clear();
+        }
+        *current = config;
+        Ok(())
+    }
+
+    // Capstone prints the same kind of operand in different forms: `-1` as
+    // 0xffffffffffffffff, small displacements in decimal (`[rbp - 8]`) but
+    // larger ones in hex. Rewrites near-wraparound immediates as negative
+    // numbers and all displacements in hex, so e.g. `[rbp - 8]` also becomes
+    // a stack variable. Branch targets and memory operands keep their values.
+    // Whether an immediate is negative depends on its operand size, which
+    // `immediates` gives (see `Disassembler::immediates`): 0xffffffff is -1
+    // for `mov eax` but not for `mov rax`. Byte immediates are left alone,
+    // as they're mostly masks and characters.
+    fn normalize_operands(disasm: &str, immediates: &[(i64, u8)]) -> String {
+        use regex::Regex;
+        use lazy_static::lazy_static;
+
+        lazy_static! {
+            static ref RE_DISP: Regex = Regex::new(r" ([+-]) (\d+)\]").unwrap();
+            static ref RE_IMM: Regex = Regex::new(r"\b0x([0-9a-f]{1,16})\b").unwrap();
+        }
+
+        let s = RE_DISP.replace_all(disasm, |caps: &regex::Captures| {
+            format!(" {} 0x{:x}]", &caps[1], caps[2].parse::<u64>().unwrap_or(0))
+        });
+
+        let mnemonic = s.split_whitespace().next().unwrap_or("");
+        if mnemonic.starts_with("call") || mnemonic.starts_with('j') || mnemonic.starts_with("loop") {
+            return s.to_string();
+        }
+        let s = RE_IMM.replace_all(&s, |caps: &regex::Captures| {
+            let m = caps.get(0).unwrap();
+            let before = &s[..m.start()];
+            let in_memory_operand = before.matches('[').count() > before.matches(']').count();
+            let raw = u64::from_str_radix(&caps[1], 16).unwrap_or(0);
+            // The operand printed as `raw`, read as a signed number of its size
+            let signed = immediates.iter().find_map(|&(value, size)| {
+                let bits = match size {
+                    2 | 4 | 8 => u32::from(size) * 8,
+                    _ => return None,
+                };
+                let shift = 64 - bits;
+                let limit = if bits == 16 { NEAR_WRAP_16 } else { NEAR_WRAP };
+                ((value as u64) << shift >> shift == raw).then(|| ((raw << shift) as i64 >> shift, limit))
+            });
+            match signed {
+                Some((value, limit)) if !in_memory_operand && (-limit..0).contains(&value) => {
+                    if value > -10 {
+                        value.to_string()
+                    } else {
+                        format!("-0x{:x}", value.unsigned_abs())
+                    }
+                }
+                _ => m.as_str().to_string(),
+            }
+        });
+
+        s.to_string()
+    }
+
+    // Rewrites immediate operands (not displacements, memory operands or
+    // branch targets) in `base`, and with `ascii` appends the text of those
+    // made of at least MIN_TEXT_IMMEDIATE bytes, all printable, e.g.
+    // `mov eax, 0x6c6c6548 ; "Hell"`.
+    fn format_immediates(disasm: &str, base: ImmediateBase, ascii: bool) -> String {
+        use regex::Regex;
+        use lazy_static::lazy_static;
+
+        lazy_static! {
+            // Preceded by the operand separator or an AT&T `$`
+            static ref RE_IMM: Regex = Regex::new(r"([\s,$])(-?)(0x[0-9a-f]+|\d+)\b").unwrap();
+        }
+
+        let mnemonic = disasm.split_whitespace().next().unwrap_or("");
+        if mnemonic.starts_with("call") || mnemonic.starts_with('j') || mnemonic.starts_with("loop") {
+            return disasm.to_string();
+        }
+
+        let mut texts = Vec::new();
+        let s = RE_IMM.replace_all(disasm, |caps: &regex::Captures| {
+            let m = caps.get(0).unwrap();
+            let before = &disasm[..m.start()];
+            let in_memory_operand = before.matches('[').count() > before.matches(']').count()
+                || disasm[m.end()..].starts_with('(');
+            let digits = &caps[3];
+            let value = match digits.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => digits.parse(),
+            };
+            let (Ok(value), false) = (value, in_memory_operand) else {
+                return m.as_str().to_string();
+            };
+            let negative = !caps[2].is_empty();
+            if ascii && !negative {
+                let bytes = value.to_le_bytes();
+                let len = 8 - value.leading_zeros() as usize / 8;
+                if len >= MIN_TEXT_IMMEDIATE && bytes[..len].iter().all(|b| (0x20..0x7f).contains(b)) {
+                    texts.push(String::from_utf8_lossy(&bytes[..len]).into_owned());
+                }
+            }
+            let formatted = match base {
+                ImmediateBase::Hex => digits.to_string(),
+                ImmediateBase::Decimal => value.to_string(),
+                ImmediateBase::Both if value >= 10 => format!("0x{:x} ({})", value, value),
+                ImmediateBase::Both => value.to_string(),
+            };
+            format!("{}{}{}", &caps[1], &caps[2], formatted)
+        });
+
+        if texts.is_empty() {
+            s.into_owned()
+        } else {
+            let quoted: Vec<_> = texts.iter().map(|t| format!("{:?}", t)).collect();
+            format!("{} ; {}", s, quoted.join(", "))
+        }
+    }
+
     fn resolve_stack_vars(&self, disasm: &str) -> String {
         use regex::Regex;
         use lazy_static::lazy_static;
@@ -343,289 +1911,2090 @@ impl TraceDB {
         s.to_string()
     }
 
-    pub fn get_disassembly_at(&self, clnum: Clnum) -> String {
-        // Find the PC at this clnum
-        // PC change is recorded as a Change with IS_START flag
-        let changes = self.changes.read();
+    // Annotates fs:/gs:-relative operands (TLS, stack canaries) with the
+    // effective address computed from the segment base captured at `clnum`.
+    // Done per clnum rather than in `disassemble` since the base is runtime state.
+    fn resolve_segment_operands(&self, disasm: &str, clnum: Clnum) -> String {
+        use regex::Regex;
+        use lazy_static::lazy_static;
 
-        // Find the instruction change for this clnum (or the one before it)
-        // Optimization: binary search could be used here if changes are sorted
-        let pc_change = changes.iter().rev().find(|c| {
-            c.clnum <= clnum
-                && ChangeFlags::from_bits_truncate(c.flags).contains(ChangeFlags::IS_START)
-        });
+        lazy_static! {
+            static ref RE_SEGMENT: Regex = Regex::new(r"\b(fs|gs):\[0x([0-9a-fA-F]+)\]").unwrap();
+        }
+
+        let caps = match RE_SEGMENT.captures(disasm) {
+            Some(c) => c,
+            None => return disasm.to_string(),
+        };
+        let index = if &caps[1] == "fs" { REG_FS_BASE } else { REG_GS_BASE };
+        let base = match self.get_registers_at(clnum).get(index) {
+            Some(&b) if b != 0 => b,
+            // Base not captured (older tracer or register unavailable)
+            _ => return disasm.to_string(),
+        };
+        let offset = u64::from_str_radix(&caps[2], 16).unwrap_or(0);
+        format!("{} ; {}:0x{:x} = 0x{:x}", disasm, &caps[1], offset, base.wrapping_add(offset))
+    }
+
+    fn resolve_branch_targets(&self, disasm: &str) -> String {
+        use regex::Regex;
+        use lazy_static::lazy_static;
+
+        lazy_static! {
+            static ref RE_BRANCH: Regex = Regex::new(r"^((?:call[a-z]?|j[a-z]+)\s+)0x([0-9a-fA-F]+)$").unwrap();
+        }
+
+        let caps = match RE_BRANCH.captures(disasm) {
+            Some(c) => c,
+            None => return disasm.to_string(),
+        };
+        let target = match u64::from_str_radix(&caps[2], 16) {
+            Ok(t) => t,
+            Err(_) => return disasm.to_string(),
+        };
+        match self.name_address(target) {
+            Some((name, 0)) => format!("{}{}", &caps[1], name),
+            _ => disasm.to_string(),
+        }
+    }
+
+    // Absolute memory operands (`[0x601040]`) inside a named region or symbol
+    fn resolve_absolute_operands(&self, disasm: &str) -> String {
+        use regex::Regex;
+        use lazy_static::lazy_static;
+
+        lazy_static! {
+            static ref RE_ABSOLUTE: Regex = Regex::new(r"\[0x([0-9a-fA-F]+)\]").unwrap();
+        }
 
-        if let Some(change) = pc_change {
-            // Get bytes for this clnum
-            let mut _bytes_ok = false;
-            if let Some(bytes) = self.instructions.get(&change.clnum) {
-                if !bytes.is_empty() && !bytes.iter().all(|&b| b == 0) {
-                    return self.disassemble(change.address, &bytes);
+        RE_ABSOLUTE
+            .replace_all(disasm, |caps: &regex::Captures| {
+                let label = u64::from_str_radix(&caps[1], 16)
+                    .ok()
+                    .and_then(|address| self.address_label(address));
+                match label {
+                    Some(label) => format!("[{}]", label),
+                    None => caps[0].to_string(),
                 }
-                // If bytes are empty or all zeros, fall through
-            }
-            
-            // Check for pre-calculated disassembly (from QEMU/Tracer)
-            if let Some(disasm) = self.instructions_disasm.get(&change.clnum) {
-                 return disasm.clone();
-            }
+            })
+            .into_owned()
+    }
 
-            // Fallback: Read from memory (static code)
-            let bytes = self.get_memory_at(change.clnum, change.address, 16);
-            if !bytes.iter().all(|&b| b == 0) {
-                return self.disassemble(change.address, &bytes);
-            }
+    // Appends the absolute address a rip-relative operand refers to, and the
+    // symbol or region it falls in: `[rip + 0x2ed5] ; 0x601018 <global_var>`
+    fn annotate_rip_target(&self, disasm: &str, target: Address) -> String {
+        match self.address_label(target) {
+            Some(label) => format!("{} ; 0x{:x} <{}>", disasm, target, label),
+            None => format!("{} ; 0x{:x}", disasm, target),
         }
+    }
 
-        String::from("???")
+    // (clnum, pc) of the instruction executed at `clnum`, or the last one before it
+    fn insn_at(&self, clnum: Clnum) -> Option<(Clnum, Address)> {
+        let changes = self.changes.read();
+        if let Some(start) = changes_at(&changes, clnum).iter().find(|ch| is_start(ch)) {
+            return Some((start.clnum, start.address));
+        }
+        // Only a clnum without an instruction of its own (an Init, or past
+        // the end) walks back, from where it would be in the log
+        let end = changes.partition_point(|ch| ch.clnum <= clnum);
+        changes[..end].iter().rev().find(|ch| is_start(ch)).map(|ch| (ch.clnum, ch.address))
     }
 
-    pub fn get_trace_log(&self, start: Clnum, count: u32, only_user_code: bool) -> Vec<TraceEntry> {
+    /// Whether control reached the instruction at `clnum` asynchronously (a
+    /// signal handler entry or return, or an interrupt) as flagged by the
+    /// tracer, rather than from the previous instruction.
+    pub fn is_async_entry(&self, clnum: Clnum) -> bool {
         let changes = self.changes.read();
-        let mut entries = Vec::new();
+        changes_at(&changes, clnum)
+            .iter()
+            .any(|ch| is_start(ch) && ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_ASYNC))
+    }
 
-        let mut c = start;
-        let mut collected = 0;
-        let max_clnum = changes.last().map(|c| c.clnum).unwrap_or(0);
+    /// Runtime address of the instruction executed at `clnum`.
+    pub fn pc_at(&self, clnum: Clnum) -> Option<Address> {
+        self.insn_at(clnum).map(|(_, pc)| pc)
+    }
 
-        // Safety break
-        while collected < count && c <= max_clnum {
-            // Find the IS_START change for this clnum
-            let start_change = changes.iter().find(|ch| {
-                ch.clnum == c
-                    && ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START)
-            });
+    /// Structured operand breakdown of the instruction executed at `clnum`.
+    pub fn get_insn_detail(&self, clnum: Clnum) -> Option<InsnDetail> {
+        let (insn_clnum, address) = self.insn_at(clnum)?;
+        self.insn_detail(insn_clnum, address)
+    }
 
-            if let Some(change) = start_change {
-                if !only_user_code || self.is_user_code(change.address) {
-                    let disassembly = {
-                        let mut d = String::new();
-                        let mut done = false;
-                        
-                        // 1. Try bytes if valid (non-zero)
-                        if let Some(bytes) = self.instructions.get(&c) {
-                            if !bytes.is_empty() && !bytes.iter().all(|&b| b == 0) {
-                                d = self.disassemble(change.address, &bytes);
-                                done = true;
-                            }
-                        }
-                        
-                        // 2. Try QEMU disasm
-                        if !done {
-                            if let Some(qs) = self.instructions_disasm.get(&c) {
-                                d = qs.clone();
-                                done = true;
-                            }
-                        }
-                        
-                        // 3. Fallback to memory (using static address)
-                        if !done {
-                            let bias = *self.bias.read();
-                            let static_addr = (change.address as i128 - bias as i128) as u64;
-                            let bytes = self.get_memory_at(c, static_addr, 16);
-                            d = self.disassemble(change.address, &bytes);
-                        }
-                        d
-                    };
+    // Operand breakdown of the instruction executed at `clnum` (located at
+    // runtime `address`), None if its bytes don't decode
+    pub(crate) fn insn_detail(&self, clnum: Clnum, address: Address) -> Option<InsnDetail> {
+        let bytes = self.insn_bytes(clnum, address);
+        self.disassembler.lock().detail(&bytes, address).ok()
+    }
 
-                    // Find register/memory effects
-                    // Just take the first one for now
-                    let reg_diff = changes
-                        .iter()
-                        .find(|ch| {
-                            ch.clnum == c
-                                && !ChangeFlags::from_bits_truncate(ch.flags)
-                                    .contains(ChangeFlags::IS_MEM)
-                                && !ChangeFlags::from_bits_truncate(ch.flags)
-                                    .contains(ChangeFlags::IS_START)
-                                && ChangeFlags::from_bits_truncate(ch.flags)
-                                    .contains(ChangeFlags::IS_WRITE)
-                        })
-                        .map(|ch| ((ch.address / 8) as usize, ch.data));
-
-                    let mem_access = changes
-                        .iter()
-                        .find(|ch| {
-                            ch.clnum == c
-                                && ChangeFlags::from_bits_truncate(ch.flags)
-                                    .contains(ChangeFlags::IS_MEM)
-                        })
-                        .map(|ch| {
-                            (
-                                ch.address,
-                                ch.data,
-                                ChangeFlags::from_bits_truncate(ch.flags)
-                                    .contains(ChangeFlags::IS_WRITE),
-                            )
-                        });
-
-                    entries.push(TraceEntry {
-                        clnum: c,
-                        address: change.address,
-                        disassembly,
-                        reg_diff,
-                        mem_access,
-                    });
-                    collected += 1;
+    /// Disassembly at `clnum` followed by the concrete values of its operands
+    /// before it executes, e.g. `mov rax, qword ptr [rbx + 0x10] ; rbx=0x7fff0010, [0x7fff0020]=0x2a`.
+    pub fn annotate_operands(&self, clnum: Clnum) -> String {
+        let disasm = self.disassembly_at(clnum, DisasmView::Timeline);
+        let detail = match self.get_insn_detail(clnum) {
+            Some(d) => d,
+            None => return disasm,
+        };
+        // Registers are captured before the instruction runs
+        let regs = self.get_registers_at(clnum);
+        let reg = |name: &str| match name {
+            // rip-relative operands are based on the next instruction
+            "rip" => Some(detail.address + detail.size as u64),
+            _ => crate::disasm::gpr_value(name, &regs),
+        };
+
+        let mut parts: Vec<String> = Vec::new();
+        let push_reg = |parts: &mut Vec<String>, name: &str| {
+            if let Some(value) = reg(name).filter(|_| name != "rip") {
+                let part = format!("{}=0x{:x}", name, value);
+                if !parts.contains(&part) {
+                    parts.push(part);
                 }
-            } else {
-                // Too noisy to log every missing clnum, but useful for debugging holes
-                // if c % 1000 == 0 {
-                //     println!("[DEBUG] No IS_START change found for clnum {}", c);
-                // }
             }
-            c += 1;
-
-            if c > start + 100000 && collected == 0 {
-                // println!("[DEBUG] Break due to scan limit. collected={}", collected);
-                break; // Prevent infinite loop if no user code found
+        };
+        for op in &detail.operands {
+            match op {
+                OperandDetail::Reg { name, .. } => push_reg(&mut parts, name),
+                OperandDetail::Mem { segment, base, index, scale, disp, size } => {
+                    for name in base.iter().chain(index.iter()) {
+                        push_reg(&mut parts, name);
+                    }
+                    let segment_base = match segment.as_deref() {
+                        Some("fs") => regs.get(REG_FS_BASE).copied().unwrap_or(0),
+                        Some("gs") => regs.get(REG_GS_BASE).copied().unwrap_or(0),
+                        _ => 0,
+                    };
+                    let base = base.as_deref().map_or(Some(0), reg);
+                    let index = index.as_deref().map_or(Some(0), reg);
+                    let (base, index) = match (base, index) {
+                        (Some(b), Some(i)) => (b, i),
+                        // Involves a register we don't capture (e.g. xmm)
+                        _ => continue,
+                    };
+                    let address = segment_base
+                        .wrapping_add(base)
+                        .wrapping_add(index.wrapping_mul(*scale as u64))
+                        .wrapping_add(*disp as u64);
+                    let location = self.address_label(address).unwrap_or_else(|| format!("0x{:x}", address));
+                    if detail.mnemonic == "lea" || !(1..=8).contains(size) {
+                        parts.push(format!("[{}]", location));
+                        continue;
+                    }
+                    // Prefer the value the tracer saw being read; otherwise
+                    // reconstruct it from memory as it was before this clnum
+                    let value = self
+                        .read_value_at(clnum, address)
+                        .or_else(|| self.get_known_value_at(clnum.saturating_sub(1), address, *size as usize));
+                    match value {
+                        Some(value) => parts.push(format!("[{}]=0x{:x}", location, value)),
+                        None => parts.push(format!("[{}]=unknown", location)),
+                    }
+                }
+                OperandDetail::Imm { .. } | OperandDetail::Invalid => {}
             }
         }
-        entries
+
+        if parts.is_empty() {
+            disasm
+        } else {
+            format!("{} ; {}", disasm, parts.join(", "))
+        }
     }
 
-    pub fn get_slice(&self, start_clnum: Clnum, target: String) -> Vec<Clnum> {
-        let mut tainted_regs = std::collections::HashSet::new();
-        let mut tainted_mem = std::collections::HashSet::new();
+    /// Linearly disassembles up to `count` instructions of code as of `clnum`,
+    /// starting at runtime `address`. This follows the image bytes (or code
+    /// the tracer saw replaced by then), not the trace, and stops early at
+    /// bytes that are unknown or don't decode.
+    pub fn disassemble_range(&self, clnum: Clnum, address: Address, count: usize) -> Vec<(Address, String)> {
+        let mut lines = Vec::with_capacity(count);
+        let mut address = address;
+        while lines.len() < count {
+            let bytes = self
+                .replaced_code(address, clnum)
+                .unwrap_or_else(|| self.code_bytes(clnum, address, MAX_INSN_LEN));
+            let Some((len, _)) = self.disassembler.lock().disassemble_one(&bytes, address) else {
+                break;
+            };
+            lines.push((address, self.disassemble(address, &bytes[..len])));
+            address += len as u64;
+        }
+        lines
+    }
 
-        if target.starts_with("0x") {
-             if let Ok(addr) = u64::from_str_radix(&target[2..], 16) {
-                 tainted_mem.insert(addr);
-             }
-        } else {
-            let reg_map: std::collections::HashMap<&str, usize> = [
-                ("rax", 0), ("rbx", 1), ("rcx", 2), ("rdx", 3),
-                ("rsi", 4), ("rdi", 5), ("rbp", 6), ("rsp", 7),
-                ("r8", 8), ("r9", 9), ("r10", 10), ("r11", 11),
-                ("r12", 12), ("r13", 13), ("r14", 14), ("r15", 15)
-            ].iter().cloned().collect();
-            if let Some(&idx) = reg_map.get(target.to_lowercase().as_str()) {
-                tainted_regs.insert(idx);
+    /// Linear disassembly of the main-binary function `name` at runtime
+    /// addresses, read from the loaded image rather than the trace, so the
+    /// parts that never executed are listed too. Symbols without a size run
+    /// to their first ret. Empty if the symbol is unknown or its bytes aren't
+    /// loaded.
+    pub fn function_listing(&self, name: &str) -> Vec<(Address, String)> {
+        let Some((start, size)) = self
+            .symbols
+            .iter()
+            .find(|r| r.value().1 == name)
+            .map(|r| (*r.key(), r.value().0))
+        else {
+            return Vec::new();
+        };
+        let start = (start as i128 + self.get_bias() as i128) as u64;
+        let end = if size > 0 { start.saturating_add(size) } else { u64::MAX };
+
+        let mut lines = Vec::new();
+        let mut address = start;
+        while address < end && lines.len() < MAX_LISTING_INSNS {
+            let len = (end - address).min(MAX_INSN_LEN as u64) as usize;
+            let bytes = self.code_bytes(0, address, len);
+            if bytes.is_empty() {
+                break;
+            }
+            let Some((len, _)) = self.disassembler.lock().disassemble_one(&bytes, address) else {
+                lines.push((address, format!(".byte 0x{:02x}", bytes[0])));
+                address += 1;
+                continue;
+            };
+            let text = self.disassemble(address, &bytes[..len]);
+            let is_ret = self
+                .disassembler
+                .lock()
+                .groups(&bytes[..len], address)
+                .is_ok_and(|groups| groups.contains(&InsnGroup::Ret));
+            lines.push((address, text));
+            address += len as u64;
+            if size == 0 && is_ret {
+                break;
             }
         }
+        lines
+    }
 
-        let changes = self.changes.read();
-        let mut slice = Vec::new();
-        
-        // Manual grouping logic
-        let mut processed_clnum = u32::MAX; // Sentinel
-        let mut current_changes: Vec<Change> = Vec::new();
-        
-        // Helper to process a group
-        // Note: Closure cannot capture self if we are iterating changes (borrowing self.changes).
-        // So we split iteration and processing? Or use RwLockReadGuard safely.
-        // changes is ReadGuard. self.disassembler is Mutex. self.instructions is DashMap.
-        // It's safe to access other fields while holding read lock on changes.
-        // But the closure definition inside the function is tricky with borrow checker.
-        // Let's inline the logic or use a macro.
-        // Inlining for now.
-        
-        for change in changes.iter().rev() {
-            if change.clnum > start_clnum { continue; }
-            
-            if change.clnum != processed_clnum {
-                if !current_changes.is_empty() && processed_clnum != u32::MAX {
-                    // PROCESS GROUP logic
-                    let clnum = processed_clnum;
-                    let group = &current_changes;
-                    
-                     let mut relevant = false;
-                     let mut written_regs = Vec::new();
-                     let mut written_mem = Vec::new();
-                     
-                     for ch in group {
-                         let flags = ChangeFlags::from_bits_truncate(ch.flags);
-                         if flags.contains(ChangeFlags::IS_WRITE) {
-                             if flags.contains(ChangeFlags::IS_MEM) {
-                                 if tainted_mem.contains(&ch.address) {
-                                     relevant = true;
-                                     written_mem.push(ch.address);
-                                 }
-                             } else {
-                                 let reg_idx = (ch.address / 8) as usize;
-                                 if tainted_regs.contains(&reg_idx) {
-                                     relevant = true;
-                                     written_regs.push(reg_idx);
-                                 }
-                             }
-                         }
-                     }
-                     
-                     if relevant {
-                         slice.push(clnum);
-                         for r in written_regs { tainted_regs.remove(&r); }
-                         for m in written_mem { tainted_mem.remove(&m); }
-                         
-                         // Inputs
-                         for ch in group {
-                             let flags = ChangeFlags::from_bits_truncate(ch.flags);
-                             if flags.contains(ChangeFlags::IS_MEM) && !flags.contains(ChangeFlags::IS_WRITE) {
-                                 tainted_mem.insert(ch.address);
-                             }
-                         }
-                         
-                         // Register Inputs via Capstone
-                         let pc = group.iter()
-                            .find(|ch| ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START))
-                            .map(|ch| ch.address)
-                            .unwrap_or(0);
-                         
-                         if pc != 0 {
-                             let bytes = if let Some(b) = self.instructions.get(&clnum) {
-                                b.clone()
-                             } else {
-                                let bias = *self.bias.read();
-                                let static_addr = (pc as i128 - bias as i128) as u64;
-                                self.get_memory_at(clnum, static_addr, 16)
-                             };
-
-                             if !bytes.is_empty() {
-                                if let Ok(reads) = self.disassembler.lock().get_read_registers(&bytes, pc) {
-                                    for r in reads {
-                                        tainted_regs.insert(r);
-                                    }
-                                }
-                             }
-                         }
-                     }
-
-                    current_changes.clear();
-                    if tainted_regs.is_empty() && tainted_mem.is_empty() {
-                        break;
-                    }
-                }
-                processed_clnum = change.clnum;
-            }
-            current_changes.push(*change);
-        }
-        // Last group (actually first instruction executed)
-        if !current_changes.is_empty() && processed_clnum != u32::MAX {
-             // Copy-paste logic or extract function?
-             // Extracting requires passing self, tainted sets, etc.
-             // Just duplicating for safety now to avoid lifetime hell with closure capturing &mut sets and &self.
-             
-             let clnum = processed_clnum;
-             let group = &current_changes;
-             let mut relevant = false;
-             let mut written_regs = Vec::new();
-             let mut written_mem = Vec::new();
-             for ch in group {
-                 let flags = ChangeFlags::from_bits_truncate(ch.flags);
-                 if flags.contains(ChangeFlags::IS_WRITE) {
-                     if flags.contains(ChangeFlags::IS_MEM) {
-                         if tainted_mem.contains(&ch.address) { relevant = true; written_mem.push(ch.address); }
-                     } else {
-                         let reg_idx = (ch.address / 8) as usize;
-                         if tainted_regs.contains(&reg_idx) { relevant = true; written_regs.push(reg_idx); }
-                     }
-                 }
-             }
-             if relevant {
-                 slice.push(clnum);
-             }
+    // Bytes at runtime `address` from the loaded images as of `clnum`, cut
+    // short at the first byte there is no data for
+    fn code_bytes(&self, clnum: Clnum, address: Address, len: usize) -> Vec<u8> {
+        let base = self.static_memory_address(address);
+        (0..len as u64)
+            .map_while(|i| {
+                self.memory
+                    .get(&(base + i))
+                    .and_then(|cell| cell.get_value_at(clnum))
+            })
+            .collect()
+    }
+
+    /// Length of the instruction executed at `clnum` (located at runtime
+    /// `address`). Uses the captured bytes when the tracer sent them,
+    /// otherwise decodes the code in memory; None if neither is available.
+    pub(crate) fn insn_len(&self, clnum: Clnum, address: Address) -> Option<u64> {
+        if let Some(bytes) = self.instructions.get(&clnum) {
+            return Some(bytes.len() as u64);
         }
-        
-        slice.reverse();
-        slice
+        let bytes = self
+            .replaced_code(address, clnum)
+            .unwrap_or_else(|| self.code_bytes(clnum, address, MAX_INSN_LEN));
+        let (len, _) = self.disassembler.lock().disassemble_one(&bytes, address)?;
+        Some(len as u64)
+    }
+
+    /// The bytes executed at `clnum` next to the loaded image's bytes at the
+    /// same address (through the load bias), to spot runtime patching. None
+    /// if the tracer captured no bytes or the address isn't in a loaded image.
+    pub fn executed_vs_static(&self, clnum: Clnum) -> Option<(Vec<u8>, Vec<u8>)> {
+        let pc = {
+            let changes = self.changes.read();
+            changes_at(&changes, clnum).iter().find(|ch| is_start(ch))?.address
+        };
+        self.static_comparison(clnum, pc)
+    }
+
+    // executed_vs_static for the instruction at runtime `pc`
+    fn static_comparison(&self, clnum: Clnum, pc: Address) -> Option<(Vec<u8>, Vec<u8>)> {
+        let executed = self.instructions.get(&clnum)?.clone();
+        let base = self.static_memory_address(pc);
+        let on_disk = (0..executed.len() as u64)
+            .map(|i| self.memory.get(&(base + i)).and_then(|cell| cell.static_value))
+            .collect::<Option<Vec<u8>>>()?;
+        Some((executed, on_disk))
+    }
+
+    /// Lifts the instruction executed at `clnum` (located at runtime `address`) to IL.
+    pub fn lift_instruction(&self, clnum: Clnum, address: Address) -> Operation {
+        let bytes = self.insn_bytes(clnum, address);
+        self.disassembler.lock().lift(&bytes, address)
+    }
+
+    // Captured instruction bytes for `clnum`, falling back to the code in
+    // memory when the tracer sent none. Captured bytes that
+    // stop short of the instruction's end are completed from memory when it
+    // agrees with them; memory is only read up to the first unknown byte.
+    pub(crate) fn insn_bytes(&self, clnum: Clnum, address: Address) -> Vec<u8> {
+        let code = || {
+            self.replaced_code(address, clnum)
+                .unwrap_or_else(|| self.code_bytes(clnum, address, MAX_INSN_LEN))
+        };
+        let mut bytes = match self.instructions.get(&clnum) {
+            Some(b) => b.clone(),
+            None => return code(),
+        };
+        let length = self.disassembler.lock().length(&bytes, address);
+        if length.is_err_and(|e| is_truncated(&e)) {
+            let code = code();
+            if code.len() > bytes.len() && code.starts_with(&bytes) {
+                bytes.extend_from_slice(&code[bytes.len()..]);
+            }
+        }
+        bytes
+    }
+
+    // Text of the instruction executed at `clnum` (at runtime `address`).
+    // Captured bytes come first, then QEMU's text, then the code in memory;
+    // QEMU's text also stands in for bytes that don't fully decode.
+    fn insn_text(&self, clnum: Clnum, address: Address, options: DisasmOptions) -> String {
+        let qemu_text = || self.instructions_disasm.get(&clnum).map(|text| text.clone());
+        if !self.instructions.contains_key(&clnum) {
+            if let Some(text) = qemu_text() {
+                return text;
+            }
+        }
+        let bytes = self.insn_bytes(clnum, address);
+        let decodes = self.disassembler.lock().length(&bytes, address).is_ok();
+        if !decodes {
+            if let Some(text) = qemu_text() {
+                return text;
+            }
+        }
+        if bytes.is_empty() {
+            return String::from("???");
+        }
+        self.disassemble_with(address, &bytes, options)
+    }
+
+    pub fn get_disassembly_at(&self, clnum: Clnum) -> String {
+        self.disassembly_at(clnum, DisasmView::Timeline)
+    }
+
+    /// Disassembly at `clnum` formatted for `view`.
+    pub fn disassembly_at(&self, clnum: Clnum, view: DisasmView) -> String {
+        let options = self.disasm_options(view);
+        let disasm = self.raw_disassembly_at(clnum, options);
+        match view {
+            DisasmView::Cfg => disasm,
+            DisasmView::Timeline => self.resolve_segment_operands(&disasm, clnum),
+        }
+    }
+
+    fn raw_disassembly_at(&self, clnum: Clnum, options: DisasmOptions) -> String {
+        if self.is_pruned(clnum) {
+            return String::from("(pruned)");
+        }
+
+        // Find the PC at this clnum
+        // PC change is recorded as a Change with IS_START flag
+        let changes = self.changes.read();
+
+        // Find the instruction change for this clnum (or the one before it)
+        // Optimization: binary search could be used here if changes are sorted
+        let pc_change = changes.iter().rev().find(|c| {
+            c.clnum <= clnum
+                && ChangeFlags::from_bits_truncate(c.flags).contains(ChangeFlags::IS_START)
+        });
+
+        match pc_change {
+            Some(change) => self.insn_text(change.clnum, change.address, options),
+            None => String::from("???"),
+        }
+    }
+
+    /// Returns true if `address` (a runtime address) lies inside the function named `name`.
+    pub fn is_in_function(&self, address: u64, name: &str) -> bool {
+        self.symbolize(address)
+            .map(|(sym, _)| sym == name)
+            .unwrap_or(false)
+    }
+
+    /// Up to `count` timeline entries from `start` on, filtered and grouped
+    /// as `options` say. A page continues past a collapsed library call at
+    /// its return, and doesn't end in the middle of a folded run.
+    pub fn get_trace_log(&self, start: Clnum, count: u32, options: TraceLogOptions) -> TraceLogPage {
+        let TraceLogOptions { only_user_code, function, collapse_library_calls, fold } = options;
+        let disasm_options = self.disasm_options(DisasmView::Timeline);
+        let changes = self.changes.read();
+        let mut entries: Vec<TraceEntry> = Vec::new();
+
+        let mut c = start;
+        let mut collected = 0;
+        let max_clnum = changes.last().map(|c| c.clnum).unwrap_or(0);
+
+        // Safety break
+        while c <= max_clnum {
+            // Find the IS_START change for this clnum
+            let start_change = changes_at(&changes, c).iter().find(|ch| {
+                ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START)
+            });
+            let shown = start_change.filter(|change| {
+                let in_function = function
+                    .map(|name| self.is_in_function(change.address, name))
+                    .unwrap_or(true);
+                (!only_user_code || self.is_user_code(change.address)) && in_function
+            });
+            let run = entries.last_mut().filter(|last| {
+                fold && last.skipped_insns == 0 && shown.is_some_and(|change| change.address == last.address)
+            });
+
+            if let Some(last) = run {
+                last.repeats += 1;
+                last.last_repeat = Some(c);
+            } else if collected == count {
+                // Page full, and this clnum doesn't extend its last entry
+                break;
+            } else if let Some(change) = shown {
+                let entry = self.trace_entry(&changes, c, change, disasm_options, collapse_library_calls);
+                // A collapsed library call continues the page at its return
+                c += entry.skipped_insns;
+                entries.push(entry);
+                collected += 1;
+            } else if start_change.is_none() {
+                tracing::trace!(clnum = c, "no instruction start for clnum");
+            }
+            c += 1;
+
+            if c > start + 100000 && collected == 0 {
+                tracing::debug!(start, "trace log scan limit reached without user code");
+                break; // Prevent infinite loop if no user code found
+            }
+        }
+
+        TraceLogPage {
+            entries,
+            next_start: if c <= max_clnum { Some(c) } else { None },
+        }
+    }
+
+    /// Timeline entries for `clnums` (in the given order), skipping any that
+    /// aren't an executed instruction. Used to show slices, which are sparse.
+    pub fn trace_entries(&self, clnums: &[Clnum]) -> Vec<TraceEntry> {
+        let options = self.disasm_options(DisasmView::Timeline);
+        let changes = self.changes.read();
+        clnums
+            .iter()
+            .filter_map(|&c| {
+                let change = changes_at(&changes, c).iter().find(|ch| is_start(ch))?;
+                Some(self.trace_entry(&changes, c, change, options, false))
+            })
+            .collect()
+    }
+
+    // The timeline entry for the instruction `change` started at `c`
+    fn trace_entry(
+        &self,
+        changes: &[Change],
+        c: Clnum,
+        change: &Change,
+        options: DisasmOptions,
+        collapse_library_calls: bool,
+    ) -> TraceEntry {
+        let disassembly = {
+            let d = self.insn_text(c, change.address, options);
+            self.resolve_segment_operands(&d, c)
+        };
+        let reg_defs = self.operand_defs(c, change.address, &disassembly);
+
+        // Find register/memory effects
+        let (reg_diffs, mem_accesses) = split_effects(changes_at(changes, c));
+        let groups = self.insn_groups(c, change.address);
+        let return_value = if groups.contains(&InsnGroup::Call) {
+            self.return_value_in(changes, c, change.address)
+        } else {
+            None
+        };
+        let resume = if collapse_library_calls && groups.contains(&InsnGroup::Call) {
+            self.library_call_end(changes, c, change.address)
+        } else {
+            None
+        };
+
+        let patched = self
+            .static_comparison(c, change.address)
+            .is_some_and(|(executed, on_disk)| executed != on_disk);
+        let async_entry = ChangeFlags::from_bits_truncate(change.flags).contains(ChangeFlags::IS_ASYNC);
+
+        TraceEntry {
+            clnum: c,
+            address: change.address,
+            disassembly,
+            reg_diffs,
+            mem_accesses,
+            return_value,
+            patched,
+            async_entry,
+            groups,
+            skipped_insns: resume.map_or(0, |end| end - c - 1),
+            repeats: 0,
+            last_repeat: None,
+            reg_defs,
+        }
+    }
+
+    /// Raw change records touching `address` (PC, register slot or memory),
+    /// for debugging the derived indices.
+    pub fn changes_for_address(&self, address: Address) -> Vec<Change> {
+        self.changes
+            .read()
+            .iter()
+            .filter(|ch| ch.address == address)
+            .copied()
+            .collect()
+    }
+
+    /// Raw change records with `start <= clnum <= end`.
+    pub fn changes_in_clnum_range(&self, start: Clnum, end: Clnum) -> Vec<Change> {
+        let changes = self.changes.read();
+        let from = changes.partition_point(|ch| ch.clnum < start);
+        let to = changes.partition_point(|ch| ch.clnum <= end);
+        changes[from..to.max(from)].to_vec()
+    }
+
+    /// Returns the clnum "step over" lands on from `current`: if the instruction
+    /// there is a call, the first clnum executing its return site with the call
+    /// stack unwound back to the caller's depth (so nested and recursive calls
+    /// are skipped); otherwise the next clnum. Calls that never return (e.g.
+    /// `exit`) land on `max`.
+    pub fn step_over(&self, current: Clnum, max: Clnum) -> Clnum {
+        let next = clamp_clnum(current.saturating_add(1), max);
+        let changes = self.changes.read();
+        let call_pc = match changes_at(&changes, current).iter().find(|ch| is_start(ch)) {
+            Some(ch) => ch.address,
+            None => return next,
+        };
+        match self.return_site(current, call_pc) {
+            Some(return_site) => self
+                .find_return(&changes, current, return_site)
+                .map_or(max, |clnum| clnum.min(max)),
+            None => next,
+        }
+    }
+
+    /// Value of rax once the call executed at `call_clnum` has returned,
+    /// read at the first instruction back at its return site (see
+    /// `step_over`). None if `call_clnum` isn't a call, the call doesn't
+    /// return within the trace, or the callee never wrote rax (void
+    /// functions and other calling conventions).
+    pub fn call_return_value(&self, call_clnum: Clnum) -> Option<u64> {
+        let changes = self.changes.read();
+        let call_pc = changes_at(&changes, call_clnum).iter().find(|ch| is_start(ch))?.address;
+        self.return_value_in(&changes, call_clnum, call_pc)
+    }
+
+    // call_return_value for a caller that already holds the change log
+    fn return_value_in(&self, changes: &[Change], call_clnum: Clnum, call_pc: Address) -> Option<u64> {
+        let return_site = self.return_site(call_clnum, call_pc)?;
+        let returned = self.find_return(changes, call_clnum, return_site)?;
+        let regs = self.registers.read();
+        let rax = regs.get(&self.vcpu_at(call_clnum))?.first()?;
+        let idx = rax.partition_point(|&(c, _)| c <= returned);
+        let (written, value) = *rax.get(idx.checked_sub(1)?)?;
+        (written > call_clnum).then_some(value)
+    }
+
+    // If the call executed at `clnum` (at `pc`) goes from user code into a
+    // library, directly or through a PLT stub, the clnum execution continues
+    // in the caller at: its return, or past the end of the trace if it never
+    // returned. None for other calls.
+    fn library_call_end(&self, changes: &[Change], clnum: Clnum, pc: Address) -> Option<Clnum> {
+        if !self.is_user_code(pc) {
+            return None;
+        }
+        let return_site = self.return_site(clnum, pc)?;
+        let vcpu = self.vcpu_at(clnum);
+        let after = changes.partition_point(|ch| ch.clnum <= clnum);
+        let callee = changes[after..].iter().find(|ch| is_start(ch) && self.vcpu_at(ch.clnum) == vcpu)?;
+        if self.is_user_code(callee.address) && !self.is_stub(callee.address) {
+            return None;
+        }
+        let last = changes.last().map_or(clnum, |ch| ch.clnum);
+        Some(self.find_return(changes, clnum, return_site).unwrap_or(last + 1))
+    }
+
+    // Address following the call instruction executed at `clnum` (at `pc`),
+    // or None if the instruction there isn't a call
+    fn return_site(&self, clnum: Clnum, pc: Address) -> Option<Address> {
+        let bytes = self.insn_bytes(clnum, pc);
+        let disassembler = self.disassembler.lock();
+        if !disassembler.groups(&bytes, pc).ok()?.contains(&InsnGroup::Call) {
+            return None;
+        }
+        let (len, _) = disassembler.disassemble_one(&bytes, pc)?;
+        Some(pc + len as u64)
+    }
+
+    // First clnum after the call at `call_clnum` that executes `return_site`
+    // with the call stack unwound back to the caller's depth, so nested and
+    // recursive calls are skipped. Only the calling vcpu's instructions count.
+    fn find_return(&self, changes: &[Change], call_clnum: Clnum, return_site: Address) -> Option<Clnum> {
+        // Execution after the call is inside the callee, one frame deep
+        let (scanned, mut depth) = match self.call_returns.get(&call_clnum).map(|scan| *scan) {
+            Some(CallScan::Returned(returned)) => return Some(returned),
+            Some(CallScan::Pending { scanned, depth }) => (scanned, depth),
+            None => (call_clnum, 1i64),
+        };
+        let Some(last) = changes.last().map(|ch| ch.clnum).filter(|&last| last > scanned) else {
+            return None;
+        };
+        let vcpu = self.vcpu_at(call_clnum);
+        let start = changes.partition_point(|ch| ch.clnum <= scanned);
+        for ch in changes[start..].iter().filter(|ch| is_start(ch) && self.vcpu_at(ch.clnum) == vcpu) {
+            if depth == 0 && ch.address == return_site {
+                self.call_returns.insert(call_clnum, CallScan::Returned(ch.clnum));
+                return Some(ch.clnum);
+            }
+            let groups = self.insn_groups(ch.clnum, ch.address);
+            if groups.contains(&InsnGroup::Call) {
+                depth += 1;
+            } else if groups.contains(&InsnGroup::Ret) {
+                depth -= 1;
+            }
+        }
+        self.call_returns.insert(call_clnum, CallScan::Pending { scanned: last, depth });
+        None
+    }
+
+    // Categories of the instruction executed at `clnum`, empty if its bytes
+    // don't decode
+    pub(crate) fn insn_groups(&self, clnum: Clnum, address: Address) -> Vec<InsnGroup> {
+        let bytes = self.insn_bytes(clnum, address);
+        self.disassembler.lock().groups(&bytes, address).unwrap_or_default()
+    }
+
+    // Registers the instruction executed at `clnum` reads, empty if its
+    // bytes don't decode
+    pub(crate) fn insn_read_registers(&self, clnum: Clnum, address: Address) -> Vec<usize> {
+        let bytes = self.insn_bytes(clnum, address);
+        self.disassembler.lock().get_read_registers(&bytes, address).unwrap_or_default()
+    }
+
+    /// Builds self-contained export records for `clnums`, skipping clnums
+    /// with no executed instruction.
+    pub fn export_region(&self, clnums: &[Clnum]) -> Vec<ExportRecord> {
+        let mut records = Vec::with_capacity(clnums.len());
+        for &clnum in clnums {
+            // Release the change log before get_disassembly_at takes it again
+            let (address, (reg_diffs, mem_accesses)) = {
+                let changes = self.changes.read();
+                let effects = changes_at(&changes, clnum);
+                match effects.iter().find(|ch| {
+                    ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START)
+                }) {
+                    Some(ch) => (ch.address, split_effects(effects)),
+                    None => continue,
+                }
+            };
+
+            let symbol = self.symbol_label(address);
+
+            records.push(ExportRecord {
+                clnum,
+                address,
+                bytes: self
+                    .instructions
+                    .get(&clnum)
+                    .map(|b| b.clone())
+                    .unwrap_or_default(),
+                disassembly: self.get_disassembly_at(clnum),
+                reg_diffs,
+                mem_accesses,
+                symbol,
+            });
+        }
+        records
+    }
+
+    /// Backward slice from `start_clnum`. `target` is a register (rax, or a
+    /// part of one such as eax or al) or a 0x address, or several of them
+    /// separated by commas, in which case the union of their slices is
+    /// returned.
+    pub fn get_slice(&self, start_clnum: Clnum, target: String) -> Result<Vec<Clnum>, QueryError> {
+        // Register index -> bits still to be explained
+        let mut tainted_regs: HashMap<usize, u64> = HashMap::new();
+        let mut tainted_mem = HashSet::new();
+
+        for part in target.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if let Some(addr) = part.strip_prefix("0x").and_then(|hex| u64::from_str_radix(hex, 16).ok()) {
+                tainted_mem.insert(addr);
+            } else if let Some(reg) = RegPart::from_name(part).filter(|reg| reg.index < 16) {
+                *tainted_regs.entry(reg.index).or_default() |= reg.mask();
+            } else {
+                return Err(QueryError::UnknownSliceTarget(part.to_string()));
+            }
+        }
+
+        if tainted_regs.is_empty() && tainted_mem.is_empty() {
+            return Err(QueryError::UnknownSliceTarget(target));
+        }
+
+        let changes = self.changes.read();
+        let mut slice = Vec::new();
+        let mut end = changes.partition_point(|ch| ch.clnum <= start_clnum);
+
+        // Walk the instructions backwards until every tainted location was
+        // explained by a write
+        while end > 0 && !(tainted_regs.is_empty() && tainted_mem.is_empty()) {
+            let clnum = changes[end - 1].clnum;
+            let begin = changes[..end].partition_point(|ch| ch.clnum < clnum);
+            if self.slice_step(clnum, &changes[begin..end], &mut tainted_regs, &mut tainted_mem) {
+                slice.push(clnum);
+            }
+            end = begin;
+        }
+
+        slice.reverse();
+        Ok(slice)
+    }
+
+    /// Forward slice from just after `after`: the instructions that read a
+    /// tainted location, starting from registers `regs` (index -> tainted
+    /// bits) and memory bytes `mem`. Whatever such an instruction writes
+    /// becomes tainted; whatever an instruction writes from untainted inputs
+    /// is cleared. Stops once nothing is tainted, or after `limit`
+    /// instructions.
+    pub fn forward_slice(
+        &self,
+        after: Clnum,
+        regs: HashMap<usize, u64>,
+        mem: HashSet<Address>,
+        limit: usize,
+    ) -> Vec<Clnum> {
+        let (mut tainted_regs, mut tainted_mem) = (regs, mem);
+        let changes = self.changes.read();
+        let mut slice = Vec::new();
+        let mut begin = changes.partition_point(|ch| ch.clnum <= after);
+        let mut walked = 0;
+
+        while begin < changes.len() && walked < limit && !(tainted_regs.is_empty() && tainted_mem.is_empty()) {
+            walked += 1;
+            let clnum = changes[begin].clnum;
+            let end = begin + changes[begin..].partition_point(|ch| ch.clnum <= clnum);
+            if self.forward_step(clnum, &changes[begin..end], &mut tainted_regs, &mut tainted_mem) {
+                slice.push(clnum);
+            }
+            begin = end;
+        }
+        slice
+    }
+
+    /// Taints what the syscall at `clnum` returned (rax, and the buffer it
+    /// filled for read-like syscalls) and follows it forward. Arguments and
+    /// the result come from the registers around the syscall.
+    pub fn taint_from_syscall(&self, clnum: Clnum) -> Result<SyscallTaint, QueryError> {
+        let pc = {
+            let changes = self.changes.read();
+            changes_at(&changes, clnum).iter().find(|ch| is_start(ch)).map(|ch| ch.address)
+        };
+        if pc.is_none_or(|pc| !self.insn_groups(clnum, pc).contains(&InsnGroup::Syscall)) {
+            return Err(QueryError::NotASyscall(clnum));
+        }
+
+        let vcpu = self.vcpu_at(clnum);
+        let regs = self.get_registers_at_vcpu(clnum, vcpu);
+        let reg = |index: usize| regs.get(index).copied().unwrap_or(0);
+        let nr = reg(syscall::NR_REG);
+        let args = syscall::ARG_REGS.map(reg);
+        let returned = self
+            .next_clnum_of(clnum, vcpu)
+            .filter(|&next| changes_at(&self.changes.read(), next).iter().any(is_start))
+            .ok_or_else(|| QueryError::NeverExecuted(format!("the return of the syscall at clnum {}", clnum)))?;
+        let result = self
+            .get_registers_at_vcpu(returned, vcpu)
+            .get(syscall::RESULT_REG)
+            .copied()
+            .unwrap_or(0);
+
+        // Errors come back as -errno and fill nothing
+        let buffer = syscall::output_buffer(nr, &args)
+            .filter(|_| (result as i64) > 0)
+            .map(|address| (address, result.min(MAX_TAINT_BUFFER)));
+        let mem = match buffer {
+            Some((address, len)) => {
+                let end = address.checked_add(len).ok_or(QueryError::BufferWraps { address, len })?;
+                (address..end).collect()
+            }
+            None => HashSet::new(),
+        };
+        let slice = self.forward_slice(clnum, HashMap::from([(syscall::RESULT_REG, u64::MAX)]), mem, MAX_TAINT_INSNS);
+        Ok(SyscallTaint { nr, result, buffer, slice })
+    }
+
+    // One instruction of a forward slice: true if it reads a tainted
+    // location, in which case everything it writes becomes tainted;
+    // otherwise what it writes is no longer tainted
+    fn forward_step(
+        &self,
+        clnum: Clnum,
+        group: &[Change],
+        tainted_regs: &mut HashMap<usize, u64>,
+        tainted_mem: &mut HashSet<Address>,
+    ) -> bool {
+        let Some(pc) = group.iter().find(|ch| is_start(ch)).map(|ch| ch.address) else {
+            return false;
+        };
+        let bytes = self.insn_bytes(clnum, pc);
+        let (read_regs, mut written_regs) = {
+            let disassembler = self.disassembler.lock();
+            let reads = disassembler.get_read_registers(&bytes, pc).unwrap_or_default();
+            let writes = disassembler.get_written_registers(&bytes, pc).unwrap_or_default();
+            (reads, writes.iter().map(|part| (part.index, part.write_mask())).collect::<Vec<_>>())
+        };
+
+        let mut read_mem = Vec::new();
+        let mut written_mem = Vec::new();
+        for ch in group.iter().filter(|ch| !is_start(ch)) {
+            let flags = ChangeFlags::from_bits_truncate(ch.flags);
+            if flags.contains(ChangeFlags::IS_MEM) {
+                let size = ((ch.flags & ChangeFlags::SIZE_MASK.bits()) as u64 / 8).max(1);
+                let bytes = ch.address..ch.address + size;
+                if flags.contains(ChangeFlags::IS_WRITE) {
+                    written_mem.extend(bytes);
+                } else {
+                    read_mem.extend(bytes);
+                }
+            } else if flags.contains(ChangeFlags::IS_WRITE) {
+                written_regs.push(((ch.address / 8) as usize, u64::MAX));
+            }
+        }
+
+        let tainted = read_regs.iter().any(|r| tainted_regs.contains_key(r))
+            || read_mem.iter().any(|addr| tainted_mem.contains(addr));
+        for (index, mask) in written_regs.into_iter().filter(|&(index, _)| index < 16) {
+            if tainted {
+                *tainted_regs.entry(index).or_default() |= mask;
+            } else if let Some(bits) = tainted_regs.get_mut(&index) {
+                *bits &= !mask;
+                if *bits == 0 {
+                    tainted_regs.remove(&index);
+                }
+            }
+        }
+        for addr in written_mem {
+            if tainted {
+                tainted_mem.insert(addr);
+            } else {
+                tainted_mem.remove(&addr);
+            }
+        }
+        tainted
+    }
+
+    // One instruction of a backward slice: true if it writes a tainted
+    // location, in which case the locations it wrote are replaced in the
+    // tainted sets by the ones it read
+    fn slice_step(
+        &self,
+        clnum: Clnum,
+        group: &[Change],
+        tainted_regs: &mut HashMap<usize, u64>,
+        tainted_mem: &mut HashSet<Address>,
+    ) -> bool {
+        let pc = group.iter().find(|ch| is_start(ch)).map(|ch| ch.address);
+        let bytes = pc.map(|pc| self.insn_bytes(clnum, pc));
+        if let (Some(pc), Some(bytes)) = (pc, &bytes) {
+            if let Err(e) = self.disassembler.lock().length(bytes, pc) {
+                tracing::warn!(clnum, "can't decode instruction at {:#x}, slice may be incomplete: {}", pc, e);
+            }
+        }
+
+        // Register writes come from the instruction itself, with their width
+        let mut written_regs: Vec<(usize, u64)> = Vec::new();
+        if let (Some(pc), Some(bytes)) = (pc, &bytes) {
+            if !tainted_regs.is_empty() && !bytes.is_empty() {
+                if let Ok(parts) = self.disassembler.lock().get_written_registers(bytes, pc) {
+                    written_regs.extend(parts.iter().map(|part| (part.index, part.write_mask())));
+                }
+            }
+        }
+        let mut written_mem = Vec::new();
+        for ch in group {
+            let flags = ChangeFlags::from_bits_truncate(ch.flags);
+            if flags.contains(ChangeFlags::IS_WRITE) && !flags.contains(ChangeFlags::IS_START) {
+                if flags.contains(ChangeFlags::IS_MEM) {
+                    written_mem.push(ch.address);
+                } else {
+                    written_regs.push(((ch.address / 8) as usize, u64::MAX));
+                }
+            }
+        }
+
+        let relevant = written_mem.iter().any(|addr| tainted_mem.contains(addr))
+            || written_regs
+                .iter()
+                .any(|(index, mask)| tainted_regs.get(index).is_some_and(|bits| bits & mask != 0));
+        if !relevant {
+            return false;
+        }
+
+        for (index, mask) in written_regs {
+            if let Some(bits) = tainted_regs.get_mut(&index) {
+                *bits &= !mask;
+                if *bits == 0 {
+                    tainted_regs.remove(&index);
+                }
+            }
+        }
+        for addr in written_mem {
+            tainted_mem.remove(&addr);
+        }
+
+        // Inputs
+        for ch in group {
+            let flags = ChangeFlags::from_bits_truncate(ch.flags);
+            if flags.contains(ChangeFlags::IS_MEM) && !flags.contains(ChangeFlags::IS_WRITE) {
+                tainted_mem.insert(ch.address);
+            }
+        }
+        if let (Some(pc), Some(bytes)) = (pc, &bytes) {
+            if !bytes.is_empty() {
+                if let Ok(reads) = self.disassembler.lock().get_read_registers(bytes, pc) {
+                    for r in reads.into_iter().filter(|&r| r < 16) {
+                        tainted_regs.insert(r, u64::MAX);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+// "name", or "name+0xoff" past its start
+fn offset_label((name, offset): (String, u64)) -> String {
+    if offset == 0 {
+        name
+    } else {
+        format!("{}+0x{:x}", name, offset)
+    }
+}
+
+/// Clamps `clnum` to the clnums that hold an instruction: 1 (the first, as
+/// numbered by the IPC loop) through `max`. Before anything was traced
+/// (`max` = 0) the only position is 0.
+pub fn clamp_clnum(clnum: Clnum, max: Clnum) -> Clnum {
+    clnum.clamp(max.min(1), max)
+}
+
+// All changes recorded for `clnum`, in the order they arrived.
+// The change log is appended in clnum order, so it can be bisected.
+pub(crate) fn changes_at(changes: &[Change], clnum: Clnum) -> &[Change] {
+    let start = changes.partition_point(|ch| ch.clnum < clnum);
+    let end = changes.partition_point(|ch| ch.clnum <= clnum);
+    &changes[start..end]
+}
+
+// Keeps the last `limit` entries of `history` once there are an eighth more
+// than that, so the drain is amortized like the window's. True if it trimmed.
+fn trim_history<T>(history: &mut Vec<T>, limit: usize) -> bool {
+    let len = history.len();
+    if len > limit + (limit / 8).max(1) {
+        history.drain(..len - limit);
+        true
+    } else {
+        false
+    }
+}
+
+// Whether `ch` records the execution of an instruction (its pc)
+pub(crate) fn is_start(ch: &Change) -> bool {
+    ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START)
+}
+
+// Splits a clnum's changes into register writes (index, value) and memory
+// accesses (address, value if captured, is_write)
+pub(crate) fn split_effects(effects: &[Change]) -> (Vec<RegDiff>, Vec<MemAccess>) {
+    let mut reg_diffs = Vec::new();
+    let mut mem_accesses = Vec::new();
+    for ch in effects {
+        let flags = ChangeFlags::from_bits_truncate(ch.flags);
+        if flags.contains(ChangeFlags::IS_MEM) {
+            let value = (!flags.contains(ChangeFlags::NO_VALUE)).then_some(ch.data);
+            mem_accesses.push((ch.address, value, flags.contains(ChangeFlags::IS_WRITE)));
+        } else if flags.contains(ChangeFlags::IS_WRITE) && !flags.contains(ChangeFlags::IS_START) {
+            reg_diffs.push(((ch.address / 8) as usize, ch.data));
+        }
+    }
+    (reg_diffs, mem_accesses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_stack_canary_load() {
+        let db = TraceDB::new(REG_COUNT);
+        let mut regs = vec![0u64; REG_COUNT];
+        regs[REG_FS_BASE] = 0x7fff_f7d8_a740;
+
+        // mov rax, qword ptr fs:[0x28]
+        db.add_instruction(1, vec![0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00]);
+        db.update_registers(1, &regs);
+        db.add_change(Change {
+            address: 0x401136,
+            data: 0,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        });
+
+        let disasm = db.get_disassembly_at(1);
+        assert!(disasm.ends_with("; fs:0x28 = 0x7ffff7d8a768"), "{}", disasm);
+    }
+
+    #[test]
+    fn value_history_combines_overlapping_writes() {
+        let db = TraceDB::new(REG_COUNT);
+        let write = |clnum, address, data, bits| Change {
+            address,
+            data,
+            clnum,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | bits,
+        };
+        db.load_static_memory(0x1000, &[0xaa, 0xbb, 0xcc, 0xdd]);
+        // A byte store inside the dword, then a qword straddling its start
+        db.add_change(write(1, 0x1001, 0x11, 8));
+        db.add_change(write(2, 0x1004, 0x22, 8));
+        db.add_change(write(3, 0x0ffe, 0x8877_6655_4433_2211, 64));
+
+        assert_eq!(db.value_history(0x1000, 4), vec![(1, 0xddcc11aa), (3, 0x66554433)]);
+        // The byte past the dword only counts once the width covers it
+        assert_eq!(db.value_history(0x1000, 8).len(), 3);
+        assert_eq!(db.value_history(0x1001, 1), vec![(1, 0x11), (3, 0x44)]);
+        assert!(db.value_history(0x2000, 4).is_empty());
+    }
+
+    #[test]
+    fn memory_reads_keep_write_history_intact() {
+        let db = TraceDB::new(REG_COUNT);
+        let mem = |clnum, data, write| Change {
+            address: 0x1000,
+            data,
+            clnum,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM).bits()
+                | if write { ChangeFlags::IS_WRITE.bits() } else { 0 }
+                | 64,
+        };
+        db.add_change(mem(1, 0x1122, true));
+        db.add_change(mem(2, 0xdead, false));
+
+        assert_eq!(db.get_memory_writes(0x1000), vec![1]);
+        assert_eq!(db.get_memory_at(2, 0x1000, 2), vec![0x22, 0x11]);
+        assert_eq!(db.read_value_at(2, 0x1000), Some(0xdead));
+        assert_eq!(db.read_value_at(1, 0x1000), None);
+        assert_eq!(db.get_read_values(0x1000), vec![(2, 0xdead)]);
+
+        // The 8-byte load covers 0x1000..0x1008
+        assert_eq!(db.get_memory_reads(0x1000), vec![2]);
+        assert_eq!(db.get_memory_reads(0x1007), vec![2]);
+        assert!(db.get_memory_reads(0x1008).is_empty());
+    }
+
+    #[test]
+    fn uncaptured_values_are_unknown_rather_than_zero() {
+        use crate::protocol::CompareOp;
+        let db = TraceDB::new(REG_COUNT);
+        let mem = |clnum, data, flags: ChangeFlags| Change {
+            address: 0x1000,
+            data,
+            clnum,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | flags).bits() | 16,
+        };
+        db.add_change(mem(1, 0x4142, ChangeFlags::IS_WRITE));
+        db.add_change(mem(2, 0, ChangeFlags::IS_WRITE | ChangeFlags::NO_VALUE));
+        db.add_change(mem(3, 0, ChangeFlags::NO_VALUE));
+
+        // The write still counts as one, but leaves the bytes unknown
+        assert_eq!(db.get_memory_writes(0x1000), vec![1, 2]);
+        assert_eq!(db.get_known_value_at(1, 0x1000, 2), Some(0x4142));
+        assert_eq!(db.get_known_value_at(2, 0x1000, 2), None);
+        assert_eq!(db.get_known_value_at(2, 0x1002, 2), Some(0));
+        assert!(db.get_read_values(0x1000).is_empty());
+
+        let changes = db.changes.read();
+        let (_, accesses) = split_effects(changes_at(&changes, 2));
+        assert_eq!(accesses, vec![(0x1000, None, true)]);
+        let (_, accesses) = split_effects(changes_at(&changes, 1));
+        assert_eq!(accesses, vec![(0x1000, Some(0x4142), true)]);
+        drop(changes);
+
+        let regs = vec![0u64; REG_COUNT];
+        let condition = BreakCondition {
+            operand: CondOperand::Mem { address: 0x1000, size: 2 },
+            op: CompareOp::Eq,
+            value: 0,
+        };
+        assert!(!db.condition_holds(3, &regs, &condition));
+    }
+
+    #[test]
+    fn stack_extent_grows_with_rsp() {
+        let db = TraceDB::new(REG_COUNT);
+        db.note_stack_pointer(0, 1, 0x7fff_ffff_e000);
+        db.note_stack_pointer(0, 5, 0x7fff_ffff_d000);
+        // A pivot far away from the known stack isn't folded in
+        db.note_stack_pointer(0, 6, 0x5555_5555_8000);
+
+        assert!(db.is_stack_address(0x7fff_ffff_e100, 1));
+        assert!(!db.is_stack_address(0x7fff_ffff_d010, 1));
+        assert!(db.is_stack_address(0x7fff_ffff_d010, 5));
+        assert!(db.is_stack_address(0x7fff_ffff_cf90, 5));
+        assert!(!db.is_stack_address(0x5555_5555_8000, 6));
+    }
+
+    #[test]
+    fn annotates_operand_values() {
+        let db = TraceDB::new(REG_COUNT);
+        let mut regs = vec![0u64; REG_COUNT];
+        regs[1] = 0x1000; // rbx
+
+        db.add_change(Change {
+            address: 0x1010,
+            data: 0x2a,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 64,
+        });
+        // mov rax, qword ptr [rbx + 0x10]
+        db.add_instruction(2, vec![0x48, 0x8b, 0x43, 0x10]);
+        db.update_registers(2, &regs);
+        db.add_change(Change {
+            address: 0x401000,
+            data: 0,
+            clnum: 2,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        });
+
+        let line = db.annotate_operands(2);
+        assert!(line.ends_with(" ; rax=0x0, rbx=0x1000, [0x1010]=0x2a"), "{}", line);
+    }
+
+    #[test]
+    fn disassembles_ahead_through_the_bias() {
+        let db = TraceDB::new(REG_COUNT);
+        // push rbp; mov rbp, rsp; ret
+        db.load_static_memory(0x1000, &[0x55, 0x48, 0x89, 0xe5, 0xc3]);
+        db.set_bias(0x5555_5555_4000);
+
+        let lines = db.disassemble_range(1, 0x5555_5555_5000, 8);
+        let addrs: Vec<_> = lines.iter().map(|(a, _)| *a).collect();
+        assert_eq!(addrs, [0x5555_5555_5000, 0x5555_5555_5001, 0x5555_5555_5004]);
+        assert_eq!(lines[1].1, "mov rbp, rsp");
+        // Nothing is loaded past the ret
+        assert!(db.disassemble_range(1, 0x5555_5555_5005, 8).is_empty());
+    }
+
+    #[test]
+    fn block_coverage_marks_functions_executed() {
+        let db = TraceDB::new(REG_COUNT);
+        db.set_bias(0x1000);
+        db.add_symbol(0x100, 0x20, "main".to_string());
+        db.add_symbol(0x200, 0x10, "helper".to_string());
+        db.add_block_coverage(&[0x1110, 0x1500]);
+
+        assert_eq!(
+            db.coverage(),
+            vec![("main".to_string(), true, 0), ("helper".to_string(), false, 0)]
+        );
+    }
+
+    #[test]
+    fn slices_several_targets_at_once() {
+        let db = TraceDB::new(REG_COUNT);
+        // Each instruction stores one qword: mov qword ptr [addr], rax
+        let stores = [0x601040u64, 0x601048, 0x601050];
+        for (i, &addr) in stores.iter().enumerate() {
+            let clnum = i as u32 + 1;
+            let mut bytes = vec![0x48, 0x89, 0x04, 0x25];
+            bytes.extend_from_slice(&(addr as u32).to_le_bytes());
+            db.add_instruction(clnum, bytes);
+            db.add_change(Change {
+                address: 0x401000 + 8 * i as u64,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+            db.add_change(Change {
+                address: addr,
+                data: clnum as u64,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 64,
+            });
+        }
+
+        assert_eq!(db.get_slice(3, "0x601040".to_string()).unwrap(), vec![1]);
+        assert_eq!(db.get_slice(3, "0x601040, 0x601050,rdi".to_string()).unwrap(), vec![1, 3]);
+        assert!(matches!(
+            db.get_slice(3, "0x601040,xmm0".to_string()),
+            Err(QueryError::UnknownSliceTarget(t)) if t == "xmm0"
+        ));
+    }
+
+    #[test]
+    fn marks_the_last_instruction_of_a_killed_program() {
+        let db = TraceDB::new(REG_COUNT);
+        for clnum in 1..=3 {
+            db.add_change(Change {
+                address: 0x401000 + clnum as u64,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+        // The tracer's exit events take clnums without instructions
+        db.add_change(Change {
+            address: 0x601000,
+            data: 1,
+            clnum: 3,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 8,
+        });
+
+        db.record_process_end(Some(0), None);
+        assert_eq!(db.crash(), None);
+
+        db.record_process_end(None, Some(6));
+        assert_eq!(db.crash(), Some(Crash { clnum: 3, address: 0x401003, signal: Some(6) }));
+
+        db.reset_trace();
+        assert_eq!(db.crash(), None);
+    }
+
+    #[test]
+    fn names_regions_ahead_of_symbols() {
+        let db = TraceDB::new(REG_COUNT);
+        db.add_symbol(0x601000, 0x100, "globals".to_string());
+        // mov rax, qword ptr [addr]
+        let load = |addr: u32| {
+            let mut bytes = vec![0x48, 0x8b, 0x04, 0x25];
+            bytes.extend_from_slice(&addr.to_le_bytes());
+            db.disassemble(0x401000, &bytes)
+        };
+        assert_eq!(load(0x601048), "mov rax, qword ptr [globals+0x48]");
+
+        db.name_region(0x601040, 64, "input_buffer").unwrap();
+        assert_eq!(load(0x601048), "mov rax, qword ptr [input_buffer+0x8]");
+        assert_eq!(load(0x601000), "mov rax, qword ptr [globals]");
+        assert_eq!(load(0x700000), "mov rax, qword ptr [0x700000]");
+
+        // Overlapping names replace the old one
+        db.name_region(0x601070, 0x20, "tail").unwrap();
+        assert_eq!(
+            db.regions(),
+            vec![NamedRegion { address: 0x601070, len: 0x20, name: "tail".to_string() }]
+        );
+        assert!(matches!(db.name_region(0x601000, 8, "two words"), Err(QueryError::InvalidRegion(_))));
+        assert!(matches!(db.name_region(0x601000, 0, "empty"), Err(QueryError::InvalidRegion(_))));
+
+        db.unname_region(Some(0x60107f));
+        assert!(db.regions().is_empty());
+        assert_eq!(load(0x601048), "mov rax, qword ptr [globals+0x48]");
+    }
+
+    #[test]
+    fn completes_truncated_bytes_from_memory() {
+        let traced = |db: &TraceDB| {
+            // mov rbx, rcx; mov rax, qword ptr [rbx] (captured short); nop
+            let program: [&[u8]; 3] = [&[0x48, 0x89, 0xcb], &[0x48, 0x8b], &[0x90]];
+            for (i, bytes) in program.into_iter().enumerate() {
+                let clnum = i as u32 + 1;
+                db.add_instruction(clnum, bytes.to_vec());
+                db.add_change(Change {
+                    address: 0x401000 + 3 * i as u64,
+                    data: 0,
+                    clnum,
+                    flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+                });
+            }
+        };
+
+        let db = TraceDB::new(REG_COUNT);
+        db.load_static_memory(0x401000, &[0x48, 0x89, 0xcb, 0x48, 0x8b, 0x03, 0x90]);
+        traced(&db);
+        assert_eq!(db.get_disassembly_at(2), "mov rax, qword ptr [rbx]");
+        assert_eq!(db.get_slice(3, "rax".to_string()).unwrap(), vec![1, 2]);
+
+        // Nothing to complete them from: reported rather than decoded
+        let db = TraceDB::new(REG_COUNT);
+        traced(&db);
+        assert_eq!(db.get_disassembly_at(2), "(truncated: 2 bytes)");
+        db.add_instruction_disasm(2, "mov rax, [rbx]".to_string());
+        assert_eq!(db.get_disassembly_at(2), "mov rax, [rbx]");
+    }
+
+    #[test]
+    fn taints_what_a_read_syscall_returned() {
+        // syscall; mov rcx, [rsi]; mov rdx, rcx; mov rbx, 1; add rdi, rax
+        let trace = |buffer: u64| {
+            let db = TraceDB::new(REG_COUNT);
+            let program: [&[u8]; 5] = [
+                &[0x0f, 0x05],
+                &[0x48, 0x8b, 0x0e],
+                &[0x48, 0x89, 0xca],
+                &[0x48, 0xc7, 0xc3, 0x01, 0x00, 0x00, 0x00],
+                &[0x48, 0x01, 0xc7],
+            ];
+            let mut regs = vec![0u64; REG_COUNT];
+            regs[4] = buffer; // rsi
+            regs[3] = 0x100; // rdx: count
+            for (i, bytes) in program.into_iter().enumerate() {
+                let clnum = i as u32 + 1;
+                if clnum == 2 {
+                    regs[0] = 8; // read returned 8 bytes
+                }
+                db.update_registers(clnum, &regs);
+                db.add_instruction(clnum, bytes.to_vec());
+                db.add_change(Change {
+                    address: 0x401000 + 8 * i as u64,
+                    data: 0,
+                    clnum,
+                    flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+                });
+                if clnum == 2 {
+                    db.add_change(Change {
+                        address: buffer,
+                        data: 0x4141,
+                        clnum,
+                        flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM).bits() | 64,
+                    });
+                }
+            }
+            db
+        };
+
+        let db = trace(0x602000);
+        let taint = db.taint_from_syscall(1).unwrap();
+        assert_eq!((taint.nr, taint.result), (0, 8));
+        assert_eq!(taint.buffer, Some((0x602000, 8)));
+        assert_eq!(taint.slice, vec![2, 3, 5]);
+        assert_eq!(db.taint_from_syscall(2), Err(QueryError::NotASyscall(2)));
+
+        // The walk stops after `limit` instructions however much is tainted
+        let buffer: HashSet<Address> = (0x602000..0x602008).collect();
+        assert_eq!(db.forward_slice(1, HashMap::from([(0, u64::MAX)]), buffer, 2), vec![2, 3]);
+
+        let db = trace(u64::MAX - 3);
+        assert_eq!(
+            db.taint_from_syscall(1).map(|taint| taint.slice),
+            Err(QueryError::BufferWraps { address: u64::MAX - 3, len: 8 })
+        );
+    }
+
+    #[test]
+    fn writes_to_register_parts_keep_the_rest() {
+        use crate::testing::TraceBuilder;
+
+        // The tracer sends full registers; what the CPU left in the rest of
+        // rax after each partial write comes through ingestion unchanged
+        let mut trace = TraceBuilder::new();
+        trace.reg("rax", 0x1122334455667788);
+        trace.insn(0x401000, &[0xb0, 0x01]).reg("rax", 0x1122334455667701); // mov al, 1
+        trace.insn(0x401002, &[0xb4, 0x02]).reg("rax", 0x1122334455660201); // mov ah, 2
+        trace.insn(0x401004, &[0xb8, 0x03, 0x00, 0x00, 0x00]).reg("rax", 3); // mov eax, 3
+        trace.insn(0x401009, &[0x90]);
+        let db = trace.build();
+
+        assert_eq!(db.get_registers_at(2)[0], 0x1122334455667701);
+        assert_eq!(db.get_registers_at(3)[0], 0x1122334455660201);
+        assert_eq!(db.get_registers_at(4)[0], 3);
+        let regs = db.get_registers_at(3);
+        assert_eq!(crate::disasm::gpr_value("al", &regs), Some(1));
+        assert_eq!(crate::disasm::gpr_value("ah", &regs), Some(2));
+        assert_eq!(crate::disasm::gpr_value("ax", &regs), Some(0x0201));
+
+        // Only the write to each part explains it; eax covers both
+        assert_eq!(db.get_slice(2, "al".to_string()).unwrap(), vec![1]);
+        assert_eq!(db.get_slice(2, "ah".to_string()).unwrap(), vec![2]);
+        assert_eq!(db.get_slice(2, "ax".to_string()).unwrap(), vec![1, 2]);
+        assert_eq!(db.get_slice(3, "rax".to_string()).unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn finds_the_instruction_that_wrote_a_register() {
+        let db = TraceDB::new(REG_COUNT);
+        // lea rax, [rbx + rcx]; nop; mov rbx, rax; with rax changed by the
+        // first and rbx by the third, on vcpu 0 with vcpu 1 interleaved
+        let program: [(u32, &[u8]); 4] = [
+            (0, &[0x48, 0x8d, 0x04, 0x0b]),
+            (1, &[0x90]),
+            (0, &[0x90]),
+            (0, &[0x48, 0x89, 0xc3]),
+        ];
+        let mut regs = vec![0u64; REG_COUNT];
+        regs[1] = 0x10;
+        regs[2] = 0x20;
+        for (i, (vcpu, bytes)) in program.into_iter().enumerate() {
+            let clnum = i as u32 + 1;
+            db.record_vcpu(clnum, vcpu);
+            db.add_instruction(clnum, bytes.to_vec());
+            db.add_change(Change {
+                address: 0x401000 + 8 * i as u64,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+            if vcpu == 0 {
+                if clnum == 3 {
+                    regs[0] = 0x30;
+                }
+                db.update_registers(clnum, &regs);
+            } else {
+                db.update_registers(clnum, &[0u64; REG_COUNT]);
+            }
+        }
+
+        // rax changed between clnum 1 and 3 on vcpu 0: clnum 1 wrote it
+        assert_eq!(db.last_writer_reg(3, 0), Some(1));
+        assert_eq!(db.last_writer_reg(4, 0), Some(1));
+        // Not written since first seen
+        assert_eq!(db.last_writer_reg(4, 1), None);
+        assert_eq!(db.last_writer_reg(1, 0), None);
+
+        let writer = db.register_writer(4, 0).unwrap();
+        assert_eq!((writer.clnum, writer.address), (1, 0x401000));
+        assert_eq!(writer.reg_inputs, vec![(1, 0x10), (2, 0x20)]);
+        assert!(writer.mem_inputs.is_empty());
+
+        // mov rbx, rax links its rax operand to the lea; the written rbx isn't linked
+        let entry = db.get_trace_log(4, 1, TraceLogOptions::default()).entries.remove(0);
+        assert_eq!(entry.reg_defs, vec![("rax".to_string(), 1)]);
+    }
+
+    #[test]
+    fn lists_whole_function_with_executed_part() {
+        let db = TraceDB::new(REG_COUNT);
+        db.set_bias(0x1000);
+        db.add_symbol(0x100, 9, "main".to_string());
+        db.add_symbol(0x200, 2, "helper".to_string());
+        db.add_symbol(0x300, 0, "stub".to_string());
+        // main: push rbp; call helper; pop rbp; ret
+        db.load_static_memory(0x100, &[0x55, 0xe8, 0xfa, 0x00, 0x00, 0x00, 0x5d, 0xc3, 0x90]);
+        // helper: ret; nop. stub has no size and is listed up to its ret
+        db.load_static_memory(0x200, &[0xc3, 0x90]);
+        db.load_static_memory(0x300, &[0xc3, 0x90]);
+        for (i, pc) in [0x1100, 0x1101, 0x1200].into_iter().enumerate() {
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum: i as u32 + 1,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        let listing = db.function_listing("main");
+        let addresses: Vec<_> = listing.iter().map(|(a, _)| *a).collect();
+        assert_eq!(addresses, vec![0x1100, 0x1101, 0x1106, 0x1107, 0x1108]);
+        assert_eq!(listing[1].1, "call helper");
+        assert_eq!(db.function_listing("helper").len(), 2);
+        let stub = db.function_listing("stub");
+        assert_eq!(stub.len(), 1);
+        assert_eq!((stub[0].0, stub[0].1.trim()), (0x1300, "ret"));
+        assert!(db.function_listing("missing").is_empty());
+
+        let first = db.first_executions(0x1100..0x1109);
+        assert_eq!(first.get(&0x1101), Some(&2));
+        assert_eq!(first.get(&0x1106), None);
+    }
+
+    #[test]
+    fn registers_are_kept_per_vcpu() {
+        let db = TraceDB::new(REG_COUNT);
+        // Two threads interleaved: vcpu 0 runs clnums 1 and 3, vcpu 1 runs 2
+        for (clnum, vcpu, rax) in [(1, 0, 0x10), (2, 1, 0x20), (3, 0, 0x11)] {
+            let mut regs = vec![0u64; REG_COUNT];
+            regs[0] = rax;
+            db.record_vcpu(clnum, vcpu);
+            db.update_registers_vcpu(clnum, vcpu, &regs);
+        }
+
+        assert_eq!(db.vcpu_at(2), 1);
+        assert_eq!(db.get_registers_at(2)[0], 0x20);
+        assert_eq!(db.get_registers_at(3)[0], 0x11);
+        assert_eq!(db.get_registers_at_vcpu(3, 1)[0], 0x20);
+        assert_eq!(db.get_registers_at_vcpu(3, 7), vec![0; REG_COUNT]);
+    }
+
+    #[test]
+    fn compares_executed_bytes_with_the_image() {
+        let db = TraceDB::new(REG_COUNT);
+        db.set_bias(0x1000);
+        // On disk: call 0x...; nop
+        db.load_static_memory(0x100, &[0xe8, 0x00, 0x00, 0x00, 0x00, 0x90]);
+        // Executed: the call was hooked to another target; then the nop as is;
+        // then code outside any image
+        let executed: [(u64, &[u8]); 3] = [
+            (0x1100, &[0xe8, 0xfb, 0x0e, 0x00, 0x00]),
+            (0x1105, &[0x90]),
+            (0x9000, &[0x90]),
+        ];
+        for (i, (pc, bytes)) in executed.into_iter().enumerate() {
+            let clnum = i as u32 + 1;
+            db.add_instruction(clnum, bytes.to_vec());
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        assert_eq!(
+            db.executed_vs_static(1),
+            Some((vec![0xe8, 0xfb, 0x0e, 0x00, 0x00], vec![0xe8, 0x00, 0x00, 0x00, 0x00]))
+        );
+        assert_eq!(db.executed_vs_static(2), Some((vec![0x90], vec![0x90])));
+        assert_eq!(db.executed_vs_static(3), None);
+
+        let patched: Vec<_> = db.get_trace_log(1, 3, TraceLogOptions::default()).entries.iter().map(|e| e.patched).collect();
+        assert_eq!(patched, vec![true, false, false]);
+    }
+
+    #[test]
+    fn reads_rax_at_the_return_of_a_call() {
+        let db = TraceDB::new(REG_COUNT);
+        let mut regs = vec![0u64; REG_COUNT];
+        let mut exec = |clnum: Clnum, pc: u64, bytes: &[u8], rax: u64| {
+            regs[0] = rax;
+            db.add_instruction(clnum, bytes.to_vec());
+            db.update_registers(clnum, &regs);
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        };
+        // call 0x2000; mov eax, 0x2a; ret; back at the return site
+        exec(1, 0x1000, &[0xe8, 0xfb, 0x0f, 0x00, 0x00], 7);
+        exec(2, 0x2000, &[0xb8, 0x2a, 0x00, 0x00, 0x00], 7);
+        exec(3, 0x2005, &[0xc3], 0x2a);
+        exec(4, 0x1005, &[0xe8, 0xf6, 0x0f, 0x00, 0x00], 0x2a);
+        // The second call (to 0x2000 again) never returns within the trace
+        exec(5, 0x2000, &[0x31, 0xc0], 0x2a);
+
+        assert_eq!(db.call_return_value(1), Some(0x2a));
+        // Not a call
+        assert_eq!(db.call_return_value(2), None);
+        assert_eq!(db.call_return_value(4), None);
+
+        let entries = db.get_trace_log(1, 5, TraceLogOptions::default()).entries;
+        let values: Vec<_> = entries.iter().map(|e| e.return_value).collect();
+        assert_eq!(values, [Some(0x2a), None, None, None, None]);
+    }
+
+    #[test]
+    fn evaluates_break_conditions_before_the_instruction() {
+        use crate::protocol::{BreakCondition, CompareOp, CondOperand};
+
+        let db = TraceDB::new(REG_COUNT);
+        let write = |clnum: Clnum, data: u64| {
+            db.add_change(Change {
+                address: 0x601040,
+                data,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 16,
+            });
+        };
+        write(1, 0x4142);
+        // Written by the instruction at clnum 2 itself, so not yet visible there
+        write(2, 0);
+
+        let mut regs = vec![0u64; REG_COUNT];
+        regs[5] = 0x10;
+        let rdi = |op, value| BreakCondition { operand: CondOperand::Reg { index: 5 }, op, value };
+        assert!(db.condition_holds(2, &regs, &rdi(CompareOp::Eq, 0x10)));
+        assert!(db.condition_holds(2, &regs, &rdi(CompareOp::Gt, 0xf)));
+        assert!(!db.condition_holds(2, &regs, &rdi(CompareOp::Ne, 0x10)));
+        // No such register
+        let bogus = BreakCondition { operand: CondOperand::Reg { index: 99 }, op: CompareOp::Eq, value: 0 };
+        assert!(!db.condition_holds(2, &regs, &bogus));
+
+        let mem = |size, value| BreakCondition {
+            operand: CondOperand::Mem { address: 0x601040, size },
+            op: CompareOp::Eq,
+            value,
+        };
+        assert!(db.condition_holds(2, &regs, &mem(2, 0x4142)));
+        assert!(db.condition_holds(2, &regs, &mem(1, 0x42)));
+        assert!(db.condition_holds(3, &regs, &mem(2, 0)));
+    }
+
+    #[test]
+    fn collapses_calls_into_libraries() {
+        let db = TraceDB::new(REG_COUNT);
+        // User code is 0x1000-0x2000, the library lives at 0x7000
+        db.set_user_ranges(vec![(0x1000, 0x2000)]);
+        db.add_symbol(0x1020, 0x10, "puts@plt".to_string());
+        let insns: [(u64, &[u8]); 14] = [
+            (0x1000, &[0xe8, 0xfb, 0x5f, 0x00, 0x00]), // call 0x7000
+            (0x7000, &[0x55]),                         // push rbp
+            (0x7001, &[0xe8, 0x0a, 0x00, 0x00, 0x00]), // call 0x7010, nested
+            (0x7010, &[0xc3]),
+            (0x7006, &[0x5d]),                         // pop rbp
+            (0x7007, &[0xc3]),
+            (0x1005, &[0xe8, 0x26, 0x00, 0x00, 0x00]), // call 0x1030, user code
+            (0x1030, &[0xc3]),
+            (0x100a, &[0xe8, 0x11, 0x00, 0x00, 0x00]), // call puts@plt
+            (0x1020, &[0xe9, 0xe2, 0x5f, 0x00, 0x00]), // jmp 0x7007
+            (0x7007, &[0xc3]),
+            (0x100f, &[0x90]),
+            (0x1010, &[0xe8, 0xeb, 0x5f, 0x00, 0x00]), // call 0x7000, never returns
+            (0x7000, &[0x55]),
+        ];
+        for (i, &(pc, bytes)) in insns.iter().enumerate() {
+            let clnum = i as Clnum + 1;
+            db.add_instruction(clnum, bytes.to_vec());
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        let page = db.get_trace_log(1, 20, TraceLogOptions { collapse_library_calls: true, ..Default::default() });
+        let entries: Vec<_> = page.entries.iter().map(|e| (e.clnum, e.skipped_insns)).collect();
+        assert_eq!(entries, [(1, 5), (7, 0), (8, 0), (9, 2), (12, 0), (13, 1)]);
+        assert_eq!(page.next_start, None);
+        // A page ends at the call and the next one starts at its return
+        let page = db.get_trace_log(1, 1, TraceLogOptions { collapse_library_calls: true, ..Default::default() });
+        assert_eq!(page.next_start, Some(7));
+
+        assert_eq!(db.get_trace_log(1, 20, TraceLogOptions::default()).entries.len(), 14);
+    }
+
+    #[test]
+    fn folds_runs_of_the_same_address() {
+        let db = TraceDB::new(REG_COUNT);
+        let insns: [(u64, &[u8]); 6] = [
+            (0x1000, &[0xf3, 0xaa]), // rep stosb, one clnum per iteration
+            (0x1000, &[0xf3, 0xaa]),
+            (0x1000, &[0xf3, 0xaa]),
+            (0x1002, &[0x90]),
+            (0x1003, &[0x90]),
+            (0x1003, &[0x90]),
+        ];
+        for (i, &(pc, bytes)) in insns.iter().enumerate() {
+            let clnum = i as Clnum + 1;
+            db.add_instruction(clnum, bytes.to_vec());
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        let page = db.get_trace_log(1, 20, TraceLogOptions { fold: true, ..Default::default() });
+        let entries: Vec<_> = page.entries.iter().map(|e| (e.clnum, e.repeats, e.last_repeat)).collect();
+        assert_eq!(entries, [(1, 2, Some(3)), (4, 0, None), (5, 1, Some(6))]);
+        // A full page still takes in the rest of its last run
+        let page = db.get_trace_log(1, 1, TraceLogOptions { fold: true, ..Default::default() });
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.next_start, Some(4));
+
+        assert_eq!(db.get_trace_log(1, 20, TraceLogOptions::default()).entries.len(), 6);
+    }
+
+    #[test]
+    fn coverage_counts_executions_per_symbol() {
+        let db = TraceDB::new(REG_COUNT);
+        db.set_bias(0x1000);
+        db.add_symbol(0x100, 0x20, "main".to_string());
+        db.add_symbol(0x200, 0x10, "unused".to_string());
+        // PE-style export without a size
+        db.add_symbol(0x300, 0, "Export".to_string());
+        for (clnum, pc) in [(1, 0x1100), (2, 0x1104), (3, 0x1300), (4, 0x1100), (5, 0x1301)] {
+            db.add_change(Change {
+                address: pc,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        assert_eq!(
+            db.coverage(),
+            vec![
+                ("main".to_string(), true, 3),
+                ("unused".to_string(), false, 0),
+                ("Export".to_string(), true, 1),
+            ]
+        );
+        let stats = db.execution_counts();
+        assert_eq!((stats[0].address, stats[0].size, stats[0].first_clnum), (0x100, 0x20, Some(1)));
+        assert_eq!(stats[1].first_clnum, None);
+        assert_eq!(stats[2].first_clnum, Some(3));
+    }
+
+    #[test]
+    fn execution_index_matches_a_scan_of_the_log() {
+        let db = TraceDB::with_window(REG_COUNT, Some(64));
+        let mut log = Vec::new();
+        // An outer loop over 0x1000..0x1010 with an inner loop at 0x1008..0x100c
+        let mut clnum = 1;
+        for _ in 0..20 {
+            let pcs = (0x1000..0x1008).chain((0..3).flat_map(|_| 0x1008..0x100c)).chain(0x100c..0x1010);
+            for pc in pcs {
+                let change = Change {
+                    address: pc,
+                    data: 0,
+                    clnum,
+                    flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+                };
+                db.add_change(change);
+                log.push(change);
+                clnum += 1;
+            }
+        }
+
+        // Only the clnums still in the log after pruning
+        let kept = db.first_retained_clnum();
+        assert!(kept > 1, "the window should have pruned the start of the trace");
+        let scan = |address: Address| -> Vec<Clnum> {
+            log.iter().filter(|c| c.address == address && c.clnum >= kept).map(|c| c.clnum).collect()
+        };
+        for pc in 0x1000..0x1010 {
+            let expected = scan(pc);
+            assert_eq!(db.executions_of(pc), expected, "pc {:#x}", pc);
+            assert_eq!(db.execution_count(pc), expected.len());
+            assert_eq!(db.first_execution_at(pc), expected.first().copied());
+        }
+        let first = db.first_executions(0x1008..0x100c);
+        assert_eq!(first.len(), 4);
+        assert!((0x1008..0x100c).all(|pc| first.get(&pc).copied() == scan(pc).first().copied()));
+        assert_eq!(db.execution_count(0x2000), 0);
+    }
+
+    #[test]
+    fn register_timeline_lists_the_writers() {
+        let db = crate::testing::counted_loop(3).build();
+        let rcx = REG_NAMES.iter().position(|&r| r == "rcx").unwrap();
+        // mov ecx, 3 and each dec ecx
+        assert_eq!(db.register_timeline(rcx, 0..=8, 100), vec![(1, 1), (2, 1), (4, 1), (6, 1)]);
+        assert_eq!(db.register_timeline(rcx, 2..=4, 100), vec![(2, 1), (4, 1)]);
+        assert!(db.register_timeline(REG_RSP, 0..=8, 100).is_empty());
+        // Two slices of clnums 0..=4 and 5..=8
+        assert_eq!(db.register_timeline(rcx, 0..=8, 2), vec![(1, 3), (6, 1)]);
+        assert!(db.register_timeline(rcx, 0..=8, 0).is_empty());
+
+        assert_eq!(db.register_change_near(rcx, 3, true), Some(4));
+        assert_eq!(db.register_change_near(rcx, 4, true), Some(6));
+        assert_eq!(db.register_change_near(rcx, 6, true), None);
+        assert_eq!(db.register_change_near(rcx, 5, false), Some(4));
+        assert_eq!(db.register_change_near(rcx, 2, false), Some(1));
+        assert_eq!(db.register_change_near(rcx, 1, false), None);
+    }
+
+    #[test]
+    fn memory_history_limit_keeps_the_last_writes() {
+        let db = TraceDB::new(REG_COUNT);
+        db.load_static_memory(0x5000, &[0xaa]);
+        db.set_memory_history_limit(Some(8));
+        for clnum in 1..=20 {
+            db.add_change(Change {
+                address: 0x5000,
+                data: clnum as u64,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 8,
+            });
+        }
+
+        // Trimmed back to the last 8 each time a byte reaches 10
+        let writes = db.get_memory_writes(0x5000);
+        assert_eq!(writes, (13..=20).collect::<Vec<_>>());
+        assert_eq!(db.get_memory_at(20, 0x5000, 1), vec![20]);
+        assert_eq!(db.get_memory_at(13, 0x5000, 1), vec![13]);
+        assert_eq!(db.write_origin(13, 0x5000), WriteOrigin::Written { clnum: 13 });
+        // Before the kept writes the value is unknown, not the static one
+        assert_eq!(db.get_memory_at(12, 0x5000, 1), vec![0]);
+        assert_eq!(db.write_origin(12, 0x5000), WriteOrigin::Truncated { before: 13 });
+        // The per-address index of writes is capped the same way
+        assert_eq!(db.access_index.get(&(0x5000, b'W')).unwrap().clone(), (13..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn block_iterations_show_loop_counters() {
+        let db = TraceDB::new(REG_COUNT);
+        let start = |clnum, address| Change {
+            address,
+            data: 0,
+            clnum,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        };
+        // loop: add rsi, 8; dec rcx; jnz loop
+        let mut regs = vec![0u64; REG_COUNT];
+        regs[2] = 3;
+        regs[4] = 0x1000;
+        let mut clnum = 1;
+        for _ in 0..3 {
+            db.update_registers(clnum, &regs);
+            for pc in [0x401000, 0x401004, 0x401007] {
+                db.add_change(start(clnum, pc));
+                clnum += 1;
+            }
+            regs[2] -= 1;
+            regs[4] += 8;
+        }
+
+        assert_eq!(
+            db.block_iteration_states(0x401000, &[2, 4]),
+            vec![(1, vec![3, 0x1000]), (4, vec![2, 0x1008]), (7, vec![1, 0x1010])]
+        );
+        assert!(db.block_iteration_states(0x402000, &[2]).is_empty());
+    }
+
+    #[test]
+    fn rewritten_code_disassembles_per_clnum() {
+        let db = TraceDB::new(REG_COUNT);
+        // push rbp; ret
+        db.load_static_memory(0x1000, &[0x55, 0xc3]);
+        let start = |clnum| Change {
+            address: 0x1000,
+            data: 0,
+            clnum,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        };
+        db.add_instruction(1, vec![0x55]);
+        db.add_change(start(1));
+        // The code at 0x1000 is rewritten to xor eax, eax before it runs again
+        db.invalidate_code(2, 0x1000, vec![0x31, 0xc0]);
+        db.add_instruction(2, vec![0x31, 0xc0]);
+        db.add_change(start(2));
+
+        assert_eq!(db.get_disassembly_at(1), "push rbp");
+        assert_eq!(db.get_disassembly_at(2), "xor eax, eax");
+        assert_eq!((db.code_version(0x1000, 1), db.code_version(0x1000, 2)), (0, 1));
+
+        // The lookahead shows whichever code is in place at the clnum
+        let before: Vec<_> = db.disassemble_range(1, 0x1000, 4).into_iter().map(|(a, _)| a).collect();
+        assert_eq!(before, [0x1000, 0x1001]);
+        let after = db.disassemble_range(2, 0x1000, 4);
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].1, "xor eax, eax");
+    }
+
+    #[test]
+    fn normalizes_immediates_and_displacements() {
+        // (text, immediate operands as (value, size), normalized text)
+        let cases: [(&str, &[(i64, u8)], &str); 13] = [
+            ("add rax, 0xffffffffffffffff", &[(-1, 8)], "add rax, -1"),
+            ("and rsp, 0xfffffffffffffff0", &[(-0x10, 8)], "and rsp, -0x10"),
+            // An 8-bit immediate sign-extended to a 64-bit operand
+            ("sub rsp, 0xffffffffffffff80", &[(-0x80, 8)], "sub rsp, -0x80"),
+            ("mov dword ptr [rbp - 4], 0xffffffff", &[(0xffffffff, 4)], "mov dword ptr [rbp - 0x4], -1"),
+            ("mov rax, qword ptr [rbp + 8]", &[], "mov rax, qword ptr [rbp + 0x8]"),
+            ("lea rdi, [rip + 0x2fe3]", &[], "lea rdi, [rip + 0x2fe3]"),
+            ("mov rax, 0x7fffffffffffffff", &[(i64::MAX, 8)], "mov rax, 0x7fffffffffffffff"),
+            ("mov eax, 0xdeadbeef", &[(0xdeadbeef, 4)], "mov eax, 0xdeadbeef"),
+            // Eight hex digits of a 64-bit operand are a positive number
+            ("mov rax, 0xffffffff", &[(0xffffffff, 8)], "mov rax, 0xffffffff"),
+            ("add ax, 0xfff0", &[(-0x10, 2)], "add ax, -0x10"),
+            ("mov ax, 0x8000", &[(0x8000, 2)], "mov ax, 0x8000"),
+            ("and al, 0xf0", &[(0xf0, 1)], "and al, 0xf0"),
+            ("call 0xfffffffffffffff0", &[], "call 0xfffffffffffffff0"),
+        ];
+        for (raw, immediates, normalized) in cases {
+            assert_eq!(TraceDB::normalize_operands(raw, immediates), normalized);
+        }
+    }
+
+    #[test]
+    fn normalization_is_part_of_the_disasm_cache_key() {
+        let db = TraceDB::new(REG_COUNT);
+        // and rsp, -0x10
+        let bytes = [0x48, 0x83, 0xe4, 0xf0];
+        assert_eq!(db.disassemble(0x401000, &bytes), "and rsp, -0x10");
+        // Signedness follows the operand size: mov eax, 0xffffffff and add ax, 0xfff0
+        assert_eq!(db.disassemble(0x401000, &[0xb8, 0xff, 0xff, 0xff, 0xff]), "mov eax, -1");
+        assert_eq!(db.disassemble(0x401000, &[0x66, 0x83, 0xc0, 0xf0]), "add ax, -0x10");
+        db.set_normalize_disasm(false);
+        assert_eq!(db.disassemble(0x401000, &bytes), "and rsp, 0xfffffffffffffff0");
+
+        // mov qword ptr [rbp - 8], rdi: the displacement becomes a stack variable
+        let bytes = [0x48, 0x89, 0x7d, 0xf8];
+        assert_eq!(db.disassemble(0x401004, &bytes), "mov qword ptr [rbp - 8], rdi");
+        db.set_normalize_disasm(true);
+        assert_eq!(db.disassemble(0x401004, &bytes), "mov qword ptr var_8, rdi");
+    }
+
+    #[test]
+    fn sign_extended_byte_immediates_are_negative() {
+        let db = TraceDB::new(REG_COUNT);
+        let cases: [(&[u8], &str); 4] = [
+            (&[0x48, 0x83, 0xe4, 0xf0], "and rsp, -0x10"),
+            (&[0x48, 0x83, 0xc4, 0xf8], "add rsp, -8"),
+            (&[0x48, 0x83, 0xf8, 0xff], "cmp rax, -1"),
+            (&[0x83, 0xe0, 0xf0], "and eax, -0x10"),
+        ];
+        for (bytes, text) in cases {
+            assert_eq!(db.disassemble(0x401000, bytes), text);
+        }
+    }
+
+    #[test]
+    fn detached_disassembly_leaves_the_session_alone() {
+        let db = TraceDB::new(REG_COUNT);
+        // push rbp; mov rbp, rsp; an invalid byte; then half of mov rax, [rbp - 8]
+        let bytes = [0x55, 0x48, 0x89, 0xe5, 0x06, 0x48, 0x8b];
+        let lines = db.disassemble_detached(0x1000, &bytes, DisasmSyntax::Att, DisasmOptions::raw()).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                (0x1000, "pushq %rbp".to_string()),
+                (0x1001, "movq %rsp, %rbp".to_string()),
+                (0x1004, ".byte 0x06".to_string()),
+                (0x1005, "(truncated: 2 bytes)".to_string()),
+            ]
+        );
+        assert_eq!(db.config().syntax, DisasmSyntax::Intel);
+        assert_eq!(db.cache_stats().entries, 0);
+        assert_eq!(db.disassemble(0x1001, &bytes[1..4]), "mov rbp, rsp");
+    }
+
+    #[test]
+    fn cfg_labels_skip_symbolization() {
+        let db = TraceDB::new(REG_COUNT);
+        // mov qword ptr [rbp - 8], rdi
+        db.add_instruction(1, vec![0x48, 0x89, 0x7d, 0xf8]);
+        db.add_change(Change {
+            address: 0x401004,
+            data: 0,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        });
+
+        assert_eq!(db.disassembly_at(1, DisasmView::Timeline), "mov qword ptr var_8, rdi");
+        assert_eq!(db.disassembly_at(1, DisasmView::Cfg), "mov qword ptr [rbp - 0x8], rdi");
+        // Both flavors stay cached side by side
+        assert_eq!(db.cache_stats().entries, 2);
+        db.configure(SessionConfig { symbolize_cfg: true, ..SessionConfig::default() }).unwrap();
+        assert_eq!(db.disassembly_at(1, DisasmView::Cfg), "mov qword ptr var_8, rdi");
+        assert_eq!(
+            db.disassemble_with(0x401004, &[0x48, 0x89, 0x7d, 0xf8], DisasmOptions::raw()),
+            "mov qword ptr [rbp - 8], rdi"
+        );
+    }
+
+    #[test]
+    fn annotates_rip_relative_targets() {
+        let db = TraceDB::new(REG_COUNT);
+        // mov rax, qword ptr [rip + 0x2ed5]: relative to the next instruction at 0x401007
+        let bytes = [0x48, 0x8b, 0x05, 0xd5, 0x2e, 0x00, 0x00];
+        assert_eq!(db.disassemble(0x401000, &bytes), "mov rax, qword ptr [rip + 0x2ed5] ; 0x403edc");
+
+        db.add_symbol(0x403ed8, 8, "global_var".to_string());
+        assert_eq!(
+            db.disassemble(0x401010, &bytes),
+            "mov rax, qword ptr [rip + 0x2ed5] ; 0x403eec"
+        );
+        assert_eq!(
+            db.disassemble(0x400ffc, &bytes),
+            "mov rax, qword ptr [rip + 0x2ed5] ; 0x403ed8 <global_var>"
+        );
+        assert_eq!(
+            db.disassemble_with(0x400ffc, &bytes, DisasmOptions::raw()),
+            "mov rax, qword ptr [rip + 0x2ed5]"
+        );
+    }
+
+    #[test]
+    fn formats_immediates_in_base_and_as_text() {
+        let db = TraceDB::new(REG_COUNT);
+        let configure = |immediate_base, ascii_immediates| {
+            db.configure(SessionConfig {
+                immediate_base,
+                ascii_immediates,
+                ..SessionConfig::default()
+            })
+            .unwrap()
+        };
+        // movabs rax, 0x6f6c6c6548
+        let hello = [0x48, 0xb8, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x00, 0x00];
+        // cmp al, 0x41
+        let cmp = [0x3c, 0x41];
+        // sub rsp, 0x28
+        let stack_adjust = [0x48, 0x83, 0xec, 0x28];
+        // mov eax, 0x64636261
+        let abcd = [0xb8, 0x61, 0x62, 0x63, 0x64];
+        // mov eax, 0x12345678
+        let binary = [0xb8, 0x78, 0x56, 0x34, 0x12];
+        // mov qword ptr [rbp - 0x18], rdi
+        let store = [0x48, 0x89, 0x7d, 0xe8];
+
+        configure(ImmediateBase::Hex, true);
+        assert_eq!(db.disassemble(0x1000, &hello), "movabs rax, 0x6f6c6c6548 ; \"Hello\"");
+        assert_eq!(db.disassemble(0x1000, &abcd), "mov eax, 0x64636261 ; \"abcd\"");
+        assert_eq!(db.disassemble(0x1000, &binary), "mov eax, 0x12345678");
+        // Too short to be text
+        assert_eq!(db.disassemble(0x1000, &cmp), "cmp al, 0x41");
+        assert_eq!(db.disassemble(0x1000, &stack_adjust), "sub rsp, 0x28");
+
+        configure(ImmediateBase::Decimal, false);
+        assert_eq!(db.disassemble(0x1000, &cmp), "cmp al, 65");
+        assert_eq!(db.disassemble(0x1000, &store), "mov qword ptr var_18, rdi");
+
+        configure(ImmediateBase::Both, false);
+        assert_eq!(db.disassemble(0x1000, &binary), "mov eax, 0x12345678 (305419896)");
+    }
+
+    #[test]
+    fn configure_switches_syntax_and_user_code() {
+        let db = TraceDB::new(REG_COUNT);
+        db.register_code_range(0x1000, 0x1000);
+        db.push_module(Module {
+            path: "/lib/x86_64-linux-gnu/libc.so.6".to_string(),
+            bias: 0x7fff_0000_0000,
+            start: 0x7fff_0000_0000,
+            end: 0x7fff_0010_0000,
+            symbols: BTreeMap::new(),
+        });
+        // mov rbp, rsp
+        let bytes = [0x48, 0x89, 0xe5];
+        assert_eq!(db.disassemble(0x1000, &bytes), "mov rbp, rsp");
+        assert!(db.is_user_code(0x1000));
+
+        let config = SessionConfig {
+            syntax: crate::protocol::DisasmSyntax::Att,
+            active_module: Some("libc.so.6".to_string()),
+            ..SessionConfig::default()
+        };
+        db.configure(config.clone()).unwrap();
+        assert_eq!(db.config(), config);
+        assert_eq!(db.disassemble(0x1000, &bytes), "movq %rsp, %rbp");
+        assert!(!db.is_user_code(0x1000));
+        assert!(db.is_user_code(0x7fff_0000_1000));
+
+        let unknown = SessionConfig {
+            active_module: Some("libm.so.6".to_string()),
+            ..SessionConfig::default()
+        };
+        assert_eq!(db.configure(unknown), Err(QueryError::UnknownModule("libm.so.6".to_string())));
+    }
+
+    #[test]
+    fn stepping_stays_within_the_traced_clnums() {
+        let db = TraceDB::new(REG_COUNT);
+        for clnum in 1..=3 {
+            // nop
+            db.add_instruction(clnum, vec![0x90]);
+            db.add_change(Change {
+                address: 0x401000 + clnum as u64,
+                data: 0,
+                clnum,
+                flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+            });
+        }
+
+        // Forward from the initial position lands on the first instruction
+        assert_eq!(clamp_clnum(1, 3), 1);
+        assert_eq!(db.step_over(0, 3), 1);
+        // Back from the first instruction stays there
+        assert_eq!(clamp_clnum(0, 3), 1);
+        // Forward from the last instruction stays there
+        assert_eq!(clamp_clnum(4, 3), 3);
+        assert_eq!(db.step_over(3, 3), 3);
+        // Nothing traced yet: 0 is the only position
+        assert_eq!(clamp_clnum(1, 0), 0);
+        assert_eq!(clamp_clnum(0, 0), 0);
     }
 }
@@ -0,0 +1,236 @@
+use crate::protocol::{DiffKind, SliceDiffLine, TraceEntry};
+
+/// Line-diffs two slices by disassembly text, so instructions shared by both
+/// runs line up even when their clnums differ. Uses Myers' algorithm with
+/// its linear-space refinement: time grows with the slices' length times the
+/// number of differing lines, and memory only with their length.
+pub fn diff_slices(a: &[TraceEntry], b: &[TraceEntry]) -> Vec<SliceDiffLine> {
+    let a_text: Vec<&str> = a.iter().map(|e| e.disassembly.as_str()).collect();
+    let b_text: Vec<&str> = b.iter().map(|e| e.disassembly.as_str()).collect();
+    let mut myers = Myers::new(&a_text, &b_text);
+    myers.diff(0, a.len(), 0, b.len());
+
+    myers
+        .steps
+        .into_iter()
+        .map(|step| match step {
+            Step::Common(i, j) => SliceDiffLine {
+                kind: DiffKind::Common,
+                clnum_a: Some(a[i].clnum),
+                clnum_b: Some(b[j].clnum),
+                disassembly: a[i].disassembly.clone(),
+            },
+            Step::Removed(i) => SliceDiffLine {
+                kind: DiffKind::Removed,
+                clnum_a: Some(a[i].clnum),
+                clnum_b: None,
+                disassembly: a[i].disassembly.clone(),
+            },
+            Step::Added(j) => SliceDiffLine {
+                kind: DiffKind::Added,
+                clnum_a: None,
+                clnum_b: Some(b[j].clnum),
+                disassembly: b[j].disassembly.clone(),
+            },
+        })
+        .collect()
+}
+
+// One line of the edit script, by index into a and b
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Common(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+struct Myers<'a> {
+    a: &'a [&'a str],
+    b: &'a [&'a str],
+    // Furthest x reached on each diagonal k = x - y, forwards from the start
+    // of a range and backwards from its end, offset so k can be negative.
+    // Sized for the whole input and reused by every subrange.
+    forward: Vec<usize>,
+    backward: Vec<usize>,
+    offset: isize,
+    steps: Vec<Step>,
+}
+
+impl<'a> Myers<'a> {
+    fn new(a: &'a [&'a str], b: &'a [&'a str]) -> Self {
+        let max_d = (a.len() + b.len()).div_ceil(2) + 1;
+        Self {
+            a,
+            b,
+            forward: vec![0; 2 * max_d + 1],
+            backward: vec![0; 2 * max_d + 1],
+            offset: max_d as isize,
+            steps: Vec::with_capacity(a.len().max(b.len())),
+        }
+    }
+
+    // Appends the edit script turning a[a_lo..a_hi] into b[b_lo..b_hi]
+    fn diff(&mut self, mut a_lo: usize, mut a_hi: usize, mut b_lo: usize, mut b_hi: usize) {
+        while a_lo < a_hi && b_lo < b_hi && self.a[a_lo] == self.b[b_lo] {
+            self.steps.push(Step::Common(a_lo, b_lo));
+            a_lo += 1;
+            b_lo += 1;
+        }
+        let suffix = (a_hi, b_hi);
+        while a_lo < a_hi && b_lo < b_hi && self.a[a_hi - 1] == self.b[b_hi - 1] {
+            a_hi -= 1;
+            b_hi -= 1;
+        }
+
+        if a_lo == a_hi {
+            self.steps.extend((b_lo..b_hi).map(Step::Added));
+        } else if b_lo == b_hi {
+            self.steps.extend((a_lo..a_hi).map(Step::Removed));
+        } else {
+            let (x, y) = self.middle_snake(a_lo, a_hi, b_lo, b_hi);
+            self.diff(a_lo, x, b_lo, y);
+            self.diff(x, a_hi, y, b_hi);
+        }
+
+        self.steps
+            .extend((a_hi..suffix.0).zip(b_hi..suffix.1).map(|(i, j)| Step::Common(i, j)));
+    }
+
+    // A point (x, y) on an optimal path through the two ranges, found where
+    // the searches from either end first meet. Both ranges are non-empty and
+    // differ in their first and last lines, so it's never a corner.
+    fn middle_snake(&mut self, a_lo: usize, a_hi: usize, b_lo: usize, b_hi: usize) -> (usize, usize) {
+        let (n, m) = ((a_hi - a_lo) as isize, (b_hi - b_lo) as isize);
+        let delta = n - m;
+        let odd = delta % 2 != 0;
+        let o = self.offset;
+        let at = |k: isize| (o + k) as usize;
+        self.forward[at(1)] = 0;
+        self.backward[at(1)] = 0;
+
+        for d in 0..=(n + m + 1) / 2 {
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && self.forward[at(k - 1)] < self.forward[at(k + 1)]) {
+                    self.forward[at(k + 1)] as isize
+                } else {
+                    self.forward[at(k - 1)] as isize + 1
+                };
+                let (x0, y0) = (x, x - k);
+                let mut y = y0;
+                while x < n && y < m && self.a[a_lo + x as usize] == self.b[b_lo + y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                self.forward[at(k)] = x as usize;
+                if odd && (k - delta).abs() < d && x + self.backward[at(delta - k)] as isize >= n {
+                    return (a_lo + x0 as usize, b_lo + y0 as usize);
+                }
+            }
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && self.backward[at(k - 1)] < self.backward[at(k + 1)]) {
+                    self.backward[at(k + 1)] as isize
+                } else {
+                    self.backward[at(k - 1)] as isize + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && self.a[a_hi - 1 - x as usize] == self.b[b_hi - 1 - y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                self.backward[at(k)] = x as usize;
+                if !odd && (k - delta).abs() <= d && x + self.forward[at(delta - k)] as isize >= n {
+                    return (a_hi - x as usize, b_hi - y as usize);
+                }
+            }
+        }
+        unreachable!("the searches from either end always meet")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slice(first_clnum: u32, lines: &[&str]) -> Vec<TraceEntry> {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, text)| TraceEntry {
+                clnum: first_clnum + i as u32,
+                address: 0x401000 + i as u64,
+                disassembly: text.to_string(),
+                reg_diffs: Vec::new(),
+                mem_accesses: Vec::new(),
+                return_value: None,
+                patched: false,
+                async_entry: false,
+                groups: Vec::new(),
+                skipped_insns: 0,
+                repeats: 0,
+                last_repeat: None,
+                reg_defs: Vec::new(),
+            })
+            .collect()
+    }
+
+    // Each line as its kind's marker and text, like a unified diff
+    fn render(lines: &[SliceDiffLine]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                let marker = match line.kind {
+                    DiffKind::Common => ' ',
+                    DiffKind::Removed => '-',
+                    DiffKind::Added => '+',
+                };
+                format!("{}{}", marker, line.disassembly)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aligns_an_insertion() {
+        let a = slice(1, &["mov eax, 1", "add eax, ebx", "ret"]);
+        let b = slice(10, &["mov eax, 1", "xor ecx, ecx", "add eax, ebx", "ret"]);
+        let lines = diff_slices(&a, &b);
+        assert_eq!(render(&lines), [" mov eax, 1", "+xor ecx, ecx", " add eax, ebx", " ret"]);
+        // Common lines keep the clnums of both runs
+        assert_eq!((lines[2].clnum_a, lines[2].clnum_b), (Some(2), Some(12)));
+        assert_eq!((lines[1].clnum_a, lines[1].clnum_b), (None, Some(11)));
+    }
+
+    #[test]
+    fn aligns_a_deletion() {
+        let a = slice(1, &["push rbp", "mov rbp, rsp", "call 0x401200", "pop rbp", "ret"]);
+        let b = slice(1, &["push rbp", "mov rbp, rsp", "pop rbp", "ret"]);
+        let lines = diff_slices(&a, &b);
+        assert_eq!(render(&lines), [" push rbp", " mov rbp, rsp", "-call 0x401200", " pop rbp", " ret"]);
+        assert_eq!((lines[2].clnum_a, lines[2].clnum_b), (Some(3), None));
+    }
+
+    #[test]
+    fn aligns_changed_lines_between_common_ones() {
+        let a = slice(1, &["mov eax, 1", "cmp eax, 2", "jne 0x401010", "ret"]);
+        let b = slice(1, &["mov eax, 1", "cmp eax, 3", "je 0x401010", "ret"]);
+        assert_eq!(
+            render(&diff_slices(&a, &b)),
+            [" mov eax, 1", "-cmp eax, 2", "-jne 0x401010", "+cmp eax, 3", "+je 0x401010", " ret"]
+        );
+    }
+
+    #[test]
+    fn finds_a_shortest_script_for_interleaved_edits() {
+        // The classic example from Myers' paper: ABCABBA -> CBABAC, 5 edits
+        let a = slice(1, &["a", "b", "c", "a", "b", "b", "a"]);
+        let b = slice(1, &["c", "b", "a", "b", "a", "c"]);
+        let lines = diff_slices(&a, &b);
+        assert_eq!(lines.iter().filter(|l| l.kind != DiffKind::Common).count(), 5);
+        // Both sides come out complete and in order
+        let side = |kind| lines.iter().filter(move |l| l.kind != kind).map(|l| l.disassembly.as_str());
+        assert!(side(DiffKind::Added).eq(["a", "b", "c", "a", "b", "b", "a"]));
+        assert!(side(DiffKind::Removed).eq(["c", "b", "a", "b", "a", "c"]));
+
+        assert!(diff_slices(&[], &[]).is_empty());
+        assert_eq!(render(&diff_slices(&a[..1], &[])), ["-a"]);
+    }
+}
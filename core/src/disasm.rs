@@ -1,9 +1,101 @@
+use crate::il::{Constant, Expression, Operation, Scalar};
+use crate::protocol::DisasmSyntax;
 use crate::Address;
 use anyhow::{anyhow, Result};
 use capstone::prelude::*;
 
+#[cfg(feature = "iced")]
+mod iced;
+
+pub use crate::protocol::{InsnDetail, InsnGroup, OperandDetail};
+
+// Longest possible x86 instruction
+const MAX_INSN_LEN: usize = 15;
+
+/// Error for bytes that end before the instruction they start does, e.g.
+/// captured bytes cut short or code read up to an unknown page. Unlike bytes
+/// that don't decode at all, more bytes can fix this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncated {
+    pub address: Address,
+    // Bytes that were available
+    pub available: usize,
+}
+
+impl std::fmt::Display for Truncated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "only {} bytes of the instruction at {:#x}", self.available, self.address)
+    }
+}
+
+impl std::error::Error for Truncated {}
+
+/// Whether a decode error means the bytes ran out (see `Truncated`).
+pub fn is_truncated(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<Truncated>().is_some()
+}
+
+// For `bytes` that decode to nothing: Some if padding them out makes an
+// instruction longer than they are, i.e. they are the start of one
+fn truncation(cs: &Capstone, bytes: &[u8], address: Address) -> Option<Truncated> {
+    if bytes.len() >= MAX_INSN_LEN {
+        return None;
+    }
+    let mut padded = bytes.to_vec();
+    padded.resize(MAX_INSN_LEN, 0);
+    let insns = cs.disasm_count(&padded, address, 1).ok()?;
+    (insns.first()?.len() > bytes.len()).then_some(Truncated { address, available: bytes.len() })
+}
+
+// The error for `bytes` that decode to nothing
+fn undecoded(cs: &Capstone, bytes: &[u8], address: Address) -> anyhow::Error {
+    match truncation(cs, bytes, address) {
+        Some(truncated) => truncated.into(),
+        None => anyhow!("No instruction decoded at {:x}", address),
+    }
+}
+
+/// Decoder used for instruction text and register-use queries. Operand
+/// detail (`Disassembler::detail`) and IL lifting always go through Capstone.
+pub trait Backend: Send {
+    fn disassemble(&self, bytes: &[u8], address: Address) -> Result<String>;
+    /// Decodes the first instruction in `bytes`, returning its length and
+    /// text, or None if no instruction decodes there.
+    fn disassemble_one(&self, bytes: &[u8], address: Address) -> Option<(usize, String)>;
+    /// Indices (see `REG_NAMES`) of the general purpose registers the first
+    /// instruction in `bytes` reads, including memory base/index registers.
+    fn get_read_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<usize>>;
+    /// The general purpose register parts the first instruction in `bytes`
+    /// writes, explicitly or implicitly (`mov al, 1` writes al, not rax).
+    fn get_written_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<RegPart>>;
+    fn set_syntax(&mut self, syntax: DisasmSyntax) -> Result<()>;
+}
+
+/// Selects the `Backend` a `Disassembler` is built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    #[default]
+    Capstone,
+    // Requires the `iced` feature
+    Iced,
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "capstone" => Ok(BackendKind::Capstone),
+            "iced" => Ok(BackendKind::Iced),
+            _ => Err(anyhow!("unknown disassembler backend '{}' (capstone, iced)", s)),
+        }
+    }
+}
+
 pub struct Disassembler {
+    // Detail-enabled instance for operand queries and lifting
     cs: Capstone,
+    backend: Box<dyn Backend>,
 }
 
 // Capstone is not thread-safe, so we cannot implement Sync for it.
@@ -12,22 +104,46 @@ pub struct Disassembler {
 // For TraceDB which is Send+Sync, we might need to wrap Disassembler in a Mutex.
 unsafe impl Send for Disassembler {}
 
-impl Disassembler {
+fn build_capstone(detail: bool) -> Result<Capstone> {
+    Capstone::new()
+        .x86()
+        .mode(arch::x86::ArchMode::Mode64)
+        .syntax(arch::x86::ArchSyntax::Intel)
+        .detail(detail)
+        .build()
+        .map_err(|e| anyhow!("Failed to initialize Capstone: {}", e))
+}
+
+/// The default `Backend`.
+pub struct CapstoneBackend {
+    // Detail-less instance for plain text disassembly, which is much cheaper
+    // when rendering long stretches of the timeline
+    fast: Capstone,
+    // Detail-enabled instance for register queries
+    cs: Capstone,
+}
+
+// See the note on Disassembler
+unsafe impl Send for CapstoneBackend {}
+
+impl CapstoneBackend {
     pub fn new() -> Result<Self> {
-        let cs = Capstone::new()
-            .x86()
-            .mode(arch::x86::ArchMode::Mode64)
-            .syntax(arch::x86::ArchSyntax::Intel)
-            .detail(true)
-            .build()
-            .map_err(|e| anyhow!("Failed to initialize Capstone: {}", e))?;
+        Ok(Self {
+            fast: build_capstone(false)?,
+            cs: build_capstone(true)?,
+        })
+    }
 
-        Ok(Self { cs })
+    // The general purpose register part behind a Capstone register id
+    fn reg_part(&self, reg: RegId) -> Option<RegPart> {
+        RegPart::from_name(&self.cs.reg_name(reg)?)
     }
+}
 
-    pub fn disassemble(&self, bytes: &[u8], address: Address) -> Result<String> {
+impl Backend for CapstoneBackend {
+    fn disassemble(&self, bytes: &[u8], address: Address) -> Result<String> {
         let insns = self
-            .cs
+            .fast
             .disasm_all(bytes, address)
             .map_err(|e| anyhow!("Disassembly failed: {}", e))?;
 
@@ -35,12 +151,21 @@ impl Disassembler {
             let mnemonic = insn.mnemonic().unwrap_or("???");
             let op_str = insn.op_str().unwrap_or("");
             Ok(format!("{} {}", mnemonic, op_str))
+        } else if let Some(truncated) = truncation(&self.fast, bytes, address) {
+            Err(truncated.into())
         } else {
             Ok(String::from("???"))
         }
     }
 
-    pub fn get_read_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<usize>> {
+    fn disassemble_one(&self, bytes: &[u8], address: Address) -> Option<(usize, String)> {
+        let insns = self.fast.disasm_count(bytes, address, 1).ok()?;
+        let insn = insns.first()?;
+        let text = format!("{} {}", insn.mnemonic().unwrap_or("???"), insn.op_str().unwrap_or(""));
+        Some((insn.len(), text))
+    }
+
+    fn get_read_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<usize>> {
         let insns = self
             .cs
             .disasm_all(bytes, address)
@@ -49,57 +174,717 @@ impl Disassembler {
         if let Some(insn) = insns.first() {
              let details = self.cs.insn_detail(insn)
                 .map_err(|e| anyhow!("Failed to get details: {}", e))?;
-             let regs = details.regs_read();
-             let mut read_regs = Vec::new();
-             
-             for r in regs {
-                 if let Some(idx) = map_capstone_reg(r.0) {
-                     read_regs.push(idx);
-                 }
-             }
-             
-             // Also check explicit operands for memory base/index
+             let mut regs: Vec<RegId> = details.regs_read().to_vec();
+
+             // Also check explicit operands: registers read, memory base/index
              let arch_detail = details.arch_detail();
              if let capstone::arch::ArchDetail::X86Detail(x86) = arch_detail {
                  for op in x86.operands() {
                      match op.op_type {
+                         capstone::arch::x86::X86OperandType::Reg(r)
+                             if op.access.is_some_and(|a| a.is_readable()) =>
+                         {
+                             regs.push(r);
+                         }
                          capstone::arch::x86::X86OperandType::Mem(m) => {
-                             if let Some(idx) = map_capstone_reg(m.base().0) { read_regs.push(idx); }
-                             if let Some(idx) = map_capstone_reg(m.index().0) { read_regs.push(idx); }
-                         },
+                             regs.push(m.base());
+                             regs.push(m.index());
+                         }
                          _ => {}
                      }
                  }
              }
 
+             let mut read_regs: Vec<usize> = regs
+                 .into_iter()
+                 .filter_map(|r| self.reg_part(r))
+                 .map(|part| part.index)
+                 .collect();
+
              // Dedup
              read_regs.sort();
              read_regs.dedup();
              Ok(read_regs)
+        } else if let Some(truncated) = truncation(&self.cs, bytes, address) {
+            Err(truncated.into())
         } else {
             Ok(Vec::new())
         }
     }
+
+    fn get_written_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<RegPart>> {
+        let insns = self
+            .cs
+            .disasm_all(bytes, address)
+            .map_err(|e| anyhow!("Disassembly failed: {}", e))?;
+        let Some(insn) = insns.first() else {
+            return match truncation(&self.cs, bytes, address) {
+                Some(truncated) => Err(truncated.into()),
+                None => Ok(Vec::new()),
+            };
+        };
+        let details = self
+            .cs
+            .insn_detail(insn)
+            .map_err(|e| anyhow!("Failed to get details: {}", e))?;
+
+        let mut regs: Vec<RegId> = details.regs_write().to_vec();
+        if let capstone::arch::ArchDetail::X86Detail(x86) = details.arch_detail() {
+            for op in x86.operands() {
+                if let capstone::arch::x86::X86OperandType::Reg(r) = op.op_type {
+                    if op.access.is_some_and(|a| a.is_writable()) {
+                        regs.push(r);
+                    }
+                }
+            }
+        }
+
+        let mut written: Vec<RegPart> = regs.into_iter().filter_map(|r| self.reg_part(r)).collect();
+        written.sort();
+        written.dedup();
+        Ok(written)
+    }
+
+    fn set_syntax(&mut self, syntax: DisasmSyntax) -> Result<()> {
+        let syntax = match syntax {
+            DisasmSyntax::Intel => capstone::Syntax::Intel,
+            DisasmSyntax::Att => capstone::Syntax::Att,
+        };
+        self.fast
+            .set_syntax(syntax)
+            .map_err(|e| anyhow!("Failed to set syntax: {}", e))
+    }
 }
 
-fn map_capstone_reg(reg: u16) -> Option<usize> {
-    use capstone::arch::x86::X86Reg::*;
-    // Basic 64-bit mapping
-    if reg == X86_REG_RAX as u16 || reg == X86_REG_EAX as u16 { return Some(0); }
-    if reg == X86_REG_RBX as u16 || reg == X86_REG_EBX as u16 { return Some(1); }
-    if reg == X86_REG_RCX as u16 || reg == X86_REG_ECX as u16 { return Some(2); }
-    if reg == X86_REG_RDX as u16 || reg == X86_REG_EDX as u16 { return Some(3); }
-    if reg == X86_REG_RSI as u16 || reg == X86_REG_ESI as u16 { return Some(4); }
-    if reg == X86_REG_RDI as u16 || reg == X86_REG_EDI as u16 { return Some(5); }
-    if reg == X86_REG_RBP as u16 || reg == X86_REG_EBP as u16 { return Some(6); }
-    if reg == X86_REG_RSP as u16 || reg == X86_REG_ESP as u16 { return Some(7); }
-    if reg == X86_REG_R8 as u16 || reg == X86_REG_R8D as u16 { return Some(8); }
-    if reg == X86_REG_R9 as u16 || reg == X86_REG_R9D as u16 { return Some(9); }
-    if reg == X86_REG_R10 as u16 || reg == X86_REG_R10D as u16 { return Some(10); }
-    if reg == X86_REG_R11 as u16 || reg == X86_REG_R11D as u16 { return Some(11); }
-    if reg == X86_REG_R12 as u16 || reg == X86_REG_R12D as u16 { return Some(12); }
-    if reg == X86_REG_R13 as u16 || reg == X86_REG_R13D as u16 { return Some(13); }
-    if reg == X86_REG_R14 as u16 || reg == X86_REG_R14D as u16 { return Some(14); }
-    if reg == X86_REG_R15 as u16 || reg == X86_REG_R15D as u16 { return Some(15); }
-    None
+impl Disassembler {
+    pub fn new() -> Result<Self> {
+        Self::with_backend(BackendKind::Capstone)
+    }
+
+    /// A disassembler whose text and register queries use `kind`. Fails for
+    /// `BackendKind::Iced` unless built with the `iced` feature.
+    pub fn with_backend(kind: BackendKind) -> Result<Self> {
+        let backend: Box<dyn Backend> = match kind {
+            BackendKind::Capstone => Box::new(CapstoneBackend::new()?),
+            #[cfg(feature = "iced")]
+            BackendKind::Iced => Box::new(iced::IcedBackend::new()),
+            #[cfg(not(feature = "iced"))]
+            BackendKind::Iced => return Err(anyhow!("built without the iced feature")),
+        };
+        Ok(Self {
+            cs: build_capstone(true)?,
+            backend,
+        })
+    }
+
+    /// Switches the syntax of `disassemble`/`disassemble_one` text. Operand
+    /// detail and lifting always use Intel syntax.
+    pub fn set_syntax(&mut self, syntax: DisasmSyntax) -> Result<()> {
+        self.backend.set_syntax(syntax)
+    }
+
+    pub fn disassemble(&self, bytes: &[u8], address: Address) -> Result<String> {
+        self.backend.disassemble(bytes, address)
+    }
+
+    /// Decodes the first instruction in `bytes`, returning its length and
+    /// text, or None if no instruction decodes there.
+    pub fn disassemble_one(&self, bytes: &[u8], address: Address) -> Option<(usize, String)> {
+        self.backend.disassemble_one(bytes, address)
+    }
+
+    pub fn get_read_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<usize>> {
+        self.backend.get_read_registers(bytes, address)
+    }
+
+    pub fn get_written_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<RegPart>> {
+        self.backend.get_written_registers(bytes, address)
+    }
+
+    /// Length of the first instruction in `bytes`. Fails with `Truncated` if
+    /// `bytes` end before it does.
+    pub fn length(&self, bytes: &[u8], address: Address) -> Result<usize> {
+        let insns = self
+            .cs
+            .disasm_count(bytes, address, 1)
+            .map_err(|e| anyhow!("Disassembly failed: {}", e))?;
+        match insns.first() {
+            Some(insn) => Ok(insn.len()),
+            None => Err(undecoded(&self.cs, bytes, address)),
+        }
+    }
+
+    /// Decodes the first instruction in `bytes` into an `InsnDetail`.
+    /// If Capstone has no detail for it, the operand lists are left empty.
+    pub fn detail(&self, bytes: &[u8], address: Address) -> Result<InsnDetail> {
+        let insns = self
+            .cs
+            .disasm_all(bytes, address)
+            .map_err(|e| anyhow!("Disassembly failed: {}", e))?;
+        let insn = insns
+            .first()
+            .ok_or_else(|| undecoded(&self.cs, bytes, address))?;
+
+        let mut detail = InsnDetail {
+            address: insn.address(),
+            size: insn.len(),
+            mnemonic: insn.mnemonic().unwrap_or("???").to_string(),
+            op_str: insn.op_str().unwrap_or("").to_string(),
+            groups: Vec::new(),
+            regs_read: Vec::new(),
+            regs_write: Vec::new(),
+            operands: Vec::new(),
+        };
+
+        let details = match self.cs.insn_detail(insn) {
+            Ok(d) => d,
+            Err(_) => return Ok(detail),
+        };
+
+        let reg_name = |r: RegId| {
+            if r.0 == 0 {
+                None
+            } else {
+                self.cs.reg_name(r)
+            }
+        };
+
+        detail.groups = details
+            .groups()
+            .iter()
+            .filter_map(|g| self.cs.group_name(*g))
+            .collect();
+        detail.regs_read = details.regs_read().iter().filter_map(|r| reg_name(*r)).collect();
+        detail.regs_write = details.regs_write().iter().filter_map(|r| reg_name(*r)).collect();
+
+        if let capstone::arch::ArchDetail::X86Detail(x86) = details.arch_detail() {
+            for op in x86.operands() {
+                let operand = match op.op_type {
+                    capstone::arch::x86::X86OperandType::Reg(r) => OperandDetail::Reg {
+                        name: reg_name(r).unwrap_or_default(),
+                        size: op.size,
+                    },
+                    capstone::arch::x86::X86OperandType::Imm(value) => OperandDetail::Imm {
+                        value,
+                        size: op.size,
+                    },
+                    capstone::arch::x86::X86OperandType::Mem(m) => OperandDetail::Mem {
+                        segment: reg_name(m.segment()),
+                        base: reg_name(m.base()),
+                        index: reg_name(m.index()),
+                        scale: m.scale(),
+                        disp: m.disp(),
+                        size: op.size,
+                    },
+                    capstone::arch::x86::X86OperandType::Invalid => OperandDetail::Invalid,
+                };
+                detail.operands.push(operand);
+            }
+        }
+
+        Ok(detail)
+    }
+
+    /// Categories of the first instruction in `bytes`, from Capstone's
+    /// instruction groups (and its id, for syscalls and integer arithmetic,
+    /// which have no group). Sorted and without duplicates.
+    pub fn groups(&self, bytes: &[u8], address: Address) -> Result<Vec<InsnGroup>> {
+        use capstone::arch::x86::X86Insn;
+
+        let insns = self
+            .cs
+            .disasm_all(bytes, address)
+            .map_err(|e| anyhow!("Disassembly failed: {}", e))?;
+        let insn = insns
+            .first()
+            .ok_or_else(|| undecoded(&self.cs, bytes, address))?;
+        let details = self
+            .cs
+            .insn_detail(insn)
+            .map_err(|e| anyhow!("Failed to get details: {}", e))?;
+
+        let id = insn.id().0;
+        let mut groups: Vec<InsnGroup> = details.groups().iter().filter_map(|g| normalize_group(g.0 as u32)).collect();
+        if id == X86Insn::X86_INS_SYSCALL as u32 || id == X86Insn::X86_INS_SYSENTER as u32 {
+            groups.retain(|&g| g != InsnGroup::Interrupt);
+            groups.push(InsnGroup::Syscall);
+        }
+        if ARITHMETIC.iter().any(|&insn| insn as u32 == id) {
+            groups.push(InsnGroup::Arithmetic);
+        }
+        groups.sort_by_key(|&g| g as u8);
+        groups.dedup();
+        Ok(groups)
+    }
+
+    /// Finds the first `jmp [rip + disp]` in `bytes` and returns the absolute
+    /// address of the memory slot it jumps through (e.g. the GOT entry of a PLT stub).
+    pub fn indirect_jump_slot(&self, bytes: &[u8], address: Address) -> Option<Address> {
+        use capstone::arch::x86::{X86Insn, X86OperandType, X86Reg};
+
+        let insns = self.cs.disasm_all(bytes, address).ok()?;
+        for insn in insns.iter() {
+            if insn.id().0 != X86Insn::X86_INS_JMP as u32 {
+                continue;
+            }
+            let details = self.cs.insn_detail(insn).ok()?;
+            if let capstone::arch::ArchDetail::X86Detail(x86) = details.arch_detail() {
+                for op in x86.operands() {
+                    if let X86OperandType::Mem(m) = op.op_type {
+                        if m.base().0 == X86Reg::X86_REG_RIP as u16 && m.index().0 == 0 {
+                            let next_ip = insn.address() + insn.len() as u64;
+                            return Some((next_ip as i64).wrapping_add(m.disp()) as u64);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Absolute address of the first instruction's rip-relative memory operand
+    /// (`[rip + disp]`), which is based on the address of the next instruction.
+    /// None if it has no such operand or doesn't decode.
+    pub fn rip_relative_target(&self, bytes: &[u8], address: Address) -> Option<Address> {
+        use capstone::arch::x86::{X86OperandType, X86Reg};
+
+        let insns = self.cs.disasm_count(bytes, address, 1).ok()?;
+        let insn = insns.first()?;
+        let details = self.cs.insn_detail(insn).ok()?;
+        let capstone::arch::ArchDetail::X86Detail(x86) = details.arch_detail() else {
+            return None;
+        };
+        x86.operands().find_map(|op| match op.op_type {
+            X86OperandType::Mem(m) if m.base().0 == X86Reg::X86_REG_RIP as u16 && m.index().0 == 0 => {
+                let next_ip = insn.address() + insn.len() as u64;
+                Some((next_ip as i64).wrapping_add(m.disp()) as u64)
+            }
+            _ => None,
+        })
+    }
+
+    /// Immediate operands of the first instruction in `bytes` as (value,
+    /// operand size in bytes), in Capstone's operand order. Empty if it
+    /// doesn't decode.
+    pub fn immediates(&self, bytes: &[u8], address: Address) -> Vec<(i64, u8)> {
+        use capstone::arch::x86::X86OperandType;
+
+        let Ok(insns) = self.cs.disasm_count(bytes, address, 1) else {
+            return Vec::new();
+        };
+        let Some(details) = insns.first().and_then(|insn| self.cs.insn_detail(insn).ok()) else {
+            return Vec::new();
+        };
+        let capstone::arch::ArchDetail::X86Detail(x86) = details.arch_detail() else {
+            return Vec::new();
+        };
+        x86.operands()
+            .filter_map(|op| match op.op_type {
+                X86OperandType::Imm(value) => Some((value, op.size)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Lifts the first instruction in `bytes` to an IL `Operation`.
+    /// Handles data movement (mov/lea), add/sub, branches, calls and syscalls;
+    /// anything else (or undecodable bytes) becomes `Operation::Nop`.
+    pub fn lift(&self, bytes: &[u8], address: Address) -> Operation {
+        use capstone::arch::x86::{X86Insn, X86OperandType};
+        use capstone::InsnGroupType;
+
+        let insns = match self.cs.disasm_all(bytes, address) {
+            Ok(i) => i,
+            Err(_) => return Operation::Nop,
+        };
+        let insn = match insns.first() {
+            Some(i) => i,
+            None => return Operation::Nop,
+        };
+        let details = match self.cs.insn_detail(insn) {
+            Ok(d) => d,
+            Err(_) => return Operation::Nop,
+        };
+        let operands = match details.arch_detail() {
+            capstone::arch::ArchDetail::X86Detail(x86) => x86.operands().collect::<Vec<_>>(),
+            _ => return Operation::Nop,
+        };
+        let next_ip = insn.address() + insn.len() as u64;
+
+        let scalar = |r: RegId, size: u8| Scalar {
+            name: self.cs.reg_name(r).unwrap_or_default(),
+            bits: size as usize * 8,
+        };
+        // Effective address: base + index * scale + disp (rip-relative folds to a constant)
+        let address_expr = |m: &capstone::arch::x86::X86OpMem| -> Expression {
+            let rip = self.cs.reg_name(m.base()).as_deref() == Some("rip");
+            let mut expr = if rip {
+                None
+            } else if m.base().0 != 0 {
+                Some(Expression::Scalar(scalar(m.base(), 8)))
+            } else {
+                None
+            };
+            if m.index().0 != 0 {
+                let scaled = Expression::Mul(
+                    Box::new(Expression::Scalar(scalar(m.index(), 8))),
+                    Box::new(Expression::Constant(Constant { value: m.scale() as u64, bits: 64 })),
+                );
+                expr = Some(match expr {
+                    Some(e) => Expression::Add(Box::new(e), Box::new(scaled)),
+                    None => scaled,
+                });
+            }
+            let disp = if rip {
+                (next_ip as i64).wrapping_add(m.disp())
+            } else {
+                m.disp()
+            };
+            match expr {
+                None => Expression::Constant(Constant { value: disp as u64, bits: 64 }),
+                Some(e) if disp == 0 => e,
+                Some(e) if disp < 0 => Expression::Sub(
+                    Box::new(e),
+                    Box::new(Expression::Constant(Constant { value: disp.unsigned_abs(), bits: 64 })),
+                ),
+                Some(e) => Expression::Add(
+                    Box::new(e),
+                    Box::new(Expression::Constant(Constant { value: disp as u64, bits: 64 })),
+                ),
+            }
+        };
+        let value_expr = |op: &capstone::arch::x86::X86Operand| -> Option<Expression> {
+            match &op.op_type {
+                X86OperandType::Reg(r) => Some(Expression::Scalar(scalar(*r, op.size))),
+                X86OperandType::Imm(v) => Some(Expression::Constant(Constant {
+                    value: *v as u64,
+                    bits: op.size as usize * 8,
+                })),
+                X86OperandType::Mem(m) => Some(address_expr(m)),
+                X86OperandType::Invalid => None,
+            }
+        };
+
+        let in_group = |group: u32| details.groups().iter().any(|g| g.0 as u32 == group);
+        let id = insn.id().0;
+
+        if in_group(InsnGroupType::CS_GRP_JUMP) {
+            return match operands.first().and_then(value_expr) {
+                Some(target) => Operation::Branch { target },
+                None => Operation::Nop,
+            };
+        }
+        if in_group(InsnGroupType::CS_GRP_CALL) {
+            let target = operands
+                .first()
+                .and_then(value_expr)
+                .map(|t| format!("call {:?}", t))
+                .unwrap_or_else(|| "call".to_string());
+            return Operation::Intrinsic { intrinsic: target };
+        }
+        if in_group(InsnGroupType::CS_GRP_RET) {
+            return Operation::Intrinsic { intrinsic: "ret".to_string() };
+        }
+        if id == X86Insn::X86_INS_SYSCALL as u32 {
+            return Operation::Intrinsic { intrinsic: "syscall".to_string() };
+        }
+
+        if operands.len() != 2 {
+            return Operation::Nop;
+        }
+        let (dst, src) = (&operands[0], &operands[1]);
+
+        if id == X86Insn::X86_INS_MOV as u32 || id == X86Insn::X86_INS_MOVABS as u32 {
+            return match (&dst.op_type, &src.op_type) {
+                (X86OperandType::Reg(d), X86OperandType::Mem(m)) => Operation::Load {
+                    dst: scalar(*d, dst.size),
+                    index: address_expr(m),
+                },
+                (X86OperandType::Reg(d), _) => match value_expr(src) {
+                    Some(src) => Operation::Assign { dst: scalar(*d, dst.size), src },
+                    None => Operation::Nop,
+                },
+                (X86OperandType::Mem(m), _) => match value_expr(src) {
+                    Some(src) => Operation::Store { index: address_expr(m), src },
+                    None => Operation::Nop,
+                },
+                _ => Operation::Nop,
+            };
+        }
+        if id == X86Insn::X86_INS_LEA as u32 {
+            if let (X86OperandType::Reg(d), X86OperandType::Mem(m)) = (&dst.op_type, &src.op_type) {
+                return Operation::Assign { dst: scalar(*d, dst.size), src: address_expr(m) };
+            }
+        }
+        if id == X86Insn::X86_INS_ADD as u32 || id == X86Insn::X86_INS_SUB as u32 {
+            if let X86OperandType::Reg(d) = dst.op_type {
+                if matches!(src.op_type, X86OperandType::Reg(_) | X86OperandType::Imm(_)) {
+                    let lhs = Box::new(Expression::Scalar(scalar(d, dst.size)));
+                    let rhs = Box::new(value_expr(src).unwrap());
+                    let src = if id == X86Insn::X86_INS_ADD as u32 {
+                        Expression::Add(lhs, rhs)
+                    } else {
+                        Expression::Sub(lhs, rhs)
+                    };
+                    return Operation::Assign { dst: scalar(d, dst.size), src };
+                }
+            }
+        }
+
+        Operation::Nop
+    }
+}
+
+// Integer ALU instructions, which Capstone doesn't group
+const ARITHMETIC: [capstone::arch::x86::X86Insn; 27] = {
+    use capstone::arch::x86::X86Insn::*;
+    [
+        X86_INS_ADD, X86_INS_ADC, X86_INS_SUB, X86_INS_SBB, X86_INS_MUL, X86_INS_IMUL, X86_INS_DIV,
+        X86_INS_IDIV, X86_INS_INC, X86_INS_DEC, X86_INS_NEG, X86_INS_AND, X86_INS_OR, X86_INS_XOR,
+        X86_INS_NOT, X86_INS_SHL, X86_INS_SHR, X86_INS_SAR, X86_INS_SAL, X86_INS_ROL, X86_INS_ROR,
+        X86_INS_RCL, X86_INS_RCR, X86_INS_SHLD, X86_INS_SHRD, X86_INS_CMP, X86_INS_TEST,
+    ]
+};
+
+// The category of a Capstone x86 instruction group, if it's one we report
+fn normalize_group(group: u32) -> Option<InsnGroup> {
+    use capstone::arch::x86::X86InsnGroup::*;
+
+    let normalized = match group {
+        X86_GRP_JUMP => InsnGroup::Jump,
+        X86_GRP_CALL => InsnGroup::Call,
+        X86_GRP_RET => InsnGroup::Ret,
+        X86_GRP_INT | X86_GRP_IRET => InsnGroup::Interrupt,
+        X86_GRP_PRIVILEGE => InsnGroup::Privileged,
+        X86_GRP_FPU => InsnGroup::Fpu,
+        X86_GRP_MMX | X86_GRP_3DNOW => InsnGroup::Mmx,
+        X86_GRP_SSE1 | X86_GRP_SSE2 | X86_GRP_SSE3 | X86_GRP_SSSE3 | X86_GRP_SSE41 | X86_GRP_SSE42
+        | X86_GRP_SSE4A => InsnGroup::Sse,
+        X86_GRP_AVX | X86_GRP_AVX2 | X86_GRP_AVX512 | X86_GRP_FMA | X86_GRP_FMA4 | X86_GRP_F16C => InsnGroup::Avx,
+        X86_GRP_AES | X86_GRP_SHA | X86_GRP_PCLMUL => InsnGroup::Crypto,
+        X86_GRP_VM => InsnGroup::Virtualization,
+        _ => return None,
+    };
+    Some(normalized)
+}
+
+/// The bits of a general purpose register an operand names: `al` is bits
+/// 0-7 of rax, `ah` bits 8-15 and `eax` bits 0-31.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegPart {
+    /// Index of the full register in `REG_NAMES`
+    pub index: usize,
+    pub shift: u32,
+    pub bits: u32,
+}
+
+impl RegPart {
+    pub fn full(index: usize) -> Self {
+        Self { index, shift: 0, bits: 64 }
+    }
+
+    /// Parses a register view name ("rax", "eax", "ax", "al", "ah", "r8d", ...).
+    pub fn from_name(name: &str) -> Option<Self> {
+        const R32: [&str; 16] = [
+            "eax", "ebx", "ecx", "edx", "esi", "edi", "ebp", "esp", "r8d", "r9d", "r10d", "r11d",
+            "r12d", "r13d", "r14d", "r15d",
+        ];
+        const R16: [&str; 16] = [
+            "ax", "bx", "cx", "dx", "si", "di", "bp", "sp", "r8w", "r9w", "r10w", "r11w", "r12w",
+            "r13w", "r14w", "r15w",
+        ];
+        const R8: [&str; 16] = [
+            "al", "bl", "cl", "dl", "sil", "dil", "bpl", "spl", "r8b", "r9b", "r10b", "r11b", "r12b",
+            "r13b", "r14b", "r15b",
+        ];
+        const R8_HIGH: [&str; 4] = ["ah", "bh", "ch", "dh"];
+
+        let name = name.to_lowercase();
+        let find = |table: &[&str]| table.iter().position(|&n| n == name);
+        let (index, shift, bits) = if let Some(i) = find(&crate::db::REG_NAMES) {
+            (i, 0, 64)
+        } else if let Some(i) = find(&R32) {
+            (i, 0, 32)
+        } else if let Some(i) = find(&R16) {
+            (i, 0, 16)
+        } else if let Some(i) = find(&R8) {
+            (i, 0, 8)
+        } else {
+            (find(&R8_HIGH)?, 8, 8)
+        };
+        Some(Self { index, shift, bits })
+    }
+
+    /// Bits of the full register this part covers.
+    pub fn mask(self) -> u64 {
+        if self.bits >= 64 {
+            u64::MAX
+        } else {
+            ((1u64 << self.bits) - 1) << self.shift
+        }
+    }
+
+    /// Bits of the full register a write to this part changes: 32-bit writes
+    /// zero the upper half, 8 and 16-bit writes leave the rest alone.
+    pub fn write_mask(self) -> u64 {
+        if self.bits == 32 {
+            u64::MAX
+        } else {
+            self.mask()
+        }
+    }
+
+    /// This part's value within the full register value `full`.
+    pub fn read(self, full: u64) -> u64 {
+        (full & self.mask()) >> self.shift
+    }
+}
+
+/// Reads the value of a general purpose register view ("rax", "eax", "ax",
+/// "al", "ah", "r8d", ...) out of a full register vector (see `REG_NAMES`).
+pub(crate) fn gpr_value(name: &str, regs: &[u64]) -> Option<u64> {
+    let part = RegPart::from_name(name)?;
+    Some(part.read(*regs.get(part.index)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reg(name: &str) -> Scalar {
+        Scalar { name: name.to_string(), bits: 64 }
+    }
+
+    fn constant(value: u64) -> Expression {
+        Expression::Constant(Constant { value, bits: 64 })
+    }
+
+    #[test]
+    fn lift_data_movement() {
+        let d = Disassembler::new().unwrap();
+
+        // mov rax, rbx
+        assert_eq!(
+            d.lift(&[0x48, 0x89, 0xd8], 0x1000),
+            Operation::Assign { dst: reg("rax"), src: Expression::Scalar(reg("rbx")) }
+        );
+        // mov rax, 0x10
+        assert_eq!(
+            d.lift(&[0x48, 0xc7, 0xc0, 0x10, 0x00, 0x00, 0x00], 0x1000),
+            Operation::Assign { dst: reg("rax"), src: constant(0x10) }
+        );
+        // mov rax, qword ptr [rbp - 8]
+        let rbp_minus_8 = Expression::Sub(Box::new(Expression::Scalar(reg("rbp"))), Box::new(constant(8)));
+        assert_eq!(
+            d.lift(&[0x48, 0x8b, 0x45, 0xf8], 0x1000),
+            Operation::Load { dst: reg("rax"), index: rbp_minus_8.clone() }
+        );
+        // mov qword ptr [rbp - 8], rax
+        assert_eq!(
+            d.lift(&[0x48, 0x89, 0x45, 0xf8], 0x1000),
+            Operation::Store { index: rbp_minus_8, src: Expression::Scalar(reg("rax")) }
+        );
+        // lea rax, [rip + 0x10] folds to an absolute address
+        assert_eq!(
+            d.lift(&[0x48, 0x8d, 0x05, 0x10, 0x00, 0x00, 0x00], 0x1000),
+            Operation::Assign { dst: reg("rax"), src: constant(0x1000 + 7 + 0x10) }
+        );
+        // add rsp, 8
+        assert_eq!(
+            d.lift(&[0x48, 0x83, 0xc4, 0x08], 0x1000),
+            Operation::Assign {
+                dst: reg("rsp"),
+                src: Expression::Add(Box::new(Expression::Scalar(reg("rsp"))), Box::new(constant(8))),
+            }
+        );
+    }
+
+    #[test]
+    fn written_registers_keep_their_width() {
+        let d = Disassembler::new().unwrap();
+        let part = |name| RegPart::from_name(name).unwrap();
+
+        // mov al, 1
+        assert_eq!(d.get_written_registers(&[0xb0, 0x01], 0x1000).unwrap(), vec![part("al")]);
+        // mov ah, 2
+        assert_eq!(d.get_written_registers(&[0xb4, 0x02], 0x1000).unwrap(), vec![part("ah")]);
+        // push rbx: rsp, implicitly
+        assert_eq!(d.get_written_registers(&[0x53], 0x1000).unwrap(), vec![part("rsp")]);
+
+        assert_eq!(part("ah").write_mask(), 0xff00);
+        assert_eq!(part("eax").write_mask(), u64::MAX);
+        assert_eq!(part("ah").read(0x0201), 2);
+    }
+
+    #[test]
+    fn lift_control_flow() {
+        let d = Disassembler::new().unwrap();
+
+        // jmp 0x1007
+        assert_eq!(d.lift(&[0xeb, 0x05], 0x1000), Operation::Branch { target: constant(0x1007) });
+        // jne 0x1007
+        assert_eq!(d.lift(&[0x75, 0x05], 0x1000), Operation::Branch { target: constant(0x1007) });
+        // call 0x1005
+        assert!(matches!(
+            d.lift(&[0xe8, 0x00, 0x00, 0x00, 0x00], 0x1000),
+            Operation::Intrinsic { intrinsic } if intrinsic.starts_with("call")
+        ));
+        assert_eq!(d.lift(&[0x0f, 0x05], 0x1000), Operation::Intrinsic { intrinsic: "syscall".to_string() });
+        assert_eq!(d.lift(&[0xc3], 0x1000), Operation::Intrinsic { intrinsic: "ret".to_string() });
+    }
+
+    #[test]
+    fn groups_classify_instructions() {
+        let d = Disassembler::new().unwrap();
+        let groups = |bytes: &[u8]| d.groups(bytes, 0x1000).unwrap();
+
+        // call 0x1005; ret; jne 0x1007
+        assert_eq!(groups(&[0xe8, 0x00, 0x00, 0x00, 0x00]), vec![InsnGroup::Call]);
+        assert_eq!(groups(&[0xc3]), vec![InsnGroup::Ret]);
+        assert_eq!(groups(&[0x75, 0x05]), vec![InsnGroup::Jump]);
+        // syscall; int3; hlt
+        assert_eq!(groups(&[0x0f, 0x05]), vec![InsnGroup::Syscall]);
+        assert_eq!(groups(&[0xcc]), vec![InsnGroup::Interrupt]);
+        assert_eq!(groups(&[0xf4]), vec![InsnGroup::Privileged]);
+        // add rsp, 8; xor eax, eax
+        assert_eq!(groups(&[0x48, 0x83, 0xc4, 0x08]), vec![InsnGroup::Arithmetic]);
+        assert_eq!(groups(&[0x31, 0xc0]), vec![InsnGroup::Arithmetic]);
+        // movaps xmm0, xmm1; aesenc xmm0, xmm1
+        assert_eq!(groups(&[0x0f, 0x28, 0xc1]), vec![InsnGroup::Sse]);
+        assert!(groups(&[0x66, 0x0f, 0x38, 0xdc, 0xc1]).contains(&InsnGroup::Crypto));
+        // mov rbp, rsp has no category
+        assert!(groups(&[0x48, 0x89, 0xe5]).is_empty());
+        assert!(d.groups(&[], 0x1000).is_err());
+    }
+
+    #[test]
+    fn truncated_bytes_are_reported() {
+        let d = Disassembler::new().unwrap();
+        // mov rax, qword ptr [rbp - 8] without its displacement
+        let cut = [0x48, 0x8b, 0x45];
+
+        let err = d.get_read_registers(&cut, 0x1000).unwrap_err();
+        assert_eq!(err.downcast_ref::<Truncated>(), Some(&Truncated { address: 0x1000, available: 3 }));
+        assert!(is_truncated(&d.get_written_registers(&cut, 0x1000).unwrap_err()));
+        assert!(is_truncated(&d.disassemble(&cut, 0x1000).unwrap_err()));
+        assert!(is_truncated(&d.detail(&cut, 0x1000).unwrap_err()));
+        assert!(is_truncated(&d.length(&[], 0x1000).unwrap_err()));
+        assert_eq!(d.length(&[0x48, 0x8b, 0x45, 0xf8, 0x90], 0x1000).unwrap(), 4);
+
+        // Bytes that can't start an instruction (push es, invalid in 64-bit
+        // mode) are not truncated
+        assert!(!is_truncated(&d.length(&[0x06], 0x1000).unwrap_err()));
+        assert_eq!(d.disassemble(&[0x06], 0x1000).unwrap(), "???");
+        assert_eq!(d.get_read_registers(&[0x06], 0x1000).unwrap(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn lift_unhandled_is_nop() {
+        let d = Disassembler::new().unwrap();
+
+        // nop, push rbp, and bytes that do not decode
+        assert_eq!(d.lift(&[0x90], 0x1000), Operation::Nop);
+        assert_eq!(d.lift(&[0x55], 0x1000), Operation::Nop);
+        assert_eq!(d.lift(&[], 0x1000), Operation::Nop);
+    }
 }
@@ -0,0 +1,232 @@
+// iced-x86 implementation of `Backend`, enabled with the `iced` feature.
+// Formatting is set up to match Capstone's output closely, so the text
+// passes (stack variables, branch symbolization) work on either backend.
+
+use super::{Backend, RegPart, Truncated};
+use crate::protocol::DisasmSyntax;
+use crate::Address;
+use anyhow::{anyhow, Result};
+use iced_x86::{
+    Decoder, DecoderError, DecoderOptions, Formatter, GasFormatter, Instruction, InstructionInfoFactory,
+    IntelFormatter, MemorySizeOptions, OpAccess, Register,
+};
+
+pub struct IcedBackend {
+    syntax: DisasmSyntax,
+}
+
+impl IcedBackend {
+    pub fn new() -> Self {
+        Self {
+            syntax: DisasmSyntax::Intel,
+        }
+    }
+
+    fn decode(bytes: &[u8], address: Address) -> Result<Instruction> {
+        let mut decoder = Decoder::with_ip(64, bytes, address, DecoderOptions::NONE);
+        let insn = decoder.decode();
+        match decoder.last_error() {
+            DecoderError::None => Ok(insn),
+            // Also reported for invalid opcodes near the end of the bytes, so
+            // only call it truncated if more bytes would make an instruction
+            DecoderError::NoMoreBytes if Self::completes(bytes, address) => {
+                Err(Truncated { address, available: bytes.len() }.into())
+            }
+            _ => Err(anyhow!("No instruction decoded at {:x}", address)),
+        }
+    }
+
+    fn completes(bytes: &[u8], address: Address) -> bool {
+        let mut padded = bytes.to_vec();
+        padded.resize(super::MAX_INSN_LEN, 0);
+        let insn = Decoder::with_ip(64, &padded, address, DecoderOptions::NONE).decode();
+        !insn.is_invalid() && insn.len() > bytes.len()
+    }
+
+    fn format(&self, insn: &Instruction) -> String {
+        let mut formatter: Box<dyn Formatter> = match self.syntax {
+            DisasmSyntax::Intel => Box::new(IntelFormatter::new()),
+            DisasmSyntax::Att => Box::new(GasFormatter::new()),
+        };
+        let options = formatter.options_mut();
+        options.set_hex_prefix("0x");
+        options.set_hex_suffix("");
+        options.set_uppercase_hex(false);
+        options.set_leading_zeros(false);
+        options.set_branch_leading_zeros(false);
+        options.set_show_branch_size(false);
+        options.set_space_after_operand_separator(true);
+        options.set_space_between_memory_add_operators(true);
+        options.set_rip_relative_addresses(true);
+        options.set_memory_size_options(MemorySizeOptions::Always);
+
+        let mut text = String::new();
+        formatter.format(insn, &mut text);
+        text
+    }
+}
+
+impl Backend for IcedBackend {
+    fn disassemble(&self, bytes: &[u8], address: Address) -> Result<String> {
+        match Self::decode(bytes, address) {
+            Ok(insn) => Ok(self.format(&insn)),
+            Err(e) if super::is_truncated(&e) => Err(e),
+            Err(_) => Ok(String::from("???")),
+        }
+    }
+
+    fn disassemble_one(&self, bytes: &[u8], address: Address) -> Option<(usize, String)> {
+        let insn = Self::decode(bytes, address).ok()?;
+        Some((insn.len(), self.format(&insn)))
+    }
+
+    fn get_read_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<usize>> {
+        let insn = Self::decode(bytes, address)?;
+        let mut factory = InstructionInfoFactory::new();
+        let mut read_regs: Vec<usize> = factory
+            .info(&insn)
+            .used_registers()
+            .iter()
+            .filter(|used| {
+                matches!(
+                    used.access(),
+                    OpAccess::Read | OpAccess::CondRead | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+                )
+            })
+            .filter_map(|used| gpr_index(used.register()))
+            .collect();
+        read_regs.sort();
+        read_regs.dedup();
+        Ok(read_regs)
+    }
+
+    fn get_written_registers(&self, bytes: &[u8], address: Address) -> Result<Vec<RegPart>> {
+        let insn = Self::decode(bytes, address)?;
+        let mut factory = InstructionInfoFactory::new();
+        let mut written: Vec<RegPart> = factory
+            .info(&insn)
+            .used_registers()
+            .iter()
+            .filter(|used| {
+                matches!(
+                    used.access(),
+                    OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+                )
+            })
+            .filter_map(|used| reg_part(used.register()))
+            .collect();
+        written.sort();
+        written.dedup();
+        Ok(written)
+    }
+
+    fn set_syntax(&mut self, syntax: DisasmSyntax) -> Result<()> {
+        self.syntax = syntax;
+        Ok(())
+    }
+}
+
+// Index in `REG_NAMES` of the 64-bit register containing `reg` (al, ax, eax -> rax)
+fn gpr_index(reg: Register) -> Option<usize> {
+    let index = match reg.full_register() {
+        Register::RAX => 0,
+        Register::RBX => 1,
+        Register::RCX => 2,
+        Register::RDX => 3,
+        Register::RSI => 4,
+        Register::RDI => 5,
+        Register::RBP => 6,
+        Register::RSP => 7,
+        Register::R8 => 8,
+        Register::R9 => 9,
+        Register::R10 => 10,
+        Register::R11 => 11,
+        Register::R12 => 12,
+        Register::R13 => 13,
+        Register::R14 => 14,
+        Register::R15 => 15,
+        _ => return None,
+    };
+    Some(index)
+}
+
+// The part of its 64-bit register `reg` covers (ah -> bits 8-15 of rax)
+fn reg_part(reg: Register) -> Option<RegPart> {
+    let index = gpr_index(reg)?;
+    let shift = if matches!(reg, Register::AH | Register::BH | Register::CH | Register::DH) { 8 } else { 0 };
+    Some(RegPart { index, shift, bits: reg.size() as u32 * 8 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::CapstoneBackend;
+
+    #[test]
+    fn matches_capstone_text_and_lengths() {
+        let iced = IcedBackend::new();
+        let capstone = CapstoneBackend::new().unwrap();
+        let cases: [&[u8]; 4] = [
+            // mov rax, qword ptr [rbp - 8]
+            &[0x48, 0x8b, 0x45, 0xf8],
+            // lea rdi, [rip + 0x2fe3]
+            &[0x48, 0x8d, 0x3d, 0xe3, 0x2f, 0x00, 0x00],
+            // call 0x401000
+            &[0xe8, 0xfb, 0xef, 0xff, 0xff],
+            // mov rbp, rsp
+            &[0x48, 0x89, 0xe5],
+        ];
+        for bytes in cases {
+            assert_eq!(
+                iced.disassemble_one(bytes, 0x402000),
+                capstone.disassemble_one(bytes, 0x402000)
+            );
+        }
+    }
+
+    #[test]
+    fn reports_truncated_bytes_like_capstone() {
+        let iced = IcedBackend::new();
+        // mov rax, qword ptr [rbp - 8] without its displacement
+        let err = iced.disassemble(&[0x48, 0x8b, 0x45], 0x1000).unwrap_err();
+        assert_eq!(err.downcast_ref::<Truncated>(), Some(&Truncated { address: 0x1000, available: 3 }));
+        assert!(super::super::is_truncated(&iced.get_read_registers(&[0x48], 0x1000).unwrap_err()));
+        // push es is invalid in 64-bit mode, not truncated
+        assert_eq!(iced.disassemble(&[0x06], 0x1000).unwrap(), "???");
+    }
+
+    #[test]
+    fn reads_memory_operand_registers() {
+        let iced = IcedBackend::new();
+        // mov qword ptr [rbx + rcx*8], rax
+        let regs = iced.get_read_registers(&[0x48, 0x89, 0x04, 0xcb], 0x1000).unwrap();
+        assert_eq!(regs, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn writes_match_capstone() {
+        let iced = IcedBackend::new();
+        let capstone = CapstoneBackend::new().unwrap();
+        let cases: [&[u8]; 4] = [
+            // mov al, 1
+            &[0xb0, 0x01],
+            // mov ah, 2
+            &[0xb4, 0x02],
+            // mov eax, 3
+            &[0xb8, 0x03, 0x00, 0x00, 0x00],
+            // push rbx
+            &[0x53],
+        ];
+        // iced reports 32-bit writes as writes to the zero-extended full
+        // register, so compare the bits changed rather than the parts named
+        let changed = |parts: Vec<RegPart>| -> Vec<(usize, u64)> {
+            parts.iter().map(|p| (p.index, p.write_mask())).collect()
+        };
+        for bytes in cases {
+            assert_eq!(
+                changed(iced.get_written_registers(bytes, 0x1000).unwrap()),
+                changed(capstone.get_written_registers(bytes, 0x1000).unwrap())
+            );
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::Clnum;
+use std::fmt;
+
+/// Reasons a client query cannot be answered. Reported to the frontend as
+/// `ServerMessage::Error` instead of an empty response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    ClnumOutOfRange { clnum: Clnum, max: Clnum },
+    UnknownSliceTarget(String),
+    SymbolNotFound(String),
+    // A two-trace query was made but only one trace is loaded
+    NoSecondTrace,
+    // The named location exists but never executed in the trace
+    NeverExecuted(String),
+    // A configuration named a module that isn't loaded
+    UnknownModule(String),
+    // A syscall query named a clnum that doesn't execute a syscall
+    NotASyscall(Clnum),
+    // A syscall's output buffer runs past the end of the address space
+    BufferWraps { address: u64, len: u64 },
+    // A region name was empty or had whitespace, or the region had no bytes
+    InvalidRegion(String),
+    // A configuration asked for a syntax the disassembler backend can't print
+    UnsupportedSyntax(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::ClnumOutOfRange { clnum, max } => {
+                write!(f, "clnum {} is out of range (max {})", clnum, max)
+            }
+            QueryError::UnknownSliceTarget(target) => write!(
+                f,
+                "unknown slice target '{}' (expected a register like rax or a 0x address)",
+                target
+            ),
+            QueryError::SymbolNotFound(name) => write!(f, "symbol '{}' not found", name),
+            QueryError::NoSecondTrace => {
+                write!(f, "no second trace loaded (pass --load-trace twice)")
+            }
+            QueryError::NeverExecuted(what) => write!(f, "{} was never executed", what),
+            QueryError::UnknownModule(name) => write!(f, "module '{}' is not loaded", name),
+            QueryError::NotASyscall(clnum) => write!(f, "the instruction at clnum {} is not a syscall", clnum),
+            QueryError::BufferWraps { address, len } => {
+                write!(f, "a {}-byte buffer at {:#x} runs past the end of the address space", len, address)
+            }
+            QueryError::InvalidRegion(why) => write!(f, "invalid region: {}", why),
+            QueryError::UnsupportedSyntax(why) => write!(f, "unsupported disassembly syntax: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Operation {
@@ -22,6 +23,7 @@ pub enum Expression {
     Constant(Constant),
     Add(Box<Expression>, Box<Expression>),
     Sub(Box<Expression>, Box<Expression>),
+    Mul(Box<Expression>, Box<Expression>),
     // ... extend as needed
 }
 
@@ -53,11 +55,21 @@ impl ControlFlowGraph {
         }
 
         let mut s = String::from("graph TD;\n");
+        if self.is_diff() {
+            for diff in [CfgDiff::OnlyA, CfgDiff::OnlyB, CfgDiff::Changed] {
+                let (fill, stroke) = diff.colors();
+                s.push_str(&format!("    classDef {} fill:{},stroke:{};\n", diff.class(), fill, stroke));
+            }
+        }
+
+        // Browsers choke on very large graphs, so only the first blocks are drawn
+        let shown = |index: usize| index < MAX_MERMAID_BLOCKS;
 
         // Group blocks by symbol
-        use std::collections::HashMap;
-        let mut groups: HashMap<Option<String>, Vec<&BasicBlock>> = HashMap::new();
-        for block in &self.blocks {
+        // Ordered by symbol (unnamed blocks first) so the output is stable
+        use std::collections::BTreeMap;
+        let mut groups: BTreeMap<Option<String>, Vec<&BasicBlock>> = BTreeMap::new();
+        for block in self.blocks.iter().filter(|b| shown(b.index)) {
             groups.entry(block.symbol.clone()).or_default().push(block);
         }
         
@@ -66,7 +78,7 @@ impl ControlFlowGraph {
             if let Some(ref sym_name) = symbol {
                 // Sanitize symbol name for ID
                 let safe_sym = sym_name.replace(|c: char| !c.is_alphanumeric(), "_");
-                s.push_str(&format!("    subgraph cluster_{} [\"{}\"]\n", safe_sym, sym_name));
+                s.push_str(&format!("    subgraph cluster_{} [\"{}\"]\n", safe_sym, mermaid_escape(sym_name)));
             }
             
             for block in blocks {
@@ -76,10 +88,11 @@ impl ControlFlowGraph {
                     format!("Block {}", block.index)
                 };
                 
-                // Limit content size for label
+                // Limit content size for label; escape each line before joining
+                // so the <br/> separators survive
                 let content = block.instructions.iter()
                     .take(5) // Show first 5 instructions
-                    .map(|i| format!("{:x}: {} {}", i.address, i.mnemonic, i.operands))
+                    .map(|i| mermaid_escape(&format!("{:x}: {} {}", i.address, i.mnemonic, i.operands)))
                     .collect::<Vec<_>>()
                     .join("<br/>");
                     
@@ -88,16 +101,19 @@ impl ControlFlowGraph {
                 } else {
                     content
                 };
-                
-                // Escape quotes in content
-                let content = content.replace("\"", "#quot;");
-                let content = content.replace("\"", "'");
     
-                // Node definition
-                s.push_str(&format!("        block{}[\"{}<br/>{}\"];\n", block.index, label, content));
+                // Node definition, styled by how it differs between traces
+                let class = match block.diff {
+                    Some(diff) if diff != CfgDiff::Common => format!(":::{}", diff.class()),
+                    _ => String::new(),
+                };
+                s.push_str(&format!("        block{}[\"{}<br/>{}\"]{};\n", block.index, label, content, class));
                 
-                // Click interaction: pass clnum
-                s.push_str(&format!("        click block{} call onCfgNodeClick({})\n", block.index, block.clnum));
+                // Click interaction: pass clnum. Blocks only in trace B have
+                // no clnum in the trace being browsed
+                if block.diff != Some(CfgDiff::OnlyB) {
+                    s.push_str(&format!("        click block{} call onCfgNodeClick({})\n", block.index, block.clnum));
+                }
             }
             
             if symbol.is_some() {
@@ -105,13 +121,344 @@ impl ControlFlowGraph {
             }
         }
         
-        // Define edges
-        for edge in &self.edges {
-            s.push_str(&format!("    block{} --> block{};\n", edge.head, edge.tail));
+        // Define edges, labelled with their kind and how often they were taken.
+        // The transition clnums go in a comment the frontend reads to make
+        // edges clickable. Asynchronous (signal/interrupt) transitions are
+        // dotted, calls thick.
+        for (link, edge) in self.edges.iter().filter(|e| shown(e.head) && shown(e.tail)).enumerate() {
+            let label = match (edge.label(), edge.clnums.len()) {
+                (Some(kind), n) if n > 1 => format!("|\"{} {}x\"|", kind, n),
+                (Some(kind), _) => format!("|\"{}\"|", kind),
+                (None, n) if n > 1 => format!("|\"{}x\"|", n),
+                (None, _) => String::new(),
+            };
+            let arrow = match edge.kind {
+                _ if edge.asynchronous => "-.->",
+                EdgeKind::Call | EdgeKind::TailCall => "==>",
+                _ => "-->",
+            };
+            s.push_str(&format!("    block{} {}{} block{};\n", edge.head, arrow, label, edge.tail));
+            if let Some(diff) = edge.diff.filter(|&d| d != CfgDiff::Common) {
+                s.push_str(&format!("    linkStyle {} stroke:{},stroke-width:2px;\n", link, diff.colors().1));
+            }
+            if !edge.clnums.is_empty() {
+                let clnums = edge.clnums.iter()
+                    .take(MAX_EDGE_CLNUMS)
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                s.push_str(&format!("    %% edge block{} block{} {}\n", edge.head, edge.tail, clnums));
+            }
+        }
+
+        if self.blocks.len() > MAX_MERMAID_BLOCKS {
+            s.push_str(&format!(
+                "    truncated[\"graph truncated ({} of {} blocks)\"];\n",
+                MAX_MERMAID_BLOCKS,
+                self.blocks.len()
+            ));
         }
 
         s
     }
+
+    /// Graphviz DOT for rendering the graph outside the browser (`dot -Tsvg`).
+    /// Blocks are grouped into one cluster per symbol like in `to_mermaid`, and
+    /// link to `onCfgNodeClick` so an inlined SVG stays clickable.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph cfg {\n");
+        s.push_str("    node [shape=box, fontname=\"monospace\", fontsize=10];\n");
+        s.push_str("    edge [fontname=\"monospace\", fontsize=9];\n");
+        if self.blocks.is_empty() {
+            s.push_str("    empty [label=\"No User Code / Empty Trace\"];\n}\n");
+            return s;
+        }
+
+        let shown = |index: usize| index < MAX_DOT_BLOCKS;
+
+        use std::collections::BTreeMap;
+        let mut groups: BTreeMap<Option<String>, Vec<&BasicBlock>> = BTreeMap::new();
+        for block in self.blocks.iter().filter(|b| shown(b.index)) {
+            groups.entry(block.symbol.clone()).or_default().push(block);
+        }
+
+        for (cluster, (symbol, blocks)) in groups.into_iter().enumerate() {
+            if let Some(ref sym_name) = symbol {
+                s.push_str(&format!("    subgraph cluster_{} {{\n", cluster));
+                s.push_str(&format!("        label=\"{}\";\n", dot_escape(sym_name)));
+            }
+            for block in blocks {
+                // One left-justified line per instruction
+                let mut label = String::new();
+                for insn in block.instructions.iter().take(MAX_DOT_BLOCK_LINES) {
+                    label.push_str(&dot_escape(&format!("{:x}: {} {}", insn.address, insn.mnemonic, insn.operands)));
+                    label.push_str("\\l");
+                }
+                if block.instructions.len() > MAX_DOT_BLOCK_LINES {
+                    label.push_str("...\\l");
+                }
+                let mut attrs = format!("label=\"{}\"", label);
+                if let Some(diff) = block.diff.filter(|&d| d != CfgDiff::Common) {
+                    let (fill, stroke) = diff.colors();
+                    attrs.push_str(&format!(", style=filled, fillcolor=\"{}\", color=\"{}\"", fill, stroke));
+                }
+                if block.diff != Some(CfgDiff::OnlyB) {
+                    attrs.push_str(&format!(", href=\"javascript:onCfgNodeClick({})\"", block.clnum));
+                }
+                s.push_str(&format!("        block{} [{}];\n", block.index, attrs));
+            }
+            if symbol.is_some() {
+                s.push_str("    }\n");
+            }
+        }
+
+        for edge in self.edges.iter().filter(|e| shown(e.head) && shown(e.tail)) {
+            let mut attrs = Vec::new();
+            let count = (edge.clnums.len() > 1).then(|| format!("{}x", edge.clnums.len()));
+            if edge.asynchronous {
+                attrs.push("style=dashed, color=\"#c00000\"".to_string());
+            } else {
+                match edge.kind {
+                    EdgeKind::Call | EdgeKind::TailCall => attrs.push("style=bold".to_string()),
+                    EdgeKind::Indirect => attrs.push("style=dotted".to_string()),
+                    EdgeKind::Ret => attrs.push("arrowhead=empty".to_string()),
+                    EdgeKind::FallThrough | EdgeKind::CondTaken | EdgeKind::UncondJump => {}
+                }
+            }
+            match (edge.label(), count) {
+                (Some(kind), Some(count)) => attrs.push(format!("label=\"{} {}\"", kind, count)),
+                (Some(kind), None) => attrs.push(format!("label=\"{}\"", kind)),
+                (None, Some(count)) => attrs.push(format!("label=\"{}\"", count)),
+                (None, None) => {}
+            }
+            if let Some(diff) = edge.diff.filter(|&d| d != CfgDiff::Common) {
+                attrs.push(format!("color=\"{}\", penwidth=2", diff.colors().1));
+            }
+            // An edge leads to the first time it was taken
+            if let Some(first) = edge.clnums.first() {
+                attrs.push(format!("href=\"javascript:onCfgNodeClick({})\"", first));
+            }
+            if attrs.is_empty() {
+                s.push_str(&format!("    block{} -> block{};\n", edge.head, edge.tail));
+            } else {
+                s.push_str(&format!("    block{} -> block{} [{}];\n", edge.head, edge.tail, attrs.join(", ")));
+            }
+        }
+
+        if self.blocks.len() > MAX_DOT_BLOCKS {
+            s.push_str(&format!(
+                "    truncated [shape=note, label=\"graph truncated ({} of {} blocks)\"];\n",
+                MAX_DOT_BLOCKS,
+                self.blocks.len()
+            ));
+        }
+
+        s.push_str("}\n");
+        s
+    }
+
+    // A merged graph from `TraceDB::diff_cfg`
+    fn is_diff(&self) -> bool {
+        self.blocks.iter().any(|b| b.diff.is_some())
+    }
+}
+
+/// A backward slice as a graph of values: nodes are the instructions in the
+/// slice, edges lead from the instruction that wrote a register or memory
+/// location to the one in the slice that read it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataFlowGraph {
+    // What was sliced, as given to `TraceDB::get_slice`
+    pub target: String,
+    // In clnum order
+    pub nodes: Vec<DataFlowNode>,
+    pub edges: Vec<DataFlowEdge>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DataFlowNode {
+    pub clnum: u32,
+    pub address: u64,
+    pub text: String,
+    pub symbol: Option<String>,
+}
+
+/// `producer` wrote `location` (a register name or memory label), and
+/// `consumer` read the value from there. Both are indices into `nodes`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DataFlowEdge {
+    pub producer: usize,
+    pub consumer: usize,
+    pub location: String,
+}
+
+impl DataFlowGraph {
+    /// Mermaid source like `ControlFlowGraph::to_mermaid`, with each edge
+    /// labelled by the location the value passed through. Instructions no
+    /// other one in the slice reads from produced the target itself, and
+    /// lead into a node for it. Nodes link to `onCfgNodeClick`.
+    pub fn to_mermaid(&self) -> String {
+        if self.nodes.is_empty() {
+            return format!("graph TD;\n    Empty[\"Nothing in the slice of {}\"];\n", mermaid_escape(&self.target));
+        }
+
+        let shown = |index: usize| index < MAX_MERMAID_BLOCKS;
+        let mut s = String::from("graph TD;\n");
+        for (index, node) in self.nodes.iter().enumerate().filter(|&(i, _)| shown(i)) {
+            let place = node.symbol.clone().unwrap_or_else(|| format!("{:x}", node.address));
+            s.push_str(&format!(
+                "    node{}[\"{}: {}<br/>{}\"];\n",
+                index,
+                node.clnum,
+                mermaid_escape(&place),
+                mermaid_escape(&node.text)
+            ));
+            s.push_str(&format!("    click node{} call onCfgNodeClick({})\n", index, node.clnum));
+        }
+
+        for edge in self.edges.iter().filter(|e| shown(e.producer) && shown(e.consumer)) {
+            s.push_str(&format!(
+                "    node{} -->|\"{}\"| node{};\n",
+                edge.producer,
+                mermaid_escape(&edge.location),
+                edge.consumer
+            ));
+        }
+
+        s.push_str(&format!("    target([\"{}\"]);\n", mermaid_escape(&self.target)));
+        let producers: HashSet<usize> = self.edges.iter().map(|e| e.producer).collect();
+        for index in (0..self.nodes.len()).filter(|&i| shown(i)) {
+            if !producers.contains(&index) {
+                s.push_str(&format!("    node{} ==> target;\n", index));
+            }
+        }
+
+        if self.nodes.len() > MAX_MERMAID_BLOCKS {
+            s.push_str(&format!(
+                "    truncated[\"graph truncated ({} of {} instructions)\"];\n",
+                MAX_MERMAID_BLOCKS,
+                self.nodes.len()
+            ));
+        }
+        s
+    }
+}
+
+/// How control got from the last instruction of an edge's head block to its
+/// tail, from that instruction's decoded groups and operands.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    // Into the next instruction: a block split by another edge's target, an
+    // untaken conditional branch, or any non-branching instruction
+    #[default]
+    FallThrough,
+    CondTaken,
+    UncondJump,
+    Call,
+    // Unconditional direct jump to the start of another function
+    TailCall,
+    // Call or jump through a register or memory operand
+    Indirect,
+    Ret,
+}
+
+impl EdgeKind {
+    // Edge label, None for fall-through
+    fn label(self) -> Option<&'static str> {
+        match self {
+            EdgeKind::FallThrough => None,
+            EdgeKind::CondTaken => Some("taken"),
+            EdgeKind::UncondJump => Some("jmp"),
+            EdgeKind::Call => Some("call"),
+            EdgeKind::TailCall => Some("tail call"),
+            EdgeKind::Indirect => Some("indirect"),
+            EdgeKind::Ret => Some("ret"),
+        }
+    }
+}
+
+/// Where a block or edge of a merged CFG (see `TraceDB::diff_cfg`) was seen.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CfgDiff {
+    // In both traces, with the same code
+    Common,
+    OnlyA,
+    OnlyB,
+    // In both traces at the same static address, but with different code
+    // (runtime patching or self-modifying code)
+    Changed,
+}
+
+impl CfgDiff {
+    // Mermaid class name
+    fn class(self) -> &'static str {
+        match self {
+            CfgDiff::Common => "common",
+            CfgDiff::OnlyA => "onlyA",
+            CfgDiff::OnlyB => "onlyB",
+            CfgDiff::Changed => "changed",
+        }
+    }
+
+    // (fill, stroke)
+    fn colors(self) -> (&'static str, &'static str) {
+        match self {
+            CfgDiff::Common => ("#ffffff", "#333333"),
+            CfgDiff::OnlyA => ("#f8d7da", "#c62828"),
+            CfgDiff::OnlyB => ("#d4edda", "#2e7d32"),
+            CfgDiff::Changed => ("#fff3cd", "#b8860b"),
+        }
+    }
+}
+
+// Upper bound on blocks emitted by `to_mermaid`
+pub const MAX_MERMAID_BLOCKS: usize = 500;
+// Upper bound on blocks emitted by `to_dot`; Graphviz copes with far larger
+// graphs than Mermaid in the browser
+pub const MAX_DOT_BLOCKS: usize = 5000;
+// Instructions shown per block by `to_dot`
+const MAX_DOT_BLOCK_LINES: usize = 8;
+// Upper bound on transition clnums emitted per edge (hot loops take edges a lot)
+const MAX_EDGE_CLNUMS: usize = 64;
+
+// Escapes text for use inside a quoted Mermaid label using Mermaid's
+// `#code;` entities. `#` goes first since every entity starts with it.
+fn mermaid_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' => out.push_str("#35;"),
+            '"' => out.push_str("#quot;"),
+            '<' => out.push_str("#lt;"),
+            '>' => out.push_str("#gt;"),
+            '[' => out.push_str("#91;"),
+            ']' => out.push_str("#93;"),
+            '{' => out.push_str("#123;"),
+            '}' => out.push_str("#125;"),
+            '|' => out.push_str("#124;"),
+            ';' => out.push_str("#59;"),
+            '-' => out.push_str("#45;"),
+            '\n' | '\r' => out.push(' '),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// Escapes text for use inside a quoted DOT string. Newlines would end the
+// line in the left-justified labels, so they become spaces.
+fn dot_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' | '\r' => out.push(' '),
+            _ => out.push(c),
+        }
+    }
+    out
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -120,6 +467,9 @@ pub struct BasicBlock {
     pub instructions: Vec<Instruction>,
     pub symbol: Option<String>,
     pub clnum: u32,
+    // Set in merged graphs only (see `TraceDB::diff_cfg`)
+    #[serde(default)]
+    pub diff: Option<CfgDiff>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -127,4 +477,115 @@ pub struct Edge {
     pub head: usize,
     pub tail: usize,
     pub condition: Option<Expression>,
+    // Clnums at which this transition was taken (the clnum of the first
+    // instruction executed in `tail`), in trace order
+    #[serde(default)]
+    pub clnums: Vec<u32>,
+    // Taken by a signal or interrupt rather than by the instruction itself
+    #[serde(default)]
+    pub asynchronous: bool,
+    // FallThrough for asynchronous edges
+    #[serde(default)]
+    pub kind: EdgeKind,
+    // Set in merged graphs only (see `TraceDB::diff_cfg`)
+    #[serde(default)]
+    pub diff: Option<CfgDiff>,
+}
+
+impl Edge {
+    fn label(&self) -> Option<&'static str> {
+        if self.asynchronous {
+            Some("async")
+        } else {
+            self.kind.label()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: usize, operands: &str, symbol: Option<&str>) -> BasicBlock {
+        BasicBlock {
+            index,
+            instructions: vec![Instruction {
+                operation: Operation::Nop,
+                address: 0x1000 + index as u64,
+                mnemonic: "mov".to_string(),
+                operands: operands.to_string(),
+            }],
+            symbol: symbol.map(String::from),
+            clnum: index as u32,
+            diff: None,
+        }
+    }
+
+    // Text between the opening `["` and the closing `"]` of a node/subgraph line
+    fn label(line: &str) -> &str {
+        let start = line.find("[\"").unwrap() + 2;
+        let end = line.rfind("\"]").unwrap();
+        &line[start..end]
+    }
+
+    #[test]
+    fn mermaid_escapes_special_characters() {
+        let graph = ControlFlowGraph {
+            blocks: vec![
+                block(0, "rax, qword ptr [rip + 0x10] ; \"a|b\" --> c", Some("operator\"[]|")),
+                block(1, "<x> #y {z}", None),
+            ],
+            edges: vec![Edge { head: 0, tail: 1, condition: None, clnums: vec![], asynchronous: false, kind: EdgeKind::FallThrough, diff: None }],
+        };
+        let mermaid = graph.to_mermaid();
+
+        for line in mermaid.lines().filter(|l| l.contains("[\"")) {
+            let text = label(line);
+            for bad in ['"', '[', ']', '|', '<', '>', '{', '}'] {
+                // `<br/>` separators are the only markup allowed in labels
+                let text = text.replace("<br/>", "");
+                assert!(!text.contains(bad), "unescaped {:?} in {:?}", bad, line);
+            }
+            assert!(!text.contains("-->"), "arrow in label {:?}", line);
+        }
+        let arrows: Vec<_> = mermaid.lines().filter(|l| l.contains("-->")).collect();
+        assert_eq!(arrows, vec!["    block0 --> block1;"]);
+        assert!(mermaid.contains("#quot;a#124;b#quot; #45;#45;#gt; c"));
+    }
+
+    #[test]
+    fn mermaid_truncates_large_graphs() {
+        let count = MAX_MERMAID_BLOCKS + 100;
+        let graph = ControlFlowGraph {
+            blocks: (0..count).map(|i| block(i, "rax, rbx", None)).collect(),
+            edges: (1..count).map(|i| Edge { head: i - 1, tail: i, condition: None, clnums: vec![], asynchronous: false, kind: EdgeKind::FallThrough, diff: None }).collect(),
+        };
+        let mermaid = graph.to_mermaid();
+
+        assert!(mermaid.contains(&format!(
+            "graph truncated ({} of {} blocks)",
+            MAX_MERMAID_BLOCKS, count
+        )));
+        assert!(!mermaid.contains(&format!("block{}[", MAX_MERMAID_BLOCKS)));
+        assert_eq!(mermaid.lines().filter(|l| l.contains("-->")).count(), MAX_MERMAID_BLOCKS - 1);
+    }
+
+    #[test]
+    fn dot_escapes_labels_and_links_blocks() {
+        let graph = ControlFlowGraph {
+            blocks: vec![
+                block(0, "rax, \"a\\b\"", Some("operator\"\"")),
+                block(1, "rax, rbx", None),
+            ],
+            edges: vec![Edge { head: 0, tail: 1, condition: None, clnums: vec![7, 9], asynchronous: false, kind: EdgeKind::FallThrough, diff: None }],
+        };
+        let dot = graph.to_dot();
+
+        assert!(dot.starts_with("digraph cfg {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches('{').count(), dot.matches('}').count());
+        assert!(dot.contains("label=\"operator\\\"\\\"\";"));
+        assert!(dot.contains("1000: mov rax, \\\"a\\\\b\\\"\\l"));
+        assert!(dot.contains("block1 [label=\"1001: mov rax, rbx\\l\", href=\"javascript:onCfgNodeClick(1)\"];"));
+        assert!(dot.contains("    block0 -> block1 [label=\"2x\", href=\"javascript:onCfgNodeClick(7)\"];"));
+    }
 }
@@ -0,0 +1,216 @@
+// Applying tracer events to a TraceDB: the one path every way of reading a
+// trace (a live tracer, a replayed or piped NDJSON stream, a saved file, the
+// synthetic traces in `testing`) goes through.
+
+use crate::db::{Change, ChangeFlags, Clnum, TraceDB, REG_RSP};
+use crate::protocol::TraceEvent;
+
+/// Whether `event` gets a clnum of its own. Memory accesses belong to the
+/// instruction that issued them, so they share its clnum; so do code
+/// invalidations, which take effect from the next clnum.
+pub fn advances_clnum(event: &TraceEvent) -> bool {
+    !matches!(
+        event,
+        TraceEvent::MemAccess { .. }
+            | TraceEvent::CodeInvalidated { .. }
+            | TraceEvent::BlockCoverage { .. }
+            | TraceEvent::ProcessEnd { .. }
+    )
+}
+
+// What tracers from before `bytes_valid` filled bytes QEMU didn't provide with
+const FILL_BYTE: u8 = 0xaa;
+
+// Bytes from such a tracer that end in its fill rather than in the
+// instruction, which shows as them not decoding to their own length (a lone
+// 0xaa is stosb). Their events parse with `bytes_valid` set, so this is the
+// only way to tell. It misfires on real bytes that happen to end in 0xaa but
+// don't decode to their length (an invalid or cut-short capture): those are
+// dropped too, and the instruction is read from memory instead, which for
+// code modified after it was loaded isn't what ran.
+fn is_fill_pattern(db: &TraceDB, bytes: &[u8], pc: u64) -> bool {
+    bytes.last() == Some(&FILL_BYTE) && !db.decodes_exactly(bytes, pc)
+}
+
+impl TraceDB {
+    /// Applies one tracer event, numbered `clnum` (see `advances_clnum`).
+    pub fn apply_event(&self, event: &TraceEvent, clnum: Clnum) {
+        match event {
+            TraceEvent::InsnExec {
+                vcpu_index,
+                pc,
+                bytes,
+                bytes_valid,
+                disasm,
+                regs,
+                discontinuous,
+            } => {
+                if clnum < 5 {
+                    tracing::debug!(clnum, pc = format_args!("{:x}", pc), regs = ?&regs[..regs.len().min(4)], "received registers");
+                }
+
+                // Better heuristic: scan all instructions, not just first 10
+                if let Some(ep) = self.get_entry_point() {
+                    // Check if this PC matches the entry point pattern
+                    // If -no-pie, pc should equal ep.
+                    // If PIE, pc = ep + bias.
+                    // Since we don't know bias, we check alignment.
+                    // 0x...1234 (pc) vs 0x...1234 (ep)
+                    if (pc & 0xFFF) == (ep & 0xFFF) {
+                        let bias = (*pc as i64) - (ep as i64);
+                        // Only set if we haven't found a bias or it's different/better
+                        // (e.g. bias=0 is preferred if -no-pie)
+                        let current_bias = self.get_bias();
+                        if current_bias == 0 && bias != 0 {
+                             // If we thought bias was 0 but found a PIE match, maybe update?
+                             // But if -no-pie, bias IS 0.
+                             tracing::debug!("candidate bias {:x} at clnum {}", bias, clnum);
+                             // If bias is huge (like 0x7fff...) it might be loader matching coincidently?
+                             // Loader addresses are usually high. Main binary usually 0x55...
+                             // If pc is 0x40xxxx, it's definitely main binary.
+
+                             // Prioritize low-memory addresses for main binary if possible
+                             if *pc < 0x7000_0000_0000 {
+                                 self.set_bias(bias);
+                             }
+                        } else if bias == 0 && current_bias != 0 {
+                            // Found exact match, prefer this!
+                            self.set_bias(0);
+                        }
+                    }
+                }
+                // Without captured bytes the disassembler reads them from memory
+                if *bytes_valid && !is_fill_pattern(self, bytes, *pc) {
+                    self.add_instruction(clnum, bytes.clone());
+                }
+                if let Some(d) = disasm {
+                    self.add_instruction_disasm(clnum, d.clone());
+                }
+
+                self.record_vcpu(clnum, *vcpu_index);
+                if !regs.is_empty() {
+                    self.update_registers_vcpu(clnum, *vcpu_index, regs);
+                    if let Some(&rsp) = regs.get(REG_RSP) {
+                        self.note_stack_pointer(*vcpu_index, clnum, rsp);
+                    }
+                }
+
+                let mut flags = ChangeFlags::IS_VALID | ChangeFlags::IS_START;
+                if *discontinuous {
+                    flags |= ChangeFlags::IS_ASYNC;
+                }
+                self.add_change(Change {
+                    address: *pc,
+                    data: 0,
+                    clnum,
+                    flags: flags.bits(),
+                });
+            }
+            TraceEvent::MemAccess {
+                vaddr,
+                is_store,
+                value,
+                size,
+                ..
+            } => {
+                // Older traces don't carry a size; assume a full 8-byte access
+                let bits = if *size == 0 { 64 } else { size * 8 };
+                let mut flags = ChangeFlags::IS_VALID | ChangeFlags::IS_MEM;
+                if *is_store {
+                    flags |= ChangeFlags::IS_WRITE;
+                }
+                if value.is_none() {
+                    flags |= ChangeFlags::NO_VALUE;
+                }
+                self.add_change(Change {
+                    address: *vaddr,
+                    data: value.unwrap_or(0),
+                    clnum,
+                    flags: flags.bits() | (bits & ChangeFlags::SIZE_MASK.bits()),
+                });
+            }
+            TraceEvent::CodeInvalidated { pc, bytes } => {
+                self.invalidate_code(clnum + 1, *pc, bytes.clone());
+            }
+            TraceEvent::BlockCoverage { blocks } => {
+                tracing::info!("tracer reported {} executed blocks", blocks.len());
+                self.add_block_coverage(blocks);
+            }
+            TraceEvent::TraceLimitReached { max_insns } => {
+                tracing::info!("tracer stopped after max_insns={} at clnum {}", max_insns, clnum);
+            }
+            TraceEvent::ProcessEnd { exit_code, signal } => {
+                self.record_process_end(*exit_code, *signal);
+                if let Some(crash) = self.crash() {
+                    tracing::info!("program was killed at clnum {} ({:#x})", crash.clnum, crash.address);
+                }
+            }
+            TraceEvent::Init { .. } => {}
+            TraceEvent::Exit { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::REG_COUNT;
+
+    #[test]
+    fn accesses_share_their_instruction_and_empty_registers_keep_the_state() {
+        let insn = |pc, regs: Vec<u64>| TraceEvent::InsnExec {
+            vcpu_index: 0,
+            pc,
+            bytes: vec![0x90],
+            bytes_valid: true,
+            disasm: None,
+            regs,
+            discontinuous: false,
+        };
+        let events = [
+            insn(0x401000, vec![7; REG_COUNT]),
+            TraceEvent::MemAccess { vcpu_index: 0, vaddr: 0x2000, is_store: true, value: Some(0x2a), size: 1 },
+            // A tracer that didn't capture registers this time
+            insn(0x401001, Vec::new()),
+        ];
+        let db = TraceDB::new(REG_COUNT);
+        let mut clnum = 0;
+        for event in &events {
+            if advances_clnum(event) {
+                clnum += 1;
+            }
+            db.apply_event(event, clnum);
+        }
+
+        assert_eq!(clnum, 2);
+        assert_eq!(db.get_memory_writes(0x2000), vec![1]);
+        assert_eq!(db.get_registers_at(2), vec![7; REG_COUNT]);
+    }
+
+    #[test]
+    fn filled_bytes_of_old_traces_are_read_from_memory() {
+        let insn = |pc, bytes: &[u8]| TraceEvent::InsnExec {
+            vcpu_index: 0,
+            pc,
+            bytes: bytes.to_vec(),
+            bytes_valid: true,
+            disasm: None,
+            regs: Vec::new(),
+            discontinuous: false,
+        };
+        let db = TraceDB::new(REG_COUNT);
+        // xor eax, eax in memory; the tracer only got its first byte
+        db.load_static_memory(0x401000, &[0x31, 0xc0]);
+        db.apply_event(&insn(0x401000, &[0x31, FILL_BYTE]), 1);
+        // stosb, whose one byte is the fill value
+        db.apply_event(&insn(0x401002, &[0xaa]), 2);
+        // add byte ptr [rax], al is all zeros but real
+        db.apply_event(&insn(0x401003, &[0x00, 0x00]), 3);
+
+        assert_eq!(db.get_disassembly_at(1), "xor eax, eax");
+        assert_eq!(db.insn_len(1, 0x401000), Some(2));
+        assert_eq!(db.get_disassembly_at(2), "stosb byte ptr [rdi], al");
+        assert_eq!(db.get_disassembly_at(3), "add byte ptr [rax], al");
+        assert_eq!(db.insn_len(3, 0x401003), Some(2));
+    }
+}
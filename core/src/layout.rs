@@ -0,0 +1,98 @@
+use crate::db::{Address, Clnum, TraceDB};
+use crate::protocol::{FieldType, StructField, StructFieldValue};
+
+// Longest C string shown for a pointee
+const MAX_POINTEE_STRING: usize = 64;
+
+impl TraceDB {
+    /// Reads memory at `address` as of `clnum` as a struct with `fields`,
+    /// laid out in order with natural alignment. Pointer fields are followed
+    /// one level to show what they point at.
+    pub fn read_struct(&self, clnum: Clnum, address: Address, fields: &[StructField]) -> Vec<StructFieldValue> {
+        let mut offset = 0u64;
+        fields
+            .iter()
+            .map(|field| {
+                let align = field.ty.align();
+                offset = offset.div_ceil(align) * align;
+                let bytes = self.get_memory_at(clnum, address + offset, field.ty.size() as usize);
+                let raw = bytes.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+
+                let (value, pointee) = match field.ty {
+                    FieldType::Chars { .. } => (format!("{:?}", c_string(&bytes)), None),
+                    FieldType::Ptr => (format!("0x{:x}", raw), self.describe_pointee(clnum, raw)),
+                    _ => (format!("0x{:x} ({})", raw, raw), None),
+                };
+                let result = StructFieldValue {
+                    name: field.name.clone(),
+                    offset,
+                    value,
+                    pointee,
+                };
+                offset += field.ty.size();
+                result
+            })
+            .collect()
+    }
+
+    // Symbol of the pointee plus its data: a C string if it looks like one,
+    // otherwise the first 8 bytes as a u64
+    fn describe_pointee(&self, clnum: Clnum, ptr: Address) -> Option<String> {
+        if ptr == 0 {
+            return None;
+        }
+        let bytes = self.get_memory_at(clnum, ptr, MAX_POINTEE_STRING);
+        let text = c_string(&bytes);
+        let data = if text.len() >= 2 && text.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+            format!("{:?}", text)
+        } else {
+            let raw = bytes[..8].iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+            format!("0x{:016x}", raw)
+        };
+        Some(match self.describe_address(ptr) {
+            Some(label) => format!("<{}> {}", label, data),
+            None => data,
+        })
+    }
+}
+
+// Bytes up to the first NUL, lossily decoded
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Change, ChangeFlags, REG_COUNT};
+
+    fn write(db: &TraceDB, address: u64, data: u64) {
+        db.add_change(Change {
+            address,
+            data,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 64,
+        });
+    }
+
+    #[test]
+    fn reads_aligned_fields_and_follows_pointers() {
+        let db = TraceDB::new(REG_COUNT);
+        write(&db, 0x1000, 0x2a); // id: u8 (padded to 8 for the pointer)
+        write(&db, 0x1008, 0x2000); // name: ptr
+        write(&db, 0x2000, u64::from_le_bytes(*b"koradar\0"));
+
+        let fields = vec![
+            StructField { name: "id".into(), ty: FieldType::U8 },
+            StructField { name: "name".into(), ty: FieldType::Ptr },
+            StructField { name: "tag".into(), ty: FieldType::Chars { len: 4 } },
+        ];
+        let values = db.read_struct(1, 0x1000, &fields);
+
+        assert_eq!(values[0].value, "0x2a (42)");
+        assert_eq!(values[1].offset, 8);
+        assert_eq!(values[1].pointee.as_deref(), Some("\"koradar\""));
+        assert_eq!(values[2].offset, 16);
+    }
+}
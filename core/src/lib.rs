@@ -1,10 +1,34 @@
+pub mod backend;
+mod cache;
 pub mod cfg;
+pub mod context;
+pub mod dataflow;
 pub mod db;
+pub mod diff;
 pub mod disasm;
+pub mod error;
 pub mod il;
+pub mod ingest;
+pub mod layout;
 pub mod loader;
-pub mod protocol;
+pub mod save;
+pub use koradar_protocol as protocol;
+pub mod sql;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod store;
+pub mod symbol_map;
+pub mod syscall;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-pub use db::{Address, Change, ChangeFlags, Clnum, TraceDB};
+pub use db::{
+    clamp_clnum, Address, Change, ChangeFlags, Clnum, Crash, DisasmOptions, DisasmView, Module, TraceDB, TraceLogOptions, TraceLogPage, REG_COUNT, REG_FS_BASE,
+    REG_GS_BASE, REG_NAMES, REG_RSP,
+};
+pub use backend::TraceBackend;
+pub use cache::CacheStats;
+pub use error::QueryError;
 pub use loader::BinaryLoader;
 pub use cfg::*;
+pub use symbol_map::{parse_symbol_map, MapSymbol};
@@ -1,11 +1,17 @@
+use crate::db::Module;
+use crate::disasm::Disassembler;
 use crate::{Address, TraceDB};
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use goblin::{elf, mach, pe, Object};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
 pub struct BinaryLoader;
 
+// Sections holding PLT stubs, with or without IBT (`.plt.sec`)
+const PLT_SECTIONS: [&str; 3] = [".plt", ".plt.sec", ".plt.got"];
+
 impl BinaryLoader {
     pub fn load_file(db: &TraceDB, path: &Path) -> Result<()> {
         let buffer = fs::read(path).context("Failed to read binary file")?;
@@ -13,7 +19,7 @@ impl BinaryLoader {
         match Object::parse(&buffer)? {
             Object::Elf(elf) => {
                 // Load loadable segments
-                for ph in elf.program_headers {
+                for ph in &elf.program_headers {
                     if ph.p_type == elf::program_header::PT_LOAD {
                         let start = ph.p_vaddr;
                         let size = ph.p_filesz;
@@ -41,8 +47,20 @@ impl BinaryLoader {
                     }
                 }
 
+                for (addr, size, name) in Self::plt_symbols(&elf, &buffer) {
+                    db.add_symbol(addr, size, name);
+                }
+                // Whole sections, as PLT0 and the lazy-binding entries behind
+                // `.plt.sec` get no name
+                for sh in &elf.section_headers {
+                    let name = elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("");
+                    if PLT_SECTIONS.contains(&name) && sh.sh_size > 0 {
+                        db.register_stub_range(sh.sh_addr, sh.sh_size);
+                    }
+                }
+
                 db.set_entry_point(elf.header.e_entry);
-                println!("Loaded ELF binary: {:?}", path);
+                tracing::info!("loaded ELF binary {:?}", path);
             }
             Object::PE(pe) => {
                 for section in pe.sections {
@@ -65,12 +83,172 @@ impl BinaryLoader {
                     }
                 }
 
-                println!("Loaded PE binary: {:?}", path);
+                tracing::info!("loaded PE binary {:?}", path);
             }
             // Add Mach-O support if needed
-            _ => println!("Unsupported binary format"),
+            _ => tracing::warn!("unsupported binary format: {:?}", path),
         }
 
         Ok(())
     }
+
+    /// Loads a shared library mapped at `runtime_base` as a separate `Module`.
+    /// Segments are placed at their runtime addresses; symbols stay static
+    /// and are translated with the module's bias on lookup.
+    pub fn load_module(db: &TraceDB, path: &Path, runtime_base: u64) -> Result<()> {
+        let buffer = fs::read(path).context("Failed to read module file")?;
+        let elf = match Object::parse(&buffer)? {
+            Object::Elf(elf) => elf,
+            _ => bail!("Unsupported module format: {:?}", path),
+        };
+
+        let loads: Vec<_> = elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == elf::program_header::PT_LOAD)
+            .collect();
+        let static_base = loads
+            .iter()
+            .map(|ph| ph.p_vaddr & !0xFFF)
+            .min()
+            .context("Module has no loadable segments")?;
+        let bias = runtime_base as i64 - static_base as i64;
+
+        let mut end = runtime_base;
+        for ph in &loads {
+            let run_start = (ph.p_vaddr as i64 + bias) as u64;
+            end = end.max(run_start + ph.p_memsz);
+            let offset = ph.p_offset as usize;
+            if ph.p_filesz > 0 && offset + ph.p_filesz as usize <= buffer.len() {
+                db.load_static_memory(run_start, &buffer[offset..offset + ph.p_filesz as usize]);
+            }
+        }
+
+        // Shared libraries are usually stripped, so take exports from .dynsym too
+        let mut symbols = BTreeMap::new();
+        for (syms, strtab) in [(&elf.syms, &elf.strtab), (&elf.dynsyms, &elf.dynstrtab)] {
+            for sym in syms.iter() {
+                if sym.st_type() == elf::sym::STT_FUNC && sym.st_value != 0 {
+                    if let Some(name) = strtab.get_at(sym.st_name) {
+                        symbols.insert(sym.st_value, (sym.st_size, name.to_string()));
+                    }
+                }
+            }
+        }
+        for (addr, size, name) in Self::plt_symbols(&elf, &buffer) {
+            symbols.insert(addr, (size, name));
+        }
+
+        db.push_module(Module {
+            path: path.display().to_string(),
+            bias,
+            start: runtime_base,
+            end,
+            symbols,
+        });
+        tracing::info!("loaded module {:?} at {:x}", path, runtime_base);
+        Ok(())
+    }
+
+    /// Names PLT stubs after the imported function they jump to (e.g. `puts@plt`).
+    ///
+    /// Every stub ends in `jmp [rip + disp]` through a GOT slot, so rather than
+    /// assuming a particular layout we decode each entry of `.plt`, `.plt.sec`
+    /// and `.plt.got` and match the slot against the JUMP_SLOT (lazy binding)
+    /// and GLOB_DAT (eager binding / `.plt.got`) relocations.
+    fn plt_symbols(elf: &elf::Elf, buffer: &[u8]) -> Vec<(u64, u64, String)> {
+        let mut symbols = Vec::new();
+        let mut slots: HashMap<u64, String> = HashMap::new();
+        for reloc in elf.pltrelocs.iter().chain(elf.dynrelas.iter()) {
+            if reloc.r_type != elf::reloc::R_X86_64_JUMP_SLOT
+                && reloc.r_type != elf::reloc::R_X86_64_GLOB_DAT
+            {
+                continue;
+            }
+            if let Some(name) = elf
+                .dynsyms
+                .get(reloc.r_sym)
+                .and_then(|sym| elf.dynstrtab.get_at(sym.st_name))
+            {
+                if !name.is_empty() {
+                    slots.insert(reloc.r_offset, name.to_string());
+                }
+            }
+        }
+        if slots.is_empty() {
+            return symbols;
+        }
+
+        let disassembler = match Disassembler::new() {
+            Ok(d) => d,
+            Err(_) => return symbols,
+        };
+
+        for sh in &elf.section_headers {
+            let name = elf.shdr_strtab.get_at(sh.sh_name).unwrap_or("");
+            if !PLT_SECTIONS.contains(&name) {
+                continue;
+            }
+            let entsize = if sh.sh_entsize > 0 { sh.sh_entsize } else { 16 };
+            let start = sh.sh_offset as usize;
+            let end = start + sh.sh_size as usize;
+            if end > buffer.len() {
+                continue;
+            }
+
+            for (i, entry) in buffer[start..end].chunks(entsize as usize).enumerate() {
+                let entry_addr = sh.sh_addr + i as u64 * entsize;
+                if let Some(slot) = disassembler.indirect_jump_slot(entry, entry_addr) {
+                    if let Some(sym) = slots.get(&slot) {
+                        symbols.push((entry_addr, entsize, format!("{}@plt", sym)));
+                    }
+                }
+            }
+        }
+        tracing::debug!("resolved {} PLT stubs", symbols.len());
+        symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::REG_COUNT;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+
+    // Builds C `source` into a dynamically linked executable with the
+    // system compiler
+    fn compile(source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("koradar-loader-test-{}", std::process::id()));
+        let mut cc = Command::new("cc")
+            .args(["-x", "c", "-", "-o"])
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("failed to run cc");
+        cc.stdin.take().unwrap().write_all(source.as_bytes()).unwrap();
+        assert!(cc.wait().unwrap().success(), "cc failed");
+        path
+    }
+
+    #[test]
+    #[ignore = "needs a C compiler (cc) and libc headers"]
+    fn names_plt_stubs_and_the_calls_through_them() {
+        let binary = compile("#include <stdio.h>\nint main(void) { puts(\"hi\"); return 0; }\n");
+        let db = TraceDB::new(REG_COUNT);
+        let loaded = BinaryLoader::load_file(&db, &binary);
+        let _ = fs::remove_file(&binary);
+        loaded.unwrap();
+
+        let stub = db.find_symbol_by_name("puts@plt").expect("no puts@plt symbol");
+        assert!(db.is_stub(stub));
+        let listing = db.function_listing("main");
+        assert!(
+            listing.iter().any(|(_, text)| text.starts_with("call") && text.contains("puts@plt")),
+            "{:?}",
+            listing
+        );
+    }
 }
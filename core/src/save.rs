@@ -0,0 +1,141 @@
+// Saving a trace in the tracer's own NDJSON format, so it loads back with
+// `--load-trace` (or `--replay`) like a recording of the live stream.
+
+use crate::db::{changes_at, ChangeFlags, Clnum, TraceDB};
+use crate::protocol::TraceEvent;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+impl TraceDB {
+    /// Writes the retained trace as tracer events, one JSON object per line:
+    /// every instruction with its registers and memory accesses, code
+    /// invalidations, block coverage and the crash point. Clnums without an
+    /// instruction become `Init` events, so loading the file numbers the
+    /// instructions as they are here (from the first retained clnum on).
+    pub fn write_events(&self, out: &mut impl Write) -> io::Result<()> {
+        // Invalidations take effect from the clnum after the one they're sent with
+        let mut invalidations: BTreeMap<Clnum, Vec<TraceEvent>> = BTreeMap::new();
+        for (&pc, history) in self.code_versions.read().iter() {
+            for (clnum, bytes) in history {
+                invalidations
+                    .entry(clnum.saturating_sub(1))
+                    .or_default()
+                    .push(TraceEvent::CodeInvalidated { pc, bytes: bytes.clone() });
+            }
+        }
+
+        let changes = self.changes.read();
+        let first = changes.first().map_or(1, |ch| ch.clnum);
+        let last = changes.last().map_or(0, |ch| ch.clnum);
+        for clnum in first..=last {
+            let effects = changes_at(&changes, clnum);
+            let vcpu_index = self.vcpu_at(clnum);
+            let start = effects
+                .iter()
+                .find(|ch| ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START));
+            let event = match start {
+                Some(start) => {
+                    let bytes = self.instructions.get(&clnum).map(|b| b.clone());
+                    let regs = if self.registers.read().contains_key(&vcpu_index) {
+                        self.get_registers_at_vcpu(clnum, vcpu_index)
+                    } else {
+                        Vec::new()
+                    };
+                    TraceEvent::InsnExec {
+                        vcpu_index,
+                        pc: start.address,
+                        bytes_valid: bytes.is_some(),
+                        bytes: bytes.unwrap_or_default(),
+                        disasm: self.instructions_disasm.get(&clnum).map(|d| d.clone()),
+                        regs,
+                        discontinuous: ChangeFlags::from_bits_truncate(start.flags).contains(ChangeFlags::IS_ASYNC),
+                    }
+                }
+                None => TraceEvent::Init { vcpu_index },
+            };
+            write_event(out, &event)?;
+
+            for ch in effects {
+                let flags = ChangeFlags::from_bits_truncate(ch.flags);
+                if flags.contains(ChangeFlags::IS_MEM) {
+                    let event = TraceEvent::MemAccess {
+                        vcpu_index,
+                        vaddr: ch.address,
+                        is_store: flags.contains(ChangeFlags::IS_WRITE),
+                        value: (!flags.contains(ChangeFlags::NO_VALUE)).then_some(ch.data),
+                        size: (ch.flags & ChangeFlags::SIZE_MASK.bits()) / 8,
+                    };
+                    write_event(out, &event)?;
+                }
+            }
+            for event in invalidations.remove(&clnum).unwrap_or_default() {
+                write_event(out, &event)?;
+            }
+        }
+        drop(changes);
+
+        let blocks: Vec<u64> = self.block_coverage.read().iter().copied().collect();
+        if !blocks.is_empty() {
+            write_event(out, &TraceEvent::BlockCoverage { blocks })?;
+        }
+        if let Some(crash) = self.crash() {
+            write_event(out, &TraceEvent::ProcessEnd { exit_code: None, signal: crash.signal })?;
+        }
+        Ok(())
+    }
+}
+
+fn write_event(out: &mut impl Write, event: &TraceEvent) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, event)?;
+    out.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Change, REG_COUNT};
+
+    #[test]
+    fn writes_one_event_per_clnum_and_its_accesses() {
+        let db = TraceDB::new(REG_COUNT);
+        db.add_instruction(1, vec![0x90]);
+        db.add_change(Change {
+            address: 0x1000,
+            data: 0,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        });
+        db.add_change(Change {
+            address: 0x2000,
+            data: 0x2a,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_MEM | ChangeFlags::IS_WRITE).bits() | 32,
+        });
+        // Clnum 2 had no instruction (e.g. a vcpu starting)
+        db.add_change(Change {
+            address: 0x1001,
+            data: 0,
+            clnum: 3,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        });
+        db.invalidate_code(3, 0x1001, vec![0xcc]);
+
+        let mut out = Vec::new();
+        db.write_events(&mut out).unwrap();
+        let events: Vec<TraceEvent> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 5);
+        assert!(matches!(
+            &events[0],
+            TraceEvent::InsnExec { pc: 0x1000, bytes, bytes_valid: true, regs, .. } if bytes == &[0x90] && regs.is_empty()
+        ));
+        assert!(matches!(events[1], TraceEvent::MemAccess { vaddr: 0x2000, is_store: true, value: Some(0x2a), size: 4, .. }));
+        assert!(matches!(events[2], TraceEvent::Init { .. }));
+        assert!(matches!(&events[3], TraceEvent::CodeInvalidated { pc: 0x1001, .. }));
+        assert!(matches!(events[4], TraceEvent::InsnExec { pc: 0x1001, bytes_valid: false, .. }));
+    }
+}
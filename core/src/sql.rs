@@ -0,0 +1,269 @@
+// Export of a trace as a SQLite script, so a session can be kept and queried
+// with ad-hoc SQL after the server is gone:
+//
+//   sqlite3 trace.db < trace.sql
+//   sqlite3 trace.db "SELECT clnum, disassembly FROM instructions WHERE disassembly LIKE 'call%'"
+//
+// SQLite integers are signed 64-bit, so addresses and values at or above
+// 2^63 come out negative; printf('%x', value) shows the original bits.
+
+use crate::db::{Change, ChangeFlags, Clnum, TraceDB};
+use std::io::{self, Write};
+
+// `registers` holds each register's value from the clnum it was first seen
+// with on (before that instruction ran), and `memory` each byte written from
+// the clnum of the write on (NULL if the tracer didn't capture it); the latest
+// row at or before a clnum is the state then. `static_memory` has the bytes
+// loaded from the binary, one row per run.
+const SCHEMA: &str = "\
+CREATE TABLE changes (clnum INTEGER NOT NULL, address INTEGER NOT NULL, data INTEGER NOT NULL, flags INTEGER NOT NULL);
+CREATE TABLE instructions (clnum INTEGER PRIMARY KEY, address INTEGER NOT NULL, vcpu INTEGER NOT NULL, bytes BLOB, disassembly TEXT NOT NULL);
+CREATE TABLE registers (vcpu INTEGER NOT NULL, reg INTEGER NOT NULL, clnum INTEGER NOT NULL, value INTEGER NOT NULL, PRIMARY KEY (vcpu, reg, clnum)) WITHOUT ROWID;
+CREATE TABLE memory (address INTEGER NOT NULL, clnum INTEGER NOT NULL, value INTEGER, PRIMARY KEY (address, clnum)) WITHOUT ROWID;
+CREATE TABLE static_memory (address INTEGER PRIMARY KEY, data BLOB NOT NULL);
+CREATE TABLE code_ranges (start INTEGER NOT NULL, end INTEGER NOT NULL);
+CREATE TABLE symbols (address INTEGER PRIMARY KEY, size INTEGER NOT NULL, name TEXT NOT NULL);
+CREATE TABLE regions (address INTEGER PRIMARY KEY, len INTEGER NOT NULL, name TEXT NOT NULL);
+CREATE TABLE meta (key TEXT PRIMARY KEY, value);
+";
+
+// Created after the data is in, which is much faster than maintaining them
+// row by row
+const INDEXES: &str = "\
+CREATE INDEX changes_clnum ON changes (clnum);
+CREATE INDEX changes_address ON changes (address);
+CREATE INDEX instructions_address ON instructions (address);
+CREATE INDEX symbols_name ON symbols (name);
+";
+
+// Rows per INSERT statement
+const INSERT_BATCH: usize = 500;
+
+// Views splitting the change log by kind. The flag bits come from
+// `ChangeFlags` so the views can't drift from the log's encoding.
+fn views() -> String {
+    let write = ChangeFlags::IS_WRITE.bits();
+    let mem = ChangeFlags::IS_MEM.bits();
+    let start = ChangeFlags::IS_START.bits();
+    let size = ChangeFlags::SIZE_MASK.bits();
+    let no_value = ChangeFlags::NO_VALUE.bits();
+    format!(
+        "\
+CREATE VIEW register_writes AS
+    SELECT clnum, address / 8 AS reg, data AS value FROM changes
+    WHERE (flags & {not_reg:#x}) = 0 AND (flags & {write:#x}) != 0;
+CREATE VIEW memory_writes AS
+    SELECT clnum, address, {value} AS value, (flags & {size:#x}) / 8 AS size FROM changes
+    WHERE (flags & {access:#x}) = {access:#x};
+CREATE VIEW memory_reads AS
+    SELECT clnum, address, {value} AS value, (flags & {size:#x}) / 8 AS size FROM changes
+    WHERE (flags & {access:#x}) = {mem:#x};
+",
+        not_reg = mem | start,
+        access = mem | write,
+        // Accesses whose value wasn't captured show NULL
+        value = format!("CASE WHEN (flags & {:#x}) != 0 THEN NULL ELSE data END", no_value),
+    )
+}
+
+impl TraceDB {
+    /// Writes the whole trace as a SQLite script: the change log, one row per
+    /// executed instruction with its bytes and disassembly, register and
+    /// memory history, the loaded image bytes, user code ranges, symbols,
+    /// named regions and the load bias, plus views splitting the changes into
+    /// register writes and memory reads and writes. `sqlite::SqliteTrace`
+    /// answers state queries from the resulting database.
+    pub fn write_sql(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(b"BEGIN TRANSACTION;\n")?;
+        out.write_all(SCHEMA.as_bytes())?;
+
+        // Copy out what the instruction rows need, since get_disassembly_at
+        // takes the change log again
+        let starts: Vec<(Clnum, u64)> = {
+            let changes = self.changes.read();
+            let mut rows = Batch::new(out, "INSERT INTO changes");
+            for ch in changes.iter() {
+                rows.row(&change_row(ch))?;
+            }
+            rows.finish()?;
+            changes
+                .iter()
+                .filter(|ch| ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_START))
+                .map(|ch| (ch.clnum, ch.address))
+                .collect()
+        };
+
+        let mut rows = Batch::new(out, "INSERT INTO instructions");
+        for (clnum, address) in starts {
+            let bytes = match self.instructions.get(&clnum) {
+                Some(bytes) if !bytes.is_empty() => blob(&bytes),
+                _ => String::from("NULL"),
+            };
+            rows.row(&format!(
+                "({}, {}, {}, {}, {})",
+                clnum,
+                address as i64,
+                self.vcpu_at(clnum),
+                bytes,
+                text(&self.get_disassembly_at(clnum))
+            ))?;
+        }
+        rows.finish()?;
+
+        let mut registers: Vec<(u32, usize, Clnum, u64)> = Vec::new();
+        for (&vcpu, history) in self.registers.read().iter() {
+            for (reg, values) in history.iter().enumerate() {
+                registers.extend(values.iter().map(|&(clnum, value)| (vcpu, reg, clnum, value)));
+            }
+        }
+        registers.sort_unstable();
+        // A register part written twice by one instruction keeps the last value
+        let mut rows = Batch::new(out, "INSERT OR REPLACE INTO registers");
+        for (vcpu, reg, clnum, value) in registers {
+            rows.row(&format!("({}, {}, {}, {})", vcpu, reg, clnum, value as i64))?;
+        }
+        rows.finish()?;
+
+        let (writes, loaded) = self.memory_contents();
+        let mut rows = Batch::new(out, "INSERT OR REPLACE INTO memory");
+        for (address, clnum, value) in writes {
+            let value = value.map_or_else(|| String::from("NULL"), |value| value.to_string());
+            rows.row(&format!("({}, {}, {})", address as i64, clnum, value))?;
+        }
+        rows.finish()?;
+
+        let mut rows = Batch::new(out, "INSERT INTO static_memory");
+        let mut run: Vec<u8> = Vec::new();
+        let mut run_start = 0;
+        for (address, value) in loaded {
+            if !run.is_empty() && address != run_start + run.len() as u64 {
+                rows.row(&format!("({}, {})", run_start as i64, blob(&run)))?;
+                run.clear();
+            }
+            if run.is_empty() {
+                run_start = address;
+            }
+            run.push(value);
+        }
+        if !run.is_empty() {
+            rows.row(&format!("({}, {})", run_start as i64, blob(&run)))?;
+        }
+        rows.finish()?;
+
+        let mut rows = Batch::new(out, "INSERT INTO code_ranges");
+        for (start, end) in self.user_ranges() {
+            rows.row(&format!("({}, {})", start as i64, end as i64))?;
+        }
+        rows.finish()?;
+
+        let mut symbols: Vec<(u64, u64, String)> = self
+            .symbols
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().0, entry.value().1.clone()))
+            .collect();
+        symbols.sort();
+        let mut rows = Batch::new(out, "INSERT INTO symbols");
+        for (address, size, name) in symbols {
+            rows.row(&format!("({}, {}, {})", address as i64, size as i64, text(&name)))?;
+        }
+        rows.finish()?;
+
+        let mut rows = Batch::new(out, "INSERT INTO regions");
+        for region in self.regions() {
+            rows.row(&format!("({}, {}, {})", region.address as i64, region.len as i64, text(&region.name)))?;
+        }
+        rows.finish()?;
+
+        writeln!(out, "INSERT INTO meta VALUES ('bias', {});", self.get_bias())?;
+        writeln!(out, "INSERT INTO meta VALUES ('reg_count', {});", self.reg_count)?;
+        out.write_all(INDEXES.as_bytes())?;
+        out.write_all(views().as_bytes())?;
+        out.write_all(b"COMMIT;\n")
+    }
+}
+
+// Multi-row INSERT statements into one table, e.g. "INSERT INTO changes"
+struct Batch<'a, W: Write> {
+    out: &'a mut W,
+    insert: &'static str,
+    pending: usize,
+}
+
+impl<'a, W: Write> Batch<'a, W> {
+    fn new(out: &'a mut W, insert: &'static str) -> Self {
+        Self { out, insert, pending: 0 }
+    }
+
+    fn row(&mut self, values: &str) -> io::Result<()> {
+        if self.pending == 0 {
+            write!(self.out, "{} VALUES\n{}", self.insert, values)?;
+        } else {
+            write!(self.out, ",\n{}", values)?;
+        }
+        self.pending += 1;
+        if self.pending == INSERT_BATCH {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.pending > 0 {
+            self.out.write_all(b";\n")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+fn change_row(ch: &Change) -> String {
+    format!("({}, {}, {}, {})", ch.clnum, ch.address as i64, ch.data as i64, ch.flags)
+}
+
+// SQL string literal
+fn text(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+// SQL blob literal
+fn blob(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("X'{}'", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_changes_instructions_and_symbols() {
+        let db = TraceDB::new(16);
+        db.add_symbol(0x401000, 8, "it's".to_string());
+        db.load_static_memory(0x400000, &[0x7f, 0x45]);
+        db.add_instruction(1, vec![0x48, 0x89, 0xcb]);
+        db.add_change(Change {
+            address: 0x401000,
+            data: 0,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_START).bits(),
+        });
+        db.add_change(Change {
+            address: 3 * 8,
+            data: u64::MAX,
+            clnum: 1,
+            flags: (ChangeFlags::IS_VALID | ChangeFlags::IS_WRITE).bits() | 64,
+        });
+
+        let mut out = Vec::new();
+        db.write_sql(&mut out).unwrap();
+        let sql = String::from_utf8(out).unwrap();
+
+        assert!(sql.starts_with("BEGIN TRANSACTION;\n"));
+        assert!(sql.ends_with("COMMIT;\n"));
+        assert!(sql.contains("INSERT INTO changes VALUES\n(1, 4198400, 0, 2415919104),\n(1, 24, -1, 3221225536);\n"));
+        assert!(sql.contains("INSERT INTO instructions VALUES\n(1, 4198400, 0, X'4889cb', 'mov rbx, rcx');\n"));
+        assert!(sql.contains("INSERT INTO symbols VALUES\n(4198400, 8, 'it''s');\n"));
+        assert!(sql.contains("INSERT OR REPLACE INTO registers VALUES\n(0, 3, 1, -1);\n"));
+        assert!(sql.contains("INSERT INTO static_memory VALUES\n(4194304, X'7f45');\n"));
+    }
+}
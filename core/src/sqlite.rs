@@ -0,0 +1,307 @@
+// A trace kept in a SQLite database (the schema `TraceDB::write_sql` writes),
+// queried in place instead of loaded into memory, so a session outlives the
+// server and can grow past RAM. Only the state queries of `TraceBackend` are
+// answered from it; analyses such as the CFG still need the trace in memory.
+
+use crate::backend::TraceBackend;
+use crate::db::{is_start, split_effects, Address, Change, ChangeFlags, Clnum, TraceDB, TraceLogOptions, TraceLogPage};
+use crate::disasm::Disassembler;
+use crate::protocol::TraceEntry;
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::Path;
+
+// Clnums `get_trace_log` scans without finding an entry before giving up, as
+// `TraceDB::get_trace_log` does
+const MAX_EMPTY_SCAN: u32 = 100_000;
+
+pub struct SqliteTrace {
+    // A connection can't be shared between threads
+    conn: Mutex<Connection>,
+    reg_count: usize,
+    bias: i64,
+    // Static [start, end) ranges of user code
+    code_ranges: Vec<(u64, u64)>,
+    // Decodes the stored instruction bytes for the timeline's groups
+    disassembler: Mutex<Disassembler>,
+}
+
+impl SqliteTrace {
+    /// Writes `db` to a new database at `path`, replacing any file there,
+    /// and opens it.
+    pub fn create(path: &Path, db: &TraceDB) -> Result<Self> {
+        let mut script = Vec::new();
+        db.write_sql(&mut script)?;
+        let script = String::from_utf8(script).context("SQL script is not UTF-8")?;
+        if path.exists() {
+            std::fs::remove_file(path).with_context(|| format!("failed to replace {}", path.display()))?;
+        }
+        let conn = Connection::open(path).with_context(|| format!("failed to create {}", path.display()))?;
+        conn.execute_batch(&script).context("failed to write the trace")?;
+        drop(conn);
+        Self::open(path)
+    }
+
+    /// Opens a database written by `create` (or from a `write_sql` script).
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let meta = |key: &str| -> Result<i64> {
+            conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+                .with_context(|| format!("{} has no {} (not a koradar trace?)", path.display(), key))
+        };
+        let bias = meta("bias")?;
+        let reg_count = meta("reg_count")? as usize;
+        let code_ranges = conn
+            .prepare("SELECT start, end FROM code_ranges")?
+            .query_map([], |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            reg_count,
+            bias,
+            code_ranges,
+            disassembler: Mutex::new(Disassembler::new()?),
+        })
+    }
+
+    fn static_address(&self, address: Address) -> u64 {
+        (address as i128 - self.bias as i128) as u64
+    }
+
+    // Like `TraceDB::is_user_code` for the main binary: everything when no
+    // ranges were recorded
+    fn is_user_code(&self, address: Address) -> bool {
+        let address = self.static_address(address);
+        self.code_ranges.is_empty()
+            || self.code_ranges.iter().any(|&(start, end)| address >= start && address < end)
+    }
+
+    // Name of the symbol containing runtime `address`
+    fn symbol_at(&self, conn: &Connection, address: Address) -> rusqlite::Result<Option<String>> {
+        let address = self.static_address(address);
+        let symbol: Option<(i64, i64, String)> = conn
+            .prepare_cached("SELECT address, size, name FROM symbols WHERE address <= ?1 ORDER BY address DESC LIMIT 1")?
+            .query_row([address as i64], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .optional()?;
+        Ok(symbol
+            .filter(|&(start, size, _)| address - (start as u64) < (size as u64).max(1))
+            .map(|(_, _, name)| name))
+    }
+
+    // Whether `bytes`, executed at runtime `address`, differ from what the
+    // binary has there. False where the binary wasn't loaded.
+    fn is_patched(&self, conn: &Connection, address: Address, bytes: &[u8]) -> rusqlite::Result<bool> {
+        let address = self.static_address(address);
+        let run: Option<(i64, Vec<u8>)> = conn
+            .prepare_cached("SELECT address, data FROM static_memory WHERE address <= ?1 ORDER BY address DESC LIMIT 1")?
+            .query_row([address as i64], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        Ok(run.is_some_and(|(start, data)| {
+            let offset = (address - start as u64) as usize;
+            data.get(offset..offset + bytes.len()).is_some_and(|on_disk| on_disk != bytes)
+        }))
+    }
+
+    fn max_clnum_in(&self, conn: &Connection) -> rusqlite::Result<Clnum> {
+        Ok(conn
+            .query_row("SELECT MAX(clnum) FROM changes", [], |row| row.get::<_, Option<u32>>(0))?
+            .unwrap_or(0))
+    }
+
+    fn query<T>(&self, query: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Option<T> {
+        let conn = self.conn.lock();
+        match query(&conn) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                tracing::warn!(error = %e, "SQLite trace query failed");
+                None
+            }
+        }
+    }
+}
+
+impl TraceBackend for SqliteTrace {
+    fn max_clnum(&self) -> Clnum {
+        self.query(|conn| self.max_clnum_in(conn)).unwrap_or(0)
+    }
+
+    fn get_registers_at(&self, clnum: Clnum) -> Vec<u64> {
+        self.query(|conn| {
+            let vcpu: u32 = conn
+                .prepare_cached("SELECT vcpu FROM instructions WHERE clnum <= ?1 ORDER BY clnum DESC LIMIT 1")?
+                .query_row([clnum], |row| row.get(0))
+                .optional()?
+                .unwrap_or(0);
+            let mut stmt = conn.prepare_cached(
+                "SELECT value FROM registers WHERE vcpu = ?1 AND reg = ?2 AND clnum <= ?3 ORDER BY clnum DESC LIMIT 1",
+            )?;
+            (0..self.reg_count)
+                .map(|reg| {
+                    let value: Option<i64> = stmt.query_row(params![vcpu, reg, clnum], |row| row.get(0)).optional()?;
+                    Ok(value.unwrap_or(0) as u64)
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| vec![0; self.reg_count])
+    }
+
+    fn get_memory_at(&self, clnum: Clnum, addr: Address, size: usize) -> Vec<u8> {
+        self.query(|conn| {
+            let mut written =
+                conn.prepare_cached("SELECT value FROM memory WHERE address = ?1 AND clnum <= ?2 ORDER BY clnum DESC LIMIT 1")?;
+            let mut loaded =
+                conn.prepare_cached("SELECT address, data FROM static_memory WHERE address <= ?1 ORDER BY address DESC LIMIT 1")?;
+            (0..size as u64)
+                .map(|i| {
+                    let address = addr + i;
+                    // A NULL value is a write the tracer didn't capture
+                    let value = written
+                        .query_row(params![address as i64, clnum], |row| row.get::<_, Option<u8>>(0))
+                        .optional()?;
+                    if let Some(value) = value {
+                        return Ok(value.unwrap_or(0));
+                    }
+                    let run: Option<(i64, Vec<u8>)> =
+                        loaded.query_row([address as i64], |row| Ok((row.get(0)?, row.get(1)?))).optional()?;
+                    Ok(run
+                        .and_then(|(start, data)| data.get((address - start as u64) as usize).copied())
+                        .unwrap_or(0))
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| vec![0; size])
+    }
+
+    fn annotated_disassembly(&self, clnum: Clnum) -> String {
+        self.query(|conn| {
+            conn.prepare_cached("SELECT disassembly FROM instructions WHERE clnum = ?1")?
+                .query_row([clnum], |row| row.get(0))
+                .optional()
+        })
+        .flatten()
+        .unwrap_or_default()
+    }
+
+    fn has_symbol(&self, name: &str) -> bool {
+        self.query(|conn| {
+            conn.prepare_cached("SELECT 1 FROM symbols WHERE name = ?1")?
+                .exists([name])
+        })
+        .unwrap_or(false)
+    }
+
+    /// As `TraceDB::get_trace_log`, except that library calls are never
+    /// collapsed (the database doesn't pair calls with their returns) and
+    /// entries carry no return values or operand links.
+    fn get_trace_log(&self, start: Clnum, count: u32, options: TraceLogOptions) -> TraceLogPage {
+        let TraceLogOptions { only_user_code, function, fold, .. } = options;
+        self.query(|conn| {
+            let mut instructions = conn.prepare_cached(
+                "SELECT clnum, address, disassembly, bytes FROM instructions WHERE clnum >= ?1 ORDER BY clnum",
+            )?;
+            let mut effects = conn.prepare_cached("SELECT address, data, flags FROM changes WHERE clnum = ?1")?;
+            let mut rows = instructions.query([start])?;
+            let mut entries: Vec<TraceEntry> = Vec::new();
+            let mut next_start = None;
+            let mut last_examined = start;
+
+            while let Some(row) = rows.next()? {
+                let clnum: Clnum = row.get(0)?;
+                let address = row.get::<_, i64>(1)? as u64;
+                let shown = (!only_user_code || self.is_user_code(address))
+                    && match function {
+                        Some(name) => self.symbol_at(conn, address)?.as_deref() == Some(name),
+                        None => true,
+                    };
+                if let Some(last) = entries.last_mut().filter(|last| fold && shown && last.address == address) {
+                    last.repeats += 1;
+                    last.last_repeat = Some(clnum);
+                } else if entries.len() == count as usize {
+                    next_start = Some(clnum);
+                    break;
+                } else if shown {
+                    let changes: Vec<Change> = effects
+                        .query_map([clnum], |row| {
+                            Ok(Change {
+                                address: row.get::<_, i64>(0)? as u64,
+                                data: row.get::<_, i64>(1)? as u64,
+                                clnum,
+                                flags: row.get(2)?,
+                            })
+                        })?
+                        .collect::<rusqlite::Result<_>>()?;
+                    let (reg_diffs, mem_accesses) = split_effects(&changes);
+                    let bytes: Vec<u8> = row.get::<_, Option<Vec<u8>>>(3)?.unwrap_or_default();
+                    let groups = self.disassembler.lock().groups(&bytes, address).unwrap_or_default();
+                    let async_entry = changes.iter().any(|ch| {
+                        is_start(ch) && ChangeFlags::from_bits_truncate(ch.flags).contains(ChangeFlags::IS_ASYNC)
+                    });
+                    entries.push(TraceEntry {
+                        clnum,
+                        address,
+                        disassembly: row.get(2)?,
+                        reg_diffs,
+                        mem_accesses,
+                        return_value: None,
+                        patched: !bytes.is_empty() && self.is_patched(conn, address, &bytes)?,
+                        async_entry,
+                        groups,
+                        skipped_insns: 0,
+                        repeats: 0,
+                        last_repeat: None,
+                        reg_defs: Vec::new(),
+                    });
+                } else if entries.is_empty() && clnum > start + MAX_EMPTY_SCAN {
+                    next_start = Some(clnum);
+                    break;
+                }
+                last_examined = clnum;
+            }
+            if next_start.is_none() && last_examined < self.max_clnum_in(conn)? {
+                next_start = Some(last_examined + 1);
+            }
+            Ok(TraceLogPage { entries, next_start })
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::memory_write;
+
+    #[test]
+    fn answers_state_queries_like_the_in_memory_trace() {
+        let db = memory_write().build();
+        db.load_static_memory(0x600000, &[0xaa, 0xbb]);
+        // The store at clnum 2 ran patched: the binary has [rbp - 0x10]
+        db.load_static_memory(0x401005, &[0x48, 0x89, 0x45, 0xf0]);
+        let path = std::env::temp_dir().join(format!("koradar-sqlite-test-{}.db", std::process::id()));
+        let trace = SqliteTrace::create(&path, &db).unwrap();
+
+        assert_eq!(TraceBackend::max_clnum(&trace), 3);
+        for clnum in 1..=3 {
+            assert_eq!(trace.get_registers_at(clnum), db.get_registers_at(clnum));
+        }
+        let slot = 0x7ffd_ffd8;
+        assert_eq!(TraceBackend::get_memory_at(&trace, 1, slot, 8), vec![0; 8]);
+        assert_eq!(TraceBackend::get_memory_at(&trace, 2, slot, 8), db.get_memory_at(2, slot, 8));
+        assert_eq!(TraceBackend::get_memory_at(&trace, 3, 0x5fffff, 4), vec![0, 0xaa, 0xbb, 0]);
+
+        let page = TraceBackend::get_trace_log(&trace, 2, 1, TraceLogOptions::default());
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].disassembly, db.get_disassembly_at(2));
+        assert_eq!(page.entries[0].mem_accesses, vec![(slot, Some(0x2a), true)]);
+        assert_eq!(page.next_start, Some(3));
+        let in_memory = db.get_trace_log(2, 1, TraceLogOptions::default()).entries.remove(0);
+        assert_eq!(page.entries[0].groups, in_memory.groups);
+        assert!(page.entries[0].patched && in_memory.patched);
+        assert!(!page.entries[0].async_entry);
+
+        drop(trace);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,203 @@
+// Backing storage for the raw change log. Changes live in a Vec by default;
+// a store created with a spill file moves them into a memory-mapped file once
+// it grows past a threshold, so very long traces don't have to fit in RAM.
+
+use crate::db::Change;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+const CHANGE_SIZE: usize = std::mem::size_of::<Change>();
+
+/// Number of changes kept in memory before a spilling store moves to its file
+/// (24 MiB of changes).
+pub(crate) const DEFAULT_SPILL_THRESHOLD: usize = 1 << 20;
+
+pub(crate) struct ChangeStore {
+    backing: Backing,
+    // File to spill into and the change count that triggers it; taken once
+    // the store is mapped
+    spill: Option<(File, usize)>,
+}
+
+enum Backing {
+    Memory(Vec<Change>),
+    // The first `len` records of `map` are live; the rest is spare capacity
+    Mapped { file: File, map: MmapMut, len: usize },
+}
+
+impl ChangeStore {
+    pub(crate) fn new() -> Self {
+        Self {
+            backing: Backing::Memory(Vec::new()),
+            spill: None,
+        }
+    }
+
+    /// A store that spills to `path` (created or truncated) after `threshold` changes.
+    pub(crate) fn spilling(path: &Path, threshold: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            backing: Backing::Memory(Vec::new()),
+            spill: Some((file, threshold.max(1))),
+        })
+    }
+
+    pub(crate) fn push(&mut self, change: Change) {
+        if let Backing::Memory(vec) = &self.backing {
+            if self.spill.as_ref().is_some_and(|&(_, threshold)| vec.len() >= threshold) {
+                self.spill_to_file();
+            }
+        }
+
+        if let Backing::Mapped { map, len, .. } = &self.backing {
+            if (len + 1) * CHANGE_SIZE > map.len() {
+                if let Err(e) = self.grow() {
+                    tracing::error!("failed to grow change spill file, falling back to memory: {}", e);
+                    self.unmap();
+                }
+            }
+        }
+
+        match &mut self.backing {
+            Backing::Memory(vec) => vec.push(change),
+            Backing::Mapped { map, len, .. } => {
+                mapped_slice_mut(map)[*len] = change;
+                *len += 1;
+            }
+        }
+    }
+
+    /// Removes the first `count` changes (the oldest clnums).
+    pub(crate) fn drain_front(&mut self, count: usize) {
+        match &mut self.backing {
+            Backing::Memory(vec) => {
+                vec.drain(..count);
+            }
+            Backing::Mapped { map, len, .. } => {
+                let count = count.min(*len);
+                mapped_slice_mut(map).copy_within(count..*len, 0);
+                *len -= count;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        match &mut self.backing {
+            Backing::Memory(vec) => vec.clear(),
+            Backing::Mapped { len, .. } => *len = 0,
+        }
+    }
+
+    // Moves the in-memory changes into the spill file, with room to double
+    fn spill_to_file(&mut self) {
+        let Some((file, _)) = self.spill.take() else {
+            return;
+        };
+        let Backing::Memory(vec) = &mut self.backing else {
+            return;
+        };
+        match map_file(&file, vec.len() * 2) {
+            Ok(mut map) => {
+                mapped_slice_mut(&mut map)[..vec.len()].copy_from_slice(vec);
+                tracing::info!("spilling {} changes to a memory-mapped file", vec.len());
+                self.backing = Backing::Mapped {
+                    file,
+                    map,
+                    len: vec.len(),
+                };
+            }
+            Err(e) => tracing::error!("failed to map change spill file, keeping changes in memory: {}", e),
+        }
+    }
+
+    fn grow(&mut self) -> io::Result<()> {
+        if let Backing::Mapped { file, map, len } = &mut self.backing {
+            *map = map_file(file, (*len * 2).max(1))?;
+        }
+        Ok(())
+    }
+
+    // Copies mapped changes back into memory; used if the file can't grow
+    fn unmap(&mut self) {
+        if let Backing::Mapped { .. } = self.backing {
+            self.backing = Backing::Memory(self.deref().to_vec());
+        }
+    }
+}
+
+impl Deref for ChangeStore {
+    type Target = [Change];
+
+    fn deref(&self) -> &[Change] {
+        match &self.backing {
+            Backing::Memory(vec) => vec,
+            Backing::Mapped { map, len, .. } => &mapped_slice(map)[..*len],
+        }
+    }
+}
+
+// Sizes `file` to hold `capacity` changes and maps it
+fn map_file(file: &File, capacity: usize) -> io::Result<MmapMut> {
+    file.set_len((capacity * CHANGE_SIZE) as u64)?;
+    // SAFETY: the file was created (truncated) by this store and is only
+    // accessed through this mapping, which lives behind the DB's lock
+    unsafe { MmapMut::map_mut(file) }
+}
+
+// SAFETY (both views): mappings are page-aligned, which satisfies Change's
+// alignment, and Change is a #[repr(C)] record of integers without padding,
+// so any bytes are a valid Change
+fn mapped_slice(map: &MmapMut) -> &[Change] {
+    unsafe { std::slice::from_raw_parts(map.as_ptr() as *const Change, map.len() / CHANGE_SIZE) }
+}
+
+fn mapped_slice_mut(map: &mut MmapMut) -> &mut [Change] {
+    unsafe { std::slice::from_raw_parts_mut(map.as_mut_ptr() as *mut Change, map.len() / CHANGE_SIZE) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(clnum: u32) -> Change {
+        Change {
+            address: 0x1000 + clnum as u64,
+            data: clnum as u64 * 3,
+            clnum,
+            flags: 0x8000_0000,
+        }
+    }
+
+    #[test]
+    fn spilled_store_keeps_append_and_read_order() {
+        let path = std::env::temp_dir().join(format!("koradar-spill-test-{}", std::process::id()));
+        let mut store = ChangeStore::spilling(&path, 16).unwrap();
+        for clnum in 0..100 {
+            store.push(change(clnum));
+        }
+        // Spilled: the file holds the changes (plus spare capacity)
+        assert!(std::fs::metadata(&path).unwrap().len() >= 100 * CHANGE_SIZE as u64);
+        assert_eq!(store.len(), 100);
+        assert!(store.iter().enumerate().all(|(i, c)| c.clnum == i as u32 && c.data == i as u64 * 3));
+
+        store.drain_front(40);
+        assert_eq!(store.len(), 60);
+        assert_eq!(store[0].clnum, 40);
+        assert_eq!(store.partition_point(|c| c.clnum < 70), 30);
+
+        store.clear();
+        store.push(change(7));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store[0].address, 0x1007);
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::db::TraceDB;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// A symbol read from a map file. `size` is None when the map doesn't give one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSymbol {
+    pub address: u64,
+    pub size: Option<u64>,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+struct JsonSymbol {
+    name: String,
+    address: JsonNumber,
+    #[serde(default)]
+    size: Option<JsonNumber>,
+}
+
+// Scripts exporting from IDA or Ghidra tend to write addresses as hex strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonNumber {
+    Number(u64),
+    Text(String),
+}
+
+impl JsonNumber {
+    fn value(&self) -> Result<u64> {
+        match self {
+            JsonNumber::Number(n) => Ok(*n),
+            JsonNumber::Text(text) => parse_number(text),
+        }
+    }
+}
+
+/// Parses a symbol map: a JSON array of `{"name", "address", "size"}` objects
+/// (size optional, numbers or "0x" strings), or text with one symbol per line
+/// as `ADDRESS NAME`, `ADDRESS SIZE NAME` or `nm` output (`ADDRESS TYPE NAME`,
+/// of which only code symbols are kept). Text addresses are hex, with or
+/// without "0x"; blank lines and lines starting with '#' are skipped.
+pub fn parse_symbol_map(text: &str) -> Result<Vec<MapSymbol>> {
+    if text.trim_start().starts_with('[') {
+        let entries: Vec<JsonSymbol> = serde_json::from_str(text).context("invalid JSON symbol map")?;
+        return entries
+            .into_iter()
+            .map(|entry| {
+                Ok(MapSymbol {
+                    address: entry.address.value()?,
+                    size: entry.size.map(|size| size.value()).transpose()?,
+                    name: entry.name,
+                })
+            })
+            .collect();
+    }
+
+    let mut symbols = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (address, size, name) = match fields[..] {
+            [address, name] => (address, None, name),
+            // nm: T/t are text, W/w weak (usually functions too)
+            [address, kind, name] if kind.len() == 1 && kind.chars().all(|c| c.is_ascii_alphabetic()) => {
+                if !matches!(kind, "T" | "t" | "W" | "w") {
+                    continue;
+                }
+                (address, None, name)
+            }
+            [address, size, name] => (address, Some(size), name),
+            _ => bail!("line {}: expected ADDRESS [SIZE|TYPE] NAME, got {:?}", i + 1, line),
+        };
+        let address = u64::from_str_radix(address.trim_start_matches("0x"), 16)
+            .with_context(|| format!("line {}: bad address {:?}", i + 1, address))?;
+        let size = size
+            .map(parse_number)
+            .transpose()
+            .with_context(|| format!("line {}: bad size", i + 1))?;
+        symbols.push(MapSymbol { address, size, name: name.to_string() });
+    }
+    Ok(symbols)
+}
+
+// "0x" hex or decimal
+fn parse_number(text: &str) -> Result<u64> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.with_context(|| format!("bad number {:?}", text))
+}
+
+impl TraceDB {
+    /// Loads symbol names from a map file (see `parse_symbol_map`), e.g.
+    /// exported from IDA, Ghidra or `nm`, for a stripped main binary.
+    /// Returns the number of symbols added.
+    pub fn import_symbols_from_map(&self, path: &Path) -> Result<usize> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let symbols = parse_symbol_map(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+        Ok(self.import_symbols(symbols))
+    }
+
+    /// Adds map symbols to the main binary's. Their addresses are static,
+    /// as these tools list them for the unrelocated binary, so like the ELF's
+    /// own symbols they follow the load bias. A symbol without a size extends
+    /// to the next known symbol, or to the end of its code segment.
+    pub fn import_symbols(&self, mut symbols: Vec<MapSymbol>) -> usize {
+        symbols.sort_by_key(|s| s.address);
+        let mut starts: Vec<u64> = self.symbols.iter().map(|r| *r.key()).collect();
+        starts.extend(symbols.iter().map(|s| s.address));
+        starts.sort_unstable();
+        starts.dedup();
+        let code_ranges = self.user_code_ranges.read().clone();
+
+        let count = symbols.len();
+        for symbol in symbols {
+            let size = symbol.size.unwrap_or_else(|| {
+                let next = starts.get(starts.partition_point(|&s| s <= symbol.address)).copied();
+                let segment_end = code_ranges
+                    .iter()
+                    .find(|&&(start, end)| symbol.address >= start && symbol.address < end)
+                    .map(|&(_, end)| end);
+                next.or(segment_end).map_or(0, |end| end - symbol.address)
+            });
+            self.add_symbol(symbol.address, size, symbol.name);
+        }
+        // Cached text has branch targets named
+        self.insn_cache.clear();
+        tracing::debug!(count, "imported map symbols");
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::REG_COUNT;
+
+    #[test]
+    fn parses_text_nm_and_json_maps() {
+        let text = "# exported names\n0x401000 _start\n401126 0x20 main\n0000000000401146 T helper\n0000000000404010 B buffer\n";
+        let symbols = parse_symbol_map(text).unwrap();
+        let parsed: Vec<_> = symbols.iter().map(|s| (s.address, s.size, s.name.as_str())).collect();
+        assert_eq!(
+            parsed,
+            [(0x401000, None, "_start"), (0x401126, Some(0x20), "main"), (0x401146, None, "helper")]
+        );
+
+        let json = r#"[{"name": "main", "address": "0x401126", "size": 32}, {"name": "f", "address": 4198720}]"#;
+        let symbols = parse_symbol_map(json).unwrap();
+        assert_eq!(symbols[0], MapSymbol { address: 0x401126, size: Some(32), name: "main".into() });
+        assert_eq!(symbols[1].address, 0x401140);
+
+        assert!(parse_symbol_map("0x401000 too many fields here").is_err());
+    }
+
+    #[test]
+    fn sizeless_symbols_extend_to_the_next_one() {
+        let db = TraceDB::new(REG_COUNT);
+        db.register_code_range(0x1000, 0x100);
+        db.set_bias(0x5000);
+        db.import_symbols(vec![
+            MapSymbol { address: 0x1000, size: None, name: "first".into() },
+            MapSymbol { address: 0x1040, size: None, name: "second".into() },
+        ]);
+
+        // Runtime addresses go through the load bias
+        assert_eq!(db.symbolize(0x603f), Some(("first".to_string(), 0x3f)));
+        assert_eq!(db.symbolize(0x60ff), Some(("second".to_string(), 0xbf)));
+        assert_eq!(db.symbolize(0x6100), None);
+    }
+}
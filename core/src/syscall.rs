@@ -0,0 +1,43 @@
+// Linux x86-64 syscall conventions, enough to know where a syscall's result
+// lands (see TraceDB::taint_from_syscall)
+
+use crate::db::Address;
+
+// Register indices (see REG_NAMES) of the syscall number and arguments
+pub const NR_REG: usize = 0;
+pub const RESULT_REG: usize = 0;
+pub const ARG_REGS: [usize; 6] = [5, 4, 3, 10, 8, 9];
+
+// (number, name, argument holding the buffer the kernel fills, if any).
+// The number of bytes filled is the syscall's return value.
+const SYSCALLS: &[(u64, &str, Option<usize>)] = &[
+    (0, "read", Some(1)),
+    (1, "write", None),
+    (2, "open", None),
+    (3, "close", None),
+    (9, "mmap", None),
+    (17, "pread64", Some(1)),
+    (41, "socket", None),
+    (43, "accept", None),
+    (45, "recvfrom", Some(1)),
+    (79, "getcwd", Some(0)),
+    (89, "readlink", Some(1)),
+    (257, "openat", None),
+    (267, "readlinkat", Some(2)),
+    (318, "getrandom", Some(0)),
+];
+
+/// Name of syscall `nr`, for the common ones.
+pub fn name(nr: u64) -> Option<&'static str> {
+    SYSCALLS.iter().find(|&&(n, _, _)| n == nr).map(|&(_, name, _)| name)
+}
+
+/// Address of the buffer syscall `nr` fills, given its arguments in order.
+/// None for syscalls that don't write to user memory (or aren't known to).
+pub fn output_buffer(nr: u64, args: &[u64; 6]) -> Option<Address> {
+    SYSCALLS
+        .iter()
+        .find(|&&(n, _, _)| n == nr)
+        .and_then(|&(_, _, arg)| arg)
+        .map(|arg| args[arg])
+}
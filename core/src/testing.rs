@@ -0,0 +1,249 @@
+// Synthetic traces for tests, so the DB and the analyses on top of it can be
+// exercised without running a program under QEMU. Available to this crate's
+// tests, and to other crates with the `testing` feature.
+
+use crate::db::{Address, Clnum, TraceDB, REG_COUNT, REG_NAMES, REG_RSP};
+use crate::ingest::advances_clnum;
+use crate::protocol::TraceEvent;
+
+/// Builds a trace as the tracer would send it: one `InsnExec` per
+/// instruction, carrying the registers as they were before it ran, followed
+/// by its memory accesses.
+///
+/// Register writes given with `reg` belong to the last instruction added, so
+/// they show up in the registers of the next one. Before the first
+/// instruction they set the initial state.
+pub struct TraceBuilder {
+    events: Vec<TraceEvent>,
+    regs: Vec<u64>,
+    vcpu: u32,
+}
+
+impl Default for TraceBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceBuilder {
+    /// An empty trace on vcpu 0, with rsp at 0x7ffe_0000 and every other
+    /// register zero.
+    pub fn new() -> Self {
+        let mut regs = vec![0; REG_COUNT];
+        regs[REG_RSP] = 0x7ffe_0000;
+        Self {
+            events: Vec::new(),
+            regs,
+            vcpu: 0,
+        }
+    }
+
+    /// Runs the following instructions on `vcpu`.
+    pub fn vcpu(&mut self, vcpu: u32) -> &mut Self {
+        self.vcpu = vcpu;
+        self
+    }
+
+    /// Executes the instruction `bytes` at `pc`.
+    pub fn insn(&mut self, pc: Address, bytes: &[u8]) -> &mut Self {
+        self.events.push(TraceEvent::InsnExec {
+            vcpu_index: self.vcpu,
+            pc,
+            bytes: bytes.to_vec(),
+            bytes_valid: true,
+            disasm: None,
+            regs: self.regs.clone(),
+            discontinuous: false,
+        });
+        self
+    }
+
+    /// Sets register `name` (one of `REG_NAMES`) to `value`. Panics on other
+    /// names.
+    pub fn reg(&mut self, name: &str, value: u64) -> &mut Self {
+        let index = REG_NAMES
+            .iter()
+            .position(|&r| r == name)
+            .unwrap_or_else(|| panic!("unknown register {}", name));
+        self.regs[index] = value;
+        self
+    }
+
+    /// The last instruction wrote the `size` low bytes of `value` at `address`.
+    pub fn mem_write(&mut self, address: Address, value: u64, size: u32) -> &mut Self {
+        self.mem_access(address, value, size, true)
+    }
+
+    /// The last instruction read `value` (`size` bytes) at `address`.
+    pub fn mem_read(&mut self, address: Address, value: u64, size: u32) -> &mut Self {
+        self.mem_access(address, value, size, false)
+    }
+
+    fn mem_access(&mut self, address: Address, value: u64, size: u32, is_store: bool) -> &mut Self {
+        self.events.push(TraceEvent::MemAccess {
+            vcpu_index: self.vcpu,
+            vaddr: address,
+            is_store,
+            value: Some(value),
+            size,
+        });
+        self
+    }
+
+    /// The program ended here, killed by `signal` if it's set.
+    pub fn process_end(&mut self, exit_code: Option<i64>, signal: Option<i32>) -> &mut Self {
+        self.events.push(TraceEvent::ProcessEnd { exit_code, signal });
+        self
+    }
+
+    /// Value of register `name` after the last instruction.
+    pub fn reg_value(&self, name: &str) -> u64 {
+        REG_NAMES.iter().position(|&r| r == name).map_or(0, |i| self.regs[i])
+    }
+
+    /// The events so far, as the tracer would send them.
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// The events as an NDJSON trace file, for `--load-trace` or `--replay`.
+    pub fn to_ndjson(&self) -> String {
+        self.events
+            .iter()
+            .filter_map(|event| serde_json::to_string(event).ok())
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// A new DB holding the trace, numbered from clnum 1.
+    pub fn build(&self) -> TraceDB {
+        let db = TraceDB::new(REG_COUNT);
+        self.apply_to(&db);
+        db
+    }
+
+    /// Records the trace in `db` the way the server ingests tracer events
+    /// (see `ingest`): memory accesses share the clnum of their instruction.
+    pub fn apply_to(&self, db: &TraceDB) {
+        let mut clnum: Clnum = 0;
+        for event in &self.events {
+            if advances_clnum(event) {
+                clnum += 1;
+            }
+            db.apply_event(event, clnum);
+        }
+    }
+}
+
+/// A countdown loop at 0x401000 running `iterations` times (at least once):
+///
+/// ```text
+/// 0x401000  mov ecx, iterations
+/// 0x401005  dec ecx
+/// 0x401007  jne 0x401005
+/// 0x401009  ret
+/// ```
+pub fn counted_loop(iterations: u8) -> TraceBuilder {
+    let mut trace = TraceBuilder::new();
+    trace.insn(0x401000, &[0xb9, iterations, 0x00, 0x00, 0x00]).reg("rcx", iterations as u64);
+    let mut rcx = iterations.max(1);
+    loop {
+        rcx -= 1;
+        trace.insn(0x401005, &[0xff, 0xc9]).reg("rcx", rcx as u64);
+        trace.insn(0x401007, &[0x75, 0xfc]);
+        if rcx == 0 {
+            break;
+        }
+    }
+    trace.insn(0x401009, &[0xc3]);
+    trace
+}
+
+/// A call into a function that sets eax and returns, with the return
+/// address pushed and popped on the stack:
+///
+/// ```text
+/// 0x401000  call 0x401010
+/// 0x401010  mov eax, 1
+/// 0x401015  ret
+/// 0x401005  nop
+/// ```
+pub fn call_and_return() -> TraceBuilder {
+    let mut trace = TraceBuilder::new();
+    let rsp = trace.reg_value("rsp");
+    trace
+        .insn(0x401000, &[0xe8, 0x0b, 0x00, 0x00, 0x00])
+        .mem_write(rsp - 8, 0x401005, 8)
+        .reg("rsp", rsp - 8);
+    trace.insn(0x401010, &[0xb8, 0x01, 0x00, 0x00, 0x00]).reg("rax", 1);
+    trace.insn(0x401015, &[0xc3]).mem_read(rsp - 8, 0x401005, 8).reg("rsp", rsp);
+    trace.insn(0x401005, &[0x90]);
+    trace
+}
+
+/// A value stored to a stack slot and loaded back into another register:
+///
+/// ```text
+/// 0x401000  mov eax, 0x2a
+/// 0x401005  mov qword ptr [rbp - 8], rax
+/// 0x401009  mov rbx, qword ptr [rbp - 8]
+/// ```
+pub fn memory_write() -> TraceBuilder {
+    let mut trace = TraceBuilder::new();
+    let rbp = trace.reg_value("rsp") - 0x20;
+    trace.reg("rbp", rbp);
+    trace.insn(0x401000, &[0xb8, 0x2a, 0x00, 0x00, 0x00]).reg("rax", 0x2a);
+    trace.insn(0x401005, &[0x48, 0x89, 0x45, 0xf8]).mem_write(rbp - 8, 0x2a, 8);
+    trace.insn(0x401009, &[0x48, 0x8b, 0x5d, 0xf8]).mem_read(rbp - 8, 0x2a, 8).reg("rbx", 0x2a);
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TraceLogOptions;
+
+    #[test]
+    fn loop_executes_its_body_once_per_iteration() {
+        let db = counted_loop(3).build();
+        assert_eq!(db.execution_count(0x401005), 3);
+        assert_eq!(db.execution_count(0x401009), 1);
+
+        // Taken back to the loop head after the first two decrements
+        let cfg = db.analyze_cfg(false, false, false).unwrap();
+        let back_edge = cfg.edges.iter().find(|e| e.head == e.tail).unwrap();
+        assert_eq!(back_edge.clnums, vec![4, 6]);
+
+        assert_eq!(db.get_trace_log(1, 100, TraceLogOptions::default()).entries.len(), 8);
+    }
+
+    #[test]
+    fn call_returns_its_value_and_steps_over() {
+        let db = call_and_return().build();
+        assert_eq!(db.step_over(1, 4), 4);
+        assert_eq!(db.call_return_value(1), Some(1));
+        assert_eq!(db.get_memory_writes(0x7ffe_0000 - 8), vec![1]);
+    }
+
+    #[test]
+    fn finds_a_return_that_arrives_after_the_first_lookup() {
+        let mut trace = call_and_return();
+        let last = trace.events.pop().unwrap();
+        let db = trace.build();
+        assert_eq!(db.call_return_value(1), None);
+        assert_eq!(db.step_over(1, 3), 3);
+
+        // The return site executes once the live trace goes on
+        db.apply_event(&last, 4);
+        assert_eq!(db.call_return_value(1), Some(1));
+        assert_eq!(db.step_over(1, 4), 4);
+    }
+
+    #[test]
+    fn slice_follows_the_value_through_memory() {
+        let trace = memory_write();
+        let db = trace.build();
+        assert_eq!(db.get_slice(3, "rbx".to_string()).unwrap(), vec![1, 2, 3]);
+        assert_eq!(trace.to_ndjson().lines().count(), trace.events().len());
+    }
+}
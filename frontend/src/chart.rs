@@ -0,0 +1,67 @@
+// Step chart of a memory value over the trace (see GetValueHistory)
+
+use yew::prelude::*;
+
+const WIDTH: f64 = 270.0;
+const HEIGHT: f64 = 100.0;
+const PAD: f64 = 6.0;
+// Points drawn; longer histories are thinned evenly, keeping the last point
+const MAX_POINTS: usize = 500;
+
+/// Draws `points` (writing clnum, value after it) as steps from the first
+/// write to `end_clnum`, with a marker at `current`. Clicking a point passes
+/// its clnum to `on_point`.
+pub fn value_chart(points: &[(u32, u64)], end_clnum: u32, current: u32, on_point: Callback<u32>) -> Html {
+    let (Some(&(first, _)), Some(&(last, _))) = (points.first(), points.last()) else {
+        return html! {};
+    };
+    let step = points.len().div_ceil(MAX_POINTS);
+    let shown: Vec<(u32, u64)> = points
+        .iter()
+        .step_by(step)
+        .chain((!(points.len() - 1).is_multiple_of(step)).then_some(&points[points.len() - 1]))
+        .copied()
+        .collect();
+
+    let end = end_clnum.max(last).max(first + 1);
+    let min = shown.iter().map(|&(_, v)| v).min().unwrap_or(0);
+    let max = shown.iter().map(|&(_, v)| v).max().unwrap_or(0);
+    let x = |clnum: u32| PAD + (clnum - first) as f64 / (end - first) as f64 * (WIDTH - 2.0 * PAD);
+    let y = |value: u64| {
+        if max == min {
+            HEIGHT / 2.0
+        } else {
+            HEIGHT - PAD - (value - min) as f64 / (max - min) as f64 * (HEIGHT - 2.0 * PAD)
+        }
+    };
+
+    let mut path = format!("M {:.1} {:.1}", x(shown[0].0), y(shown[0].1));
+    for &(clnum, value) in &shown[1..] {
+        path.push_str(&format!(" H {:.1} V {:.1}", x(clnum), y(value)));
+    }
+    path.push_str(&format!(" H {:.1}", x(end)));
+
+    let marker = (first..=end).contains(&current).then(|| x(current));
+
+    html! {
+        <svg class="value-chart" width={WIDTH.to_string()} height={HEIGHT.to_string()}>
+            if let Some(mx) = marker {
+                <line x1={mx.to_string()} y1="0" x2={mx.to_string()} y2={HEIGHT.to_string()} class="value-chart-now" />
+            }
+            <path d={path} class="value-chart-line" />
+            {
+                for shown.iter().map(|&(clnum, value)| {
+                    let on_click = {
+                        let on_point = on_point.clone();
+                        Callback::from(move |_: MouseEvent| on_point.emit(clnum))
+                    };
+                    html! {
+                        <circle cx={format!("{:.1}", x(clnum))} cy={format!("{:.1}", y(value))} r="3" class="value-chart-point" onclick={on_click}>
+                            <title>{ format!("clnum {}: {:#x}", clnum, value) }</title>
+                        </circle>
+                    }
+                })
+            }
+        </svg>
+    }
+}
@@ -0,0 +1,77 @@
+// Interpretation of raw bytes for the value inspector in the memory panel
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Width {
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl Width {
+    pub const ALL: [Width; 6] = [Width::U8, Width::U16, Width::U32, Width::U64, Width::F32, Width::F64];
+
+    pub fn size(self) -> usize {
+        match self {
+            Width::U8 => 1,
+            Width::U16 => 2,
+            Width::U32 | Width::F32 => 4,
+            Width::U64 | Width::F64 => 8,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Width::U8 => "8",
+            Width::U16 => "16",
+            Width::U32 => "32",
+            Width::U64 => "64",
+            Width::F32 => "float",
+            Width::F64 => "double",
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Width::F32 | Width::F64)
+    }
+}
+
+/// Bytes decoded under one interpretation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Value {
+    // The `width` bytes assembled in the chosen byte order
+    pub raw: u64,
+    pub hex: String,
+    pub text: String,
+}
+
+/// Decodes the first `width.size()` bytes of `bytes`, or None if there are
+/// too few. `signed` only affects integer widths.
+pub fn interpret(bytes: &[u8], width: Width, big_endian: bool, signed: bool) -> Option<Value> {
+    let size = width.size();
+    let bytes = bytes.get(..size)?;
+    let raw = if big_endian {
+        bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+    } else {
+        bytes.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+    };
+
+    let text = match width {
+        Width::F32 => f32::from_bits(raw as u32).to_string(),
+        Width::F64 => f64::from_bits(raw).to_string(),
+        _ if signed => {
+            // Sign-extend from the top bit of the selected width
+            let shift = 64 - size as u32 * 8;
+            (((raw << shift) as i64) >> shift).to_string()
+        }
+        _ => raw.to_string(),
+    };
+
+    Some(Value {
+        raw,
+        hex: format!("0x{:0w$x}", raw, w = size * 2),
+        text,
+    })
+}
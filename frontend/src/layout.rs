@@ -0,0 +1,43 @@
+// Parsing of struct overlay layouts typed into the memory panel
+
+use crate::{FieldType, StructField};
+
+/// Parses "name: type" fields separated by ';', ',' or newlines, where type
+/// is one of u8, u16, u32, u64, ptr or char[N].
+pub fn parse_layout(text: &str) -> Result<Vec<StructField>, String> {
+    let fields = text
+        .split([';', ',', '\n'])
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(|field| {
+            let (name, ty) = field
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'name: type', got '{}'", field))?;
+            Ok(StructField {
+                name: name.trim().to_string(),
+                ty: parse_type(ty.trim())?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if fields.is_empty() {
+        return Err("struct layout has no fields".to_string());
+    }
+    Ok(fields)
+}
+
+fn parse_type(ty: &str) -> Result<FieldType, String> {
+    match ty {
+        "u8" => Ok(FieldType::U8),
+        "u16" => Ok(FieldType::U16),
+        "u32" => Ok(FieldType::U32),
+        "u64" => Ok(FieldType::U64),
+        "ptr" => Ok(FieldType::Ptr),
+        _ => ty
+            .strip_prefix("char[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|len| len.trim().parse::<usize>().ok())
+            .filter(|&len| len > 0)
+            .map(|len| FieldType::Chars { len })
+            .ok_or_else(|| format!("unknown field type '{}' (u8..u64, ptr, char[N])", ty)),
+    }
+}
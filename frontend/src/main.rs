@@ -1,136 +1,261 @@
 use futures::{SinkExt, StreamExt};
 use gloo_net::websocket::{futures::WebSocket, Message};
-use serde::{Deserialize, Serialize};
+use gloo_timers::future::TimeoutFuture;
+use koradar_protocol::{
+    BreakCondition, Breakpoint, CfgFormat, ClientMessage, CompareOp, CondOperand, DisasmSyntax, FieldType, ImmediateBase, InsnDetail, NamedRegion, OperandDetail, RegisterWriter, ServerMessage,
+    SessionConfig, StructField, StructFieldValue, SymbolStats, TraceEntry, WriteOrigin, PROTOCOL_VERSION,
+};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::{spawn_local, JsFuture};
-use web_sys::{Event, HtmlInputElement, InputEvent, KeyboardEvent};
+use web_sys::{Element, Event, HtmlInputElement, HtmlSelectElement, InputEvent, KeyboardEvent};
 use yew::prelude::*;
 
+mod chart;
+mod interp;
+mod layout;
+mod permalink;
+mod symbols;
+
+use chart::value_chart;
+use interp::{interpret, Width};
+use layout::parse_layout;
+use permalink::Permalink;
+use symbols::{sorted_symbols, visible_rows, SymbolColumn, ROW_HEIGHT};
+
+const REG_NAMES: [&str; 18] = [
+    "RAX", "RBX", "RCX", "RDX", "RSI", "RDI", "RBP", "RSP", "R8", "R9", "R10", "R11", "R12", "R13",
+    "R14", "R15", "FS_BASE", "GS_BASE",
+];
+const REG_RBP: usize = 6;
+const REG_RSP: usize = 7;
+
+// Instructions previewed after the current one
+const LOOKAHEAD_COUNT: usize = 5;
+// Slider idle time before the dragged-to clnum is queried
+const SLIDER_DEBOUNCE_MS: u32 = 50;
+// Entries fetched per live append while following the tail
+const LIVE_APPEND_COUNT: u32 = 200;
+// Clnums the tail cursor may lag behind before following refetches the
+// tail outright instead of catching up page by page
+const LIVE_APPEND_MAX_GAP: u32 = 2000;
+// Entries kept in the timeline; appends drop the oldest beyond this
+const TIMELINE_LIMIT: usize = 2000;
+// Rows of the block iteration table rendered; later iterations are counted only
+const ITERATION_ROWS_SHOWN: usize = 2000;
+// Slices of the trace the register change marks are grouped into, one per
+// 0.2% of the slider so long traces don't draw thousands of markers
+const MARKER_BUCKETS: u32 = 500;
+
+// What the value inspector is decoding
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum InspectSource {
+    // Bytes of the memory view starting at this address
+    Memory(u64),
+    // A register value, laid out in memory (little-endian) order
+    Register(usize),
+}
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_name = renderMermaid)]
     fn render_mermaid(id: &str, text: &str) -> js_sys::Promise;
 
+    #[wasm_bindgen(js_name = renderSvg)]
+    fn render_svg(id: &str, svg: &str);
+
     #[wasm_bindgen(js_name = searchFunctionInCFG)]
     fn search_function_in_cfg(func_name: &str);
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct TraceEntry {
-    clnum: u32,
-    address: u64,
-    disassembly: String,
-    reg_diff: Option<(usize, u64)>,
-    mem_access: Option<(u64, u64, bool)>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "type")]
-enum ClientMessage {
-    QueryState {
-        clnum: u32,
-        memory_addr: Option<u64>,
-    },
-    GetTraceLog {
-        start: u32,
-        count: u32,
-        only_user_code: bool,
-    },
-    StepForward {
-        current: u32,
-    },
-    StepBackward {
-        current: u32,
-    },
-    GetCFG {
-        only_user_code: bool,
-        start_from_main: bool,
-    },
-    GetSlice {
-        clnum: u32,
-        target: String,
-    },
-    AskAI {
-        clnum: u32,
-    },
-    GetMemoryWrites {
-        address: u64,
-    },
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(tag = "type")]
-enum ServerMessage {
-    StateUpdate {
-        clnum: u32,
-        registers: Vec<u64>,
-        memory: Vec<u8>,
-        memory_addr: u64,
-        disassembly: String,
-    },
-    TraceEvent(serde_json::Value),
-    TraceLog {
-        entries: Vec<TraceEntry>,
-    },
-    MaxClnum {
-        max: u32,
-    },
-    CFG {
-        graph: String,
-    },
-    AIResponse {
-        text: String,
-    },
-    MemoryWrites {
-        address: u64,
-        writes: Vec<u32>,
-    },
-    Slice {
-        entries: Vec<TraceEntry>,
-    },
+    #[wasm_bindgen(js_name = downloadJSON)]
+    fn download_json(filename: &str, text: &str);
 }
 
 #[function_component(App)]
 pub fn app() -> Html {
     let trace_log = use_state(Vec::new);
     let current_clnum = use_state(|| 0u32);
+    // Clnum of the last StateUpdate. Views that fetch per clnum follow this
+    // rather than `current_clnum`, which moves with every slider step
+    let settled_clnum = use_state(|| 0u32);
+    // Bumped on every slider input; a pending query only fires if it is
+    // still the latest
+    let slider_generation = use_mut_ref(|| 0u32);
     let max_clnum = use_state(|| 0u32);
-    let registers = use_state(|| vec![0u64; 16]);
+    // Instructions per second arriving from a live tracer, 0 when idle
+    let trace_rate = use_state(|| 0u64);
+    // (clnum, address, signal) of the last instruction of a killed program
+    let crash = use_state(|| None::<(u32, u64, Option<i32>)>);
+    let registers = use_state(|| vec![0u64; 18]);
+    // Registers whose value differs from the previous StateUpdate:
+    // (index, bits that changed), so a write to al highlights only its byte
+    let changed_regs = use_state(Vec::<(usize, u64)>::new);
     let memory = use_state(|| vec![0u8; 256]);
     let memory_addr = use_state(|| 0u64);
+    // Re-point the hexdump at rsp on every StateUpdate. A ref, not state,
+    // so the websocket task sees it change
+    let follow_rsp = use_mut_ref(|| false);
     let memory_writes = use_state(Vec::<u32>::new);
+    let memory_reads = use_state(Vec::<u32>::new);
+    // Last ValueHistory reply: (address, width, (clnum, value) per write)
+    let value_history = use_state(|| None::<(u64, u32, Vec<(u32, u64)>)>);
+    // Access history panel shows reads instead of writes
+    let show_reads = use_state(|| false);
+    // Stack ranges [low, high] known at the current clnum, used to label stack memory
+    let stack_regions = use_state(Vec::<(u64, u64)>::new);
+    // Result of clicking a hex byte when it has no writing instruction to jump to
+    let last_writer_note = use_state(|| None::<String>);
+    // Register under the mouse, and the last RegisterProvenance reply:
+    // (clnum, register, instruction that wrote it)
+    let hovered_reg = use_state(|| None::<usize>);
+    let reg_provenance = use_state(|| None::<(u32, usize, Option<RegisterWriter>)>);
+    // Register whose changes are marked on the slider, and the clnums that changed it
+    let reg_markers = use_state(|| None::<(usize, Vec<(u32, u32)>)>);
     let current_disasm = use_state(|| String::from("Waiting for trace..."));
+    // Value inspector: selection (shift+click a byte or click a register) and interpretation
+    let inspect_sel = use_state(|| None::<InspectSource>);
+    let inspect_width = use_state(|| Width::U64);
+    let inspect_big_endian = use_state(|| false);
+    let inspect_signed = use_state(|| false);
+    // Struct overlay: layout text, parsed fields applied at the memory view
+    // address, and the last StructView reply
+    let struct_text = use_state(String::new);
+    let struct_layout = use_state(|| None::<Vec<StructField>>);
+    let struct_view = use_state(|| None::<Vec<StructFieldValue>>);
+    // Last AddressInfo reply, used to label pointer-looking values
+    let address_label = use_state(|| None::<(u64, Option<String>)>);
+    // Breakpoints known to the server, and the address and optional
+    // condition being typed in
+    let breakpoints = use_state(Vec::<Breakpoint>::new);
+    let breakpoint_text = use_state(String::new);
+    let breakpoint_condition = use_state(String::new);
+    let breakpoint_static = use_state(|| false);
+    // Clnum and hex address being typed into the jump boxes
+    let goto_clnum_text = use_state(String::new);
+    let goto_address_text = use_state(String::new);
+    // User-named memory regions, and the "name [len]" being typed in
+    let regions = use_state(Vec::<NamedRegion>::new);
+    let region_text = use_state(String::new);
+    // Server-side settings (None until the first Config reply) and the
+    // modules that can be made the active one
+    let session_config = use_state(|| None::<SessionConfig>);
+    let config_modules = use_state(Vec::<String>::new);
+    let show_settings = use_state(|| false);
+    // Client-side window sizes: instructions either side of the current one
+    // in the AskAI context and in the timeline
+    let ai_context_lines = use_state(|| 5u32);
+    let timeline_radius = use_state(|| 20u32);
+    // Operand breakdown popover for the current instruction (Some(None) = not decodable)
+    let insn_detail = use_state(|| None::<Option<InsnDetail>>);
+    // Static disassembly of the instructions following the current one
+    let lookahead = use_state(Vec::<(u64, String)>::new);
+    // Last Coverage reply: (symbol, executed, executed instruction count)
+    let coverage = use_state(Vec::<(String, bool, u32)>::new);
+    // Last FunctionListing reply: (function, (address, disassembly, first clnum executed))
+    let function_listing = use_state(|| (String::new(), Vec::<(u64, String, Option<u32>)>::new()));
+    // Registers to follow across block iterations, and the last BlockIterations
+    // reply: (block address, register indices, (clnum, values) per execution)
+    let iteration_regs = use_state(|| String::from("rcx, rsi, rdi"));
+    // Last Symbols reply, with the function list's filter, sort order
+    // (column, descending) and scroll position (scroll top, viewport height)
+    let symbols = use_state(|| Rc::new(Vec::<SymbolStats>::new()));
+    let symbol_filter = use_state(String::new);
+    let symbol_sort = use_state(|| (SymbolColumn::Executions, true));
+    let symbol_viewport = use_state(|| (0i32, 600i32));
+    let block_iterations = use_state(|| (0u64, Vec::<usize>::new(), Vec::<(u32, Vec<u64>)>::new()));
     let ws_sender = use_state(|| None::<futures::channel::mpsc::UnboundedSender<Message>>);
 
-    let ai_response = use_state(|| String::new());
+    let ai_response = use_state(String::new);
+    // Last error reported by the server, shown as a dismissable banner
+    let error_banner = use_state(|| None::<String>);
     let is_ai_loading = use_state(|| false);
 
     let view_mode = use_state(|| "timeline"); // "log" or "timeline" or "cfg"
     let only_user_code = use_state(|| false);
     let start_from_main = use_state(|| false);
-    let search_term = use_state(|| String::new());
-    let slice_target = use_state(|| String::new());
+    // Have the server render the CFG to SVG with Graphviz instead of Mermaid
+    let cfg_svg = use_state(|| false);
+    // Show trace A's CFG merged with trace B's (see DiffCFG)
+    let cfg_diff = use_state(|| false);
+    // Draw calls through PLT stubs straight to the function they resolved to
+    let cfg_collapse_stubs = use_state(|| false);
+    // The server has a second trace loaded to diff against
+    let has_second_trace = use_state(|| false);
+    let search_term = use_state(String::new);
+    let function_filter = use_state(String::new);
+    let slice_target = use_state(String::new);
+    // Header for slices that didn't come from slice_target (syscall taint)
+    let slice_title = use_state(|| None::<String>);
     
     let timeline_entries = use_state(Vec::<TraceEntry>::new);
-    let cfg_graph = use_state(|| String::new());
+    // Cursor for the next timeline page (infinite scroll)
+    let timeline_next = use_state(|| None::<u32>);
+    // Set while a next-page request is in flight, so its reply is appended
+    let timeline_appending = use_mut_ref(|| false);
+    // Where scanning past the last timeline entry resumes; unlike
+    // timeline_next it stays set at the end of the trace, for live appends
+    let timeline_tail = use_mut_ref(|| None::<u32>);
+    // Append new entries as the live trace grows instead of refetching
+    let follow_tail = use_state(|| false);
+    // Show calls from user code into libraries as one timeline line
+    let collapse_lib_calls = use_state(|| false);
+    // Fold back-to-back executions of one address into a single line
+    let fold_loops = use_state(|| false);
+    // Folded entry (by clnum) whose unfolded rows are being fetched
+    let timeline_expanding = use_mut_ref(|| None::<u32>);
+    let trace_content_ref = use_node_ref();
+    let cfg_graph = use_state(String::new);
 
     {
         let trace_log = trace_log.clone();
         let current_clnum = current_clnum.clone();
+        let settled_clnum = settled_clnum.clone();
         let max_clnum = max_clnum.clone();
+        let trace_rate = trace_rate.clone();
+        let crash = crash.clone();
         let registers = registers.clone();
+        let changed_regs = changed_regs.clone();
         let memory = memory.clone();
         let memory_addr = memory_addr.clone();
+        let follow_rsp = follow_rsp.clone();
         let memory_writes = memory_writes.clone();
+        let value_history = value_history.clone();
+        let memory_reads = memory_reads.clone();
+        let stack_regions = stack_regions.clone();
+        let last_writer_note = last_writer_note.clone();
+        let regions = regions.clone();
+        let reg_provenance = reg_provenance.clone();
+        let reg_markers = reg_markers.clone();
         let current_disasm = current_disasm.clone();
+        let insn_detail = insn_detail.clone();
+        let lookahead = lookahead.clone();
+        let coverage = coverage.clone();
+        let function_listing = function_listing.clone();
+        let block_iterations = block_iterations.clone();
+        let symbols = symbols.clone();
+        let symbol_viewport = symbol_viewport.clone();
+        let trace_content_ref = trace_content_ref.clone();
+        let address_label = address_label.clone();
+        let struct_view = struct_view.clone();
+        let breakpoints = breakpoints.clone();
+        let session_config = session_config.clone();
+        let config_modules = config_modules.clone();
         let ws_sender = ws_sender.clone();
         let timeline_entries = timeline_entries.clone();
+        let timeline_next = timeline_next.clone();
+        let timeline_appending = timeline_appending.clone();
+        let timeline_tail = timeline_tail.clone();
+        let timeline_expanding = timeline_expanding.clone();
         let cfg_graph = cfg_graph.clone();
         let ai_response = ai_response.clone();
         let is_ai_loading = is_ai_loading.clone();
         let view_mode = view_mode.clone();
+        let only_user_code = only_user_code.clone();
         let slice_target = slice_target.clone();
+        let slice_title = slice_title.clone();
+        let has_second_trace = has_second_trace.clone();
+        let error_banner = error_banner.clone();
 
         use_effect_with((), move |_| {
             let ws = WebSocket::open("ws://localhost:3000/ws").unwrap();
@@ -140,6 +265,14 @@ pub fn app() -> Html {
             let (tx, mut rx) = futures::channel::mpsc::unbounded();
             ws_sender.set(Some(tx.clone()));
 
+            // Ask for large replies as MessagePack rather than JSON, and for
+            // the server's protocol version to catch mismatched builds
+            for msg in [ClientMessage::Hello { binary_frames: true }, ClientMessage::GetServerInfo] {
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = tx.unbounded_send(Message::Text(json));
+                }
+            }
+
             // Setup global CFG click handler
             let tx_clone = tx.clone();
             let callback = Closure::wrap(Box::new(move |clnum_val: u32| {
@@ -162,22 +295,44 @@ pub fn app() -> Html {
             .unwrap();
             callback.forget();
 
-            // Initial State from URL Hash
-            // Format: #clnum=123
-            if let Ok(hash) = window.location().hash() {
-                if hash.starts_with("#clnum=") {
-                    if let Ok(clnum) = hash[7..].parse::<u32>() {
-                        let msg = ClientMessage::QueryState {
-                            clnum,
-                            memory_addr: None,
-                        };
-                        if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = tx.unbounded_send(Message::Text(json));
-                        }
+            // Initial state from a permalink in the URL hash (see Permalink)
+            if let Some(link) = window.location().hash().ok().as_deref().and_then(Permalink::decode) {
+                view_mode.set(link.view_mode);
+                only_user_code.set(link.only_user_code);
+                memory_addr.set(link.memory_addr);
+                slice_target.set(link.slice_target.clone());
+                let mut msgs = vec![ClientMessage::QueryState {
+                    clnum: link.clnum,
+                    memory_addr: Some(link.memory_addr),
+                }];
+                // The slice, coverage and function views have no data of their own until re-run
+                if link.view_mode == "slice" && !link.slice_target.is_empty() {
+                    msgs.push(ClientMessage::GetSlice {
+                        clnum: link.clnum,
+                        target: link.slice_target,
+                        targets: Vec::new(),
+                    });
+                } else if link.view_mode == "coverage" {
+                    msgs.push(ClientMessage::GetCoverage);
+                } else if link.view_mode == "functions" {
+                    msgs.push(ClientMessage::GetSymbols);
+                }
+                for msg in msgs {
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = tx.unbounded_send(Message::Text(json));
                     }
                 }
             }
 
+            // Breakpoints, region names and settings outlive page reloads on the server
+            for msg in [ClientMessage::GetBreakpoints, ClientMessage::GetRegions, ClientMessage::GetConfig] {
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = tx.unbounded_send(Message::Text(json));
+                }
+            }
+
+            let jump_tx = tx.clone();
+
             // Spawn task to send messages
             spawn_local(async move {
                 while let Some(msg) = rx.next().await {
@@ -186,86 +341,363 @@ pub fn app() -> Html {
             });
 
             spawn_local(async move {
+                // Register vector of the previous StateUpdate, kept locally since
+                // state handles captured here never observe later updates
+                let mut prev_regs: Vec<u64> = Vec::new();
+                // Same for the timeline rows, which next pages are appended to
+                let mut timeline: Vec<TraceEntry> = Vec::new();
+                // Data of the CFGChunks received so far
+                let mut cfg_parts: Vec<String> = Vec::new();
                 while let Some(msg) = read.next().await {
-                    if let Ok(Message::Text(text)) = msg {
-                        // Try to parse as ServerMessage
-                        if let Ok(server_msg) = serde_json::from_str::<ServerMessage>(&text) {
-                            match server_msg {
-                                ServerMessage::StateUpdate {
-                                    clnum,
-                                    registers: regs,
-                                    memory: mem,
-                                    memory_addr: mem_addr,
-                                    disassembly,
-                                    ..
-                                } => {
-                                    // #region agent log
-                                    {
-                                        web_sys::console::log_1(&format!("StateUpdate: clnum={}, regs_len={}", clnum, regs.len()).into());
-                                        if !regs.is_empty() {
-                                            web_sys::console::log_1(&format!("Reg0 (RAX?): {:x}", regs[0]).into());
+                    // Large replies arrive as MessagePack once binary frames are negotiated
+                    let (server_msg, text) = match msg {
+                        Ok(Message::Text(text)) => (serde_json::from_str::<ServerMessage>(&text).ok(), Some(text)),
+                        Ok(Message::Bytes(bytes)) => (rmp_serde::from_slice::<ServerMessage>(&bytes).ok(), None),
+                        Err(_) => continue,
+                    };
+                    // Handle a chunked CFG as one CFG message once all of it is in
+                    let server_msg = match server_msg {
+                        Some(ServerMessage::CFGChunk { seq, total, data, format, warning }) => {
+                            if seq == 0 {
+                                cfg_parts.clear();
+                            }
+                            // A gap means a part was lost; wait for the next graph
+                            if seq as usize == cfg_parts.len() {
+                                cfg_parts.push(data);
+                            }
+                            if cfg_parts.len() as u32 != total {
+                                continue;
+                            }
+                            let graph = cfg_parts.concat();
+                            cfg_parts.clear();
+                            Some(ServerMessage::CFG { graph, format, warning })
+                        }
+                        other => other,
+                    };
+                    if let Some(server_msg) = server_msg {
+                        match server_msg {
+                            ServerMessage::StateUpdate {
+                                clnum,
+                                registers: regs,
+                                memory: mem,
+                                memory_addr: mem_addr,
+                                disassembly,
+                                pruned,
+                                stack_regions: stacks,
+                            } => {
+                                current_clnum.set(clnum);
+                                settled_clnum.set(clnum);
+                                let changed: Vec<(usize, u64)> = if prev_regs.is_empty() {
+                                    Vec::new()
+                                } else {
+                                    regs.iter()
+                                        .enumerate()
+                                        .map(|(i, v)| (i, prev_regs.get(i).map_or(u64::MAX, |prev| prev ^ v)))
+                                        .filter(|&(_, bits)| bits != 0)
+                                        .collect()
+                                };
+                                changed_regs.set(changed);
+                                if *follow_rsp.borrow() {
+                                    if let Some(&rsp) = regs.get(REG_RSP).filter(|&&rsp| rsp != mem_addr) {
+                                        let msg = ClientMessage::QueryState { clnum, memory_addr: Some(rsp) };
+                                        if let Ok(json) = serde_json::to_string(&msg) {
+                                            let _ = jump_tx.unbounded_send(Message::Text(json));
                                         }
                                     }
-                                    // #endregion
-                                    current_clnum.set(clnum);
-                                    registers.set(regs);
-                                    memory.set(mem);
-                                    memory_addr.set(mem_addr);
-                                    current_disasm.set(disassembly);
-
-                                    // Update URL hash
-                                    let window = web_sys::window().unwrap();
-                                    let _ = window.location().set_hash(&format!("clnum={}", clnum));
-                                }
-                                ServerMessage::MaxClnum { max } => {
-                                    max_clnum.set(max);
-                                    // Don't reset current_clnum here, it disturbs tracing
                                 }
-                                ServerMessage::TraceLog { entries } => {
-                                    timeline_entries.set(entries);
+                                prev_regs = regs.clone();
+                                registers.set(regs);
+                                memory.set(mem);
+                                memory_addr.set(mem_addr);
+                                stack_regions.set(stacks);
+                                if pruned {
+                                    current_disasm.set(String::from("(pruned: outside the trace window)"));
+                                } else {
+                                    current_disasm.set(disassembly);
                                 }
-                                ServerMessage::TraceEvent(_) => {
-                                    // Keep raw JSON for display
-                                    trace_log.set({
-                                        let mut current = (*trace_log).clone();
-                                        current.push(text);
-                                        if current.len() > 100 {
-                                            current.remove(0);
+                                insn_detail.set(None);
+                            }
+                            ServerMessage::MaxClnum { max } => {
+                                max_clnum.set(max);
+                                // Don't reset current_clnum here, it disturbs tracing
+                            }
+                            ServerMessage::TraceProgress { insns_per_sec, .. } => {
+                                trace_rate.set(insns_per_sec);
+                            }
+                            ServerMessage::TraceLog { entries, next_start } => {
+                                if let Some(folded) = timeline_expanding.replace(None) {
+                                    // Unfolded rows replace their folded entry; paging is unchanged
+                                    if let Some(i) = timeline.iter().position(|e| e.clnum == folded && e.repeats > 0) {
+                                        timeline.splice(i..=i, entries);
+                                        timeline_entries.set(timeline.clone());
+                                    }
+                                } else {
+                                    if timeline_appending.replace(false) {
+                                        timeline.extend(entries);
+                                        if timeline.len() > TIMELINE_LIMIT {
+                                            timeline.drain(..timeline.len() - TIMELINE_LIMIT);
                                         }
-                                        current
-                                    });
+                                    } else {
+                                        timeline = entries;
+                                    }
+                                    let tail = next_start.or_else(|| timeline.last().map(|e| e.clnum + 1));
+                                    *timeline_tail.borrow_mut() = tail;
+                                    timeline_entries.set(timeline.clone());
+                                    timeline_next.set(next_start);
+                                }
+                            }
+                            ServerMessage::TraceEvent(event) => {
+                                // Keep raw JSON for display
+                                trace_log.set({
+                                    let mut current = (*trace_log).clone();
+                                    current.push(text.unwrap_or_else(|| serde_json::to_string(&event).unwrap_or_default()));
+                                    if current.len() > 100 {
+                                        current.remove(0);
+                                    }
+                                    current
+                                });
+                            }
+                            ServerMessage::CFG { graph, format, warning } => {
+                                if let Some(warning) = warning {
+                                    error_banner.set(Some(warning));
                                 }
-                                ServerMessage::CFG { graph } => {
-                                    cfg_graph.set(graph.clone());
+                                cfg_graph.set(graph.clone());
+                                match format {
+                                    CfgFormat::Svg => render_svg("cfg-view", &graph),
                                     // Trigger render
-                                    spawn_local(async move {
+                                    CfgFormat::Mermaid => spawn_local(async move {
                                         let promise = render_mermaid("cfg-view", &graph);
                                         let _ = JsFuture::from(promise).await;
-                                    });
+                                    }),
+                                }
+                            }
+                            ServerMessage::DataFlow { clnum: _, target: _, graph } => {
+                                spawn_local(async move {
+                                    let promise = render_mermaid("dataflow-view", &graph);
+                                    let _ = JsFuture::from(promise).await;
+                                });
+                            }
+                            ServerMessage::AIResponse { text } => {
+                                ai_response.set(text);
+                                is_ai_loading.set(false);
+                            }
+                            ServerMessage::MemoryWrites { address: _, writes } => {
+                                memory_writes.set(writes);
+                            }
+                            ServerMessage::MemoryReads { address: _, reads } => {
+                                memory_reads.set(reads);
+                            }
+                            ServerMessage::ValueHistory { address, width, points } => {
+                                value_history.set(Some((address, width, points)));
+                            }
+                            ServerMessage::Slice { entries } => {
+                                timeline = entries;
+                                timeline_next.set(None);
+                                *timeline_tail.borrow_mut() = None;
+                                timeline_entries.set(timeline.clone());
+                                slice_title.set(None);
+                                view_mode.set("slice");
+                            }
+                            ServerMessage::SyscallTaint { clnum, syscall, result, buffer, entries } => {
+                                timeline = entries;
+                                timeline_next.set(None);
+                                *timeline_tail.borrow_mut() = None;
+                                timeline_entries.set(timeline.clone());
+                                let seeds = match buffer {
+                                    Some((address, len)) => format!("rax, {} bytes at {:#x}", len, address),
+                                    None => "rax".to_string(),
+                                };
+                                slice_title.set(Some(format!(
+                                    "Taint from {} at clnum {} (returned {:#x}; {})",
+                                    syscall, clnum, result, seeds
+                                )));
+                                view_mode.set("slice");
+                            }
+                            ServerMessage::InsnDetail { clnum: _, detail } => {
+                                insn_detail.set(Some(detail));
+                            }
+                            ServerMessage::Lookahead { clnum: _, lines } => {
+                                lookahead.set(lines);
+                            }
+                            ServerMessage::Coverage { functions } => {
+                                coverage.set(functions);
+                                view_mode.set("coverage");
+                            }
+                            ServerMessage::FunctionListing { name, lines } => {
+                                function_listing.set((name, lines));
+                                view_mode.set("listing");
+                            }
+                            ServerMessage::Symbols { symbols: list } => {
+                                symbols.set(Rc::new(list));
+                                view_mode.set("functions");
+                                // A fresh list starts at the top
+                                if let Some(el) = trace_content_ref.cast::<Element>() {
+                                    el.set_scroll_top(0);
+                                    symbol_viewport.set((0, el.client_height()));
                                 }
-                                ServerMessage::AIResponse { text } => {
-                                    ai_response.set(text);
-                                    is_ai_loading.set(false);
+                            }
+                            ServerMessage::BlockIterations { address, regs, rows } => {
+                                block_iterations.set((address, regs, rows));
+                                view_mode.set("iterations");
+                            }
+                            ServerMessage::LastWriter { clnum: _, address, origin } => {
+                                match origin {
+                                    WriteOrigin::Written { clnum: writer } => {
+                                        last_writer_note.set(None);
+                                        // Jump to the writing instruction, keeping the byte in view
+                                        let msg = ClientMessage::QueryState {
+                                            clnum: writer,
+                                            memory_addr: Some(address & !0xf),
+                                        };
+                                        if let Ok(json) = serde_json::to_string(&msg) {
+                                            let _ = jump_tx.unbounded_send(Message::Text(json));
+                                        }
+                                    }
+                                    WriteOrigin::Static => {
+                                        last_writer_note.set(Some(format!("{:x}: never written (static)", address)));
+                                    }
+                                    WriteOrigin::NoData => {
+                                        last_writer_note.set(Some(format!("{:x}: no data", address)));
+                                    }
+                                    WriteOrigin::Truncated { before } => {
+                                        last_writer_note.set(Some(format!(
+                                            "{:x}: written before clnum {} (older history not kept)",
+                                            address, before
+                                        )));
+                                    }
                                 }
-                                ServerMessage::MemoryWrites { address: _, writes } => {
-                                    memory_writes.set(writes);
+                            }
+                            ServerMessage::RegisterTimeline { reg, marks } => {
+                                reg_markers.set(Some((reg, marks)));
+                            }
+                            ServerMessage::RegisterChange { reg: _, clnum } => {
+                                if let Some(clnum) = clnum {
+                                    let msg = ClientMessage::QueryState { clnum, memory_addr: None };
+                                    if let Ok(json) = serde_json::to_string(&msg) {
+                                        let _ = jump_tx.unbounded_send(Message::Text(json));
+                                    }
                                 }
-                                ServerMessage::Slice { entries } => {
-                                    timeline_entries.set(entries);
-                                    view_mode.set("slice");
+                            }
+                            ServerMessage::RegisterProvenance { clnum, reg, writer } => {
+                                reg_provenance.set(Some((clnum, reg, writer)));
+                            }
+                            ServerMessage::AddressInfo { address, label } => {
+                                address_label.set(Some((address, label)));
+                            }
+                            ServerMessage::StructView { clnum: _, address: _, fields } => {
+                                struct_view.set(Some(fields));
+                            }
+                            ServerMessage::Export { records } => {
+                                if let Ok(json) = serde_json::to_string_pretty(&records) {
+                                    download_json("koradar-export.json", &json);
+                                }
+                            }
+                            ServerMessage::Lagged { missed } => {
+                                // The live log has a gap; MaxClnum catches up on the next tick
+                                trace_log.set({
+                                    let mut current = (*trace_log).clone();
+                                    current.push(format!("(missed {} live messages)", missed));
+                                    current
+                                });
+                            }
+                            ServerMessage::SessionReset => {
+                                // A new run replaced the old one; nothing shown so far is valid
+                                prev_regs.clear();
+                                timeline.clear();
+                                trace_log.set(Vec::new());
+                                current_clnum.set(0);
+                                settled_clnum.set(0);
+                                max_clnum.set(0);
+                                trace_rate.set(0);
+                                crash.set(None);
+                                registers.set(vec![0u64; 18]);
+                                changed_regs.set(Vec::new());
+                                memory.set(vec![0u8; 256]);
+                                memory_addr.set(0);
+                                memory_writes.set(Vec::new());
+                                memory_reads.set(Vec::new());
+                                stack_regions.set(Vec::new());
+                                last_writer_note.set(None);
+                                reg_markers.set(None);
+                                current_disasm.set(String::from("Waiting for trace..."));
+                                insn_detail.set(None);
+                                lookahead.set(Vec::new());
+                                timeline_entries.set(Vec::new());
+                                timeline_next.set(None);
+                                *timeline_appending.borrow_mut() = false;
+                                *timeline_tail.borrow_mut() = None;
+                                *timeline_expanding.borrow_mut() = None;
+                                cfg_graph.set(String::new());
+                                ai_response.set(String::new());
+                                let _ = web_sys::window().unwrap().location().set_hash("");
+                            }
+                            ServerMessage::Config { config, modules } => {
+                                session_config.set(Some(config));
+                                config_modules.set(modules);
+                            }
+                            ServerMessage::Breakpoints { breakpoints: list } => {
+                                breakpoints.set(list);
+                            }
+                            ServerMessage::Regions { regions: list } => {
+                                regions.set(list);
+                            }
+                            ServerMessage::BreakpointHit { clnum, address } => {
+                                trace_log.set({
+                                    let mut current = (*trace_log).clone();
+                                    current.push(format!("breakpoint hit at {:x} (clnum {})", address, clnum));
+                                    current
+                                });
+                                let msg = ClientMessage::QueryState {
+                                    clnum,
+                                    memory_addr: None,
+                                };
+                                if let Ok(json) = serde_json::to_string(&msg) {
+                                    let _ = jump_tx.unbounded_send(Message::Text(json));
+                                }
+                            }
+                            ServerMessage::CrashInfo { clnum, address, signal } => {
+                                crash.set(Some((clnum, address, signal)));
+                                trace_log.set({
+                                    let mut current = (*trace_log).clone();
+                                    current.push(format!("program killed at {:x} (clnum {}){}", address, clnum, signal_suffix(signal)));
+                                    current
+                                });
+                                let msg = ClientMessage::QueryState {
+                                    clnum,
+                                    memory_addr: None,
+                                };
+                                if let Ok(json) = serde_json::to_string(&msg) {
+                                    let _ = jump_tx.unbounded_send(Message::Text(json));
+                                }
+                            }
+                            ServerMessage::Error { request_id: _, message } => {
+                                is_ai_loading.set(false);
+                                error_banner.set(Some(message));
+                            }
+                            // Replies to queries the UI doesn't send (two-trace diffs, --debug and other tooling)
+                            ServerMessage::SliceDiff { .. } | ServerMessage::RawChanges { .. } | ServerMessage::Disassembly { .. } => {}
+                            // Reassembled into a CFG above
+                            ServerMessage::CFGChunk { .. } => {}
+                            ServerMessage::ServerInfo { version, protocol_version, capabilities } => {
+                                has_second_trace.set(capabilities.iter().any(|c| c == "diff_cfg"));
+                                if protocol_version != PROTOCOL_VERSION {
+                                    error_banner.set(Some(format!(
+                                        "Server {} speaks protocol v{}, this frontend v{}; rebuild the older one",
+                                        version, protocol_version, PROTOCOL_VERSION
+                                    )));
                                 }
                             }
-                        } else {
-                            // Fallback: treat as raw trace event
-                            trace_log.set({
-                                let mut current = (*trace_log).clone();
-                                current.push(text);
-                                if current.len() > 100 {
-                                    current.remove(0);
-                                }
-                                current
-                            });
                         }
+                    } else if let Some(text) = text {
+                        // Fallback: treat as raw trace event
+                        trace_log.set({
+                            let mut current = (*trace_log).clone();
+                            current.push(text);
+                            if current.len() > 100 {
+                                current.remove(0);
+                            }
+                            current
+                        });
                     }
                 }
             });
@@ -273,25 +705,44 @@ pub fn app() -> Html {
         });
     }
 
-    let on_slider_change = {
+    // Moves the slider immediately but only queries once it has been idle
+    // for SLIDER_DEBOUNCE_MS, so a drag doesn't send a QueryState per step
+    let on_slider_input = {
         let ws_sender = ws_sender.clone();
         let current_clnum = current_clnum.clone();
         let memory_addr = memory_addr.clone();
-        Callback::from(move |e: Event| {
-            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
-                if let Ok(clnum) = input.value().parse::<u32>() {
-                    current_clnum.set(clnum);
-                    if let Some(sender) = &*ws_sender {
-                        let msg = ClientMessage::QueryState {
-                            clnum,
-                            memory_addr: Some(*memory_addr),
-                        };
-                        if let Ok(json) = serde_json::to_string(&msg) {
-                            let _ = sender.unbounded_send(Message::Text(json));
-                        }
+        let slider_generation = slider_generation.clone();
+        Callback::from(move |e: InputEvent| {
+            let Some(clnum) = e
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.value().parse::<u32>().ok())
+            else {
+                return;
+            };
+            current_clnum.set(clnum);
+            let generation = {
+                let mut latest = slider_generation.borrow_mut();
+                *latest = latest.wrapping_add(1);
+                *latest
+            };
+            let ws_sender = ws_sender.clone();
+            let memory_addr = *memory_addr;
+            let slider_generation = slider_generation.clone();
+            spawn_local(async move {
+                TimeoutFuture::new(SLIDER_DEBOUNCE_MS).await;
+                if *slider_generation.borrow() != generation {
+                    return;
+                }
+                if let Some(sender) = &*ws_sender {
+                    let msg = ClientMessage::QueryState {
+                        clnum,
+                        memory_addr: Some(memory_addr),
+                    };
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = sender.unbounded_send(Message::Text(json));
                     }
                 }
-            }
+            });
         })
     };
 
@@ -317,6 +768,45 @@ pub fn app() -> Html {
         })
     };
 
+    // Points the hexdump at the current value of register `index`
+    let point_memory_at = |index: usize| {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let memory_addr = memory_addr.clone();
+        let registers = registers.clone();
+        Callback::from(move |_: ()| {
+            let Some(&addr) = registers.get(index) else {
+                return;
+            };
+            memory_addr.set(addr);
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::QueryState {
+                    clnum: *current_clnum,
+                    memory_addr: Some(addr),
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+    let on_memory_rsp = point_memory_at(REG_RSP).reform(|_: MouseEvent| ());
+    let on_memory_rbp = point_memory_at(REG_RBP).reform(|_: MouseEvent| ());
+
+    let on_follow_rsp = {
+        let follow_rsp = follow_rsp.clone();
+        let point_at_rsp = point_memory_at(REG_RSP);
+        Callback::from(move |e: Event| {
+            let Some(input) = e.target_dyn_into::<HtmlInputElement>() else {
+                return;
+            };
+            *follow_rsp.borrow_mut() = input.checked();
+            if input.checked() {
+                point_at_rsp.emit(());
+            }
+        })
+    };
+
     let on_step_forward = {
         let ws_sender = ws_sender.clone();
         let current_clnum = current_clnum.clone();
@@ -331,13 +821,13 @@ pub fn app() -> Html {
         })
     };
 
-    let on_step_backward = {
+    let on_step_over = {
         let ws_sender = ws_sender.clone();
         let current_clnum = current_clnum.clone();
         Callback::from(move |_| {
             let current = *current_clnum;
             if let Some(sender) = &*ws_sender {
-                let msg = ClientMessage::StepBackward { current };
+                let msg = ClientMessage::StepOver { current };
                 if let Ok(json) = serde_json::to_string(&msg) {
                     let _ = sender.unbounded_send(Message::Text(json));
                 }
@@ -345,124 +835,217 @@ pub fn app() -> Html {
         })
     };
 
-    let toggle_view = {
-        let view_mode = view_mode.clone();
+    let on_goto_main = {
         let ws_sender = ws_sender.clone();
-        let only_user_code = *only_user_code;
-        let start_from_main = *start_from_main;
-        
-        Callback::from(move |_: MouseEvent| {
-            if *view_mode == "log" {
-                view_mode.set("timeline");
-            } else if *view_mode == "timeline" || *view_mode == "slice" {
-                view_mode.set("cfg");
-                // Fetch CFG
-                if let Some(sender) = &*ws_sender {
-                    let msg = ClientMessage::GetCFG { only_user_code, start_from_main };
-                    if let Ok(json) = serde_json::to_string(&msg) {
-                        let _ = sender.unbounded_send(Message::Text(json));
-                    }
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                if let Ok(json) = serde_json::to_string(&ClientMessage::GotoMain) {
+                    let _ = sender.unbounded_send(Message::Text(json));
                 }
-            } else {
-                view_mode.set("log");
             }
         })
     };
 
-    let toggle_user_code = {
-        let only_user_code = only_user_code.clone();
+    // Marks where the chosen register changes along the slider
+    let on_marker_reg_change = {
+        let ws_sender = ws_sender.clone();
+        let reg_markers = reg_markers.clone();
         Callback::from(move |e: Event| {
-            let target: Option<HtmlInputElement> = e.target_dyn_into();
-            if let Some(input) = target {
-                let val = input.checked();
-                only_user_code.set(val);
+            let Some(select) = e.target_dyn_into::<HtmlSelectElement>() else {
+                return;
+            };
+            let Ok(reg) = select.value().parse::<usize>() else {
+                reg_markers.set(None);
+                return;
+            };
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetRegisterTimeline { reg, buckets: MARKER_BUCKETS };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
             }
         })
     };
 
-    let toggle_start_main = {
-        let start_from_main = start_from_main.clone();
-        Callback::from(move |e: Event| {
-            let target: Option<HtmlInputElement> = e.target_dyn_into();
-            if let Some(input) = target {
-                let val = input.checked();
-                start_from_main.set(val);
-            }
-        })
+    // Jumps to the marked register's previous (or next) change from the current clnum
+    let on_marker_step = {
+        let ws_sender = ws_sender.clone();
+        let reg_markers = reg_markers.clone();
+        let current_clnum = current_clnum.clone();
+        move |forward: bool| {
+            let ws_sender = ws_sender.clone();
+            let reg_markers = reg_markers.clone();
+            let current_clnum = current_clnum.clone();
+            Callback::from(move |_: MouseEvent| {
+                // The markers are downsampled, so ask the server for the exact change
+                let (Some((reg, _)), Some(sender)) = (&*reg_markers, &*ws_sender) else {
+                    return;
+                };
+                let msg = ClientMessage::GetRegisterChange { reg: *reg, clnum: *current_clnum, forward };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            })
+        }
     };
-    
-    let on_search_change = {
-        let search_term = search_term.clone();
-        Callback::from(move |e: InputEvent| {
-            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
-                search_term.set(input.value());
+
+    // Enter in the clnum box jumps straight to that clnum
+    let on_goto_clnum = {
+        let ws_sender = ws_sender.clone();
+        let goto_clnum_text = goto_clnum_text.clone();
+        let max_clnum = max_clnum.clone();
+        let error_banner = error_banner.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() != "Enter" {
+                return;
+            }
+            let text = goto_clnum_text.trim();
+            let Ok(clnum) = text.parse::<u32>() else {
+                error_banner.set(Some(format!("invalid clnum '{}'", text)));
+                return;
+            };
+            if clnum == 0 || clnum > *max_clnum {
+                error_banner.set(Some(format!("clnum {} is out of range (1 to {})", clnum, *max_clnum)));
+                return;
+            }
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::QueryState { clnum, memory_addr: None };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
             }
         })
     };
-    
-    let on_search_submit = {
-        let search_term = search_term.clone();
+
+    // Enter in the address box jumps to the next execution of that address,
+    // so pressing it again steps through them
+    let on_goto_address = {
+        let ws_sender = ws_sender.clone();
+        let goto_address_text = goto_address_text.clone();
+        let current_clnum = current_clnum.clone();
+        let error_banner = error_banner.clone();
         Callback::from(move |e: KeyboardEvent| {
-            if e.key() == "Enter" {
-                let term = (*search_term).clone();
-                search_function_in_cfg(&term);
+            if e.key() != "Enter" {
+                return;
+            }
+            let text = goto_address_text.trim();
+            let Ok(address) = u64::from_str_radix(text.trim_start_matches("0x"), 16) else {
+                error_banner.set(Some(format!("invalid address '{}'", text)));
+                return;
+            };
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GotoAddress { address, after: *current_clnum };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
             }
         })
     };
 
-    let on_ask_ai = {
+    // Names the selected bytes (or the top of the hexdump) from "name [len]";
+    // the length defaults to the inspector width, or 16 bytes
+    let on_name_region = {
         let ws_sender = ws_sender.clone();
-        let current_clnum = current_clnum.clone();
-        let is_ai_loading = is_ai_loading.clone();
-        let ai_response = ai_response.clone();
-
+        let region_text = region_text.clone();
+        let inspect_sel = inspect_sel.clone();
+        let inspect_width = inspect_width.clone();
+        let memory_addr = memory_addr.clone();
+        let error_banner = error_banner.clone();
         Callback::from(move |_| {
-            is_ai_loading.set(true);
-            ai_response.set(String::new());
+            let mut words = region_text.split_whitespace();
+            let Some(name) = words.next() else {
+                return;
+            };
+            let (address, default_len) = match *inspect_sel {
+                Some(InspectSource::Memory(start)) => (start, inspect_width.size() as u64),
+                _ => (*memory_addr, 16),
+            };
+            let len = match words.next() {
+                None => Some(default_len),
+                Some(text) => match text.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                    None => text.parse::<u64>().ok(),
+                },
+            };
+            let Some(len) = len else {
+                error_banner.set(Some(format!("invalid region length in '{}'", *region_text)));
+                return;
+            };
             if let Some(sender) = &*ws_sender {
-                let msg = ClientMessage::AskAI {
-                    clnum: *current_clnum,
-                };
+                let msg = ClientMessage::NameRegion { address, len, name: name.to_string() };
                 if let Ok(json) = serde_json::to_string(&msg) {
                     let _ = sender.unbounded_send(Message::Text(json));
                 }
             }
+            region_text.set(String::new());
         })
     };
 
-    let on_get_writes = {
+    let on_set_breakpoint = {
         let ws_sender = ws_sender.clone();
-        let memory_addr = memory_addr.clone();
+        let breakpoint_text = breakpoint_text.clone();
+        let breakpoint_condition = breakpoint_condition.clone();
+        let breakpoint_static = breakpoint_static.clone();
+        let error_banner = error_banner.clone();
         Callback::from(move |_| {
-             if let Some(sender) = &*ws_sender {
-                 let msg = ClientMessage::GetMemoryWrites {
-                     address: *memory_addr,
-                 };
-                 if let Ok(json) = serde_json::to_string(&msg) {
-                     let _ = sender.unbounded_send(Message::Text(json));
-                 }
-             }
+            let text = breakpoint_text.trim();
+            let Ok(address) = u64::from_str_radix(text.trim_start_matches("0x"), 16) else {
+                error_banner.set(Some(format!("invalid breakpoint address '{}'", text)));
+                return;
+            };
+            let is_static = *breakpoint_static;
+            let msg = match breakpoint_condition.trim() {
+                "" => ClientMessage::SetBreakpoint { address, is_static },
+                condition => match parse_break_condition(condition) {
+                    Ok(condition) => ClientMessage::SetConditionalBreakpoint { address, is_static, condition },
+                    Err(e) => {
+                        error_banner.set(Some(e));
+                        return;
+                    }
+                },
+            };
+            if let Some(sender) = &*ws_sender {
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+            breakpoint_text.set(String::new());
+            breakpoint_condition.set(String::new());
         })
     };
 
-    let on_slice_target_change = {
-        let slice_target = slice_target.clone();
-        Callback::from(move |e: InputEvent| {
-            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
-                slice_target.set(input.value());
+    // Sends a new configuration, then re-queries the current state so the
+    // disassembly is redrawn with it
+    let on_configure = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let memory_addr = memory_addr.clone();
+        Callback::from(move |config: SessionConfig| {
+            if let Some(sender) = &*ws_sender {
+                let msgs = [
+                    ClientMessage::Configure { config },
+                    ClientMessage::QueryState {
+                        clnum: *current_clnum,
+                        memory_addr: Some(*memory_addr),
+                    },
+                ];
+                for msg in msgs {
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = sender.unbounded_send(Message::Text(json));
+                    }
+                }
             }
         })
     };
 
-    let on_slice = {
+    let on_clear_breakpoint = {
         let ws_sender = ws_sender.clone();
-        let current_clnum = current_clnum.clone();
-        let slice_target = slice_target.clone();
-        Callback::from(move |_| {
+        Callback::from(move |bp: Breakpoint| {
             if let Some(sender) = &*ws_sender {
-                let msg = ClientMessage::GetSlice {
-                    clnum: *current_clnum,
-                    target: (*slice_target).clone(),
+                let msg = ClientMessage::ClearBreakpoint {
+                    address: Some(bp.address),
+                    is_static: bp.is_static,
+                    condition: bp.condition,
                 };
                 if let Ok(json) = serde_json::to_string(&msg) {
                     let _ = sender.unbounded_send(Message::Text(json));
@@ -471,31 +1054,624 @@ pub fn app() -> Html {
         })
     };
 
-    // Auto-refresh timeline when clnum, view_mode, or only_user_code changes
-    {
+    let on_step_backward = {
         let ws_sender = ws_sender.clone();
         let current_clnum = current_clnum.clone();
+        Callback::from(move |_| {
+            let current = *current_clnum;
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::StepBackward { current };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let toggle_view = {
         let view_mode = view_mode.clone();
+        let ws_sender = ws_sender.clone();
+        let only_user_code = *only_user_code;
+        let start_from_main = *start_from_main;
+        let svg = *cfg_svg;
+        let diff = *cfg_diff;
+        let collapse_stubs = *cfg_collapse_stubs;
+
+        Callback::from(move |_: MouseEvent| {
+            if *view_mode == "log" {
+                view_mode.set("timeline");
+            } else if *view_mode == "timeline" || *view_mode == "slice" {
+                view_mode.set("cfg");
+                // Fetch CFG
+                if let Some(sender) = &*ws_sender {
+                    let msg = cfg_request(only_user_code, start_from_main, collapse_stubs, svg, diff);
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = sender.unbounded_send(Message::Text(json));
+                    }
+                }
+            } else {
+                view_mode.set("log");
+            }
+        })
+    };
+
+    let toggle_user_code = {
         let only_user_code = only_user_code.clone();
+        Callback::from(move |e: Event| {
+            let target: Option<HtmlInputElement> = e.target_dyn_into();
+            if let Some(input) = target {
+                let val = input.checked();
+                only_user_code.set(val);
+            }
+        })
+    };
+
+    let toggle_collapse_lib_calls = {
+        let collapse_lib_calls = collapse_lib_calls.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                collapse_lib_calls.set(input.checked());
+            }
+        })
+    };
+
+    let toggle_fold_loops = {
+        let fold_loops = fold_loops.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                fold_loops.set(input.checked());
+            }
+        })
+    };
+
+    let toggle_follow_tail = {
+        let follow_tail = follow_tail.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                follow_tail.set(input.checked());
+            }
+        })
+    };
+
+    let toggle_cfg_svg = {
+        let cfg_svg = cfg_svg.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cfg_svg.set(input.checked());
+            }
+        })
+    };
+
+    let toggle_cfg_diff = {
+        let cfg_diff = cfg_diff.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cfg_diff.set(input.checked());
+            }
+        })
+    };
+
+    let toggle_cfg_collapse_stubs = {
+        let cfg_collapse_stubs = cfg_collapse_stubs.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                cfg_collapse_stubs.set(input.checked());
+            }
+        })
+    };
+
+    let toggle_start_main = {
         let start_from_main = start_from_main.clone();
+        Callback::from(move |e: Event| {
+            let target: Option<HtmlInputElement> = e.target_dyn_into();
+            if let Some(input) = target {
+                let val = input.checked();
+                start_from_main.set(val);
+            }
+        })
+    };
+    
+    let on_search_change = {
+        let search_term = search_term.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                search_term.set(input.value());
+            }
+        })
+    };
+    
+    let on_search_submit = {
+        let search_term = search_term.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() == "Enter" {
+                let term = (*search_term).clone();
+                search_function_in_cfg(&term);
+            }
+        })
+    };
+
+    let on_function_filter_change = {
+        let function_filter = function_filter.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                function_filter.set(input.value().trim().to_string());
+            }
+        })
+    };
+
+    let on_ask_ai = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let ai_context_lines = ai_context_lines.clone();
+        let is_ai_loading = is_ai_loading.clone();
+        let ai_response = ai_response.clone();
+
+        Callback::from(move |_| {
+            is_ai_loading.set(true);
+            ai_response.set(String::new());
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::AskAI {
+                    clnum: *current_clnum,
+                    context_lines: *ai_context_lines,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_dismiss_error = {
+        let error_banner = error_banner.clone();
+        Callback::from(move |_| error_banner.set(None))
+    };
+
+    let on_insn_detail = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let insn_detail = insn_detail.clone();
+        Callback::from(move |_| {
+            if insn_detail.is_some() {
+                insn_detail.set(None);
+                return;
+            }
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetInsnDetail {
+                    clnum: *current_clnum,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_get_writes = {
+        let ws_sender = ws_sender.clone();
+        let memory_addr = memory_addr.clone();
+        let show_reads = show_reads.clone();
+        Callback::from(move |_| {
+             show_reads.set(false);
+             if let Some(sender) = &*ws_sender {
+                 let msg = ClientMessage::GetMemoryWrites {
+                     address: *memory_addr,
+                 };
+                 if let Ok(json) = serde_json::to_string(&msg) {
+                     let _ = sender.unbounded_send(Message::Text(json));
+                 }
+             }
+        })
+    };
+
+    let on_get_reads = {
+        let ws_sender = ws_sender.clone();
+        let memory_addr = memory_addr.clone();
+        let show_reads = show_reads.clone();
+        Callback::from(move |_| {
+            show_reads.set(true);
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetMemoryReads {
+                    address: *memory_addr,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    // Plot the selected bytes (or the start of the memory view) over time,
+    // as wide as the value inspector reads them
+    let on_plot_value = {
+        let ws_sender = ws_sender.clone();
+        let memory_addr = memory_addr.clone();
+        let inspect_sel = inspect_sel.clone();
+        let inspect_width = inspect_width.clone();
+        Callback::from(move |_| {
+            let address = match *inspect_sel {
+                Some(InspectSource::Memory(address)) => address,
+                _ => *memory_addr,
+            };
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetValueHistory {
+                    address,
+                    width: inspect_width.size() as u32,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_value_point = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let memory_addr = memory_addr.clone();
+        Callback::from(move |clnum: u32| {
+            current_clnum.set(clnum);
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::QueryState { clnum, memory_addr: Some(*memory_addr) };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_close_value_chart = {
+        let value_history = value_history.clone();
+        Callback::from(move |_| value_history.set(None))
+    };
+
+    let on_struct_text_change = {
+        let struct_text = struct_text.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                struct_text.set(input.value());
+            }
+        })
+    };
+
+    let on_apply_struct = {
+        let struct_text = struct_text.clone();
+        let struct_layout = struct_layout.clone();
+        let struct_view = struct_view.clone();
+        let error_banner = error_banner.clone();
+        Callback::from(move |_| {
+            if struct_text.trim().is_empty() {
+                struct_layout.set(None);
+                struct_view.set(None);
+                return;
+            }
+            match parse_layout(&struct_text) {
+                Ok(fields) => struct_layout.set(Some(fields)),
+                Err(e) => error_banner.set(Some(e)),
+            }
+        })
+    };
+
+    // Fetch the static lookahead for the new current instruction
+    {
+        let ws_sender = ws_sender.clone();
+        use_effect_with(*settled_clnum, move |clnum| {
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetLookahead {
+                    clnum: *clnum,
+                    count: LOOKAHEAD_COUNT,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        });
+    }
+
+    // Re-read the struct overlay whenever the clnum, address or layout changes
+    {
+        let ws_sender = ws_sender.clone();
+        use_effect_with(
+            (*settled_clnum, *memory_addr, (*struct_layout).clone()),
+            move |(clnum, address, layout)| {
+                if let (Some(fields), Some(sender)) = (layout, &*ws_sender) {
+                    let msg = ClientMessage::ReadStruct {
+                        clnum: *clnum,
+                        address: *address,
+                        fields: fields.clone(),
+                    };
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        let _ = sender.unbounded_send(Message::Text(json));
+                    }
+                }
+            },
+        );
+    }
+
+    let on_slice_target_change = {
+        let slice_target = slice_target.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                slice_target.set(input.value());
+            }
+        })
+    };
+
+    let on_slice = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let slice_target = slice_target.clone();
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetSlice {
+                    clnum: *current_clnum,
+                    target: (*slice_target).clone(),
+                    targets: Vec::new(),
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    // Shows the slice as a graph of values; the view waits for the reply
+    let on_data_flow = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let slice_target = slice_target.clone();
+        let view_mode = view_mode.clone();
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetDataFlow {
+                    clnum: *current_clnum,
+                    target: (*slice_target).clone(),
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    view_mode.set("dataflow");
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_taint_syscall = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::TaintFromSyscall { clnum: *current_clnum };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_coverage = {
+        let ws_sender = ws_sender.clone();
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                if let Ok(json) = serde_json::to_string(&ClientMessage::GetCoverage) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_functions = {
+        let ws_sender = ws_sender.clone();
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                if let Ok(json) = serde_json::to_string(&ClientMessage::GetSymbols) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let on_symbol_filter_change = {
+        let symbol_filter = symbol_filter.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                symbol_filter.set(input.value());
+            }
+        })
+    };
+
+    // Function list rows in display order, re-sorted only when the list, the
+    // filter or the sort order change (not on every scroll)
+    let symbol_order = use_memo(
+        ((*symbols).clone(), (*symbol_filter).clone(), *symbol_sort),
+        |(symbols, filter, (column, descending))| sorted_symbols(symbols, filter, *column, *descending),
+    );
+
+    let on_iteration_regs_change = {
+        let iteration_regs = iteration_regs.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                iteration_regs.set(input.value());
+            }
+        })
+    };
+
+    // Follow the chosen registers across every execution of the current
+    // instruction (a block leader when picked from the CFG)
+    let on_iterations = {
+        let ws_sender = ws_sender.clone();
+        let current_clnum = current_clnum.clone();
+        let iteration_regs = iteration_regs.clone();
+        let error_banner = error_banner.clone();
+        Callback::from(move |_| {
+            let mut regs = Vec::new();
+            for name in iteration_regs.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                match REG_NAMES.iter().position(|r| r.eq_ignore_ascii_case(name)) {
+                    Some(i) => regs.push(i),
+                    None => {
+                        error_banner.set(Some(format!("unknown register '{}'", name)));
+                        return;
+                    }
+                }
+            }
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::GetBlockIterations { clnum: *current_clnum, regs };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    // Export the entries currently shown in the timeline/slice view
+    let on_export = {
+        let ws_sender = ws_sender.clone();
+        let timeline_entries = timeline_entries.clone();
+        Callback::from(move |_| {
+            if let Some(sender) = &*ws_sender {
+                let msg = ClientMessage::ExportRegion {
+                    clnums: timeline_entries.iter().map(|e| e.clnum).collect(),
+                    range: None,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    // Fetch the next timeline page when scrolled near the bottom
+    let on_trace_scroll = {
+        let ws_sender = ws_sender.clone();
+        let view_mode = view_mode.clone();
+        let timeline_next = timeline_next.clone();
+        let timeline_appending = timeline_appending.clone();
+        let only_user_code = only_user_code.clone();
+        let collapse_lib_calls = collapse_lib_calls.clone();
+        let fold_loops = fold_loops.clone();
+        let function_filter = function_filter.clone();
+        let symbol_viewport = symbol_viewport.clone();
+        Callback::from(move |e: Event| {
+            // The function list only renders the rows in view
+            if *view_mode == "functions" {
+                if let Some(el) = e.target_dyn_into::<Element>() {
+                    symbol_viewport.set((el.scroll_top(), el.client_height()));
+                }
+                return;
+            }
+            if *view_mode != "timeline" || *timeline_appending.borrow() {
+                return;
+            }
+            let (Some(el), Some(start)) = (e.target_dyn_into::<Element>(), *timeline_next) else {
+                return;
+            };
+            if el.scroll_top() + el.client_height() < el.scroll_height() - 50 {
+                return;
+            }
+            if let Some(sender) = &*ws_sender {
+                let function = if function_filter.is_empty() {
+                    None
+                } else {
+                    Some((*function_filter).clone())
+                };
+                let msg = ClientMessage::GetTraceLog {
+                    start,
+                    count: 40,
+                    only_user_code: *only_user_code,
+                    function,
+                    radius: None,
+                    collapse_library_calls: *collapse_lib_calls,
+                    fold: *fold_loops,
+                };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    *timeline_appending.borrow_mut() = true;
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        })
+    };
+
+    let in_stack = {
+        let stack_regions = stack_regions.clone();
+        move |addr: u64| stack_regions.iter().any(|&(low, high)| addr >= low && addr <= high)
+    };
+
+    // Bytes under the value inspector's selection, in memory order
+    let inspected_bytes: Option<Vec<u8>> = match *inspect_sel {
+        Some(InspectSource::Memory(addr)) => addr
+            .checked_sub(*memory_addr)
+            .and_then(|off| memory.get(off as usize..))
+            .map(|b| b.to_vec()),
+        Some(InspectSource::Register(i)) => registers.get(i).map(|v| v.to_le_bytes().to_vec()),
+        None => None,
+    };
+    let inspected = inspected_bytes
+        .as_deref()
+        .and_then(|b| interpret(b, *inspect_width, *inspect_big_endian, *inspect_signed));
+
+    // Resolve integer values that could be pointers to a symbol or module
+    {
+        let ws_sender = ws_sender.clone();
+        let pointer_candidate = inspected
+            .as_ref()
+            .filter(|_| !inspect_width.is_float() && inspect_width.size() >= 4)
+            .map(|v| v.raw)
+            .filter(|&raw| raw >= 0x1000);
+        use_effect_with(pointer_candidate, move |candidate| {
+            if let (Some(address), Some(sender)) = (*candidate, &*ws_sender) {
+                let msg = ClientMessage::DescribeAddress { address };
+                if let Ok(json) = serde_json::to_string(&msg) {
+                    let _ = sender.unbounded_send(Message::Text(json));
+                }
+            }
+        });
+    }
+
+    // Auto-refresh timeline when the settled clnum, view_mode, only_user_code,
+    // library call collapsing, loop folding or the timeline radius changes
+    {
+        let ws_sender = ws_sender.clone();
+        let settled_clnum = settled_clnum.clone();
+        let view_mode = view_mode.clone();
+        let only_user_code = only_user_code.clone();
+        let collapse_lib_calls = collapse_lib_calls.clone();
+        let fold_loops = fold_loops.clone();
+        let start_from_main = start_from_main.clone();
+        let function_filter = function_filter.clone();
+        let timeline_radius = timeline_radius.clone();
+        let cfg_svg = cfg_svg.clone();
+        let cfg_diff = cfg_diff.clone();
+        let cfg_collapse_stubs = cfg_collapse_stubs.clone();
 
         use_effect_with(
             (
-                current_clnum.clone(),
+                settled_clnum.clone(),
                 view_mode.clone(),
                 only_user_code.clone(),
                 start_from_main.clone(),
+                function_filter.clone(),
+                timeline_radius.clone(),
+                cfg_svg.clone(),
+                cfg_diff.clone(),
+                cfg_collapse_stubs.clone(),
+                collapse_lib_calls.clone(),
+                fold_loops.clone(),
             ),
-            move |(current_clnum, view_mode, only_user_code, start_from_main)| {
+            move |(settled_clnum, view_mode, only_user_code, start_from_main, function_filter, timeline_radius, cfg_svg, cfg_diff, cfg_collapse_stubs, collapse_lib_calls, fold_loops)| {
                 if **view_mode == "timeline" {
-                    let center = **current_clnum;
-                    let start = center.saturating_sub(20);
-                    let count = 40;
+                    let radius = (**timeline_radius).max(1);
                     if let Some(sender) = &*ws_sender {
+                        let function = if function_filter.is_empty() {
+                            None
+                        } else {
+                            Some((**function_filter).clone())
+                        };
                         let msg = ClientMessage::GetTraceLog {
-                            start,
-                            count,
+                            start: **settled_clnum,
+                            count: radius * 2,
                             only_user_code: **only_user_code,
+                            function,
+                            radius: Some(radius),
+                            collapse_library_calls: **collapse_lib_calls,
+                            fold: **fold_loops,
                         };
                         if let Ok(json) = serde_json::to_string(&msg) {
                             let _ = sender.unbounded_send(Message::Text(json));
@@ -503,10 +1679,7 @@ pub fn app() -> Html {
                     }
                 } else if **view_mode == "cfg" {
                     if let Some(sender) = &*ws_sender {
-                        let msg = ClientMessage::GetCFG {
-                            only_user_code: **only_user_code,
-                            start_from_main: **start_from_main,
-                        };
+                        let msg = cfg_request(**only_user_code, **start_from_main, **cfg_collapse_stubs, **cfg_svg, **cfg_diff);
                         if let Ok(json) = serde_json::to_string(&msg) {
                             let _ = sender.unbounded_send(Message::Text(json));
                         }
@@ -517,6 +1690,111 @@ pub fn app() -> Html {
         );
     }
 
+    // While following the tail, fetch only the clnums that arrived since the
+    // last timeline reply. MaxClnum is coalesced by the server and at most one
+    // append is in flight, so a fast trace can't queue up requests.
+    {
+        let ws_sender = ws_sender.clone();
+        let timeline_appending = timeline_appending.clone();
+        let timeline_tail = timeline_tail.clone();
+        let only_user_code = only_user_code.clone();
+        let collapse_lib_calls = collapse_lib_calls.clone();
+        let fold_loops = fold_loops.clone();
+        let function_filter = function_filter.clone();
+        let timeline_radius = timeline_radius.clone();
+        let following = *follow_tail && *view_mode == "timeline";
+
+        use_effect_with((*max_clnum, following), move |&(max, following)| {
+            if following && !*timeline_appending.borrow() {
+                let tail = *timeline_tail.borrow();
+                let (start, count, append) = match tail {
+                    Some(tail) if tail > max => (tail, 0, true),
+                    Some(tail) if max - tail <= LIVE_APPEND_MAX_GAP => (tail, LIVE_APPEND_COUNT, true),
+                    // Navigated away or nothing fetched yet: load the tail itself
+                    _ => {
+                        let window = (*timeline_radius).max(1) * 2;
+                        ((max + 1).saturating_sub(window), window, false)
+                    }
+                };
+                if let Some(sender) = ws_sender.as_ref().filter(|_| count > 0) {
+                    let function = if function_filter.is_empty() {
+                        None
+                    } else {
+                        Some((*function_filter).clone())
+                    };
+                    let msg = ClientMessage::GetTraceLog {
+                        start,
+                        count,
+                        only_user_code: *only_user_code,
+                        function,
+                        radius: None,
+                        collapse_library_calls: *collapse_lib_calls,
+                        fold: *fold_loops,
+                    };
+                    if let Ok(json) = serde_json::to_string(&msg) {
+                        *timeline_appending.borrow_mut() = append;
+                        let _ = sender.unbounded_send(Message::Text(json));
+                    }
+                }
+            }
+            || {}
+        });
+    }
+
+    // Keep the newest entries in view while following the tail
+    {
+        let trace_content_ref = trace_content_ref.clone();
+        let following = *follow_tail && *view_mode == "timeline";
+        use_effect_with((timeline_entries.len(), following), move |&(_, following)| {
+            if let (true, Some(el)) = (following, trace_content_ref.cast::<Element>()) {
+                el.set_scroll_top(el.scroll_height());
+            }
+            || {}
+        });
+    }
+
+    // Keep the URL hash a permalink to the current view
+    {
+        let permalink = Permalink {
+            clnum: *settled_clnum,
+            view_mode: *view_mode,
+            only_user_code: *only_user_code,
+            memory_addr: *memory_addr,
+            slice_target: (*slice_target).clone(),
+        };
+        use_effect_with(permalink, |permalink| {
+            // Nothing traced yet; leave an incoming link in place
+            if permalink.clnum != 0 {
+                let _ = web_sys::window().unwrap().location().set_hash(&permalink.encode());
+            }
+        });
+    }
+
+    let on_copy_link = Callback::from(|_| {
+        let window = web_sys::window().unwrap();
+        if let Ok(href) = window.location().href() {
+            let promise = window.navigator().clipboard().write_text(&href);
+            spawn_local(async move {
+                let _ = JsFuture::from(promise).await;
+            });
+        }
+    });
+
+    // onchange handler storing a number input's value in `setting`
+    let number_setting = |setting: &UseStateHandle<u32>| {
+        let setting = setting.clone();
+        Callback::from(move |e: Event| {
+            if let Some(value) = e
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.value().parse::<u32>().ok())
+            {
+                setting.set(value);
+            }
+        })
+    };
+
+    let access_history = if *show_reads { &*memory_reads } else { &*memory_writes };
+
     html! {
         <>
             <style>
@@ -531,6 +1809,10 @@ pub fn app() -> Html {
                 
                 .controls { width: 100%; padding: 10px; background: #252526; border-bottom: 1px solid #333; flex-shrink: 0; }
                 .controls-inner { display: flex; align-items: center; gap: 10px; }
+                .controls-inner.settings { margin-top: 6px; font-size: 12px; }
+                .controls-inner.settings input[type=number] { width: 50px; }
+                .controls-inner.breakpoints { margin-top: 6px; font-size: 12px; }
+                .breakpoint { padding: 2px 6px; background: #5a1d1d; border-radius: 3px; cursor: pointer; font-family: monospace; }
                 
                 .trace-content { flex: 1; overflow-y: auto; overflow-x: hidden; }
                 
@@ -548,6 +1830,68 @@ pub fn app() -> Html {
                 .col-insn { color: #d4d4d4; }
                 .col-effect { color: #6a9955; }
 
+                .error-banner { display: flex; justify-content: space-between; align-items: center; padding: 5px 10px; background: #5a1d1d; color: #f48771; border-bottom: 1px solid #be1100; font-size: 12px; }
+                .coverage-missed { color: #6a6a6a; }
+                .symbol-toolbar { position: sticky; top: 0; z-index: 1; display: flex; gap: 10px; align-items: center; background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555; }
+                .symbol-table { table-layout: fixed; }
+                .symbol-table th { position: sticky; top: 20px; background: #1e1e1e; cursor: pointer; user-select: none; }
+                .symbol-table th.symbol-col-addr { width: 100px; }
+                .symbol-table th.symbol-col-size, .symbol-table th.symbol-col-executed { width: 80px; }
+                .symbol-table th.symbol-col-covered { width: 70px; }
+                .symbol-row td { height: 19px; padding: 0 4px; line-height: 19px; white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+                .symbol-spacer { padding: 0 !important; border: none !important; }
+                .iteration-delta { color: #b5cea8; font-size: 10px; margin-left: 6px; }
+                .return-value { color: #b5cea8; }
+                .value-chart { background: #1e1e1e; border: 1px solid #333; }
+                .value-chart-line { fill: none; stroke: #569cd6; stroke-width: 1.5; }
+                .value-chart-point { fill: #ce9178; cursor: pointer; }
+                .value-chart-point:hover { fill: #ffd700; }
+                .value-chart-now { stroke: #6a9955; stroke-dasharray: 2 2; }
+                .async-entry td { border-top: 1px dashed #c586c0; }
+                .async-tag { color: #c586c0; font-size: 9px; }
+                .patched-tag { color: #f48771; font-size: 9px; margin-left: 4px; }
+                .skipped-tag { color: #808080; font-size: 9px; }
+                .reg-def { text-decoration: underline dotted; cursor: pointer; }
+                .reg-def:hover { color: #9cdcfe; }
+                .fold-tag { color: #dcdcaa; font-size: 9px; cursor: pointer; }
+                .slider-track { flex: 1; position: relative; }
+                .reg-marker { position: absolute; top: -4px; width: 2px; height: 6px; margin-left: -1px; background: #f48771; cursor: pointer; }
+                .reg-marker:hover { background: #fff; height: 9px; }
+                .cfg-legend span { font-size: 9px; padding: 0 3px; margin-right: 3px; color: #000; }
+                .cfg-only-a { background: #f8d7da; }
+                .cfg-only-b { background: #d4edda; }
+                .cfg-changed { background: #fff3cd; }
+                .insn-jump { color: #c586c0; }
+                .insn-call { color: #dcdcaa; }
+                .insn-ret { color: #d7ba7d; }
+                .insn-interrupt, .insn-syscall, .insn-privileged { color: #f48771; }
+                .insn-arithmetic { color: #b5cea8; }
+                .insn-fpu, .insn-mmx, .insn-sse, .insn-avx { color: #4ec9b0; }
+                .insn-crypto, .insn-virtualization { color: #ce9178; }
+                .lookahead { padding: 0 10px 4px 24px; background: #2d2d2d; border-bottom: 1px solid #444; font-size: 12px; color: #6a6a6a; font-family: monospace; }
+                .insn-detail { padding: 5px 10px; background: #252526; border-bottom: 1px solid #444; font-size: 11px; color: #9cdcfe; }
+                .reg-row { padding: 0 2px; }
+                .reg-row.changed { background: #4d4d1f; color: #ffd700; font-weight: bold; }
+                .changed-byte { text-decoration: underline; }
+                .provenance-card { margin: 2px 0 4px 8px; padding: 3px 5px; background: #252526; border: 1px solid #444; color: #d4d4d4; font-weight: normal; font-size: 11px; cursor: pointer; }
+                .provenance-inputs { color: #9cdcfe; }
+                .hex-byte { cursor: pointer; }
+                .hex-byte:hover { background: #264f78; }
+                .hex-byte.selected { background: #094771; }
+                .stack-access { color: #c586c0; }
+                .stack-tag { color: #c586c0; font-size: 9px; margin-left: 4px; }
+                .trace-rate { color: #6a9955; font-size: 11px; white-space: nowrap; }
+                .crash-tag { color: #f44747; font-weight: bold; }
+                button.crash-tag { background: #3c1f1f; border: 1px solid #f44747; cursor: pointer; padding: 2px 6px; white-space: nowrap; }
+                .timeline-row.crash-row { background: #3c1f1f; }
+                .hex-byte.named { color: #dcdcaa; }
+                .region-tag { color: #dcdcaa; font-size: 9px; margin-left: 4px; }
+                .region-unname { cursor: pointer; color: #888; }
+                .reg-row.selected { outline: 1px solid #569cd6; }
+                .inspector { margin-top: 6px; padding: 5px; border: 1px solid #444; background: #252526; font-size: 11px; }
+                .inspector button { font-size: 10px; margin-right: 3px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer; }
+                .inspector button.active { background: #094771; }
+
                 /* Scrollbar */
                 ::-webkit-scrollbar { width: 10px; height: 10px; }
                 ::-webkit-scrollbar-track { background: #1e1e1e; }
@@ -564,14 +1908,102 @@ pub fn app() -> Html {
                 <div class="panel regs">
                     <div class="header">{ "REGISTERS" }</div>
                     {
-                        for registers.iter().enumerate().map(|(i, &val)| {
-                            let reg_names = ["RAX", "RBX", "RCX", "RDX", "RSI", "RDI", "RBP", "RSP",
-                                             "R8", "R9", "R10", "R11", "R12", "R13", "R14", "R15"];
-                            let name = if i < reg_names.len() { reg_names[i] } else { "REG" };
+                        {
+                            // Also honour the server-side reg_diffs of the active timeline row
+                            let diff_regs: Vec<usize> = timeline_entries
+                                .iter()
+                                .find(|e| e.clnum == *current_clnum)
+                                .map(|e| e.reg_diffs.iter().map(|(idx, _)| *idx).collect())
+                                .unwrap_or_default();
                             html! {
-                                <div>{ format!("{}: {:016x}", name, val) }</div>
+                                for registers.iter().enumerate().map(|(i, &val)| {
+                                    let name = REG_NAMES.get(i).copied().unwrap_or("REG");
+                                    let changed_bits = changed_regs.iter().find(|(idx, _)| *idx == i).map(|&(_, bits)| bits);
+                                    let changed = changed_bits.is_some() || diff_regs.contains(&i);
+                                    let selected = *inspect_sel == Some(InspectSource::Register(i));
+                                    let class = classes!(
+                                        "reg-row",
+                                        changed.then_some("changed"),
+                                        selected.then_some("selected")
+                                    );
+                                    let on_click = {
+                                        let inspect_sel = inspect_sel.clone();
+                                        Callback::from(move |_| inspect_sel.set(Some(InspectSource::Register(i))))
+                                    };
+                                    let on_enter = {
+                                        let hovered_reg = hovered_reg.clone();
+                                        let reg_provenance = reg_provenance.clone();
+                                        let ws_sender = ws_sender.clone();
+                                        let clnum = *current_clnum;
+                                        Callback::from(move |_: MouseEvent| {
+                                            hovered_reg.set(Some(i));
+                                            let cached = matches!(&*reg_provenance, Some((c, r, _)) if *c == clnum && *r == i);
+                                            if let Some(sender) = ws_sender.as_ref().filter(|_| !cached) {
+                                                let msg = ClientMessage::GetRegisterProvenance { clnum, reg: i };
+                                                if let Ok(json) = serde_json::to_string(&msg) {
+                                                    let _ = sender.unbounded_send(Message::Text(json));
+                                                }
+                                            }
+                                        })
+                                    };
+                                    let on_leave = {
+                                        let hovered_reg = hovered_reg.clone();
+                                        Callback::from(move |_: MouseEvent| hovered_reg.set(None))
+                                    };
+                                    // Hover card: the instruction that wrote this value and what it read
+                                    let card = match &*reg_provenance {
+                                        Some((c, r, writer)) if *hovered_reg == Some(i) && *c == *current_clnum && *r == i => {
+                                            Some(match writer {
+                                                Some(writer) => {
+                                                    let on_jump = {
+                                                        let ws_sender = ws_sender.clone();
+                                                        let current_clnum = current_clnum.clone();
+                                                        let memory_addr = memory_addr.clone();
+                                                        let clnum = writer.clnum;
+                                                        Callback::from(move |e: MouseEvent| {
+                                                            e.stop_propagation();
+                                                            current_clnum.set(clnum);
+                                                            if let Some(sender) = &*ws_sender {
+                                                                let msg = ClientMessage::QueryState { clnum, memory_addr: Some(*memory_addr) };
+                                                                if let Ok(json) = serde_json::to_string(&msg) {
+                                                                    let _ = sender.unbounded_send(Message::Text(json));
+                                                                }
+                                                            }
+                                                        })
+                                                    };
+                                                    let inputs = writer
+                                                        .reg_inputs
+                                                        .iter()
+                                                        .map(|&(r, v)| format!("{}={:x}", REG_NAMES.get(r).copied().unwrap_or("REG"), v))
+                                                        .chain(writer.mem_inputs.iter().map(|&(a, v)| format!("[{:x}]={}", a, access_value(v))))
+                                                        .collect::<Vec<_>>()
+                                                        .join(" ");
+                                                    html! {
+                                                        <div class="provenance-card" onclick={on_jump} title="Jump to the writing instruction">
+                                                            <div>{ format!("#{} {:x}: {}", writer.clnum, writer.address, writer.disassembly) }</div>
+                                                            <div class="provenance-inputs">
+                                                                { if inputs.is_empty() { "no inputs".to_string() } else { format!("from {}", inputs) } }
+                                                            </div>
+                                                        </div>
+                                                    }
+                                                }
+                                                None => html! {
+                                                    <div class="provenance-card">{ "unchanged since first seen" }</div>
+                                                },
+                                            })
+                                        }
+                                        _ => None,
+                                    };
+                                    html! {
+                                        <div class={class} onclick={on_click} onmouseenter={on_enter} onmouseleave={on_leave} title="Inspect this value">
+                                            { format!("{}: ", name) }
+                                            { register_hex(val, changed_bits.unwrap_or(0)) }
+                                            { for card }
+                                        </div>
+                                    }
+                                })
                             }
-                        })
+                        }
                     }
                 </div>
 
@@ -586,6 +2018,11 @@ pub fn app() -> Html {
                                     "timeline" => "Switch to CFG",
                                     "slice" => "Switch to CFG",
                                     "cfg" => "Switch to Raw Log",
+                                    "coverage" => "Switch to Raw Log",
+                                    "listing" => "Switch to Raw Log",
+                                    "iterations" => "Switch to Raw Log",
+                                    "functions" => "Switch to Raw Log",
+                                    "dataflow" => "Switch to Raw Log",
                                     _ => "Unknown"
                                 } }
                              </button>
@@ -597,6 +2034,33 @@ pub fn app() -> Html {
                                                 <input type="checkbox" checked={*only_user_code} onchange={toggle_user_code} />
                                                 {" User Code"}
                                             </label>
+                                            {
+                                                if *view_mode == "timeline" {
+                                                    html! {
+                                                        <>
+                                                            <label title="Append new instructions as the live trace grows" style="font-size: 10px; cursor: pointer; margin-right: 5px;">
+                                                                <input type="checkbox" checked={*follow_tail} onchange={toggle_follow_tail} />
+                                                                {" Follow"}
+                                                            </label>
+                                                            <label title="Show each call from user code into a library as one line" style="font-size: 10px; cursor: pointer; margin-right: 5px;">
+                                                                <input type="checkbox" checked={*collapse_lib_calls} onchange={toggle_collapse_lib_calls} />
+                                                                {" Collapse Lib Calls"}
+                                                            </label>
+                                                            <label title="Show back-to-back executions of one address as one line" style="font-size: 10px; cursor: pointer; margin-right: 5px;">
+                                                                <input type="checkbox" checked={*fold_loops} onchange={toggle_fold_loops} />
+                                                                {" Fold Loops"}
+                                                            </label>
+                                                            <input
+                                                                type="text"
+                                                                placeholder="Function..."
+                                                                value={(*function_filter).clone()}
+                                                                onchange={on_function_filter_change}
+                                                                style="font-size: 10px; padding: 2px; width: 100px; margin-right: 5px; background: #333; color: white; border: 1px solid #555;"
+                                                            />
+                                                        </>
+                                                    }
+                                                } else { html! {} }
+                                            }
                                             {
                                                 if *view_mode == "cfg" {
                                                     html! {
@@ -605,6 +2069,36 @@ pub fn app() -> Html {
                                                                 <input type="checkbox" checked={*start_from_main} onchange={toggle_start_main} />
                                                                 {" From Main"}
                                                             </label>
+                                                            <label title="Draw calls through PLT stubs straight to the function they resolved to" style="font-size: 10px; cursor: pointer; margin-right: 5px;">
+                                                                <input type="checkbox" checked={*cfg_collapse_stubs} onchange={toggle_cfg_collapse_stubs} />
+                                                                {" Collapse PLT"}
+                                                            </label>
+                                                            <label title="Render with Graphviz on the server; copes with larger graphs" style="font-size: 10px; cursor: pointer; margin-right: 5px;">
+                                                                <input type="checkbox" checked={*cfg_svg} onchange={toggle_cfg_svg} />
+                                                                {" SVG"}
+                                                            </label>
+                                                            if *has_second_trace {
+                                                                <label title="Merge with the second trace's CFG" style="font-size: 10px; cursor: pointer; margin-right: 5px;">
+                                                                    <input type="checkbox" checked={*cfg_diff} onchange={toggle_cfg_diff} />
+                                                                    {" Diff A/B"}
+                                                                </label>
+                                                            }
+                                                            if *has_second_trace && *cfg_diff {
+                                                                <span class="cfg-legend">
+                                                                    <span class="cfg-only-a">{ "only A" }</span>
+                                                                    <span class="cfg-only-b">{ "only B" }</span>
+                                                                    <span class="cfg-changed">{ "changed" }</span>
+                                                                </span>
+                                                            }
+                                                            <a
+                                                                href={format!(
+                                                                    "http://localhost:3000/api/cfg.svg?only_user_code={}&start_from_main={}&collapse_stubs={}",
+                                                                    *only_user_code, *start_from_main, *cfg_collapse_stubs
+                                                                )}
+                                                                download="cfg.svg"
+                                                                title="Download the graph as SVG (needs Graphviz on the server)"
+                                                                style="font-size: 10px; color: #569cd6; margin-right: 5px;"
+                                                            >{ "⬇ SVG" }</a>
                                                             <input 
                                                                 type="text" 
                                                                 placeholder="Search Func..." 
@@ -629,39 +2123,402 @@ pub fn app() -> Html {
                              <div style="display: flex; gap: 5px; margin-left: 10px; align-items: center;">
                                  <input
                                      type="text"
-                                     placeholder="Slice (rax..)"
+                                     placeholder="Slice (rdi, rsi, 0x..)"
+                                     title="Registers and/or 0x addresses, comma-separated"
                                      value={(*slice_target).clone()}
                                      oninput={on_slice_target_change}
                                      style="font-size: 10px; width: 80px; background: #333; color: white; border: 1px solid #555; padding: 2px;"
                                  />
                                  <button onclick={on_slice} style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Slice" }</button>
+                                 <button onclick={on_data_flow} title="The slice as a graph: which instruction produced each value the next one used" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Data Flow" }</button>
+                                 <button onclick={on_taint_syscall} title="Follow what the syscall at the current clnum returned" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Taint input" }</button>
+                                 <button onclick={on_coverage} title="Which functions executed" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Coverage" }</button>
+                                 <button onclick={on_functions} title="All functions with execution counts" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Functions" }</button>
+                                 <input
+                                     type="text"
+                                     placeholder="Loop regs (rcx, rsi)"
+                                     title="Registers to compare across executions of the current instruction's block"
+                                     value={(*iteration_regs).clone()}
+                                     oninput={on_iteration_regs_change}
+                                     style="font-size: 10px; width: 80px; background: #333; color: white; border: 1px solid #555; padding: 2px;"
+                                 />
+                                 <button onclick={on_iterations} title="Registers at each execution of the current block" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Iterations" }</button>
+                                 if *view_mode == "timeline" || *view_mode == "slice" {
+                                     <button onclick={on_export} style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Download JSON" }</button>
+                                 }
                              </div>
                         </div>
                     </div>
 
+                    {
+                        if let Some(message) = &*error_banner {
+                            html! {
+                                <div class="error-banner">
+                                    <span>{ format!("Error: {}", message) }</span>
+                                    <button onclick={on_dismiss_error} style="font-size: 10px; cursor: pointer;">{ "✕" }</button>
+                                </div>
+                            }
+                        } else { html! {} }
+                    }
+
                     // Current Instruction Display
-                    <div style="padding: 10px; background: #2d2d2d; border-bottom: 1px solid #444; font-size: 14px; color: #4ec9b0;">
+                    <div onclick={on_insn_detail} title="Click for operand detail" style="padding: 10px; background: #2d2d2d; border-bottom: 1px solid #444; font-size: 14px; color: #4ec9b0; cursor: pointer;">
                         { &*current_disasm }
                     </div>
+                    <div class="lookahead">
+                        {
+                            for lookahead.iter().map(|(address, text)| html! {
+                                <div>{ format!("{:x}  {}", address, text) }</div>
+                            })
+                        }
+                    </div>
+                    {
+                        match &*insn_detail {
+                            Some(Some(detail)) => html! {
+                                <div class="insn-detail">
+                                    <div>{ format!("{:x}: {} {} ({} bytes)", detail.address, detail.mnemonic, detail.op_str, detail.size) }</div>
+                                    {
+                                        for detail.operands.iter().enumerate().map(|(i, op)| {
+                                            let text = match op {
+                                                OperandDetail::Reg { name, size } => format!("reg {} ({}B)", name, size),
+                                                OperandDetail::Imm { value, size } => format!("imm {:#x} ({}B)", value, size),
+                                                OperandDetail::Mem { segment, base, index, scale, disp, size } => format!(
+                                                    "mem {}[{} + {}*{} + {:#x}] ({}B)",
+                                                    segment.as_ref().map(|s| format!("{}:", s)).unwrap_or_default(),
+                                                    base.as_deref().unwrap_or("-"),
+                                                    index.as_deref().unwrap_or("-"),
+                                                    scale,
+                                                    disp,
+                                                    size
+                                                ),
+                                                OperandDetail::Invalid => "invalid".to_string(),
+                                            };
+                                            html! { <div>{ format!("op{}: {}", i, text) }</div> }
+                                        })
+                                    }
+                                    <div>{ format!("read: {}", detail.regs_read.join(", ")) }</div>
+                                    <div>{ format!("write: {}", detail.regs_write.join(", ")) }</div>
+                                    <div>{ format!("groups: {}", detail.groups.join(", ")) }</div>
+                                </div>
+                            },
+                            Some(None) => html! {
+                                <div class="insn-detail">{ "No detail available for this instruction" }</div>
+                            },
+                            None => html! {},
+                        }
+                    }
 
                     // Controls
                     <div class="controls">
                         <div class="controls-inner">
+                            <button onclick={on_goto_main} title="Jump to the start of main" style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "⏮ Main" }</button>
                             <button onclick={on_step_backward.clone()} style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "◀ Step Back" }</button>
+                            <div class="slider-track">
+                                <input
+                                    type="range"
+                                    min={(*max_clnum).min(1).to_string()}
+                                    max={max_clnum.to_string()}
+                                    value={current_clnum.to_string()}
+                                    oninput={on_slider_input.clone()}
+                                    style="width: 100%;"
+                                />
+                                if let Some((reg, marks)) = &*reg_markers {
+                                    {
+                                        for marks.iter().map(|&(clnum, changes)| {
+                                            let percent = marker_position(clnum, *max_clnum);
+                                            let ws_sender = ws_sender.clone();
+                                            let name = REG_NAMES.get(*reg).copied().unwrap_or("REG");
+                                            html! {
+                                                <span
+                                                    class="reg-marker"
+                                                    style={format!("left: {:.2}%;", percent)}
+                                                    title={if changes > 1 {
+                                                        format!("{} changed at clnum {} ({} changes here)", name, clnum, changes)
+                                                    } else {
+                                                        format!("{} changed at clnum {}", name, clnum)
+                                                    }}
+                                                    onclick={Callback::from(move |_| {
+                                                        if let Some(sender) = &*ws_sender {
+                                                            let msg = ClientMessage::QueryState { clnum, memory_addr: None };
+                                                            if let Ok(json) = serde_json::to_string(&msg) {
+                                                                let _ = sender.unbounded_send(Message::Text(json));
+                                                            }
+                                                        }
+                                                    })}
+                                                />
+                                            }
+                                        })
+                                    }
+                                }
+                            </div>
+                            <select onchange={on_marker_reg_change} title="Mark where a register changes along the slider">
+                                <option value="" selected={reg_markers.is_none()}>{ "Marks: none" }</option>
+                                {
+                                    for REG_NAMES.iter().enumerate().map(|(i, name)| html! {
+                                        <option value={i.to_string()} selected={reg_markers.as_ref().is_some_and(|(reg, _)| *reg == i)}>{ *name }</option>
+                                    })
+                                }
+                            </select>
+                            if reg_markers.is_some() {
+                                <button onclick={on_marker_step(false)} title="Previous change of the marked register" style="padding: 5px 8px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "◂" }</button>
+                                <button onclick={on_marker_step(true)} title="Next change of the marked register" style="padding: 5px 8px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "▸" }</button>
+                            }
+                            <span>{ format!("{} / {}", *current_clnum, *max_clnum) }</span>
                             <input
-                                type="range"
-                                min="0"
+                                type="number"
+                                placeholder="Go to clnum"
+                                min="1"
                                 max={max_clnum.to_string()}
-                                value={current_clnum.to_string()}
-                                onchange={on_slider_change.clone()}
-                                style="flex: 1;"
+                                value={(*goto_clnum_text).clone()}
+                                oninput={
+                                    let goto_clnum_text = goto_clnum_text.clone();
+                                    Callback::from(move |e: InputEvent| {
+                                        if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                                            goto_clnum_text.set(input.value());
+                                        }
+                                    })
+                                }
+                                onkeydown={on_goto_clnum}
+                                style="width: 100px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 4px;"
                             />
-                            <span>{ format!("{} / {}", *current_clnum, *max_clnum) }</span>
+                            <input
+                                type="text"
+                                placeholder="Go to address"
+                                title="Jump to the next execution of this hex address"
+                                value={(*goto_address_text).clone()}
+                                oninput={
+                                    let goto_address_text = goto_address_text.clone();
+                                    Callback::from(move |e: InputEvent| {
+                                        if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                                            goto_address_text.set(input.value());
+                                        }
+                                    })
+                                }
+                                onkeydown={on_goto_address}
+                                style="width: 120px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 4px;"
+                            />
+                            if let Some((clnum, _, signal)) = *crash {
+                                <button
+                                    class="crash-tag"
+                                    title="Go to the last instruction before the program was killed"
+                                    onclick={
+                                        let ws_sender = ws_sender.clone();
+                                        Callback::from(move |_| {
+                                            if let Some(sender) = &*ws_sender {
+                                                let msg = ClientMessage::QueryState { clnum, memory_addr: None };
+                                                if let Ok(json) = serde_json::to_string(&msg) {
+                                                    let _ = sender.unbounded_send(Message::Text(json));
+                                                }
+                                            }
+                                        })
+                                    }
+                                >{ format!("✖ crash{}", signal_suffix(signal)) }</button>
+                            }
+                            if *trace_rate > 0 {
+                                <span class="trace-rate" title="Instructions arriving from the tracer per second">
+                                    { format!("● {}/s", insn_rate(*trace_rate)) }
+                                </span>
+                            }
                             <button onclick={on_step_forward.clone()} style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "Step Forward ▶" }</button>
+                            <button onclick={on_step_over.clone()} style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "Step Over ⤼" }</button>
+                            <button
+                                onclick={
+                                    let show_settings = show_settings.clone();
+                                    Callback::from(move |_| show_settings.set(!*show_settings))
+                                }
+                                title="Disassembly and analysis settings"
+                                style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;"
+                            >{ "⚙" }</button>
+                            <button onclick={on_copy_link} title="Copy a link to this clnum and view" style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "🔗 Copy link" }</button>
+                        </div>
+                        {
+                            match (&*session_config, *show_settings) {
+                                (Some(config), true) => {
+                                    let update = |apply: fn(&mut SessionConfig, String)| {
+                                        let on_configure = on_configure.clone();
+                                        let config = config.clone();
+                                        move |value: String| {
+                                            let mut config = config.clone();
+                                            apply(&mut config, value);
+                                            on_configure.emit(config);
+                                        }
+                                    };
+                                    let set_syntax = update(|c, v| {
+                                        c.syntax = if v == "att" { DisasmSyntax::Att } else { DisasmSyntax::Intel }
+                                    });
+                                    let toggle_normalize = update(|c, _| c.normalize_immediates = !c.normalize_immediates);
+                                    let toggle_symbolize = update(|c, _| c.symbolize = !c.symbolize);
+                                    let set_base = update(|c, v| {
+                                        c.immediate_base = match v.as_str() {
+                                            "decimal" => ImmediateBase::Decimal,
+                                            "both" => ImmediateBase::Both,
+                                            _ => ImmediateBase::Hex,
+                                        }
+                                    });
+                                    let toggle_ascii = update(|c, _| c.ascii_immediates = !c.ascii_immediates);
+                                    let toggle_symbolize_cfg = update(|c, _| c.symbolize_cfg = !c.symbolize_cfg);
+                                    let set_module = update(|c, v| c.active_module = (!v.is_empty()).then_some(v));
+                                    html! {
+                                        <div class="controls-inner settings">
+                                            <label>
+                                                { "Syntax " }
+                                                <select onchange={Callback::from(move |e: Event| {
+                                                    if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                                                        set_syntax(select.value());
+                                                    }
+                                                })}>
+                                                    <option value="intel" selected={config.syntax == DisasmSyntax::Intel}>{ "Intel" }</option>
+                                                    <option value="att" selected={config.syntax == DisasmSyntax::Att}>{ "AT&T" }</option>
+                                                </select>
+                                            </label>
+                                            <label title="Show near-wraparound immediates as negative numbers and displacements in hex">
+                                                <input
+                                                    type="checkbox"
+                                                    checked={config.normalize_immediates}
+                                                    onchange={Callback::from(move |_| toggle_normalize(String::new()))}
+                                                />
+                                                { "signed immediates" }
+                                            </label>
+                                            <label title="Show branch targets and stack slots as symbol and variable names">
+                                                <input
+                                                    type="checkbox"
+                                                    checked={config.symbolize}
+                                                    onchange={Callback::from(move |_| toggle_symbolize(String::new()))}
+                                                />
+                                                { "symbolize" }
+                                            </label>
+                                            <label title="Number base for immediate operands">
+                                                { "Immediates " }
+                                                <select onchange={Callback::from(move |e: Event| {
+                                                    if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                                                        set_base(select.value());
+                                                    }
+                                                })}>
+                                                    <option value="hex" selected={config.immediate_base == ImmediateBase::Hex}>{ "hex" }</option>
+                                                    <option value="decimal" selected={config.immediate_base == ImmediateBase::Decimal}>{ "decimal" }</option>
+                                                    <option value="both" selected={config.immediate_base == ImmediateBase::Both}>{ "both" }</option>
+                                                </select>
+                                            </label>
+                                            <label title="Annotate immediates made of printable bytes with their text">
+                                                <input
+                                                    type="checkbox"
+                                                    checked={config.ascii_immediates}
+                                                    onchange={Callback::from(move |_| toggle_ascii(String::new()))}
+                                                />
+                                                { "ASCII immediates" }
+                                            </label>
+                                            <label title="Rewrite CFG node labels like the timeline instead of keeping them narrow">
+                                                <input
+                                                    type="checkbox"
+                                                    checked={config.symbolize_cfg}
+                                                    onchange={Callback::from(move |_| toggle_symbolize_cfg(String::new()))}
+                                                />
+                                                { "symbolize CFG" }
+                                            </label>
+                                            <label title="Image treated as user code by the user-code filters">
+                                                { "User code " }
+                                                <select onchange={Callback::from(move |e: Event| {
+                                                    if let Some(select) = e.target_dyn_into::<HtmlSelectElement>() {
+                                                        set_module(select.value());
+                                                    }
+                                                })}>
+                                                    <option value="" selected={config.active_module.is_none()}>{ "main binary" }</option>
+                                                    {
+                                                        for config_modules.iter().map(|name| html! {
+                                                            <option value={name.clone()} selected={config.active_module.as_ref() == Some(name)}>{ name }</option>
+                                                        })
+                                                    }
+                                                </select>
+                                            </label>
+                                            <label title="Instructions either side of the current one sent with Ask AI">
+                                                { "AI context " }
+                                                <input
+                                                    type="number"
+                                                    min="0"
+                                                    max="200"
+                                                    value={ai_context_lines.to_string()}
+                                                    onchange={number_setting(&ai_context_lines)}
+                                                />
+                                            </label>
+                                            <label title="Instructions either side of the current one shown in the timeline">
+                                                { "Timeline radius " }
+                                                <input
+                                                    type="number"
+                                                    min="1"
+                                                    max="500"
+                                                    value={timeline_radius.to_string()}
+                                                    onchange={number_setting(&timeline_radius)}
+                                                />
+                                            </label>
+                                        </div>
+                                    }
+                                }
+                                _ => html! {},
+                            }
+                        }
+                        <div class="controls-inner breakpoints">
+                            <input
+                                type="text"
+                                placeholder="Breakpoint (hex address)"
+                                value={(*breakpoint_text).clone()}
+                                oninput={
+                                    let breakpoint_text = breakpoint_text.clone();
+                                    Callback::from(move |e: InputEvent| {
+                                        if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                                            breakpoint_text.set(input.value());
+                                        }
+                                    })
+                                }
+                                style="width: 160px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 4px;"
+                            />
+                            <input
+                                type="text"
+                                placeholder="if (e.g. rdi == 0)"
+                                title="Only break while this holds: a register or [hex address] (optionally byte/word/dword), a comparison and a value"
+                                value={(*breakpoint_condition).clone()}
+                                oninput={
+                                    let breakpoint_condition = breakpoint_condition.clone();
+                                    Callback::from(move |e: InputEvent| {
+                                        if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                                            breakpoint_condition.set(input.value());
+                                        }
+                                    })
+                                }
+                                style="width: 140px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 4px;"
+                            />
+                            <label title="Static (file) address; resolved through the load bias">
+                                <input
+                                    type="checkbox"
+                                    checked={*breakpoint_static}
+                                    onchange={
+                                        let breakpoint_static = breakpoint_static.clone();
+                                        Callback::from(move |_| breakpoint_static.set(!*breakpoint_static))
+                                    }
+                                />
+                                { "static" }
+                            </label>
+                            <button onclick={on_set_breakpoint} style="padding: 5px 10px; background: #333; color: #d4d4d4; border: 1px solid #555; cursor: pointer;">{ "● Break" }</button>
+                            {
+                                for breakpoints.iter().map(|&bp| {
+                                    let on_clear_breakpoint = on_clear_breakpoint.clone();
+                                    html! {
+                                        <span class="breakpoint" title="Click to remove" onclick={Callback::from(move |_| on_clear_breakpoint.emit(bp))}>
+                                            {
+                                                format!(
+                                                    "{}{:x}{} ×",
+                                                    if bp.is_static { "s:" } else { "" },
+                                                    bp.address,
+                                                    bp.condition.map(|c| format!(" if {}", condition_text(&c))).unwrap_or_default()
+                                                )
+                                            }
+                                        </span>
+                                    }
+                                })
+                            }
                         </div>
                     </div>
 
-                    <div class="trace-content">
+                    <div class="trace-content" ref={trace_content_ref} onscroll={on_trace_scroll}>
                         {
                             if !ai_response.is_empty() {
                                 html! {
@@ -680,6 +2537,260 @@ pub fn app() -> Html {
                                         <div class="log-entry">{ line }</div>
                                     })
                                 }
+                            } else if *view_mode == "coverage" {
+                                let executed = coverage.iter().filter(|(_, hit, _)| *hit).count();
+                                html! {
+                                    <>
+                                        <div style="background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555;">
+                                            { format!("{} / {} functions executed", executed, coverage.len()) }
+                                        </div>
+                                        <table class="timeline-table">
+                                            <thead>
+                                                <tr>
+                                                    <th>{ "Function" }</th>
+                                                    <th>{ "Executed" }</th>
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                {
+                                                    for coverage.iter().map(|(name, hit, count)| {
+                                                        let on_click = {
+                                                            let ws_sender = ws_sender.clone();
+                                                            let name = name.clone();
+                                                            Callback::from(move |_| {
+                                                                if let Some(sender) = &*ws_sender {
+                                                                    let msg = ClientMessage::GetFunctionListing { name: name.clone() };
+                                                                    if let Ok(json) = serde_json::to_string(&msg) {
+                                                                        let _ = sender.unbounded_send(Message::Text(json));
+                                                                    }
+                                                                }
+                                                            })
+                                                        };
+                                                        html! {
+                                                            <tr class={classes!("timeline-row", (!*hit).then_some("coverage-missed"))} onclick={on_click} title="Show the function's disassembly">
+                                                                <td>{ name }</td>
+                                                                <td>{ if *hit { count.to_string() } else { "-".to_string() } }</td>
+                                                            </tr>
+                                                        }
+                                                    })
+                                                }
+                                            </tbody>
+                                        </table>
+                                    </>
+                                }
+                            } else if *view_mode == "listing" {
+                                let (name, lines) = &*function_listing;
+                                let executed = lines.iter().filter(|(_, _, first)| first.is_some()).count();
+                                html! {
+                                    <>
+                                        <div style="background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555;">
+                                            { format!("{}: {} / {} instructions executed", name, executed, lines.len()) }
+                                        </div>
+                                        <table class="timeline-table">
+                                            <thead>
+                                                <tr>
+                                                    <th>{ "Addr" }</th>
+                                                    <th>{ "Instruction" }</th>
+                                                    <th>{ "First" }</th>
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                {
+                                                    for lines.iter().map(|(address, text, first)| {
+                                                        // Executed lines jump to their first execution
+                                                        let on_click = {
+                                                            let ws_sender = ws_sender.clone();
+                                                            let current_clnum = current_clnum.clone();
+                                                            let memory_addr = memory_addr.clone();
+                                                            let first = *first;
+                                                            Callback::from(move |_| {
+                                                                let Some(clnum) = first else { return };
+                                                                current_clnum.set(clnum);
+                                                                if let Some(sender) = &*ws_sender {
+                                                                    let msg = ClientMessage::QueryState { clnum, memory_addr: Some(*memory_addr) };
+                                                                    if let Ok(json) = serde_json::to_string(&msg) {
+                                                                        let _ = sender.unbounded_send(Message::Text(json));
+                                                                    }
+                                                                }
+                                                            })
+                                                        };
+                                                        html! {
+                                                            <tr class={classes!("timeline-row", first.is_none().then_some("coverage-missed"))} onclick={on_click}>
+                                                                <td class="col-addr">{ format!("{:08x}", address) }</td>
+                                                                <td class="col-insn">{ text }</td>
+                                                                <td class="col-clnum">{ first.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string()) }</td>
+                                                            </tr>
+                                                        }
+                                                    })
+                                                }
+                                            </tbody>
+                                        </table>
+                                    </>
+                                }
+                            } else if *view_mode == "functions" {
+                                let (scroll_top, height) = *symbol_viewport;
+                                let rows = visible_rows(scroll_top, height, symbol_order.len());
+                                let below = symbol_order.len() - rows.end;
+                                let (sort_column, descending) = *symbol_sort;
+                                let covered = symbols.iter().filter(|s| s.covered).count();
+                                html! {
+                                    <>
+                                        <div class="symbol-toolbar">
+                                            <input
+                                                type="text"
+                                                placeholder="Filter functions"
+                                                value={(*symbol_filter).clone()}
+                                                oninput={on_symbol_filter_change}
+                                                style="font-size: 10px; width: 140px; background: #252526; color: white; border: 1px solid #555; padding: 1px;"
+                                            />
+                                            <span>{ format!("{} of {} functions, {} executed", symbol_order.len(), symbols.len(), covered) }</span>
+                                        </div>
+                                        <table class="timeline-table symbol-table">
+                                            <thead>
+                                                <tr>
+                                                    {
+                                                        for SymbolColumn::ALL.iter().map(|&column| {
+                                                            // Clicking the sorted column flips it; counts
+                                                            // sort highest first, names and addresses lowest
+                                                            let on_click = {
+                                                                let symbol_sort = symbol_sort.clone();
+                                                                Callback::from(move |_| {
+                                                                    let (current, descending) = *symbol_sort;
+                                                                    if current == column {
+                                                                        symbol_sort.set((column, !descending));
+                                                                    } else {
+                                                                        let numeric = matches!(column, SymbolColumn::Size | SymbolColumn::Executions | SymbolColumn::Covered);
+                                                                        symbol_sort.set((column, numeric));
+                                                                    }
+                                                                })
+                                                            };
+                                                            let arrow = match (column == sort_column, descending) {
+                                                                (false, _) => "",
+                                                                (true, true) => " ▼",
+                                                                (true, false) => " ▲",
+                                                            };
+                                                            html! {
+                                                                <th class={format!("symbol-col-{}", column.title().to_lowercase())} onclick={on_click}>
+                                                                    { format!("{}{}", column.title(), arrow) }
+                                                                </th>
+                                                            }
+                                                        })
+                                                    }
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                <tr><td colspan="5" class="symbol-spacer" style={format!("height: {}px", rows.start as i32 * ROW_HEIGHT)}></td></tr>
+                                                {
+                                                    for symbol_order[rows.clone()].iter().map(|&i| {
+                                                        let symbol = &symbols[i];
+                                                        // Executed functions jump to their first
+                                                        // execution; the others show their listing
+                                                        let on_click = {
+                                                            let ws_sender = ws_sender.clone();
+                                                            let current_clnum = current_clnum.clone();
+                                                            let memory_addr = memory_addr.clone();
+                                                            let first = symbol.first_clnum;
+                                                            let name = symbol.name.clone();
+                                                            Callback::from(move |_| {
+                                                                let msg = match first {
+                                                                    Some(clnum) => {
+                                                                        current_clnum.set(clnum);
+                                                                        ClientMessage::QueryState { clnum, memory_addr: Some(*memory_addr) }
+                                                                    }
+                                                                    None => ClientMessage::GetFunctionListing { name: name.clone() },
+                                                                };
+                                                                if let Some(sender) = &*ws_sender {
+                                                                    if let Ok(json) = serde_json::to_string(&msg) {
+                                                                        let _ = sender.unbounded_send(Message::Text(json));
+                                                                    }
+                                                                }
+                                                            })
+                                                        };
+                                                        html! {
+                                                            <tr class={classes!("timeline-row", "symbol-row", (!symbol.covered).then_some("coverage-missed"))} onclick={on_click} title={symbol.name.clone()}>
+                                                                <td>{ &symbol.name }</td>
+                                                                <td class="col-addr">{ format!("{:08x}", symbol.address) }</td>
+                                                                <td>{ if symbol.size == 0 { "-".to_string() } else { format!("{:#x}", symbol.size) } }</td>
+                                                                <td>{ symbol.executions }</td>
+                                                                <td>{ if symbol.covered { "✓" } else { "" } }</td>
+                                                            </tr>
+                                                        }
+                                                    })
+                                                }
+                                                <tr><td colspan="5" class="symbol-spacer" style={format!("height: {}px", below as i32 * ROW_HEIGHT)}></td></tr>
+                                            </tbody>
+                                        </table>
+                                    </>
+                                }
+                            } else if *view_mode == "iterations" {
+                                let (address, regs, rows) = &*block_iterations;
+                                html! {
+                                    <>
+                                        <div style="background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555;">
+                                            {
+                                                if rows.len() > ITERATION_ROWS_SHOWN {
+                                                    format!("{:08x}: {} executions (first {} shown)", address, rows.len(), ITERATION_ROWS_SHOWN)
+                                                } else {
+                                                    format!("{:08x}: {} executions", address, rows.len())
+                                                }
+                                            }
+                                        </div>
+                                        <table class="timeline-table">
+                                            <thead>
+                                                <tr>
+                                                    <th>{ "#" }</th>
+                                                    <th>{ "Clnum" }</th>
+                                                    {
+                                                        for regs.iter().map(|&r| html! {
+                                                            <th>{ REG_NAMES.get(r).copied().unwrap_or("REG") }</th>
+                                                        })
+                                                    }
+                                                </tr>
+                                            </thead>
+                                            <tbody>
+                                                {
+                                                    for rows.iter().take(ITERATION_ROWS_SHOWN).enumerate().map(|(n, (clnum, values))| {
+                                                        let previous = n.checked_sub(1).map(|p| &rows[p].1);
+                                                        let on_click = {
+                                                            let ws_sender = ws_sender.clone();
+                                                            let current_clnum = current_clnum.clone();
+                                                            let memory_addr = memory_addr.clone();
+                                                            let clnum = *clnum;
+                                                            Callback::from(move |_| {
+                                                                current_clnum.set(clnum);
+                                                                if let Some(sender) = &*ws_sender {
+                                                                    let msg = ClientMessage::QueryState { clnum, memory_addr: Some(*memory_addr) };
+                                                                    if let Ok(json) = serde_json::to_string(&msg) {
+                                                                        let _ = sender.unbounded_send(Message::Text(json));
+                                                                    }
+                                                                }
+                                                            })
+                                                        };
+                                                        html! {
+                                                            <tr class="timeline-row" onclick={on_click}>
+                                                                <td class="col-clnum">{ n + 1 }</td>
+                                                                <td class="col-clnum">{ clnum }</td>
+                                                                {
+                                                                    for values.iter().enumerate().map(|(i, &value)| {
+                                                                        let delta = previous.and_then(|p| p.get(i)).map(|&before| iteration_delta(before, value));
+                                                                        html! {
+                                                                            <td class="col-insn">
+                                                                                { format!("{:x}", value) }
+                                                                                if let Some(delta) = delta {
+                                                                                    <span class="iteration-delta">{ delta }</span>
+                                                                                }
+                                                                            </td>
+                                                                        }
+                                                                    })
+                                                                }
+                                                            </tr>
+                                                        }
+                                                    })
+                                                }
+                                            </tbody>
+                                        </table>
+                                    </>
+                                }
                             } else if *view_mode == "cfg" {
                                 html! {
                                     <div id="cfg-view" style="width: 100%; height: 100%; overflow: auto; background: white;">
@@ -687,11 +2798,20 @@ pub fn app() -> Html {
                                         { "Loading CFG..." }
                                     </div>
                                 }
+                            } else if *view_mode == "dataflow" {
+                                html! {
+                                    <>
+                                        <div style="background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555;">{ format!("Data Flow for '{}'", *slice_target) }</div>
+                                        <div id="dataflow-view" style="width: 100%; height: 100%; overflow: auto; background: white;">
+                                            { "Loading data flow..." }
+                                        </div>
+                                    </>
+                                }
                             } else {
                                 html! {
                                     <>
                                         { if *view_mode == "slice" {
-                                            html! { <div style="background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555;">{ format!("Slice Results for '{}'", *slice_target) }</div> }
+                                            html! { <div style="background: #333; color: #fff; padding: 2px; font-size: 10px; border-bottom: 1px solid #555;">{ (*slice_title).clone().unwrap_or_else(|| format!("Slice Results for '{}'", *slice_target)) }</div> }
                                         } else { html! {} } }
                                         <table class="timeline-table">
                                         <thead>
@@ -706,7 +2826,13 @@ pub fn app() -> Html {
                                             {
                                                 for timeline_entries.iter().map(|entry| {
                                                     let is_active = entry.clnum == *current_clnum;
-                                                    let class = if is_active { "timeline-row active" } else { "timeline-row" };
+                                                    let crashed = crash.is_some_and(|(clnum, _, _)| clnum == entry.clnum);
+                                                    let class = classes!(
+                                                        "timeline-row",
+                                                        is_active.then_some("active"),
+                                                        entry.async_entry.then_some("async-entry"),
+                                                        crashed.then_some("crash-row")
+                                                    );
                                                     let clnum = entry.clnum;
                                                     let on_click = {
                                                         let ws_sender = ws_sender.clone();
@@ -722,27 +2848,111 @@ pub fn app() -> Html {
                                                             }
                                                         })
                                                     };
+                                                    // Swap a folded entry for the rows it stands for
+                                                    let on_expand = {
+                                                        let ws_sender = ws_sender.clone();
+                                                        let timeline_expanding = timeline_expanding.clone();
+                                                        let timeline_appending = timeline_appending.clone();
+                                                        let only_user_code = only_user_code.clone();
+                                                        let collapse_lib_calls = collapse_lib_calls.clone();
+                                                        let function_filter = function_filter.clone();
+                                                        let count = entry.repeats + 1;
+                                                        Callback::from(move |e: MouseEvent| {
+                                                            e.stop_propagation();
+                                                            if *timeline_appending.borrow() || timeline_expanding.borrow().is_some() {
+                                                                return;
+                                                            }
+                                                            if let Some(sender) = &*ws_sender {
+                                                                let function = if function_filter.is_empty() {
+                                                                    None
+                                                                } else {
+                                                                    Some((*function_filter).clone())
+                                                                };
+                                                                let msg = ClientMessage::GetTraceLog {
+                                                                    start: clnum,
+                                                                    count,
+                                                                    only_user_code: *only_user_code,
+                                                                    function,
+                                                                    radius: None,
+                                                                    collapse_library_calls: *collapse_lib_calls,
+                                                                    fold: false,
+                                                                };
+                                                                if let Ok(json) = serde_json::to_string(&msg) {
+                                                                    *timeline_expanding.borrow_mut() = Some(clnum);
+                                                                    let _ = sender.unbounded_send(Message::Text(json));
+                                                                }
+                                                            }
+                                                        })
+                                                    };
 
                                                     let effect_str = {
-                                                        let mut s = String::new();
-                                                        if let Some((idx, val)) = entry.reg_diff {
-                                                            let reg_names = ["RAX", "RBX", "RCX", "RDX", "RSI", "RDI", "RBP", "RSP",
-                                                                            "R8", "R9", "R10", "R11", "R12", "R13", "R14", "R15"];
-                                                            let name = if idx < reg_names.len() { reg_names[idx] } else { "REG" };
-                                                            s.push_str(&format!("{}={:x} ", name, val));
-                                                        }
-                                                        if let Some((addr, val, is_write)) = entry.mem_access {
+                                                        let regs = entry.reg_diffs.iter().map(|&(idx, val)| {
+                                                            let name = REG_NAMES.get(idx).copied().unwrap_or("REG");
+                                                            html! { <span>{ format!("{}={:x} ", name, val) }</span> }
+                                                        });
+                                                        let mems = entry.mem_accesses.iter().map(|&(addr, val, is_write)| {
                                                             let op = if is_write { "W" } else { "R" };
-                                                            s.push_str(&format!("Mem{}[{:x}]={:x}", op, addr, val));
-                                                        }
-                                                        s
+                                                            let class = in_stack(addr).then_some("stack-access");
+                                                            html! { <span class={classes!(class)}>{ format!("Mem{}[{:x}]={} ", op, addr, access_value(val)) }</span> }
+                                                        });
+                                                        regs.chain(mems).collect::<Html>()
                                                     };
 
                                                     html! {
                                                         <tr class={class} onclick={on_click}>
                                                             <td class="col-clnum">{ entry.clnum }</td>
                                                             <td class="col-addr">{ format!("{:08x}", entry.address) }</td>
-                                                            <td class="col-insn">{ &entry.disassembly }</td>
+                                                            <td
+                                                                class={classes!("col-insn", entry.groups.first().map(|g| format!("insn-{}", g.name())))}
+                                                                title={entry.groups.iter().map(|g| g.name()).collect::<Vec<_>>().join(", ")}
+                                                            >
+                                                                if entry.async_entry {
+                                                                    <span class="async-tag" title="reached by a signal or interrupt, not from the previous instruction">{ "⚡ async " }</span>
+                                                                }
+                                                                if crashed {
+                                                                    <span class="crash-tag" title="the program was killed after this instruction">{ format!("✖ crash{} ", signal_suffix(crash.and_then(|(_, _, signal)| signal))) }</span>
+                                                                }
+                                                                {
+                                                                    for split_words(&entry.disassembly).into_iter().map(|word| {
+                                                                        let Some(&(_, def)) = entry.reg_defs.iter().find(|(name, _)| name == word) else {
+                                                                            return html! { { word.to_string() } };
+                                                                        };
+                                                                        // Jump to the instruction that wrote the operand
+                                                                        let on_def = {
+                                                                            let ws_sender = ws_sender.clone();
+                                                                            let current_clnum = current_clnum.clone();
+                                                                            let memory_addr = memory_addr.clone();
+                                                                            Callback::from(move |e: MouseEvent| {
+                                                                                e.stop_propagation();
+                                                                                current_clnum.set(def);
+                                                                                if let Some(sender) = &*ws_sender {
+                                                                                    let msg = ClientMessage::QueryState { clnum: def, memory_addr: Some(*memory_addr) };
+                                                                                    if let Ok(json) = serde_json::to_string(&msg) {
+                                                                                        let _ = sender.unbounded_send(Message::Text(json));
+                                                                                    }
+                                                                                }
+                                                                            })
+                                                                        };
+                                                                        html! {
+                                                                            <span class="reg-def" title={format!("{} last written at clnum {}", word, def)} onclick={on_def}>{ word.to_string() }</span>
+                                                                        }
+                                                                    })
+                                                                }
+                                                                if entry.patched {
+                                                                    <span class="patched-tag" title="executed bytes differ from the loaded image">{ "patched" }</span>
+                                                                }
+                                                                if let Some(value) = entry.return_value {
+                                                                    <span class="return-value" title="rax when the call returned">{ format!(" -> {:#x}", value) }</span>
+                                                                }
+                                                                if entry.skipped_insns > 0 {
+                                                                    <span class="skipped-tag" title="library instructions hidden until the call returned">{ format!(" [{} insns]", entry.skipped_insns) }</span>
+                                                                }
+                                                                if let Some(last) = entry.last_repeat {
+                                                                    <span class="fold-tag" title={format!("clnums {}-{}, click to expand", entry.clnum, last)} onclick={on_expand}>
+                                                                        { format!(" [x{}]", entry.repeats + 1) }
+                                                                    </span>
+                                                                }
+                                                            </td>
                                                             <td class="col-effect">{ effect_str }</td>
                                                         </tr>
                                                     }
@@ -769,37 +2979,248 @@ pub fn app() -> Html {
                                 style="width: 80px; font-size: 11px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 2px;"
                                 value={format!("{:x}", *memory_addr)}
                             />
+                            <button onclick={on_memory_rsp} title="Show memory at rsp" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "rsp" }</button>
+                            <button onclick={on_memory_rbp} title="Show memory at rbp" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "rbp" }</button>
+                            <label title="Keep the view on rsp as execution moves" style="font-size: 10px; display: flex; align-items: center;">
+                                <input type="checkbox" checked={*follow_rsp.borrow()} onchange={on_follow_rsp} />
+                                { "follow" }
+                            </label>
                             <button onclick={on_get_writes} style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Writes" }</button>
+                            <button onclick={on_get_reads} style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Reads" }</button>
+                            <button onclick={on_plot_value} title="Plot the selected value over time" style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Plot" }</button>
                         </div>
                     </div>
                     <div style="font-size: 11px; line-height: 1.4; font-family: monospace;">
                         {
                             for memory.chunks(16).enumerate().map(|(i, chunk)| {
                                 let addr = *memory_addr + (i * 16) as u64;
-                                let hex: String = chunk.iter().map(|b| format!("{:02x} ", b)).collect();
+                                let row_end = addr + chunk.len() as u64;
+                                let row_regions: Vec<&NamedRegion> = regions
+                                    .iter()
+                                    .filter(|r| r.address < row_end && r.address.saturating_add(r.len) > addr)
+                                    .collect();
+                                let hex = chunk.iter().enumerate().map(|(j, b)| {
+                                    let byte_addr = addr + j as u64;
+                                    let region = row_regions
+                                        .iter()
+                                        .find(|r| byte_addr >= r.address && byte_addr - r.address < r.len);
+                                    let on_click = {
+                                        let ws_sender = ws_sender.clone();
+                                        let current_clnum = current_clnum.clone();
+                                        let inspect_sel = inspect_sel.clone();
+                                        Callback::from(move |e: MouseEvent| {
+                                            if e.shift_key() {
+                                                inspect_sel.set(Some(InspectSource::Memory(byte_addr)));
+                                                return;
+                                            }
+                                            if let Some(sender) = &*ws_sender {
+                                                let msg = ClientMessage::GetLastWriter {
+                                                    clnum: *current_clnum,
+                                                    address: byte_addr,
+                                                };
+                                                if let Ok(json) = serde_json::to_string(&msg) {
+                                                    let _ = sender.unbounded_send(Message::Text(json));
+                                                }
+                                            }
+                                        })
+                                    };
+                                    let selected = matches!(*inspect_sel, Some(InspectSource::Memory(start))
+                                        if byte_addr >= start && byte_addr < start + inspect_width.size() as u64);
+                                    let title = match region {
+                                        Some(r) => format!("{}+0x{:x}. ", r.name, byte_addr - r.address),
+                                        None => String::new(),
+                                    } + "Click: jump to the instruction that wrote this byte. Shift+click: inspect the value here";
+                                    html! {
+                                        <span
+                                            class={classes!("hex-byte", selected.then_some("selected"), region.map(|_| "named"))}
+                                            onclick={on_click}
+                                            {title}
+                                        >{ format!("{:02x} ", b) }</span>
+                                    }
+                                }).collect::<Html>();
                                 let ascii: String = chunk.iter().map(|&b| {
-                                    if b >= 32 && b < 127 { b as char } else { '.' }
+                                    if (32..127).contains(&b) { b as char } else { '.' }
                                 }).collect();
                                 html! {
                                     <div style="margin-bottom: 2px; display: flex;">
                                         <span style="color: #ce9178; width: 70px; flex-shrink: 0;">{ format!("{:08x}:", addr) }</span>
                                         <span style="color: #d4d4d4; margin-right: 10px; width: 230px; flex-shrink: 0;">{ hex }</span>
                                         <span style="color: #6a9955;">{ format!("|{}|", ascii) }</span>
+                                        if in_stack(addr) || in_stack(addr + 15) {
+                                            <span class="stack-tag">{ "stack" }</span>
+                                        }
+                                        {
+                                            for row_regions.iter().filter(|r| r.address >= addr).map(|r| {
+                                                let on_unname = {
+                                                    let ws_sender = ws_sender.clone();
+                                                    let address = r.address;
+                                                    Callback::from(move |_| {
+                                                        if let Some(sender) = &*ws_sender {
+                                                            let msg = ClientMessage::UnnameRegion { address: Some(address) };
+                                                            if let Ok(json) = serde_json::to_string(&msg) {
+                                                                let _ = sender.unbounded_send(Message::Text(json));
+                                                            }
+                                                        }
+                                                    })
+                                                };
+                                                html! {
+                                                    <span class="region-tag" title={format!("{} bytes at {:x}", r.len, r.address)}>
+                                                        { &r.name }
+                                                        <span class="region-unname" onclick={on_unname} title="Remove this name">{ " ×" }</span>
+                                                    </span>
+                                                }
+                                            })
+                                        }
                                     </div>
                                 }
                             })
                         }
                     </div>
+                    if let Some(note) = &*last_writer_note {
+                        <div style="color: #888; font-size: 10px; margin-top: 4px;">{ note }</div>
+                    }
+                    <div style="margin-top: 6px; display: flex; gap: 5px;">
+                        <input
+                            type="text"
+                            placeholder="Name selection: input_buffer 64"
+                            value={(*region_text).clone()}
+                            oninput={
+                                let region_text = region_text.clone();
+                                Callback::from(move |e: InputEvent| {
+                                    if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                                        region_text.set(input.value());
+                                    }
+                                })
+                            }
+                            style="flex: 1; font-size: 11px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 2px;"
+                        />
+                        <button
+                            onclick={on_name_region}
+                            title="Name the bytes selected with shift+click (or the first row) so addresses in them show the name"
+                            style="font-size: 10px; cursor: pointer; padding: 2px;"
+                        >{ "Name" }</button>
+                    </div>
+                    <div style="margin-top: 6px; display: flex; gap: 5px;">
+                        <input
+                            type="text"
+                            placeholder="Struct: id: u32; name: ptr; buf: char[16]"
+                            value={(*struct_text).clone()}
+                            oninput={on_struct_text_change}
+                            style="flex: 1; font-size: 11px; background: #333; color: #d4d4d4; border: 1px solid #555; padding: 2px;"
+                        />
+                        <button onclick={on_apply_struct} style="font-size: 10px; cursor: pointer; padding: 2px;">{ "Overlay" }</button>
+                    </div>
+                    if let (Some(_), Some(fields)) = (&*struct_layout, &*struct_view) {
+                        <table class="timeline-table" style="margin-top: 4px;">
+                            {
+                                for fields.iter().map(|f| html! {
+                                    <tr>
+                                        <td class="col-addr">{ format!("+0x{:x}", f.offset) }</td>
+                                        <td class="col-clnum">{ &f.name }</td>
+                                        <td class="col-insn">{ &f.value }</td>
+                                        <td class="col-effect">{ f.pointee.as_deref().map(|p| format!("→ {}", p)).unwrap_or_default() }</td>
+                                    </tr>
+                                })
+                            }
+                        </table>
+                    }
+                    if let Some(source) = *inspect_sel {
+                        <div class="inspector">
+                            <div style="display: flex; justify-content: space-between; margin-bottom: 4px;">
+                                <span style="font-weight: bold;">{
+                                    match source {
+                                        InspectSource::Memory(addr) => format!("Value at {:x}", addr),
+                                        InspectSource::Register(i) => format!("Value of {}", REG_NAMES.get(i).copied().unwrap_or("REG")),
+                                    }
+                                }</span>
+                                <button onclick={{
+                                    let inspect_sel = inspect_sel.clone();
+                                    Callback::from(move |_| inspect_sel.set(None))
+                                }}>{ "×" }</button>
+                            </div>
+                            <div style="margin-bottom: 4px;">
+                                {
+                                    for Width::ALL.iter().map(|&w| {
+                                        let inspect_width = inspect_width.clone();
+                                        let active = *inspect_width == w;
+                                        html! {
+                                            <button
+                                                class={classes!(active.then_some("active"))}
+                                                onclick={Callback::from(move |_| inspect_width.set(w))}
+                                            >{ w.label() }</button>
+                                        }
+                                    })
+                                }
+                                <button onclick={{
+                                    let inspect_big_endian = inspect_big_endian.clone();
+                                    Callback::from(move |_| inspect_big_endian.set(!*inspect_big_endian))
+                                }}>{ if *inspect_big_endian { "BE" } else { "LE" } }</button>
+                                <label style="cursor: pointer;">
+                                    <input
+                                        type="checkbox"
+                                        checked={*inspect_signed}
+                                        disabled={inspect_width.is_float()}
+                                        onchange={{
+                                            let inspect_signed = inspect_signed.clone();
+                                            Callback::from(move |_| inspect_signed.set(!*inspect_signed))
+                                        }}
+                                    />
+                                    { " signed" }
+                                </label>
+                            </div>
+                            {
+                                match &inspected {
+                                    Some(value) => {
+                                        let pointer = address_label
+                                            .as_ref()
+                                            .filter(|(addr, _)| *addr == value.raw && !inspect_width.is_float())
+                                            .and_then(|(_, label)| label.clone());
+                                        html! {
+                                            <>
+                                                <div>{ format!("{} = {}", value.hex, value.text) }</div>
+                                                if let Some(label) = pointer {
+                                                    <div style="color: #4ec9b0;">{ format!("→ {}", label) }</div>
+                                                }
+                                            </>
+                                        }
+                                    }
+                                    None => html! {
+                                        <div style="color: #888;">{ "Selection runs past the loaded memory view" }</div>
+                                    },
+                                }
+                            }
+                        </div>
+                    }
+                    if let Some((address, width, points)) = &*value_history {
+                        <div style="margin-top: 10px; border-top: 1px solid #444; padding-top: 5px; font-size: 11px;">
+                            <div style="display: flex; justify-content: space-between; font-weight: bold; margin-bottom: 5px;">
+                                <span>{ format!("{}-byte value at {:x}", width, address) }</span>
+                                <button onclick={on_close_value_chart} style="font-size: 10px; cursor: pointer;">{ "✕" }</button>
+                            </div>
+                            if points.is_empty() {
+                                <div style="color: #666; font-size: 10px;">{ "Never written" }</div>
+                            } else {
+                                <div style="color: #888; font-size: 10px;">
+                                    { format!("{} writes, {:#x} .. {:#x}", points.len(),
+                                        points.iter().map(|&(_, v)| v).min().unwrap_or(0),
+                                        points.iter().map(|&(_, v)| v).max().unwrap_or(0)) }
+                                </div>
+                                { value_chart(points, *max_clnum, *current_clnum, on_value_point) }
+                            }
+                        </div>
+                    }
                     <div style="margin-top: 10px; border-top: 1px solid #444; padding-top: 5px;">
-                        <div style="font-weight: bold; margin-bottom: 5px; font-size: 11px;">{ "Write History" }</div>
+                        <div style="font-weight: bold; margin-bottom: 5px; font-size: 11px;">
+                            { if *show_reads { "Read History" } else { "Write History" } }
+                        </div>
                          {
-                             if memory_writes.is_empty() {
-                                 html! { <div style="color: #666; font-size: 10px;">{ "No writes found" }</div> }
+                             if access_history.is_empty() {
+                                 html! { <div style="color: #666; font-size: 10px;">{ if *show_reads { "No reads found" } else { "No writes found" } }</div> }
                              } else {
                                  html! {
                                      <div style="display: flex; flex-wrap: wrap; gap: 5px; font-size: 10px;">
                                          {
-                                             for memory_writes.iter().map(|&w| {
+                                             for access_history.iter().map(|&w| {
                                                  let on_click = {
                                                      let ws_sender = ws_sender.clone();
                                                      let current_clnum = current_clnum.clone();
@@ -833,6 +3254,159 @@ pub fn app() -> Html {
     }
 }
 
+// GetCFG, or DiffCFG to merge in the second trace
+fn cfg_request(only_user_code: bool, start_from_main: bool, collapse_stubs: bool, svg: bool, diff: bool) -> ClientMessage {
+    let format = if svg { CfgFormat::Svg } else { CfgFormat::Mermaid };
+    if diff {
+        ClientMessage::DiffCFG { only_user_code, start_from_main, collapse_stubs, format }
+    } else {
+        ClientMessage::GetCFG { only_user_code, start_from_main, collapse_stubs, format }
+    }
+}
+
+// A register value as 16 hex digits, underlining the bytes in `changed`
+fn register_hex(value: u64, changed: u64) -> Html {
+    (0..8)
+        .rev()
+        .map(|byte| {
+            let text = format!("{:02x}", (value >> (byte * 8)) & 0xff);
+            if (changed >> (byte * 8)) & 0xff != 0 {
+                html! { <span class="changed-byte">{ text }</span> }
+            } else {
+                html! { { text } }
+            }
+        })
+        .collect()
+}
+
+// A memory access value in hex, or "unknown" if the tracer didn't capture it
+fn access_value(value: Option<u64>) -> String {
+    value.map_or_else(|| "unknown".to_string(), |value| format!("{:x}", value))
+}
+
+// " (SIGABRT)" for a known signal, "" for none
+fn signal_suffix(signal: Option<i32>) -> String {
+    const NAMES: [(i32, &str); 8] = [
+        (4, "SIGILL"),
+        (5, "SIGTRAP"),
+        (6, "SIGABRT"),
+        (7, "SIGBUS"),
+        (8, "SIGFPE"),
+        (9, "SIGKILL"),
+        (11, "SIGSEGV"),
+        (15, "SIGTERM"),
+    ];
+    match signal {
+        Some(signal) => match NAMES.iter().find(|(n, _)| *n == signal) {
+            Some((_, name)) => format!(" ({})", name),
+            None => format!(" (signal {})", signal),
+        },
+        None => String::new(),
+    }
+}
+
+// Parses a breakpoint condition such as `rdi == 0`, `[601040] != 0x41` or
+// `byte [601040] == 65`. Addresses are hex, values hex with 0x or decimal,
+// and memory operands are 8 bytes unless sized.
+fn parse_break_condition(text: &str) -> Result<BreakCondition, String> {
+    // Two-character operators first so `<=` isn't read as `<`
+    let (lhs, op, rhs) = ["==", "!=", "<=", ">=", "<", ">"]
+        .into_iter()
+        .find_map(|symbol| {
+            let (lhs, rhs) = text.split_once(symbol)?;
+            Some((lhs.trim(), CompareOp::from_symbol(symbol)?, rhs.trim()))
+        })
+        .ok_or_else(|| format!("no comparison in '{}' (==, !=, <, <=, >, >=)", text))?;
+
+    let value = match rhs.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => rhs.parse::<u64>().ok().or_else(|| rhs.parse::<i64>().ok().map(|v| v as u64)),
+    }
+    .ok_or_else(|| format!("invalid value '{}'", rhs))?;
+
+    let operand = match lhs.split_once('[') {
+        Some((width, address)) => {
+            let size = match width.trim().trim_end_matches("ptr").trim().to_ascii_lowercase().as_str() {
+                "" | "qword" => 8,
+                "dword" => 4,
+                "word" => 2,
+                "byte" => 1,
+                other => return Err(format!("unknown operand size '{}'", other)),
+            };
+            let address = address.trim_end_matches(']').trim();
+            let address = u64::from_str_radix(address.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("invalid address '{}'", address))?;
+            CondOperand::Mem { address, size }
+        }
+        None => match REG_NAMES.iter().position(|r| r.eq_ignore_ascii_case(lhs)) {
+            Some(index) => CondOperand::Reg { index },
+            None => return Err(format!("unknown register '{}'", lhs)),
+        },
+    };
+    Ok(BreakCondition { operand, op, value })
+}
+
+// A breakpoint condition in the syntax parse_break_condition reads
+fn condition_text(condition: &BreakCondition) -> String {
+    let operand = match condition.operand {
+        CondOperand::Reg { index } => REG_NAMES.get(index).map_or(format!("reg{}", index), |r| r.to_lowercase()),
+        CondOperand::Mem { address, size } => {
+            let width = match size {
+                1 => "byte ",
+                2 => "word ",
+                4 => "dword ",
+                _ => "",
+            };
+            format!("{}[{:x}]", width, address)
+        }
+    };
+    format!("{} {} {:#x}", operand, condition.op.symbol(), condition.value)
+}
+
+// Splits disassembly text into identifiers and the text between them, so
+// that concatenating the parts gives the text back
+fn split_words(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        let word_char = c.is_ascii_alphanumeric() || c == '_';
+        if i > start && word_char != in_word {
+            parts.push(&text[start..i]);
+            start = i;
+        }
+        in_word = word_char;
+    }
+    if start < text.len() {
+        parts.push(&text[start..]);
+    }
+    parts
+}
+
+// Instructions per second as e.g. "950", "12.3k", "1.2M"
+fn insn_rate(per_sec: u64) -> String {
+    match per_sec {
+        0..=999 => per_sec.to_string(),
+        1_000..=999_999 => format!("{:.1}k", per_sec as f64 / 1e3),
+        _ => format!("{:.1}M", per_sec as f64 / 1e6),
+    }
+}
+
+// Slider position (percent of the track) of `clnum`
+fn marker_position(clnum: u32, max_clnum: u32) -> f64 {
+    let span = max_clnum.saturating_sub(1).max(1) as f64;
+    (clnum.saturating_sub(1) as f64 / span * 100.0).min(100.0)
+}
+
+// Change of a register since the previous iteration, as a signed hex delta
+fn iteration_delta(before: u64, after: u64) -> String {
+    match after.wrapping_sub(before) as i64 {
+        0 => "=".to_string(),
+        d if d < 0 => format!("-{:x}", d.unsigned_abs()),
+        d => format!("+{:x}", d),
+    }
+}
+
 fn main() {
     yew::Renderer::<App>::new().render();
 }
@@ -0,0 +1,107 @@
+// Encoding of the shareable view state kept in the URL hash
+
+// (view_mode, one-letter code used in the hash)
+const VIEW_CODES: [(&str, &str); 6] = [
+    ("log", "l"),
+    ("timeline", "t"),
+    ("cfg", "g"),
+    ("slice", "s"),
+    ("coverage", "f"),
+    ("functions", "n"),
+];
+
+/// View state restored from (and written to) the URL hash.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Permalink {
+    pub clnum: u32,
+    pub view_mode: &'static str,
+    pub only_user_code: bool,
+    pub memory_addr: u64,
+    pub slice_target: String,
+}
+
+impl Permalink {
+    /// Hash fragment without the '#', e.g. `c=120&v=s&u=1&m=7ffd0000&s=rax`.
+    /// Fields still at their defaults (timeline view, all code, address 0,
+    /// no slice target) are left out.
+    pub fn encode(&self) -> String {
+        let mut parts = vec![format!("c={}", self.clnum)];
+        if let Some((_, code)) = VIEW_CODES.iter().find(|(mode, _)| *mode == self.view_mode) {
+            if self.view_mode != "timeline" {
+                parts.push(format!("v={}", code));
+            }
+        }
+        if self.only_user_code {
+            parts.push("u=1".to_string());
+        }
+        if self.memory_addr != 0 {
+            parts.push(format!("m={:x}", self.memory_addr));
+        }
+        if !self.slice_target.is_empty() {
+            parts.push(format!("s={}", percent_encode(&self.slice_target)));
+        }
+        parts.join("&")
+    }
+
+    /// Parses a hash with or without its leading '#', including the older
+    /// `#clnum=N` form. Unknown keys and malformed values are ignored; None
+    /// if the hash has no clnum.
+    pub fn decode(hash: &str) -> Option<Self> {
+        let mut link = Permalink {
+            clnum: 0,
+            view_mode: "timeline",
+            only_user_code: false,
+            memory_addr: 0,
+            slice_target: String::new(),
+        };
+        let mut clnum = None;
+        for (key, value) in hash.trim_start_matches('#').split('&').filter_map(|p| p.split_once('=')) {
+            match key {
+                "c" | "clnum" => clnum = value.parse().ok().or(clnum),
+                "v" => {
+                    if let Some((mode, _)) = VIEW_CODES.iter().find(|(_, code)| *code == value) {
+                        link.view_mode = mode;
+                    }
+                }
+                "u" => link.only_user_code = value == "1",
+                "m" => link.memory_addr = u64::from_str_radix(value, 16).unwrap_or(0),
+                "s" => link.slice_target = percent_decode(value),
+                _ => {}
+            }
+        }
+        link.clnum = clnum?;
+        Some(link)
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
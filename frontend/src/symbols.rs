@@ -0,0 +1,74 @@
+// Sorting, filtering and windowing of the function list
+
+use koradar_protocol::SymbolStats;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+// Height of one function list row in pixels (fixed, so rows can be windowed)
+pub const ROW_HEIGHT: i32 = 20;
+// Rows rendered beyond either edge of the visible area
+const OVERSCAN: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymbolColumn {
+    Name,
+    Address,
+    Size,
+    Executions,
+    Covered,
+}
+
+impl SymbolColumn {
+    pub const ALL: [SymbolColumn; 5] = [
+        SymbolColumn::Name,
+        SymbolColumn::Address,
+        SymbolColumn::Size,
+        SymbolColumn::Executions,
+        SymbolColumn::Covered,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            SymbolColumn::Name => "Function",
+            SymbolColumn::Address => "Addr",
+            SymbolColumn::Size => "Size",
+            SymbolColumn::Executions => "Executed",
+            SymbolColumn::Covered => "Covered",
+        }
+    }
+
+    fn compare(self, a: &SymbolStats, b: &SymbolStats) -> Ordering {
+        match self {
+            SymbolColumn::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SymbolColumn::Address => a.address.cmp(&b.address),
+            SymbolColumn::Size => a.size.cmp(&b.size),
+            SymbolColumn::Executions => a.executions.cmp(&b.executions),
+            SymbolColumn::Covered => a.covered.cmp(&b.covered),
+        }
+    }
+}
+
+/// Indices into `symbols` of those whose name contains `filter` (ignoring
+/// case), ordered by `column`. Ties keep address order.
+pub fn sorted_symbols(symbols: &[SymbolStats], filter: &str, column: SymbolColumn, descending: bool) -> Vec<usize> {
+    let filter = filter.to_lowercase();
+    let mut order: Vec<usize> = (0..symbols.len())
+        .filter(|&i| filter.is_empty() || symbols[i].name.to_lowercase().contains(&filter))
+        .collect();
+    order.sort_by(|&a, &b| {
+        let by_column = column.compare(&symbols[a], &symbols[b]);
+        let by_column = if descending { by_column.reverse() } else { by_column };
+        by_column.then(symbols[a].address.cmp(&symbols[b].address))
+    });
+    order
+}
+
+/// Rows to render for a list of `total` rows scrolled to `scroll_top` in a
+/// viewport `height` pixels tall.
+pub fn visible_rows(scroll_top: i32, height: i32, total: usize) -> Range<usize> {
+    let first = (scroll_top.max(0) / ROW_HEIGHT) as usize;
+    let shown = (height.max(0) / ROW_HEIGHT) as usize + 1;
+    let start = first.saturating_sub(OVERSCAN).min(total);
+    let end = (first + shown + OVERSCAN).min(total);
+    start..end
+}
@@ -0,0 +1,988 @@
+// Messages exchanged between the tracer, the server and the frontend. Every
+// component builds against these definitions, re-exported by koradar-core as
+// `koradar_core::protocol`.
+
+use serde::{Deserialize, Serialize};
+
+/// Version of the client/server protocol below, checked by the frontend
+/// against the server's `ServerInfo`. Bump it on any change a peer built
+/// from an older revision would misread.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TraceEvent {
+    Init {
+        vcpu_index: u32,
+    },
+    InsnExec {
+        vcpu_index: u32,
+        pc: u64,
+        // Always the instruction's decoded length; the content is only
+        // meaningful if `bytes_valid`, otherwise read it from memory
+        bytes: Vec<u8>,
+        #[serde(default = "default_true")]
+        bytes_valid: bool,
+        #[serde(default)]
+        disasm: Option<String>,
+        #[serde(default)]
+        regs: Vec<u64>,
+        // Control can't have come here from the previous instruction on this
+        // vcpu: a signal was delivered or returned from, or an interrupt hit
+        #[serde(default)]
+        discontinuous: bool,
+    }, // Simplified for now
+    MemAccess {
+        vcpu_index: u32,
+        vaddr: u64,
+        is_store: bool,
+        // None if the tracer couldn't capture it (e.g. a 128-bit access)
+        value: Option<u64>,
+        // Access size in bytes (0 if the tracer didn't report it)
+        #[serde(default)]
+        size: u32,
+    },
+    // The tracer re-translated `pc` and found other bytes there than before
+    // (self-modifying code); they apply from the next instruction on
+    CodeInvalidated {
+        pc: u64,
+        bytes: Vec<u8>,
+    },
+    Exit {
+        vcpu_index: u32,
+    },
+    // The tracer hit its max_insns cap and stopped emitting events
+    TraceLimitReached {
+        max_insns: u64,
+    },
+    // Sent once at exit by a tracer in coverage mode (mode=coverage) instead
+    // of per-instruction events: runtime pcs of every executed block
+    BlockCoverage {
+        blocks: Vec<u64>,
+    },
+    // Sent once at exit of a fully traced user-mode program: the status it
+    // passed to exit/exit_group, or None if it was killed instead (a fault,
+    // or the signal it sent itself in `signal`, e.g. SIGABRT from abort())
+    ProcessEnd {
+        exit_code: Option<i64>,
+        #[serde(default)]
+        signal: Option<i32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TraceEntry {
+    pub clnum: u32,
+    pub address: u64,
+    pub disassembly: String,
+    pub reg_diffs: Vec<(usize, u64)>,                // (index, value) for every register written
+    pub mem_accesses: Vec<(u64, Option<u64>, bool)>, // (addr, value if captured, is_write) for every access
+    // For calls: rax once the call returned (see TraceDB::call_return_value)
+    #[serde(default)]
+    pub return_value: Option<u64>,
+    // The executed bytes differ from the loaded image's (runtime patching,
+    // relocation or self-modifying code), see TraceDB::executed_vs_static
+    #[serde(default)]
+    pub patched: bool,
+    // Reached asynchronously (signal or interrupt entry/return), see
+    // TraceDB::is_async_entry
+    #[serde(default)]
+    pub async_entry: bool,
+    // Categories of the instruction (see Disassembler::groups)
+    #[serde(default)]
+    pub groups: Vec<InsnGroup>,
+    // For a library call collapsed into this entry (see GetTraceLog's
+    // `collapse_library_calls`): clnums left out until it returned
+    #[serde(default)]
+    pub skipped_insns: u32,
+    // Further back-to-back executions of this address folded into the entry
+    // (see GetTraceLog's `fold`), and the clnum of the last one
+    #[serde(default)]
+    pub repeats: u32,
+    #[serde(default)]
+    pub last_repeat: Option<u32>,
+    // Register operands the instruction reads, named as in `disassembly`,
+    // each with the clnum that last wrote it (see TraceDB::last_writer_reg)
+    #[serde(default)]
+    pub reg_defs: Vec<(String, u32)>,
+}
+
+/// One instruction of an exported slice or trace region.
+///
+/// This is the stable export schema consumed by external tooling; add fields
+/// with `#[serde(default)]` rather than renaming or removing existing ones.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportRecord {
+    pub clnum: u32,
+    // Runtime address of the instruction
+    pub address: u64,
+    // Instruction bytes as captured by the tracer (empty if none were sent)
+    pub bytes: Vec<u8>,
+    pub disassembly: String,
+    // (register index, new value) for every register written
+    pub reg_diffs: Vec<(usize, u64)>,
+    // (address, value, is_write) for every memory access; the value is
+    // None if the tracer didn't capture it
+    pub mem_accesses: Vec<(u64, Option<u64>, bool)>,
+    // Containing symbol, as "name" or "name+0xoff"
+    pub symbol: Option<String>,
+}
+
+/// Instructions shown on either side of the current one in the AskAI context
+/// when the client doesn't say.
+pub const DEFAULT_CONTEXT_LINES: u32 = 5;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_context_lines() -> u32 {
+    DEFAULT_CONTEXT_LINES
+}
+
+/// Envelope for client messages. `request_id` is optional and is echoed back
+/// in `ServerMessage::Error` so the client can correlate failures.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClientRequest {
+    #[serde(default)]
+    pub request_id: Option<u32>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+// Client -> Server messages
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    // Capabilities sent once after connecting. With `binary_frames`, large
+    // replies (StateUpdate, CFG, TraceLog) come as MessagePack binary frames
+    // instead of JSON text
+    Hello {
+        #[serde(default)]
+        binary_frames: bool,
+    },
+    // Answered with ServerInfo
+    GetServerInfo,
+    QueryState {
+        clnum: u32,
+        #[serde(default)]
+        memory_addr: Option<u64>,
+    },
+    GetTraceLog {
+        start: u32,
+        count: u32,
+        #[serde(default)]
+        only_user_code: bool,
+        // Restrict entries to the function with this symbol name
+        #[serde(default)]
+        function: Option<String>,
+        // Center the page on `start` instead: it covers `radius` clnums on
+        // either side and `count` is ignored
+        #[serde(default)]
+        radius: Option<u32>,
+        // Show a call from user code into a library as one entry and resume
+        // at its return, instead of listing the library's instructions
+        #[serde(default)]
+        collapse_library_calls: bool,
+        // Fold consecutive entries for the same address (a tight loop) into
+        // the first one, counting the others in its `repeats`
+        #[serde(default)]
+        fold: bool,
+    },
+    StepForward {
+        current: u32,
+    },
+    StepBackward {
+        current: u32,
+    },
+    // Like StepForward, but runs through the callee if `current` is a call
+    StepOver {
+        current: u32,
+    },
+    // Symbol or module an address (e.g. a pointer value) falls into
+    DescribeAddress {
+        address: u64,
+    },
+    // Read memory at `address` as a struct with these fields (C layout)
+    ReadStruct {
+        clnum: u32,
+        address: u64,
+        fields: Vec<StructField>,
+    },
+    // Jump to the first execution of main (or the entry point without a main symbol)
+    GotoMain,
+    // Jump to the next execution of runtime `address` after clnum `after`,
+    // wrapping around to its first
+    GotoAddress {
+        address: u64,
+        #[serde(default)]
+        after: u32,
+    },
+    // Breakpoints for live tracing; `is_static` addresses are resolved through
+    // the main binary's load bias when checked
+    SetBreakpoint {
+        address: u64,
+        #[serde(default)]
+        is_static: bool,
+    },
+    // A breakpoint that only hits while `condition` holds as the instruction
+    // is reached, e.g. at 0x401234 when rdi == 0
+    SetConditionalBreakpoint {
+        address: u64,
+        #[serde(default)]
+        is_static: bool,
+        condition: BreakCondition,
+    },
+    // Clears one breakpoint, or all of them without an address
+    ClearBreakpoint {
+        #[serde(default)]
+        address: Option<u64>,
+        #[serde(default)]
+        is_static: bool,
+        #[serde(default)]
+        condition: Option<BreakCondition>,
+    },
+    GetBreakpoints,
+    // Names `len` bytes at runtime `address`, replacing any named region they
+    // overlap; answered (to every client) with Regions
+    NameRegion {
+        address: u64,
+        len: u64,
+        name: String,
+    },
+    // Removes the named region containing `address`, or all of them without one
+    UnnameRegion {
+        #[serde(default)]
+        address: Option<u64>,
+    },
+    GetRegions,
+    // Replaces the session configuration; answered (to every client) with Config
+    Configure {
+        config: SessionConfig,
+    },
+    GetConfig,
+    // Disassembles `bytes_hex` (e.g. "55 4889e5") at `address` with a
+    // disassembler of its own, without touching the session; answered with
+    // Disassembly
+    Disassemble {
+        address: u64,
+        bytes_hex: String,
+        #[serde(default)]
+        options: DisasmRequestOptions,
+    },
+    GetCFG {
+        #[serde(default)]
+        only_user_code: bool,
+        #[serde(default)]
+        start_from_main: bool,
+        // Calls through PLT stubs go straight to the function they resolved to
+        #[serde(default)]
+        collapse_stubs: bool,
+        #[serde(default)]
+        format: CfgFormat,
+    },
+    AskAI {
+        clnum: u32,
+        // Surrounding instructions to include on either side of `clnum`
+        #[serde(default = "default_context_lines")]
+        context_lines: u32,
+    },
+    GetMemoryWrites {
+        address: u64,
+    },
+    GetMemoryReads {
+        address: u64,
+    },
+    // The `width`-byte value at `address` after each write to it (see
+    // TraceDB::value_history)
+    GetValueHistory {
+        address: u64,
+        width: u32,
+    },
+    // Slice on `target` (comma-separated registers/0x addresses) plus any
+    // `targets`, returning the union
+    GetSlice {
+        clnum: u32,
+        #[serde(default)]
+        target: String,
+        #[serde(default)]
+        targets: Vec<String>,
+    },
+    // The backward slice of `target` as a data-flow graph (answered with DataFlow)
+    GetDataFlow {
+        clnum: u32,
+        target: String,
+    },
+    // Forward slice from what the syscall at `clnum` returned: rax and,
+    // for read-like syscalls, the buffer it filled
+    TaintFromSyscall {
+        clnum: u32,
+    },
+    GetInsnDetail {
+        clnum: u32,
+    },
+    // Which functions of the main binary executed (see TraceDB::coverage)
+    GetCoverage,
+    // Every symbol of the main binary with its execution statistics
+    GetSymbols,
+    // Add names from a symbol map file on the server (see
+    // TraceDB::import_symbols_from_map); answered with Symbols
+    ImportSymbols {
+        path: String,
+    },
+    // Static disassembly of a whole function (see TraceDB::function_listing)
+    GetFunctionListing {
+        name: String,
+    },
+    // Static disassembly of the `count` instructions following the one at `clnum`
+    GetLookahead {
+        clnum: u32,
+        count: usize,
+    },
+    GetLastWriter {
+        clnum: u32,
+        address: u64,
+    },
+    // The instruction that wrote register `reg` (index into the register
+    // list) as seen at `clnum`, with its inputs
+    GetRegisterProvenance {
+        clnum: u32,
+        reg: usize,
+    },
+    // Where register `reg` (an index into the register list) changed across
+    // the trace, downsampled to `buckets` equal slices of it (see
+    // TraceDB::register_timeline)
+    GetRegisterTimeline {
+        reg: usize,
+        buckets: u32,
+    },
+    // The nearest instruction after `clnum` (before it, unless `forward`)
+    // that changed register `reg`
+    GetRegisterChange {
+        reg: usize,
+        clnum: u32,
+        forward: bool,
+    },
+    // The registers `regs` (indices into the register list) at every execution
+    // of the instruction at `clnum`, normally a block leader
+    GetBlockIterations {
+        clnum: u32,
+        regs: Vec<usize>,
+    },
+    // Debug only (server --debug): raw change records by address and/or
+    // inclusive clnum range
+    GetRawChanges {
+        #[serde(default)]
+        address: Option<u64>,
+        #[serde(default)]
+        range: Option<(u32, u32)>,
+    },
+    // CFG of trace A merged with trace B's, blocks and edges colored by
+    // which trace they appear in; answered with CFG
+    DiffCFG {
+        #[serde(default)]
+        only_user_code: bool,
+        #[serde(default)]
+        start_from_main: bool,
+        // Calls through PLT stubs go straight to the function they resolved to
+        #[serde(default)]
+        collapse_stubs: bool,
+        #[serde(default)]
+        format: CfgFormat,
+    },
+    // Slice trace A from clnum_a and trace B from clnum_b, then diff the results
+    DiffSlices {
+        clnum_a: u32,
+        clnum_b: u32,
+        target: String,
+    },
+    ExportRegion {
+        #[serde(default)]
+        clnums: Vec<u32>,
+        // Inclusive clnum range, exported in addition to `clnums`
+        #[serde(default)]
+        range: Option<(u32, u32)>,
+    },
+}
+
+// Server -> Client messages (beyond raw TraceEvent)
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    StateUpdate {
+        clnum: u32,
+        registers: Vec<u64>,
+        memory: Vec<u8>, // Memory dump at a specific address
+        memory_addr: u64,
+        disassembly: String,
+        // True if clnum fell outside the trace window and its state was evicted
+        #[serde(default)]
+        pruned: bool,
+        // Stack ranges [low, high] known at clnum, one per vcpu
+        #[serde(default)]
+        stack_regions: Vec<(u64, u64)>,
+    },
+    TraceEvent(TraceEvent),
+    TraceLog {
+        entries: Vec<TraceEntry>,
+        // Cursor for the following page; pass it back as `start`
+        #[serde(default)]
+        next_start: Option<u32>,
+    },
+    MaxClnum {
+        max: u32,
+    },
+    // Live tracing throughput, broadcast once a second while instructions
+    // arrive, and once more with a rate of 0 when they stop
+    TraceProgress {
+        total_insns: u32,
+        insns_per_sec: u64,
+    },
+    CFG {
+        graph: String,
+        // What `graph` holds; Mermaid when SVG rendering was asked for but
+        // failed, with the reason in `warning`
+        #[serde(default)]
+        format: CfgFormat,
+        #[serde(default)]
+        warning: Option<String>,
+    },
+    // Part `seq` of `total` of a CFG too large for one frame; concatenating
+    // the `data` of all parts in order gives the `graph` of a CFG message.
+    // Every part carries `format` and `warning`.
+    CFGChunk {
+        seq: u32,
+        total: u32,
+        data: String,
+        #[serde(default)]
+        format: CfgFormat,
+        #[serde(default)]
+        warning: Option<String>,
+    },
+    AIResponse {
+        text: String,
+    },
+    MemoryWrites {
+        address: u64,
+        writes: Vec<u32>,
+    },
+    MemoryReads {
+        address: u64,
+        reads: Vec<u32>,
+    },
+    ValueHistory {
+        address: u64,
+        width: u32,
+        // (writing clnum, value right after it), in clnum order
+        points: Vec<(u32, u64)>,
+    },
+    Slice {
+        entries: Vec<TraceEntry>,
+    },
+    // Mermaid source of the graph, see DataFlowGraph::to_mermaid
+    DataFlow {
+        clnum: u32,
+        target: String,
+        graph: String,
+    },
+    SyscallTaint {
+        clnum: u32,
+        // Name, or the number if it isn't a well-known syscall
+        syscall: String,
+        result: u64,
+        // (address, length) of the tainted buffer
+        buffer: Option<(u64, u64)>,
+        entries: Vec<TraceEntry>,
+    },
+    InsnDetail {
+        clnum: u32,
+        // None if no instruction could be decoded at this clnum
+        detail: Option<InsnDetail>,
+    },
+    Disassembly {
+        address: u64,
+        // (address, disassembly) of each instruction in the requested bytes
+        lines: Vec<(u64, String)>,
+    },
+    Lookahead {
+        clnum: u32,
+        // (runtime address, disassembly); shorter than requested if decoding stopped
+        lines: Vec<(u64, String)>,
+    },
+    Coverage {
+        // (symbol, executed, executed instruction count), in address order
+        functions: Vec<(String, bool, u32)>,
+    },
+    Symbols {
+        symbols: Vec<SymbolStats>,
+    },
+    FunctionListing {
+        name: String,
+        // (address, disassembly, first clnum it executed at)
+        lines: Vec<(u64, String, Option<u32>)>,
+    },
+    LastWriter {
+        clnum: u32,
+        address: u64,
+        origin: WriteOrigin,
+    },
+    RegisterProvenance {
+        clnum: u32,
+        reg: usize,
+        // None if the register held its value since the trace started
+        writer: Option<RegisterWriter>,
+    },
+    RegisterTimeline {
+        reg: usize,
+        // (clnum of the first change, number of changes) per slice with any
+        marks: Vec<(u32, u32)>,
+    },
+    RegisterChange {
+        reg: usize,
+        // None if there is no change in that direction
+        clnum: Option<u32>,
+    },
+    BlockIterations {
+        address: u64,
+        regs: Vec<usize>,
+        // (clnum, value of each of `regs` on entry), one per execution
+        rows: Vec<(u32, Vec<u64>)>,
+    },
+    AddressInfo {
+        address: u64,
+        // None if the address isn't inside any known symbol or module
+        label: Option<String>,
+    },
+    StructView {
+        clnum: u32,
+        address: u64,
+        fields: Vec<StructFieldValue>,
+    },
+    Export {
+        records: Vec<ExportRecord>,
+    },
+    SliceDiff {
+        lines: Vec<SliceDiffLine>,
+    },
+    RawChanges {
+        changes: Vec<ChangeRecord>,
+    },
+    // This client fell behind the live broadcast and `missed` messages were
+    // dropped; views built from the stream should be re-queried
+    Lagged {
+        missed: u64,
+    },
+    // A new tracer connected and the previous run was discarded; clnums
+    // restart from 1 and all views should be cleared
+    SessionReset,
+    // Current breakpoint set, sent in reply to any breakpoint message
+    Breakpoints {
+        breakpoints: Vec<Breakpoint>,
+    },
+    // Current user-named memory regions, in address order
+    Regions {
+        regions: Vec<NamedRegion>,
+    },
+    Config {
+        config: SessionConfig,
+        // File names of loaded modules, the choices for `active_module`
+        modules: Vec<String>,
+    },
+    // The traced program was killed rather than exiting; `clnum` is its last
+    // executed instruction (the faulting one for a crash). Sent on connect
+    // when known and broadcast when a live run ends this way.
+    CrashInfo {
+        clnum: u32,
+        address: u64,
+        // Signal the program sent itself, None for faults
+        signal: Option<i32>,
+    },
+    // Broadcast when an incoming instruction matches a breakpoint; at most one
+    // per MaxClnum update, for the first hit since the previous one
+    BreakpointHit {
+        clnum: u32,
+        address: u64,
+    },
+    Error {
+        request_id: Option<u32>,
+        message: String,
+    },
+    // Reply to GetServerInfo (also served at /api/info). Its shape must stay
+    // stable so mismatched clients can still read the protocol version.
+    ServerInfo {
+        // Server crate version
+        version: String,
+        protocol_version: u32,
+        // Optional features this server has enabled, e.g. "diff_slices"
+        capabilities: Vec<String>,
+    },
+}
+
+/// A raw change log record (see `koradar_core::Change`), for debug queries.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeRecord {
+    pub address: u64,
+    pub data: u64,
+    pub clnum: u32,
+    pub flags: u32,
+}
+
+/// Category of an instruction, normalized from the decoder's instruction
+/// groups. An instruction can be in several (`vaddps` is both Avx and Sse).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum InsnGroup {
+    // Conditional and unconditional jumps, loops
+    Jump,
+    Call,
+    Ret,
+    // int, int3, into and the iret family
+    Interrupt,
+    // syscall, sysenter
+    Syscall,
+    // Only valid at ring 0 (hlt, wrmsr, sysret, ...)
+    Privileged,
+    // Integer ALU: add/sub/mul/div, logic, shifts, compares
+    Arithmetic,
+    Fpu,
+    Mmx,
+    Sse,
+    Avx,
+    // AES, SHA and carry-less multiply
+    Crypto,
+    // VMX/SVM
+    Virtualization,
+}
+
+impl InsnGroup {
+    pub fn name(self) -> &'static str {
+        match self {
+            InsnGroup::Jump => "jump",
+            InsnGroup::Call => "call",
+            InsnGroup::Ret => "ret",
+            InsnGroup::Interrupt => "interrupt",
+            InsnGroup::Syscall => "syscall",
+            InsnGroup::Privileged => "privileged",
+            InsnGroup::Arithmetic => "arithmetic",
+            InsnGroup::Fpu => "fpu",
+            InsnGroup::Mmx => "mmx",
+            InsnGroup::Sse => "sse",
+            InsnGroup::Avx => "avx",
+            InsnGroup::Crypto => "crypto",
+            InsnGroup::Virtualization => "virtualization",
+        }
+    }
+
+    /// True for instructions that can transfer control somewhere other than
+    /// the next instruction.
+    pub fn is_control_flow(self) -> bool {
+        matches!(
+            self,
+            InsnGroup::Jump | InsnGroup::Call | InsnGroup::Ret | InsnGroup::Interrupt | InsnGroup::Syscall
+        )
+    }
+}
+
+/// Structured breakdown of a single decoded instruction.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InsnDetail {
+    pub address: u64,
+    pub size: usize,
+    pub mnemonic: String,
+    pub op_str: String,
+    pub groups: Vec<String>,
+    pub regs_read: Vec<String>,
+    pub regs_write: Vec<String>,
+    pub operands: Vec<OperandDetail>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum OperandDetail {
+    Reg {
+        name: String,
+        size: u8,
+    },
+    Imm {
+        value: i64,
+        size: u8,
+    },
+    Mem {
+        segment: Option<String>,
+        base: Option<String>,
+        index: Option<String>,
+        scale: i32,
+        disp: i64,
+        size: u8,
+    },
+    Invalid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Breakpoint {
+    pub address: u64,
+    // `address` is a static (file) address rather than a runtime one
+    pub is_static: bool,
+    // Only hit while this holds; None hits on every execution
+    #[serde(default)]
+    pub condition: Option<BreakCondition>,
+}
+
+/// A comparison of a register or memory value, taken before the instruction
+/// at a conditional breakpoint executes, with a constant.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BreakCondition {
+    pub operand: CondOperand,
+    pub op: CompareOp,
+    pub value: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(tag = "kind")]
+pub enum CondOperand {
+    // Index into the register vector (see TraceEvent::InsnExec)
+    Reg { index: usize },
+    // `size` bytes (1 to 8, little-endian) at runtime `address`
+    Mem { address: u64, size: u8 },
+}
+
+/// Unsigned comparison of an operand with a constant.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    pub fn holds(self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        [CompareOp::Eq, CompareOp::Ne, CompareOp::Lt, CompareOp::Le, CompareOp::Gt, CompareOp::Ge]
+            .into_iter()
+            .find(|op| op.symbol() == symbol)
+    }
+}
+
+/// A range of memory the user gave a name, e.g. `input_buffer` for the 64
+/// bytes at 0x601040. Shown in place of addresses inside it, ahead of symbols.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct NamedRegion {
+    // Runtime address of the first byte
+    pub address: u64,
+    pub len: u64,
+    pub name: String,
+}
+
+/// Assembly syntax for disassembly text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisasmSyntax {
+    #[default]
+    Intel,
+    Att,
+}
+
+/// How a CFG is delivered: Mermaid source rendered by the client, or an SVG
+/// rendered by the server with Graphviz.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CfgFormat {
+    #[default]
+    Mermaid,
+    Svg,
+}
+
+/// Number base for immediate operands in disassembly text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ImmediateBase {
+    #[default]
+    Hex,
+    Decimal,
+    // Hex followed by the decimal value, e.g. `0x100 (256)`
+    Both,
+}
+
+/// Formatting for a one-off `ClientMessage::Disassemble`. Defaults to the
+/// disassembler's own Intel syntax text without any rewriting.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct DisasmRequestOptions {
+    pub syntax: DisasmSyntax,
+    pub normalize_immediates: bool,
+    pub symbolize: bool,
+    pub immediate_base: ImmediateBase,
+    pub ascii_immediates: bool,
+}
+
+/// Display and analysis switches shared by all clients, set with
+/// `ClientMessage::Configure`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionConfig {
+    pub syntax: DisasmSyntax,
+    // Near-wraparound immediates as negative numbers, displacements in hex
+    pub normalize_immediates: bool,
+    // Branch targets and rbp slots shown as symbol and variable names
+    pub symbolize: bool,
+    #[serde(default)]
+    pub immediate_base: ImmediateBase,
+    // Immediates whose bytes are all printable annotated as text, e.g.
+    // `mov rax, 0x6f6c6c6548 ; "Hello"`
+    #[serde(default)]
+    pub ascii_immediates: bool,
+    // CFG node labels rewritten like the timeline (symbols, variables, text
+    // of immediates); off keeps the nodes narrow
+    #[serde(default)]
+    pub symbolize_cfg: bool,
+    // Loaded module (path or file name) treated as user code instead of the
+    // main executable
+    pub active_module: Option<String>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            syntax: DisasmSyntax::Intel,
+            normalize_immediates: true,
+            symbolize: true,
+            immediate_base: ImmediateBase::Hex,
+            ascii_immediates: false,
+            symbolize_cfg: false,
+            active_module: None,
+        }
+    }
+}
+
+/// Primitive field types for struct overlays.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    // 64-bit pointer, dereferenced one level for display
+    Ptr,
+    // Fixed-size character array, char[len]
+    Chars { len: usize },
+}
+
+impl FieldType {
+    pub fn size(self) -> u64 {
+        match self {
+            FieldType::U8 => 1,
+            FieldType::U16 => 2,
+            FieldType::U32 => 4,
+            FieldType::U64 | FieldType::Ptr => 8,
+            FieldType::Chars { len } => len as u64,
+        }
+    }
+
+    // Natural alignment, as a C compiler would lay the field out on x86-64
+    pub fn align(self) -> u64 {
+        match self {
+            FieldType::Chars { .. } => 1,
+            ty => ty.size(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StructField {
+    pub name: String,
+    pub ty: FieldType,
+}
+
+/// A struct field as read from memory at some clnum.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StructFieldValue {
+    pub name: String,
+    // Offset from the struct base, after natural alignment
+    pub offset: u64,
+    pub value: String,
+    // For pointers: what the pointer refers to (symbol and/or data)
+    pub pointee: Option<String>,
+}
+
+/// One line of a diff between a slice of trace A and a slice of trace B.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SliceDiffLine {
+    pub kind: DiffKind,
+    // Set for lines present in trace A (Common/Removed)
+    pub clnum_a: Option<u32>,
+    // Set for lines present in trace B (Common/Added)
+    pub clnum_b: Option<u32>,
+    pub disassembly: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Common,
+    // Only in trace A
+    Removed,
+    // Only in trace B
+    Added,
+}
+
+/// One step back in a register's data flow: the instruction that wrote it
+/// and what that instruction read.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RegisterWriter {
+    pub clnum: u32,
+    pub address: u64,
+    pub disassembly: String,
+    // (register index, value) of the registers it read, before it ran
+    pub reg_inputs: Vec<(usize, u64)>,
+    // (address, value if captured) of the memory it read
+    pub mem_inputs: Vec<(u64, Option<u64>)>,
+}
+
+/// A symbol of the main binary and how its code executed in the trace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SymbolStats {
+    pub name: String,
+    // Static address and size (0 if the symbol table has none)
+    pub address: u64,
+    pub size: u64,
+    // Executed instructions within the symbol
+    pub executions: u32,
+    // Executed at all, including blocks seen by a coverage-mode tracer
+    pub covered: bool,
+    // Earliest clnum at which an instruction of the symbol executed
+    pub first_clnum: Option<u32>,
+}
+
+/// Where the value of a memory byte at a given clnum came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum WriteOrigin {
+    // Last written by the instruction at this clnum
+    Written { clnum: u32 },
+    // Never written during the trace; the value comes from the loaded binary
+    Static,
+    // Neither written nor backed by a loaded image
+    NoData,
+    // Written before `before`, by a write the per-address history limit dropped
+    Truncated { before: u32 },
+}
@@ -2,7 +2,9 @@ use reqwest::Client;
 use serde_json::json;
 use std::env;
 
-pub async fn ask_ai(context: String) -> Result<String, String> {
+/// Asks the configured model to explain `context`, or to answer `question`
+/// about it.
+pub async fn ask_ai(context: String, question: Option<&str>) -> Result<String, String> {
     // Check if API Key is set
     let api_key = match env::var("OPENAI_API_KEY") {
         Ok(k) => k,
@@ -13,10 +15,11 @@ pub async fn ask_ai(context: String) -> Result<String, String> {
     let model = env::var("KORADAR_AI_MODEL").unwrap_or_else(|_| "gpt-4o".to_string());
 
     let client = Client::new();
-    let prompt = format!(
-        "You are a binary analysis expert. Explain what is happening in the following execution context of a program trace.\n\nContext:\n{}",
-        context
-    );
+    let task = match question {
+        Some(question) => format!("Answer this question about the following execution context of a program trace: {}", question),
+        None => "Explain what is happening in the following execution context of a program trace.".to_string(),
+    };
+    let prompt = format!("You are a binary analysis expert. {}\n\nContext:\n{}", task, context);
 
     let body = json!({
         "model": model,
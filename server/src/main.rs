@@ -1,31 +1,119 @@
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        rejection::JsonRejection,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, Request, State,
     },
-    response::IntoResponse,
-    routing::get,
-    Router,
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
 };
 use koradar_core::{
-    protocol::{ClientMessage, ServerMessage, TraceEvent},
-    BinaryLoader, Change, ChangeFlags, TraceDB,
+    diff::diff_slices,
+    disasm::BackendKind,
+    ingest::advances_clnum,
+    protocol::{
+        BreakCondition, Breakpoint, CfgFormat, ClientMessage, CondOperand, ClientRequest, NamedRegion, ServerMessage, TraceEntry, TraceEvent,
+        DEFAULT_CONTEXT_LINES, PROTOCOL_VERSION,
+    },
+    syscall,
+    clamp_clnum, db::DEFAULT_INSN_CACHE_CAPACITY, BinaryLoader, QueryError, TraceBackend, TraceDB, TraceLogOptions, REG_COUNT,
 };
 use serde_json;
+use std::collections::{BTreeSet, VecDeque};
 use std::env;
 use std::path::Path;
-use std::sync::atomic::Ordering;
-use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::broadcast;
-use tower_http::services::ServeDir;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::{broadcast, watch};
+use tower_http::{compression::CompressionLayer, services::ServeDir};
 
 mod ai;
+mod render;
+
+// How often TraceProgress is broadcast during a live run
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+// How often the ticker in main reports trace growth
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
 
 struct AppState {
     db: Arc<TraceDB>,
+    // Answers trace A's state queries: `db` itself, or a SQLite copy of it
+    // with --sqlite
+    backend: Arc<dyn TraceBackend>,
+    // Trace A is only in the SQLite file (--sqlite without --load-trace), so
+    // `db` is empty and only requests `backend` answers are served
+    sqlite_only: bool,
+    // Second trace ("B") for differential queries; `db` is trace "A"
+    db_b: Option<Arc<TraceDB>>,
     tx: broadcast::Sender<String>,
     max_clnum: Arc<std::sync::atomic::AtomicU32>,
+    // Checked against trace A's instructions as they arrive from the tracer
+    breakpoints: Arc<LiveBreakpoints>,
+    // Enables debug-only queries such as GetRawChanges
+    debug: bool,
+    // JSON file trace A's named regions are loaded from and saved to
+    labels: Option<String>,
+    // Set once on Ctrl-C; client sockets close when it changes
+    shutdown: watch::Sender<bool>,
+}
+
+// Breakpoints for live tracing, with the first hit the MaxClnum ticker hasn't
+// reported yet. Hits go out at its pace rather than one per hit, so a
+// breakpoint in a hot loop doesn't flood clients and the broadcast channel.
+#[derive(Default)]
+struct LiveBreakpoints {
+    set: RwLock<BTreeSet<Breakpoint>>,
+    // (clnum, address) of the first hit since the last report
+    pending_hit: Mutex<Option<(u32, u64)>>,
+}
+
+// Coalesces MaxClnum updates: one per instruction would flood the channel.
+// Throughput and breakpoint hits go out from the same ticker, throughput
+// measured over about a second.
+struct Ticker {
+    last_sent: u32,
+    // When throughput was last measured, and max clnum then
+    measured_from: (Instant, u32),
+    last_rate: u64,
+}
+
+impl Ticker {
+    fn new() -> Self {
+        Self { last_sent: 0, measured_from: (Instant::now(), 0), last_rate: 0 }
+    }
+
+    // Messages to broadcast now that the trace reached `max`
+    fn tick(&mut self, max: u32, breakpoints: &LiveBreakpoints) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+
+        let elapsed = self.measured_from.0.elapsed();
+        if elapsed >= PROGRESS_INTERVAL {
+            // A new run restarts the clnums, so the count can go down
+            let insns = max.saturating_sub(self.measured_from.1) as f64;
+            let rate = (insns / elapsed.as_secs_f64()).round() as u64;
+            if rate > 0 || self.last_rate > 0 {
+                messages.push(ServerMessage::TraceProgress { total_insns: max, insns_per_sec: rate });
+            }
+            self.measured_from = (Instant::now(), max);
+            self.last_rate = rate;
+        }
+
+        if max != self.last_sent {
+            messages.push(ServerMessage::MaxClnum { max });
+            self.last_sent = max;
+        }
+
+        // After MaxClnum, so clients can already navigate to the hit
+        if let Some((clnum, address)) = breakpoints.pending_hit.lock().unwrap().take() {
+            messages.push(ServerMessage::BreakpointHit { clnum, address });
+        }
+        messages
+    }
 }
 
 #[tokio::main]
@@ -33,40 +121,288 @@ async fn main() {
     // Load .env
     dotenv::dotenv().ok();
 
-    println!("Koradar Server Starting...");
+    // RUST_LOG controls verbosity, e.g. RUST_LOG=koradar_core=debug,info
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .init();
 
-    let db = Arc::new(TraceDB::new(16));
+    tracing::info!("Koradar server starting");
 
-    // Load binary if provided
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        let binary_path = &args[1];
-        println!("Loading binary: {}", binary_path);
-        match BinaryLoader::load_file(&db, Path::new(binary_path)) {
-            Ok(_) => {
-                println!("Binary loaded successfully");
+    // Usage: koradar-server [--window N] [--module PATH@0xBASE]...
+    //                      [--replay FILE [--replay-rate EVENTS_PER_SEC]] [--stdin]
+    //                      [--load-trace A.ndjson [--load-trace B.ndjson] [--sql-dump FILE]] [--sqlite FILE]
+    //                      [--user-range 0xSTART-0xEND]... [--debug]
+    //                      [--broadcast-buffer N] [--spill FILE] [--labels FILE] [--symbols FILE]... [--raw-disasm]
+    //                      [--autosave FILE] [--memory-history N]
+    //                      [--disasm-backend capstone|iced] [--insn-cache N] [BINARY]
+    let mut binary_path = None;
+    let mut broadcast_buffer = 100;
+    let mut debug = false;
+    let mut disasm_backend = BackendKind::default();
+    let mut insn_cache = DEFAULT_INSN_CACHE_CAPACITY;
+    let mut memory_history = None;
+    let mut raw_disasm = false;
+    let mut user_ranges = Vec::new();
+    let mut traces = Vec::new();
+    let mut window = None;
+    let mut replay = None;
+    let mut replay_rate = None;
+    let mut stdin = false;
+    let mut spill = None;
+    let mut sql_dump = None;
+    let mut sqlite = None;
+    let mut autosave = None;
+    let mut labels = None;
+    let mut symbol_maps = Vec::new();
+    let mut modules = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--window" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => window = Some(n),
+                None => tracing::warn!("--window expects a number of clnums"),
+            },
+            "--module" => match args.next().as_deref().and_then(parse_module_arg) {
+                Some(m) => modules.push(m),
+                None => tracing::warn!("--module expects PATH@0xBASE"),
+            },
+            "--replay" => match args.next() {
+                Some(path) => replay = Some(path),
+                None => tracing::warn!("--replay expects an NDJSON trace file"),
+            },
+            "--debug" => debug = true,
+            // Read tracer NDJSON from stdin, like a tracer connection
+            "--stdin" => stdin = true,
+            // Keep Capstone's operand formatting (no signed immediates / hex displacements)
+            "--raw-disasm" => raw_disasm = true,
+            // iced requires building with --features koradar-core/iced
+            "--disasm-backend" => match args.next().map(|kind| kind.parse::<BackendKind>()) {
+                Some(Ok(kind)) => disasm_backend = kind,
+                Some(Err(e)) => tracing::warn!("{}", e),
+                None => tracing::warn!("--disasm-backend expects capstone or iced"),
+            },
+            // Disassembly text cache size in entries (0 disables it)
+            "--insn-cache" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => insn_cache = n,
+                None => tracing::warn!("--insn-cache expects a number of entries"),
+            },
+            // Writes kept per memory byte (unbounded by default)
+            "--memory-history" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => memory_history = Some(n),
+                _ => tracing::warn!("--memory-history expects a positive number of writes"),
+            },
+            "--broadcast-buffer" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => broadcast_buffer = n,
+                _ => tracing::warn!("--broadcast-buffer expects a positive message count"),
+            },
+            "--user-range" => match args.next().as_deref().and_then(parse_range_arg) {
+                Some(r) => user_ranges.push(r),
+                None => tracing::warn!("--user-range expects 0xSTART-0xEND"),
+            },
+            "--load-trace" => match args.next() {
+                Some(path) => traces.push(path),
+                None => tracing::warn!("--load-trace expects an NDJSON trace file"),
+            },
+            "--spill" => match args.next() {
+                Some(path) => spill = Some(path),
+                None => tracing::warn!("--spill expects a file path for the change log"),
+            },
+            // Named memory regions, kept across restarts
+            "--labels" => match args.next() {
+                Some(path) => labels = Some(path),
+                None => tracing::warn!("--labels expects a JSON file path for named regions"),
+            },
+            // Names for a stripped binary, from IDA, Ghidra or nm
+            "--symbols" => match args.next() {
+                Some(path) => symbol_maps.push(path),
+                None => tracing::warn!("--symbols expects a symbol map file (text or JSON)"),
+            },
+            // Write trace A as a SQLite script once it's loaded
+            "--sql-dump" => match args.next() {
+                Some(path) => sql_dump = Some(path),
+                None => tracing::warn!("--sql-dump expects a file path for the SQL script"),
+            },
+            // Serve trace A's state from a SQLite database: written from
+            // --load-trace's trace, or an existing one opened in place
+            "--sqlite" => match args.next() {
+                Some(path) => sqlite = Some(path),
+                None => tracing::warn!("--sqlite expects a file path for the database"),
+            },
+            // Save trace A here on Ctrl-C, loadable with --load-trace
+            "--autosave" => match args.next() {
+                Some(path) => autosave = Some(path),
+                None => tracing::warn!("--autosave expects a file path for the trace"),
+            },
+            "--replay-rate" => match args.next().and_then(|n| n.parse::<u32>().ok()) {
+                Some(n) => replay_rate = Some(n),
+                None => tracing::warn!("--replay-rate expects a number of events per second"),
+            },
+            _ => binary_path = Some(arg),
+        }
+    }
+
+    if let Some(n) = window {
+        tracing::info!("trace window: keeping the last {} clnums", n);
+    }
+    let mut db = TraceDB::with_window(REG_COUNT, window);
+    if let Some(path) = &spill {
+        db = match db.with_spill_file(path) {
+            Ok(db) => {
+                tracing::info!("change log spills to {} when large", path);
+                db
             }
-            Err(e) => eprintln!("Failed to load binary: {}", e),
+            Err(e) => {
+                tracing::error!("failed to create spill file {}: {}", path, e);
+                TraceDB::with_window(REG_COUNT, window)
+            }
+        };
+    }
+    let db = Arc::new(db);
+    load_images(&db, binary_path.as_deref(), &modules);
+    import_symbol_maps(&db, &symbol_maps);
+    db.set_user_ranges(user_ranges.clone());
+    db.set_normalize_disasm(!raw_disasm);
+    db.set_insn_cache_capacity(insn_cache);
+    db.set_memory_history_limit(memory_history);
+    set_disasm_backend(&db, disasm_backend);
+    if let Some(path) = &labels {
+        match load_labels(path, &db) {
+            Ok(count) => tracing::info!("loaded {} named regions from {}", count, path),
+            // Created on the first NameRegion
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::error!("failed to load named regions {}: {}", path, e),
         }
     }
 
-    let (tx, _rx) = broadcast::channel(100);
     let max_clnum = Arc::new(std::sync::atomic::AtomicU32::new(0));
+    if let Some(path) = traces.first() {
+        match load_trace(path, &db) {
+            Ok(max) => {
+                tracing::info!("loaded trace A: {} ({} clnums)", path, max);
+                max_clnum.store(max, Ordering::Relaxed);
+            }
+            Err(e) => tracing::error!("failed to load trace {}: {}", path, e),
+        }
+    }
+    if let Some(path) = &sql_dump {
+        if traces.is_empty() {
+            tracing::warn!("--sql-dump needs a trace loaded with --load-trace");
+        } else {
+            match write_sql_dump(path, &db) {
+                Ok(()) => tracing::info!("wrote SQL dump of trace A to {}", path),
+                Err(e) => tracing::error!("failed to write SQL dump {}: {}", path, e),
+            }
+        }
+    }
+
+    let backend = trace_backend(&db, sqlite.as_deref(), !traces.is_empty());
+    if traces.is_empty() {
+        max_clnum.store(backend.max_clnum(), Ordering::Relaxed);
+    }
+    let sqlite_only = !backend.is_in_memory() && traces.is_empty();
+    if sqlite_only && (stdin || replay.is_some()) {
+        tracing::warn!("--stdin and --replay are ignored with --sqlite: the trace in it can't grow");
+    }
+
+    let db_b = traces.get(1).map(|path| {
+        let db_b = Arc::new(TraceDB::with_window(REG_COUNT, window));
+        load_images(&db_b, binary_path.as_deref(), &modules);
+        import_symbol_maps(&db_b, &symbol_maps);
+        db_b.set_user_ranges(user_ranges.clone());
+        db_b.set_normalize_disasm(!raw_disasm);
+        db_b.set_insn_cache_capacity(insn_cache);
+        db_b.set_memory_history_limit(memory_history);
+        set_disasm_backend(&db_b, disasm_backend);
+        match load_trace(path, &db_b) {
+            Ok(max) => tracing::info!("loaded trace B: {} ({} clnums)", path, max),
+            Err(e) => tracing::error!("failed to load trace {}: {}", path, e),
+        }
+        db_b
+    });
+
+    let (tx, _rx) = broadcast::channel(broadcast_buffer);
+    let breakpoints = Arc::new(LiveBreakpoints::default());
+
+    {
+        let tx = tx.clone();
+        let max_clnum = max_clnum.clone();
+        let breakpoints = breakpoints.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            let mut ticker = Ticker::new();
+            loop {
+                interval.tick().await;
+                for msg in ticker.tick(max_clnum.load(Ordering::Relaxed), &breakpoints) {
+                    if let Ok(json_str) = serde_json::to_string(&msg) {
+                        let _ = tx.send(json_str);
+                    }
+                }
+            }
+        });
+    }
     let state = Arc::new(AppState {
         db: db.clone(),
+        backend,
+        sqlite_only,
+        db_b,
         tx: tx.clone(),
         max_clnum: max_clnum.clone(),
+        breakpoints: breakpoints.clone(),
+        debug,
+        labels,
+        shutdown: watch::channel(false).0,
     });
 
-    // Start IPC Listener
+    // Live tracing, unless trace A is a SQLite file: the tracer's events would
+    // only reach the empty in-memory trace
+    if !sqlite_only {
+        spawn_ingestion(&tx, &db, &max_clnum, &breakpoints, stdin, replay, replay_rate);
+    }
+
+    // Static files are gzipped on the fly; /ws stays outside these layers
+    let static_files = Router::new()
+        .nest_service("/", ServeDir::new("frontend/dist"))
+        .layer(middleware::from_fn(static_cache_headers))
+        .layer(CompressionLayer::new());
+    let app = routes(state.clone()).fallback_service(static_files);
+
+    let listener = match tokio::net::TcpListener::bind("0.0.0.0:3000").await {
+        Ok(l) => l,
+        Err(e) => {
+            panic!("Failed to bind TCP listener: {}", e);
+        }
+    };
+    tracing::info!("listening on http://localhost:3000");
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(state, autosave))
+        .await
+        .unwrap();
+}
+
+// Starts reading tracer events: from tracer connections over TCP and the
+// Unix socket, and from stdin or a replayed file if asked to
+fn spawn_ingestion(
+    tx: &broadcast::Sender<String>,
+    db: &Arc<TraceDB>,
+    max_clnum: &Arc<AtomicU32>,
+    breakpoints: &Arc<LiveBreakpoints>,
+    stdin: bool,
+    replay: Option<String>,
+    replay_rate: Option<u32>,
+) {
     let ipc_tx = tx.clone();
     let ipc_db = db.clone();
     let ipc_max_clnum = max_clnum.clone();
+    let ipc_breakpoints = breakpoints.clone();
 
     tokio::spawn(async move {
         let listener = match tokio::net::TcpListener::bind("0.0.0.0:3001").await {
             Ok(l) => {
-                println!("IPC Listener listening on 0.0.0.0:3001");
+                tracing::info!("IPC listener listening on 0.0.0.0:3001");
                 l
             }
             Err(e) => {
@@ -76,272 +412,983 @@ async fn main() {
 
         loop {
             if let Ok((stream, _addr)) = listener.accept().await {
-                let ipc_tx = ipc_tx.clone();
-                let ipc_db = ipc_db.clone();
-                let ipc_max_clnum = ipc_max_clnum.clone();
-
-                tokio::spawn(async move {
-                    let mut reader = BufReader::new(stream);
-                    let mut line = String::new();
-                    let mut current_clnum = 0;
-
-                    while let Ok(bytes_read) = reader.read_line(&mut line).await {
-                        if bytes_read == 0 {
-                            break;
-                        }
+                tokio::spawn(handle_ipc_stream(
+                    stream,
+                    ipc_tx.clone(),
+                    ipc_db.clone(),
+                    ipc_max_clnum.clone(),
+                    ipc_breakpoints.clone(),
+                ));
+            }
+        }
+    });
 
-                        // Parse JSON
-                        if let Ok(event) = serde_json::from_str::<TraceEvent>(&line) {
-                            current_clnum += 1;
-                            ipc_max_clnum.store(current_clnum, Ordering::Relaxed);
-
-                            // Apply to DB
-                            match &event {
-                                    TraceEvent::InsnExec {
-                                        vcpu_index: _,
-                                        pc,
-                                        bytes,
-                                        disasm,
-                                        regs,
-                                    } => {
-                                        // Debug log for instruction addresses
-                                        // #region agent log
-                                        if current_clnum < 5 {
-                                            use std::fs::OpenOptions;
-                                            use std::io::Write;
-                                            let path = "/Users/shinta/git/github.com/geohot/qira/.cursor/debug.log";
-                                            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
-                                                let _ = writeln!(file, "{{\"id\":\"log_insn_regs\",\"timestamp\":{},\"location\":\"server/main.rs:InsnExec\",\"message\":\"Received registers\",\"data\":{{\"clnum\":{}, \"pc\":{}, \"regs_len\":{}, \"regs_sample\":{:?}}},\"sessionId\":\"debug-session\",\"runId\":\"debug-run\",\"hypothesisId\":\"regs-zero\"}}", 
-                                                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                                                    current_clnum, pc, regs.len(), regs.iter().take(4).collect::<Vec<_>>()
-                                                );
-                                            }
-                                        }
-                                        // #endregion
-                                        /*
-                                        if current_clnum < 200 {
-                                            println!("[DEBUG] Insn: {:x}", pc);
-                                        }
-                                        */
-
-                                        // Better heuristic: scan all instructions, not just first 10
-                                        if let Some(ep) = ipc_db.get_entry_point() {
-                                            // Check if this PC matches the entry point pattern
-                                            // If -no-pie, pc should equal ep.
-                                            // If PIE, pc = ep + bias.
-                                            // Since we don't know bias, we check alignment.
-                                            // 0x...1234 (pc) vs 0x...1234 (ep)
-                                            if (pc & 0xFFF) == (ep & 0xFFF) {
-                                                let bias = (*pc as i64) - (ep as i64);
-                                                // Only set if we haven't found a bias or it's different/better
-                                                // (e.g. bias=0 is preferred if -no-pie)
-                                                let current_bias = ipc_db.get_bias();
-                                                if current_bias == 0 && bias != 0 {
-                                                     // If we thought bias was 0 but found a PIE match, maybe update?
-                                                     // But if -no-pie, bias IS 0.
-                                                     // Let's print for debug.
-                                                     println!("[INFO] Candidate bias: {:x} at clnum {}", bias, current_clnum);
-                                                     // If bias is huge (like 0x7fff...) it might be loader matching coincidently?
-                                                     // Loader addresses are usually high. Main binary usually 0x55...
-                                                     // If pc is 0x40xxxx, it's definitely main binary.
-                                                     
-                                                     // Prioritize low-memory addresses for main binary if possible
-                                                     if *pc < 0x7000_0000_0000 {
-                                                         ipc_db.set_bias(bias);
-                                                     }
-                                                } else if bias == 0 && current_bias != 0 {
-                                                    // Found exact match, prefer this!
-                                                    ipc_db.set_bias(0);
-                                                }
-                                            }
-                                        }
-                                        ipc_db.add_instruction(current_clnum, bytes.clone());
-                                        if let Some(d) = disasm {
-                                            ipc_db.add_instruction_disasm(current_clnum, d.clone());
-                                        }
-                                        
-                                        if !regs.is_empty() {
-                                            ipc_db.update_registers(current_clnum, regs);
-                                        }
+    // Local-only tracing over a Unix domain socket (tracer arg: socket=/path)
+    #[cfg(unix)]
+    {
+        let ipc_tx = tx.clone();
+        let ipc_db = db.clone();
+        let ipc_max_clnum = max_clnum.clone();
+        let ipc_breakpoints = breakpoints.clone();
+        let socket_path = env::var("KORADAR_IPC_SOCKET")
+            .unwrap_or_else(|_| "/tmp/koradar.sock".to_string());
 
-                                        ipc_db.add_change(Change {
-                                        address: *pc,
-                                        data: 0,
-                                        clnum: current_clnum,
-                                        flags: ChangeFlags::IS_VALID.bits()
-                                            | ChangeFlags::IS_START.bits(),
-                                    });
-                                }
-                                TraceEvent::Init { .. } => {}
-                                TraceEvent::Exit { .. } => {}
-                                _ => {}
-                            }
+        tokio::spawn(async move {
+            // Remove a stale socket file left behind by a previous run
+            let _ = std::fs::remove_file(&socket_path);
+            let listener = match tokio::net::UnixListener::bind(&socket_path) {
+                Ok(l) => {
+                    tracing::info!("IPC listener listening on unix:{}", socket_path);
+                    l
+                }
+                Err(e) => {
+                    tracing::error!("failed to bind IPC Unix socket {}: {}", socket_path, e);
+                    return;
+                }
+            };
 
-                            // Broadcast as ServerMessage::TraceEvent
-                            let server_msg = ServerMessage::TraceEvent(event);
-                            if let Ok(json_str) = serde_json::to_string(&server_msg) {
-                                let _ = ipc_tx.send(json_str);
-                            }
+            loop {
+                if let Ok((stream, _addr)) = listener.accept().await {
+                    tokio::spawn(handle_ipc_stream(
+                        stream,
+                        ipc_tx.clone(),
+                        ipc_db.clone(),
+                        ipc_max_clnum.clone(),
+                        ipc_breakpoints.clone(),
+                    ));
+                }
+            }
+        });
+    }
 
-                            // Broadcast MaxClnum
-                            let max_msg = ServerMessage::MaxClnum { max: current_clnum };
-                            if let Ok(json_str) = serde_json::to_string(&max_msg) {
-                                let _ = ipc_tx.send(json_str);
-                            }
-                        }
-                        line.clear();
-                    }
-                });
+    if stdin {
+        tokio::spawn(read_stdin(tx.clone(), db.clone(), max_clnum.clone(), breakpoints.clone()));
+    }
+
+    if let Some(path) = replay {
+        tokio::spawn(replay_file(
+            path,
+            replay_rate,
+            tx.clone(),
+            db.clone(),
+            max_clnum.clone(),
+            breakpoints.clone(),
+        ));
+    }
+}
+
+// The WebSocket and the HTTP API, without the static frontend files
+fn routes(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/ws", get(ws_handler))
+        .route("/api/info", get(info_handler))
+        .route("/api/cfg.svg", get(cfg_svg_handler))
+        .route("/api/ask_ai", post(ask_ai_handler))
+        .with_state(state)
+}
+
+// Resolves on Ctrl-C once client sockets were told to close and trace A was
+// saved to `autosave`, if set. The server then stops once they're gone.
+async fn shutdown_signal(state: Arc<AppState>, autosave: Option<String>) {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::error!("failed to listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+    tracing::info!("shutting down");
+    state.shutdown.send_replace(true);
+
+    if let Some(path) = autosave {
+        let db = state.db.clone();
+        let saved = tokio::task::spawn_blocking(move || save_trace(&path, &db).map(|()| path)).await;
+        match saved {
+            Ok(Ok(path)) => tracing::info!("saved trace A to {}", path),
+            Ok(Err(e)) => tracing::error!("failed to save trace: {}", e),
+            Err(e) => tracing::error!("trace save task failed: {}", e),
+        }
+    }
+}
+
+/// Writes trace A as tracer NDJSON (see TraceDB::write_events). The file is
+/// written next to `path` and renamed over it, so an interrupted save leaves
+/// the previous one intact.
+fn save_trace(path: &str, db: &TraceDB) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let tmp = format!("{}.tmp", path);
+    let file = std::fs::File::create(&tmp)?;
+    let mut out = std::io::BufWriter::new(file);
+    db.write_events(&mut out)?;
+    out.flush()?;
+    out.get_ref().sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+// Loads the main binary and any extra modules into `db`
+fn load_images(db: &TraceDB, binary_path: Option<&str>, modules: &[(String, u64)]) {
+    if let Some(binary_path) = binary_path {
+        tracing::info!("loading binary: {}", binary_path);
+        match BinaryLoader::load_file(db, Path::new(binary_path)) {
+            Ok(_) => {
+                tracing::info!("binary loaded");
             }
+            Err(e) => tracing::error!("failed to load binary: {}", e),
         }
-    });
+    }
 
-    let app = Router::new()
-        .route("/ws", get(ws_handler))
-        .nest_service("/", ServeDir::new("frontend/dist"))
-        .with_state(state.clone());
+    for (path, base) in modules {
+        if let Err(e) = db.add_module(Path::new(path), *base) {
+            tracing::error!("failed to load module {}: {}", path, e);
+        }
+    }
+}
 
-    let listener = match tokio::net::TcpListener::bind("0.0.0.0:3000").await {
-        Ok(l) => l,
+// Applies `--disasm-backend`, staying on Capstone if the backend is unavailable
+fn set_disasm_backend(db: &TraceDB, kind: BackendKind) {
+    if kind == BackendKind::Capstone {
+        return;
+    }
+    if let Err(e) = db.set_disasm_backend(kind) {
+        tracing::error!("failed to use the {:?} disassembler backend: {}", kind, e);
+    }
+}
+
+// Parses a `--module` argument of the form PATH@0xBASE
+fn parse_module_arg(arg: &str) -> Option<(String, u64)> {
+    let (path, base) = arg.rsplit_once('@')?;
+    let base = base.trim_start_matches("0x");
+    Some((path.to_string(), u64::from_str_radix(base, 16).ok()?))
+}
+
+// Parses a `--user-range` argument of the form 0xSTART-0xEND (static addresses)
+fn parse_range_arg(arg: &str) -> Option<(u64, u64)> {
+    let (start, end) = arg.split_once('-')?;
+    let parse = |s: &str| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok();
+    let (start, end) = (parse(start)?, parse(end)?);
+    (start < end).then_some((start, end))
+}
+
+// Parses the bytes of a Disassemble request: hex digit pairs, optionally
+// separated by whitespace, e.g. "55 48 89 e5" or "554889e5"
+fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!("'{}' is not a whole number of hex bytes", hex));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("'{}' is not a hex byte", pair))
+        })
+        .collect()
+}
+
+/// Consumes an NDJSON stream of `TraceEvent`s from a tracer connection,
+/// applying each event to the DB and broadcasting it to WebSocket clients.
+///
+/// Each connection is a new session: the previous run is discarded and
+/// clnums restart from 1, so only one tracer should be connected at a time.
+async fn handle_ipc_stream<R: AsyncRead + Unpin>(
+    stream: R,
+    ipc_tx: broadcast::Sender<String>,
+    ipc_db: Arc<TraceDB>,
+    ipc_max_clnum: Arc<AtomicU32>,
+    ipc_breakpoints: Arc<LiveBreakpoints>,
+) {
+    tracing::debug!(stats = ?ipc_db.cache_stats(), "disassembly cache before reset");
+    ipc_db.reset_trace();
+    ipc_max_clnum.store(0, Ordering::Relaxed);
+    // A hit of the previous run would point into the new one
+    ipc_breakpoints.pending_hit.lock().unwrap().take();
+    if let Ok(json_str) = serde_json::to_string(&ServerMessage::SessionReset) {
+        let _ = ipc_tx.send(json_str);
+    }
+
+    let clnums = ingest_stream(stream, None, &ipc_tx, &ipc_db, &ipc_max_clnum, &ipc_breakpoints).await;
+    tracing::info!("tracer disconnected after {} clnums", clnums);
+}
+
+/// `--stdin`: one run piped into the server, e.g. `nc -l 3001 | koradar-server --stdin`.
+/// Unlike a tracer connection it doesn't reset the DB, and at EOF the trace
+/// stays loaded for inspection.
+async fn read_stdin(
+    tx: broadcast::Sender<String>,
+    db: Arc<TraceDB>,
+    max_clnum: Arc<AtomicU32>,
+    breakpoints: Arc<LiveBreakpoints>,
+) {
+    tracing::info!("reading trace events from stdin");
+    let clnums = ingest_stream(tokio::io::stdin(), None, &tx, &db, &max_clnum, &breakpoints).await;
+    tracing::info!("stdin closed after {} clnums; the trace stays available", clnums);
+}
+
+/// Applies an NDJSON stream of `TraceEvent`s until EOF, numbering and
+/// broadcasting each one (see `ingest_event`), and returns the last clnum.
+/// Lines that aren't events are skipped. With `rate` set, events are
+/// throttled to that many per second.
+async fn ingest_stream<R: AsyncRead + Unpin>(
+    stream: R,
+    rate: Option<u32>,
+    tx: &broadcast::Sender<String>,
+    db: &TraceDB,
+    max_clnum: &AtomicU32,
+    breakpoints: &LiveBreakpoints,
+) -> u32 {
+    let mut ticker = rate
+        .filter(|&r| r > 0)
+        .map(|r| tokio::time::interval(Duration::from_secs_f64(1.0 / r as f64)));
+    let mut lines = BufReader::new(stream).lines();
+    let mut current_clnum = 0;
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Ok(event) = serde_json::from_str::<TraceEvent>(&line) {
+            if let Some(ticker) = &mut ticker {
+                ticker.tick().await;
+            }
+            if advances_clnum(&event) {
+                current_clnum += 1;
+            }
+            ingest_event(event, current_clnum, tx, db, max_clnum, breakpoints);
+        }
+    }
+    current_clnum
+}
+
+/// Feeds a saved tracer NDJSON file through the same path as a live connection.
+/// With `rate` set, events are throttled to that many per second; otherwise
+/// the file is replayed as fast as possible.
+async fn replay_file(
+    path: String,
+    rate: Option<u32>,
+    tx: broadcast::Sender<String>,
+    db: Arc<TraceDB>,
+    max_clnum: Arc<AtomicU32>,
+    breakpoints: Arc<LiveBreakpoints>,
+) {
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(f) => f,
         Err(e) => {
-            panic!("Failed to bind TCP listener: {}", e);
+            tracing::error!("failed to open replay file {}: {}", path, e);
+            return;
+        }
+    };
+    tracing::info!("replaying trace: {}", path);
+    let clnums = ingest_stream(file, rate, &tx, &db, &max_clnum, &breakpoints).await;
+    tracing::info!("replay finished: {} clnums", clnums);
+}
+
+// Applies one tracer event (numbered `clnum`) to the DB and broadcasts it.
+// If the instruction matches a breakpoint whose condition, if any, holds,
+// it's kept for the ticker in main to report unless an earlier hit is
+// still waiting.
+fn ingest_event(
+    event: TraceEvent,
+    clnum: u32,
+    tx: &broadcast::Sender<String>,
+    db: &TraceDB,
+    max_clnum: &AtomicU32,
+    breakpoints: &LiveBreakpoints,
+) {
+    max_clnum.store(clnum, Ordering::Relaxed);
+
+    db.apply_event(&event, clnum);
+    let hit = match &event {
+        TraceEvent::InsnExec { pc, regs, .. } => breakpoints
+            .set
+            .read()
+            .map(|bps| {
+                hits_breakpoint(&bps, *pc, db.get_bias(), |condition| db.condition_holds(clnum, regs, condition))
+            })
+            .unwrap_or(false)
+            .then_some(*pc),
+        _ => None,
+    };
+
+    // Broadcast as ServerMessage::TraceEvent (MaxClnum is sent by a ticker in main)
+    let server_msg = ServerMessage::TraceEvent(event);
+    if let Ok(json_str) = serde_json::to_string(&server_msg) {
+        let _ = tx.send(json_str);
+    }
+    if let Some(address) = hit {
+        let mut pending = breakpoints.pending_hit.lock().unwrap();
+        if pending.is_none() {
+            tracing::info!("breakpoint hit at {:#x} (clnum {})", address, clnum);
+            *pending = Some((clnum, address));
+        }
+    }
+    if let ServerMessage::TraceEvent(TraceEvent::ProcessEnd { .. }) = server_msg {
+        if let Some(json_str) = crash_message(db).and_then(|msg| serde_json::to_string(&msg).ok()) {
+            let _ = tx.send(json_str);
         }
+    }
+}
+
+// Whether the instruction at runtime address `pc` matches a breakpoint, either
+// directly or, for static breakpoints, after removing the load bias. Conditions
+// are only evaluated for breakpoints at `pc`, so the check stays a set lookup
+// for every other instruction.
+fn hits_breakpoint(
+    breakpoints: &BTreeSet<Breakpoint>,
+    pc: u64,
+    bias: i64,
+    holds: impl Fn(&BreakCondition) -> bool,
+) -> bool {
+    if breakpoints.is_empty() {
+        return false;
+    }
+    let static_pc = pc.wrapping_sub(bias as u64);
+    [(pc, false), (static_pc, true)].into_iter().any(|(address, is_static)| {
+        // Unconditional breakpoints sort first among those at an address
+        breakpoints
+            .range(Breakpoint { address, is_static, condition: None }..)
+            .take_while(|bp| bp.address == address && bp.is_static == is_static)
+            .any(|bp| bp.condition.as_ref().is_none_or(&holds))
+    })
+}
+
+fn crash_message(db: &TraceDB) -> Option<ServerMessage> {
+    db.crash().map(|crash| ServerMessage::CrashInfo {
+        clnum: crash.clnum,
+        address: crash.address,
+        signal: crash.signal,
+    })
+}
+
+fn import_symbol_maps(db: &TraceDB, paths: &[String]) {
+    for path in paths {
+        match db.import_symbols_from_map(Path::new(path)) {
+            Ok(count) => tracing::info!("imported {} symbols from {}", count, path),
+            Err(e) => tracing::error!("failed to import symbols: {:#}", e),
+        }
+    }
+}
+
+/// Loads a saved tracer NDJSON file into `db` up front, returning the last clnum.
+fn load_trace(path: &str, db: &TraceDB) -> std::io::Result<u32> {
+    use std::io::BufRead;
+
+    let file = std::fs::File::open(path)?;
+    let mut clnum = 0;
+    for line in std::io::BufReader::new(file).lines() {
+        if let Ok(event) = serde_json::from_str::<TraceEvent>(&line?) {
+            if advances_clnum(&event) {
+                clnum += 1;
+            }
+            db.apply_event(&event, clnum);
+        }
+    }
+    Ok(clnum)
+}
+
+fn load_labels(path: &str, db: &TraceDB) -> std::io::Result<usize> {
+    let regions: Vec<NamedRegion> = serde_json::from_slice(&std::fs::read(path)?)?;
+    for region in &regions {
+        if let Err(e) = db.name_region(region.address, region.len, &region.name) {
+            tracing::warn!("skipping named region at {:#x}: {}", region.address, e);
+        }
+    }
+    Ok(regions.len())
+}
+
+fn save_labels(path: &str, db: &TraceDB) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_vec_pretty(&db.regions())?)
+}
+
+// Trace A's named regions, then saved to --labels if set. Every client gets
+// them since they rename addresses everywhere.
+fn broadcast_regions(state: &AppState) {
+    if let Some(path) = &state.labels {
+        if let Err(e) = save_labels(path, &state.db) {
+            tracing::error!("failed to save named regions to {}: {}", path, e);
+        }
+    }
+    let response = ServerMessage::Regions { regions: state.db.regions() };
+    if let Ok(json) = serde_json::to_string(&response) {
+        let _ = state.tx.send(json);
+    }
+}
+
+fn write_sql_dump(path: &str, db: &TraceDB) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    db.write_sql(&mut out)?;
+    out.flush()
+}
+
+#[cfg(feature = "sqlite")]
+fn trace_backend(db: &Arc<TraceDB>, sqlite: Option<&str>, loaded: bool) -> Arc<dyn TraceBackend> {
+    use koradar_core::sqlite::SqliteTrace;
+
+    let Some(path) = sqlite else {
+        return db.clone();
+    };
+    let trace = if loaded {
+        SqliteTrace::create(Path::new(path), db)
+    } else {
+        SqliteTrace::open(Path::new(path))
     };
-    println!("Listening on http://localhost:3000");
+    match trace {
+        Ok(trace) => {
+            tracing::info!("serving trace A's state from {}", path);
+            Arc::new(trace)
+        }
+        Err(e) => {
+            tracing::error!("failed to use SQLite trace {}: {:#}", path, e);
+            db.clone()
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn trace_backend(db: &Arc<TraceDB>, sqlite: Option<&str>, _loaded: bool) -> Arc<dyn TraceBackend> {
+    if sqlite.is_some() {
+        tracing::warn!("--sqlite needs the server built with --features sqlite");
+    }
+    db.clone()
+}
+
+// Why a request was refused when trace A is only in a SQLite file
+const NEEDS_LOADED_TRACE: &str = "not available for a trace served from SQLite; load it with --load-trace";
 
-    axum::serve(listener, app).await.unwrap();
+// Requests answered through `AppState::backend` (or needing no trace), the
+// only ones served when trace A is only in a SQLite file
+fn answered_by_backend(message: &ClientMessage) -> bool {
+    matches!(
+        message,
+        ClientMessage::Hello { .. }
+            | ClientMessage::GetServerInfo
+            | ClientMessage::QueryState { .. }
+            | ClientMessage::GetTraceLog { .. }
+            | ClientMessage::StepForward { .. }
+            | ClientMessage::StepBackward { .. }
+            | ClientMessage::Disassemble { .. }
+    )
+}
+
+// Snapshot of registers, 256 bytes of memory at `memory_addr` and the
+// instruction at `clnum` annotated with its operand values
+fn state_update(state: &AppState, clnum: u32, memory_addr: u64) -> ServerMessage {
+    ServerMessage::StateUpdate {
+        clnum,
+        registers: state.backend.get_registers_at(clnum),
+        memory: state.backend.get_memory_at(clnum, memory_addr, 256),
+        memory_addr,
+        disassembly: state.backend.annotated_disassembly(clnum),
+        pruned: state.db.is_pruned(clnum),
+        stack_regions: state.db.stack_regions_at(clnum),
+    }
+}
+
+// Computes a slice and expands its clnums into trace log entries
+fn slice_entries(db: &TraceDB, clnum: u32, target: String) -> Result<Vec<TraceEntry>, QueryError> {
+    Ok(db.trace_entries(&db.get_slice(clnum, target)?))
+}
+
+// Trunk fingerprints every bundled asset, so everything but the HTML shell can
+// be cached indefinitely; index.html is revalidated to pick up new hashes
+async fn static_cache_headers(req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    let is_html = path.ends_with('/') || path.ends_with(".html");
+    let mut res = next.run(req).await;
+    if res.status().is_success() {
+        let value = if is_html {
+            "no-cache"
+        } else {
+            "public, max-age=31536000, immutable"
+        };
+        res.headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+    }
+    res
+}
+
+// Current session configuration and the modules it can select
+fn config_message(db: &TraceDB) -> ServerMessage {
+    ServerMessage::Config {
+        config: db.config(),
+        modules: db.modules().iter().map(|m| m.file_name()).collect(),
+    }
 }
 
 async fn ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> impl IntoResponse {
     ws.on_upgrade(|socket| handle_socket(socket, state))
 }
 
+// Health check and version for scripts: the ServerInfo reply as JSON
+async fn info_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(server_info(&state))
+}
+
+#[derive(serde::Deserialize)]
+struct CfgQuery {
+    #[serde(default)]
+    only_user_code: bool,
+    #[serde(default)]
+    start_from_main: bool,
+    #[serde(default)]
+    collapse_stubs: bool,
+}
+
+// The CFG of trace A as a standalone SVG, e.g. for embedding in reports:
+// /api/cfg.svg?only_user_code=true&start_from_main=true&collapse_stubs=true
+async fn cfg_svg_handler(State(state): State<Arc<AppState>>, Query(query): Query<CfgQuery>) -> Response {
+    if state.sqlite_only {
+        return (StatusCode::NOT_IMPLEMENTED, NEEDS_LOADED_TRACE).into_response();
+    }
+    let dot = match state.db.analyze_cfg(query.only_user_code, query.start_from_main, query.collapse_stubs) {
+        Ok(cfg) => cfg.to_dot(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    match render::dot_to_svg(&dot).await {
+        Ok(svg) => ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e).into_response(),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct AskAiRequest {
+    clnum: u32,
+    #[serde(default)]
+    question: Option<String>,
+    #[serde(default = "default_context_lines")]
+    context_lines: u32,
+}
+
+fn default_context_lines() -> u32 {
+    DEFAULT_CONTEXT_LINES
+}
+
+// AskAI for scripts: POST {"clnum": 1234, "question": "..."} to /api/ask_ai
+// returns {"clnum", "text"}, or {"error"} with a 4xx/5xx status
+async fn ask_ai_handler(
+    State(state): State<Arc<AppState>>,
+    request: Result<Json<AskAiRequest>, JsonRejection>,
+) -> Response {
+    let error = |status: StatusCode, message: String| {
+        (status, Json(serde_json::json!({ "error": message }))).into_response()
+    };
+    let Json(request) = match request {
+        Ok(request) => request,
+        Err(rejection) => return error(rejection.status(), rejection.body_text()),
+    };
+    if state.sqlite_only {
+        return error(StatusCode::NOT_IMPLEMENTED, NEEDS_LOADED_TRACE.to_string());
+    }
+    let max = state.max_clnum.load(Ordering::Relaxed);
+    if request.clnum > max {
+        return error(StatusCode::BAD_REQUEST, QueryError::ClnumOutOfRange { clnum: request.clnum, max }.to_string());
+    }
+    // Disassembles up to a few thousand instructions, so keep it off the runtime
+    let db = state.db.clone();
+    let (clnum, context_lines) = (request.clnum, request.context_lines.min(200));
+    let context = match tokio::task::spawn_blocking(move || db.ai_context(clnum, context_lines)).await {
+        Ok(context) => context,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("building the context failed: {}", e)),
+    };
+    match ai::ask_ai(context, request.question.as_deref()).await {
+        Ok(text) => Json(serde_json::json!({ "clnum": request.clnum, "text": text })).into_response(),
+        Err(e) => error(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+fn server_info(state: &AppState) -> ServerMessage {
+    let mut capabilities = vec!["binary_frames".to_string()];
+    if state.db_b.is_some() {
+        capabilities.push("diff_slices".to_string());
+        capabilities.push("diff_cfg".to_string());
+    }
+    if state.debug {
+        capabilities.push("raw_changes".to_string());
+    }
+    ServerMessage::ServerInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: PROTOCOL_VERSION,
+        capabilities,
+    }
+}
+
+async fn send_error(socket: &mut WebSocket, request_id: Option<u32>, message: impl ToString) {
+    let response = ServerMessage::Error {
+        request_id,
+        message: message.to_string(),
+    };
+    if let Ok(json) = serde_json::to_string(&response) {
+        let _ = socket.send(Message::Text(json)).await;
+    }
+}
+
+// CFG graphs larger than this go out as CFGChunk messages of about this size
+const CFG_CHUNK_BYTES: usize = 64 * 1024;
+// Most slices a register timeline is split into, whatever the client asks for
+const MAX_TIMELINE_BUCKETS: u32 = 4096;
+
+// Splits a CFG reply into CFGChunk messages of at most CFG_CHUNK_BYTES,
+// cutting only at character boundaries
+fn cfg_chunks(graph: &str, format: CfgFormat, warning: Option<String>) -> VecDeque<ServerMessage> {
+    let mut parts = Vec::new();
+    let mut rest = graph;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(CFG_CHUNK_BYTES);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+    }
+    let total = parts.len() as u32;
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(seq, data)| ServerMessage::CFGChunk {
+            seq: seq as u32,
+            total,
+            data: data.to_string(),
+            format,
+            warning: warning.clone(),
+        })
+        .collect()
+}
+
+// The CFG reply to send right away, or None if the graph is large and was
+// queued in `pending` as chunks. Either way it replaces the rest of an
+// earlier graph still being sent, whose chunks would overwrite it.
+fn cfg_reply(
+    pending: &mut VecDeque<ServerMessage>,
+    graph: String,
+    format: CfgFormat,
+    warning: Option<String>,
+) -> Option<ServerMessage> {
+    if graph.len() > CFG_CHUNK_BYTES {
+        *pending = cfg_chunks(&graph, format, warning);
+        None
+    } else {
+        pending.clear();
+        Some(ServerMessage::CFG { graph, format, warning })
+    }
+}
+
+// Sends a reply that can get large, as a MessagePack binary frame if the
+// client negotiated binary frames and as JSON text otherwise
+async fn send_large(socket: &mut WebSocket, binary_frames: bool, response: &ServerMessage) {
+    if binary_frames {
+        match rmp_serde::to_vec_named(response) {
+            Ok(bytes) => {
+                let _ = socket.send(Message::Binary(bytes)).await;
+            }
+            Err(e) => tracing::error!("failed to encode binary reply: {}", e),
+        }
+    } else if let Ok(json) = serde_json::to_string(response) {
+        let _ = socket.send(Message::Text(json)).await;
+    }
+}
+
 async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
     let mut rx = state.tx.subscribe();
+    let mut shutdown = state.shutdown.subscribe();
     let db = state.db.clone();
     let max_clnum = state.max_clnum.clone();
+    // Set by the client's Hello; JSON text until then
+    let mut binary_frames = false;
+    // CFGChunks still to send. They go out one per loop iteration so
+    // requests arriving meanwhile aren't stuck behind a large graph
+    let mut pending_chunks: VecDeque<ServerMessage> = VecDeque::new();
 
     // Send initial max_clnum
     let max = max_clnum.load(Ordering::Relaxed);
     if let Ok(json) = serde_json::to_string(&ServerMessage::MaxClnum { max }) {
         let _ = socket.send(Message::Text(json)).await;
     }
+    // So a client opening a crashed trace can go straight to the crash
+    if let Some(json) = crash_message(&db).and_then(|msg| serde_json::to_string(&msg).ok()) {
+        let _ = socket.send(Message::Text(json)).await;
+    }
 
     loop {
         tokio::select! {
             msg = socket.recv() => {
                 match msg {
                     Some(Ok(Message::Text(text))) => {
-                        match serde_json::from_str::<ClientMessage>(&text) {
-                            Ok(client_msg) => {
+                        match serde_json::from_str::<ClientRequest>(&text) {
+                            Ok(ClientRequest { request_id, message: client_msg }) => {
+                                if state.sqlite_only && !answered_by_backend(&client_msg) {
+                                    send_error(&mut socket, request_id, NEEDS_LOADED_TRACE).await;
+                                    continue;
+                                }
                                 match client_msg {
+                                    ClientMessage::Hello { binary_frames: enabled } => {
+                                        binary_frames = enabled;
+                                    }
+                                    ClientMessage::GetServerInfo => {
+                                        if let Ok(json) = serde_json::to_string(&server_info(&state)) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
                                     ClientMessage::QueryState { clnum, memory_addr } => {
-                                        let regs = db.get_registers_at(clnum);
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        }
                                         // Default to 0 or use provided address
-                                        let mem_start = memory_addr.unwrap_or(0);
-                                        let mem = db.get_memory_at(clnum, mem_start, 256);
-                                        let disasm = db.get_disassembly_at(clnum);
-                                        let response = ServerMessage::StateUpdate {
-                                            clnum,
-                                            registers: regs,
-                                            memory: mem,
-                                            memory_addr: mem_start,
-                                            disassembly: disasm,
+                                        let response = state_update(&state, clnum, memory_addr.unwrap_or(0));
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GetTraceLog { start, count, only_user_code, function, radius, collapse_library_calls, fold } => {
+                                        if collapse_library_calls && !state.backend.is_in_memory() {
+                                            send_error(&mut socket, request_id, "collapsing library calls needs the trace in memory (not served with --sqlite)").await;
+                                            continue;
+                                        }
+                                        if let Some(name) = &function {
+                                            if !state.backend.has_symbol(name) {
+                                                send_error(&mut socket, request_id, QueryError::SymbolNotFound(name.clone())).await;
+                                                continue;
+                                            }
+                                        }
+                                        let options = TraceLogOptions { only_user_code, function: function.as_deref(), collapse_library_calls, fold };
+                                        let page = match radius {
+                                            Some(radius) => state.backend.get_trace_log_around(start, radius, options),
+                                            None => state.backend.get_trace_log(start, count, options),
+                                        };
+                                        tracing::debug!(start, count, only_user_code, entries = page.entries.len(), "GetTraceLog");
+                                        let response = ServerMessage::TraceLog { entries: page.entries, next_start: page.next_start };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::StepForward { current } | ClientMessage::StepOver { current } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        let next_clnum = if matches!(client_msg, ClientMessage::StepOver { .. }) {
+                                            db.step_over(current, max)
+                                        } else {
+                                            clamp_clnum(current.saturating_add(1), max)
+                                        };
+                                        let response = state_update(&state, next_clnum, 0);
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::StepBackward { current } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        let prev_clnum = clamp_clnum(current.saturating_sub(1), max);
+                                        let response = state_update(&state, prev_clnum, 0);
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::DescribeAddress { address } => {
+                                        let response = ServerMessage::AddressInfo {
+                                            address,
+                                            label: db.describe_address(address),
                                         };
                                         if let Ok(json) = serde_json::to_string(&response) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
-                                    ClientMessage::GetTraceLog { start, count, only_user_code } => {
-                                        let entries = db.get_trace_log(start, count, only_user_code);
-                                        // #region agent log
-                                        {
-                                            use std::fs::OpenOptions;
-                                            use std::io::Write;
-                                            let path = "/Users/shinta/git/github.com/geohot/qira/.cursor/debug.log";
-                                            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
-                                                let _ = writeln!(file, "{{\"id\":\"log_get_trace\",\"timestamp\":{},\"location\":\"server/main.rs:GetTraceLog\",\"message\":\"GetTraceLog query\",\"data\":{{\"start\":{}, \"count\":{}, \"only_user_code\":{}, \"result_count\":{}}},\"sessionId\":\"debug-session\",\"runId\":\"debug-run\",\"hypothesisId\":\"user-code-filter\"}}", 
-                                                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                                                    start, count, only_user_code, entries.len()
-                                                );
-                                            }
+                                    ClientMessage::ReadStruct { clnum, address, fields } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
                                         }
-                                        // #endregion
-                                        let response = ServerMessage::TraceLog { entries };
+                                        let response = ServerMessage::StructView {
+                                            clnum,
+                                            address,
+                                            fields: db.read_struct(clnum, address, &fields),
+                                        };
                                         if let Ok(json) = serde_json::to_string(&response) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
-                                    ClientMessage::StepForward { current } => {
-                                        let next_clnum = (current + 1).min(max_clnum.load(Ordering::Relaxed));
-                                        let regs = db.get_registers_at(next_clnum);
-                                        let mem = db.get_memory_at(next_clnum, 0, 256);
-                                        let response = ServerMessage::StateUpdate {
-                                            clnum: next_clnum,
-                                            registers: regs,
-                                            memory: mem,
-                                            memory_addr: 0,
-                                            disassembly: db.get_disassembly_at(next_clnum),
+                                    ClientMessage::GotoMain => {
+                                        let clnum = match db.main_clnum() {
+                                            Ok(clnum) => clnum,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let response = state_update(&state, clnum, 0);
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GotoAddress { address, after } => {
+                                        let executions = db.executions_of(address);
+                                        let next = executions.iter().find(|&&c| c > after).or(executions.first());
+                                        let Some(&clnum) = next else {
+                                            let what = format!("{:#x}", address);
+                                            send_error(&mut socket, request_id, QueryError::NeverExecuted(what)).await;
+                                            continue;
                                         };
+                                        let response = state_update(&state, clnum, 0);
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::SetBreakpoint { address, is_static } => {
+                                        let breakpoints = {
+                                            let mut bps = state.breakpoints.set.write().unwrap();
+                                            bps.insert(Breakpoint { address, is_static, condition: None });
+                                            bps.iter().copied().collect()
+                                        };
+                                        let response = ServerMessage::Breakpoints { breakpoints };
                                         if let Ok(json) = serde_json::to_string(&response) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
-                                    ClientMessage::StepBackward { current } => {
-                                        let prev_clnum = current.saturating_sub(1).max(1);
-                                        let regs = db.get_registers_at(prev_clnum);
-                                        let mem = db.get_memory_at(prev_clnum, 0, 256);
-                                        let response = ServerMessage::StateUpdate {
-                                            clnum: prev_clnum,
-                                            registers: regs,
-                                            memory: mem,
-                                            memory_addr: 0,
-                                            disassembly: db.get_disassembly_at(prev_clnum),
+                                    ClientMessage::SetConditionalBreakpoint { address, is_static, condition } => {
+                                        let invalid = match condition.operand {
+                                            CondOperand::Reg { index } if index >= REG_COUNT => {
+                                                Some(format!("no register with index {}", index))
+                                            }
+                                            CondOperand::Mem { size, .. } if !(1..=8).contains(&size) => {
+                                                Some(format!("memory operands are 1 to 8 bytes, not {}", size))
+                                            }
+                                            _ => None,
+                                        };
+                                        if let Some(message) = invalid {
+                                            send_error(&mut socket, request_id, message).await;
+                                            continue;
+                                        }
+                                        let breakpoints = {
+                                            let mut bps = state.breakpoints.set.write().unwrap();
+                                            bps.insert(Breakpoint { address, is_static, condition: Some(condition) });
+                                            bps.iter().copied().collect()
                                         };
+                                        let response = ServerMessage::Breakpoints { breakpoints };
                                         if let Ok(json) = serde_json::to_string(&response) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
-                                    ClientMessage::GetCFG { only_user_code, start_from_main } => {
-                                        let cfg = db.analyze_cfg(only_user_code, start_from_main);
-                                        let mermaid = cfg.to_mermaid();
-                                        println!("[INFO] Generated CFG size: {} bytes", mermaid.len());
-                                        
-                                        // #region agent log
-                                        {
-                                            use std::fs::OpenOptions;
-                                            use std::io::Write;
-                                            let path = "/Users/shinta/git/github.com/geohot/qira/.cursor/debug.log";
-                                            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
-                                                let _ = writeln!(file, "{{\"id\":\"log_cfg_gen\",\"timestamp\":{},\"location\":\"server/main.rs:GetCFG\",\"message\":\"Generated CFG\",\"data\":{{\"size\":{}, \"head\":\"{}\"}},\"sessionId\":\"debug-session\",\"runId\":\"debug-run\",\"hypothesisId\":\"mermaid-syntax\"}}", 
-                                                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
-                                                    mermaid.len(),
-                                                    mermaid.chars().take(500).collect::<String>().replace("\"", "'").replace("\n", "\\n")
-                                                );
+                                    ClientMessage::ClearBreakpoint { address, is_static, condition } => {
+                                        let breakpoints = {
+                                            let mut bps = state.breakpoints.set.write().unwrap();
+                                            match address {
+                                                Some(address) => {
+                                                    bps.remove(&Breakpoint { address, is_static, condition });
+                                                }
+                                                None => bps.clear(),
                                             }
+                                            bps.iter().copied().collect()
+                                        };
+                                        let response = ServerMessage::Breakpoints { breakpoints };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
                                         }
-                                        // #endregion
-
-                                        let response = ServerMessage::CFG { graph: mermaid };
+                                    }
+                                    ClientMessage::GetBreakpoints => {
+                                        let breakpoints = state.breakpoints.set.read().unwrap().iter().copied().collect();
+                                        let response = ServerMessage::Breakpoints { breakpoints };
                                         if let Ok(json) = serde_json::to_string(&response) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
-                                    ClientMessage::AskAI { clnum } => {
-                                        // Build context
-                                        let disasm = db.get_disassembly_at(clnum);
-                                        let regs = db.get_registers_at(clnum);
-                                        // Simple register dump
-                                        let regs_str = regs.iter().enumerate().map(|(i, v)| format!("R{}: {:x}", i, v)).collect::<Vec<_>>().join(", ");
-                                        
-                                        // Get surrounding code (5 before, 5 after)
-                                        // We need addresses... just get 10 disassembly lines
-                                        // This is a bit inefficient without `get_trace_log` helper but acceptable
-                                        let log = db.get_trace_log(clnum.saturating_sub(5), 10, true);
-                                        let code_context = log.iter().map(|e| format!("{:x}: {}", e.address, e.disassembly)).collect::<Vec<_>>().join("\n");
+                                    ClientMessage::NameRegion { address, len, name } => {
+                                        if let Err(e) = db.name_region(address, len, &name) {
+                                            send_error(&mut socket, request_id, e).await;
+                                            continue;
+                                        }
+                                        broadcast_regions(&state);
+                                    }
+                                    ClientMessage::UnnameRegion { address } => {
+                                        db.unname_region(address);
+                                        broadcast_regions(&state);
+                                    }
+                                    ClientMessage::GetRegions => {
+                                        let response = ServerMessage::Regions { regions: db.regions() };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::Configure { config } => {
+                                        if let Err(e) = db.configure(config.clone()) {
+                                            send_error(&mut socket, request_id, e).await;
+                                            continue;
+                                        }
+                                        if let Some(db_b) = &state.db_b {
+                                            // Loaded with the same images as trace A, so this can't fail
+                                            let _ = db_b.configure(config);
+                                        }
+                                        // Shared by every client, so all of them get the new settings
+                                        if let Ok(json) = serde_json::to_string(&config_message(&db)) {
+                                            let _ = state.tx.send(json);
+                                        }
+                                    }
+                                    ClientMessage::GetConfig => {
+                                        if let Ok(json) = serde_json::to_string(&config_message(&db)) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::Disassemble { address, bytes_hex, options } => {
+                                        let bytes = match parse_hex_bytes(&bytes_hex) {
+                                            Ok(bytes) => bytes,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let lines = match db.disassemble_detached(address, &bytes, options.syntax, (&options).into()) {
+                                            Ok(lines) => lines,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let response = ServerMessage::Disassembly { address, lines };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetCFG { only_user_code, start_from_main, collapse_stubs, format }
+                                    | ClientMessage::DiffCFG { only_user_code, start_from_main, collapse_stubs, format } => {
+                                        let cfg = if matches!(client_msg, ClientMessage::DiffCFG { .. }) {
+                                            match &state.db_b {
+                                                Some(db_b) => db.diff_cfg(db_b, only_user_code, start_from_main, collapse_stubs),
+                                                None => Err(QueryError::NoSecondTrace),
+                                            }
+                                        } else {
+                                            db.analyze_cfg(only_user_code, start_from_main, collapse_stubs)
+                                        };
+                                        let cfg = match cfg {
+                                            Ok(cfg) => cfg,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let rendered = match format {
+                                            CfgFormat::Svg => Some(render::dot_to_svg(&cfg.to_dot()).await),
+                                            CfgFormat::Mermaid => None,
+                                        };
+                                        let (graph, format, warning) = match rendered {
+                                            Some(Ok(svg)) => (svg, CfgFormat::Svg, None),
+                                            Some(Err(e)) => {
+                                                // The client can still draw the Mermaid graph itself
+                                                tracing::warn!(error = %e, "CFG SVG rendering failed, sending Mermaid");
+                                                let warning = format!("SVG rendering failed ({}), showing the Mermaid graph", e);
+                                                (cfg.to_mermaid(), CfgFormat::Mermaid, Some(warning))
+                                            }
+                                            None => (cfg.to_mermaid(), CfgFormat::Mermaid, None),
+                                        };
+                                        tracing::debug!(bytes = graph.len(), ?format, "generated CFG");
+
+                                        if let Some(response) = cfg_reply(&mut pending_chunks, graph, format, warning) {
+                                            send_large(&mut socket, binary_frames, &response).await;
+                                        }
+                                    }
+                                    ClientMessage::AskAI { clnum, context_lines } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        }
+                                        // The context is cut to a fixed budget anyway; don't render far more than fits.
+                                        // Built on a blocking thread since it disassembles the backtrace.
+                                        let context_db = db.clone();
+                                        let context_str = match tokio::task::spawn_blocking(move || {
+                                            context_db.ai_context(clnum, context_lines.min(200))
+                                        })
+                                        .await
+                                        {
+                                            Ok(context) => context,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, format!("building the AI context failed: {}", e)).await;
+                                                continue;
+                                            }
+                                        };
 
-                                        let context_str = format!("Instruction: {}\nRegisters: {}\n\nSurrounding Code:\n{}", disasm, regs_str, code_context);
-                                        
                                         // Call AI (in background task to avoid blocking)
                                         // Ideally we should use a separate tokio task
                                         // For now, simple spawn
@@ -354,7 +1401,7 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
                                         // Send "Thinking..." message?
                                         let _ = socket.send(Message::Text(serde_json::to_string(&ServerMessage::AIResponse { text: "Thinking...".to_string() }).unwrap())).await;
 
-                                        match ai::ask_ai(context_str).await {
+                                        match ai::ask_ai(context_str, None).await {
                                             Ok(ans) => {
                                                 let _ = socket.send(Message::Text(serde_json::to_string(&ServerMessage::AIResponse { text: ans }).unwrap())).await;
                                             }
@@ -370,43 +1417,466 @@ async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
-                                    ClientMessage::GetSlice { clnum, target } => {
-                                        let clnums = db.get_slice(clnum, target);
-                                        let mut entries = Vec::new();
-                                        for c in clnums {
-                                            // Inefficient but works for now
-                                            // get_trace_log returns Vec<TraceEntry>
-                                            if let Some(e) = db.get_trace_log(c, 1, false).first() {
-                                                entries.push(e.clone());
-                                            }
+                                    ClientMessage::GetMemoryReads { address } => {
+                                        let reads = db.get_memory_reads(address);
+                                        let response = ServerMessage::MemoryReads { address, reads };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
                                         }
+                                    }
+                                    ClientMessage::GetValueHistory { address, width } => {
+                                        let points = db.value_history(address, width as usize);
+                                        let response = ServerMessage::ValueHistory { address, width, points };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GetSlice { clnum, target, targets } => {
+                                        let target = std::iter::once(target)
+                                            .chain(targets)
+                                            .filter(|t| !t.is_empty())
+                                            .collect::<Vec<_>>()
+                                            .join(",");
+                                        let entries = match slice_entries(&db, clnum, target) {
+                                            Ok(entries) => entries,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
                                         let response = ServerMessage::Slice { entries };
                                         if let Ok(json) = serde_json::to_string(&response) {
                                             let _ = socket.send(Message::Text(json)).await;
                                         }
                                     }
+                                    ClientMessage::GetDataFlow { clnum, target } => {
+                                        let graph = match db.data_flow_graph(clnum, target.clone()) {
+                                            Ok(graph) => graph.to_mermaid(),
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let response = ServerMessage::DataFlow { clnum, target, graph };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::TaintFromSyscall { clnum } => {
+                                        let taint = match db.taint_from_syscall(clnum) {
+                                            Ok(taint) => taint,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let response = ServerMessage::SyscallTaint {
+                                            clnum,
+                                            syscall: syscall::name(taint.nr).map_or_else(|| taint.nr.to_string(), str::to_string),
+                                            result: taint.result,
+                                            buffer: taint.buffer,
+                                            entries: db.trace_entries(&taint.slice),
+                                        };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GetRawChanges { address, range } => {
+                                        if !state.debug {
+                                            send_error(&mut socket, request_id, "raw change queries require the server's --debug flag").await;
+                                            continue;
+                                        }
+                                        let mut changes = match range {
+                                            Some((start, end)) => db.changes_in_clnum_range(start, end),
+                                            None => match address {
+                                                Some(address) => db.changes_for_address(address),
+                                                None => Vec::new(),
+                                            },
+                                        };
+                                        if let (Some(address), Some(_)) = (address, range) {
+                                            changes.retain(|ch| ch.address == address);
+                                        }
+                                        let response = ServerMessage::RawChanges {
+                                            changes: changes.into_iter().map(Into::into).collect(),
+                                        };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::DiffSlices { clnum_a, clnum_b, target } => {
+                                        let db_b = match &state.db_b {
+                                            Some(db_b) => db_b,
+                                            None => {
+                                                send_error(&mut socket, request_id, QueryError::NoSecondTrace).await;
+                                                continue;
+                                            }
+                                        };
+                                        let slices = slice_entries(&db, clnum_a, target.clone())
+                                            .and_then(|a| Ok((a, slice_entries(db_b, clnum_b, target)?)));
+                                        let (a, b) = match slices {
+                                            Ok(slices) => slices,
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, e).await;
+                                                continue;
+                                            }
+                                        };
+                                        let response = ServerMessage::SliceDiff { lines: diff_slices(&a, &b) };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetInsnDetail { clnum } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        }
+                                        let detail = db.get_insn_detail(clnum);
+                                        let response = ServerMessage::InsnDetail { clnum, detail };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetLookahead { clnum, count } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        }
+                                        // Start at the current instruction so its length is known, then drop it
+                                        let lines = db
+                                            .pc_at(clnum)
+                                            .map(|pc| db.disassemble_range(clnum, pc, count.min(64) + 1).into_iter().skip(1).collect())
+                                            .unwrap_or_default();
+                                        let response = ServerMessage::Lookahead { clnum, lines };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetCoverage => {
+                                        let response = ServerMessage::Coverage { functions: db.coverage() };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetSymbols => {
+                                        let response = ServerMessage::Symbols { symbols: db.execution_counts() };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::ImportSymbols { path } => {
+                                        match db.import_symbols_from_map(Path::new(&path)) {
+                                            Ok(count) => tracing::info!("imported {} symbols from {}", count, path),
+                                            Err(e) => {
+                                                send_error(&mut socket, request_id, format!("{:#}", e)).await;
+                                                continue;
+                                            }
+                                        }
+                                        // Trace B runs the same binary
+                                        if let Some(db_b) = &state.db_b {
+                                            let _ = db_b.import_symbols_from_map(Path::new(&path));
+                                        }
+                                        let response = ServerMessage::Symbols { symbols: db.execution_counts() };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GetFunctionListing { name } => {
+                                        if db.find_symbol_by_name(&name).is_none() {
+                                            send_error(&mut socket, request_id, QueryError::SymbolNotFound(name)).await;
+                                            continue;
+                                        }
+                                        let listing = db.function_listing(&name);
+                                        let first = match (listing.first(), listing.last()) {
+                                            (Some(&(start, _)), Some(&(last, _))) => db.first_executions(start..last + 1),
+                                            _ => Default::default(),
+                                        };
+                                        let lines = listing
+                                            .into_iter()
+                                            .map(|(address, text)| (address, text, first.get(&address).copied()))
+                                            .collect();
+                                        let response = ServerMessage::FunctionListing { name, lines };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GetLastWriter { clnum, address } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        }
+                                        let origin = db.write_origin(clnum, address);
+                                        let response = ServerMessage::LastWriter { clnum, address, origin };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetRegisterProvenance { clnum, reg } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        if clnum > max {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        }
+                                        let writer = db.register_writer(clnum, reg);
+                                        let response = ServerMessage::RegisterProvenance { clnum, reg, writer };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetRegisterTimeline { reg, buckets } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        let marks = db.register_timeline(reg, 0..=max, buckets.min(MAX_TIMELINE_BUCKETS));
+                                        let response = ServerMessage::RegisterTimeline { reg, marks };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::GetRegisterChange { reg, clnum, forward } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        let clnum = db.register_change_near(reg, clnum, forward).filter(|&c| c <= max);
+                                        let response = ServerMessage::RegisterChange { reg, clnum };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
+                                    ClientMessage::GetBlockIterations { clnum, mut regs } => {
+                                        let max = max_clnum.load(Ordering::Relaxed);
+                                        let Some(address) = db.pc_at(clnum).filter(|_| clnum <= max) else {
+                                            send_error(&mut socket, request_id, QueryError::ClnumOutOfRange { clnum, max }).await;
+                                            continue;
+                                        };
+                                        regs.retain(|&r| r < REG_COUNT);
+                                        let rows = db.block_iteration_states(address, &regs);
+                                        let response = ServerMessage::BlockIterations { address, regs, rows };
+                                        send_large(&mut socket, binary_frames, &response).await;
+                                    }
+                                    ClientMessage::ExportRegion { mut clnums, range } => {
+                                        if let Some((start, end)) = range {
+                                            clnums.extend(start..=end.min(max_clnum.load(Ordering::Relaxed)));
+                                        }
+                                        let records = db.export_region(&clnums);
+                                        let response = ServerMessage::Export { records };
+                                        if let Ok(json) = serde_json::to_string(&response) {
+                                            let _ = socket.send(Message::Text(json)).await;
+                                        }
+                                    }
                                 }
                             }
                             Err(e) => {
-                                eprintln!("[ERROR] Failed to parse ClientMessage: {} | Text: {}", e, text);
+                                tracing::warn!("failed to parse ClientMessage: {} | text: {}", e, text);
+                                send_error(&mut socket, None, format!("Malformed request: {}", e)).await;
                             }
                         }
                     }
                     Some(Ok(_)) => {} // Ignore other message types
                     Some(Err(e)) => {
-                        eprintln!("WebSocket receive error: {}", e);
+                        tracing::warn!("WebSocket receive error: {}", e);
                         break;
                     }
                     None => break,
                 }
             }
             msg = rx.recv() => {
-                if let Ok(msg) = msg {
-                    if socket.send(Message::Text(msg)).await.is_err() {
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("WebSocket client lagged, dropped {} messages", missed);
+                        match serde_json::to_string(&ServerMessage::Lagged { missed }) {
+                            Ok(json) => json,
+                            Err(_) => continue,
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(msg)).await.is_err() {
+                    break;
+                }
+            }
+            _ = shutdown.changed() => {
+                let close = CloseFrame { code: close_code::AWAY, reason: "server shutting down".into() };
+                let _ = socket.send(Message::Close(Some(close))).await;
+                break;
+            }
+            _ = std::future::ready(()), if !pending_chunks.is_empty() => {
+                if let Some(chunk) = pending_chunks.pop_front() {
+                    send_large(&mut socket, binary_frames, &chunk).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use koradar_core::testing::{counted_loop, TraceBuilder};
+    use tokio_tungstenite::{tungstenite::Message as Frame, MaybeTlsStream, WebSocketStream};
+
+    type Client = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+    fn state_for(db: TraceDB) -> Arc<AppState> {
+        let db = Arc::new(db);
+        Arc::new(AppState {
+            backend: db.clone(),
+            sqlite_only: false,
+            max_clnum: Arc::new(AtomicU32::new(db.max_clnum())),
+            db,
+            db_b: None,
+            tx: broadcast::channel(16).0,
+            breakpoints: Arc::new(LiveBreakpoints::default()),
+            debug: false,
+            labels: None,
+            shutdown: watch::channel(false).0,
+        })
+    }
+
+    // Serves `state` on a free local port and connects to /ws, past the
+    // MaxClnum a new client is greeted with
+    async fn connect(state: Arc<AppState>) -> Client {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, routes(state)).await });
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        assert!(matches!(decode(&next_frame(&mut client).await), ServerMessage::MaxClnum { .. }));
+        client
+    }
+
+    async fn send(client: &mut Client, message: ClientMessage) {
+        let request = ClientRequest { request_id: None, message };
+        client.send(Frame::Text(serde_json::to_string(&request).unwrap())).await.unwrap();
+    }
+
+    async fn next_frame(client: &mut Client) -> Frame {
+        tokio::time::timeout(Duration::from_secs(10), client.next())
+            .await
+            .expect("no reply from the server")
+            .expect("connection closed")
+            .unwrap()
+    }
+
+    fn decode(frame: &Frame) -> ServerMessage {
+        match frame {
+            Frame::Text(json) => serde_json::from_str(json).unwrap(),
+            Frame::Binary(bytes) => rmp_serde::from_slice(bytes).unwrap(),
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    fn cfg_request(only_user_code: bool) -> ClientMessage {
+        ClientMessage::GetCFG { only_user_code, start_from_main: false, collapse_stubs: false, format: CfgFormat::Mermaid }
+    }
+
+    // `blocks` basic blocks from 0x401000 on, 0x40 bytes apart, of five
+    // `mov rax, 0x1122334455667788` and a jmp to the next one: about 300
+    // bytes of Mermaid each
+    fn straight_blocks(blocks: u64) -> TraceBuilder {
+        let mut trace = TraceBuilder::new();
+        for block in 0..blocks {
+            let start = 0x401000 + block * 0x40;
+            for i in 0..5 {
+                trace.insn(start + i * 10, &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]);
+            }
+            trace.insn(start + 50, &[0xe9, 0x09, 0x00, 0x00, 0x00]);
+        }
+        trace
+    }
+
+    #[test]
+    fn a_small_cfg_drops_the_rest_of_a_chunked_one() {
+        let mut pending = VecDeque::new();
+        let large = "x".repeat(2 * CFG_CHUNK_BYTES + 1);
+        assert!(cfg_reply(&mut pending, large, CfgFormat::Mermaid, None).is_none());
+        assert_eq!(pending.len(), 3);
+
+        let small = cfg_reply(&mut pending, "graph TD;\n".to_string(), CfgFormat::Mermaid, None);
+        assert!(matches!(small, Some(ServerMessage::CFG { .. })));
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ticker_coalesces_max_clnum_and_breakpoint_hits() {
+        let db = TraceDB::new(REG_COUNT);
+        let (tx, _rx) = broadcast::channel(1024);
+        let max_clnum = AtomicU32::new(0);
+        let breakpoints = LiveBreakpoints::default();
+        // The loop's dec runs three times
+        breakpoints.set.write().unwrap().insert(Breakpoint { address: 0x401005, is_static: false, condition: None });
+        let ndjson = counted_loop(3).to_ndjson();
+        let last = ingest_stream(ndjson.as_bytes(), None, &tx, &db, &max_clnum, &breakpoints).await;
+
+        let mut ticker = Ticker::new();
+        match ticker.tick(max_clnum.load(Ordering::Relaxed), &breakpoints).as_slice() {
+            [ServerMessage::MaxClnum { max }, ServerMessage::BreakpointHit { clnum, address }] => {
+                assert_eq!(*max, last);
+                assert_eq!((*clnum, *address), (2, 0x401005));
+            }
+            other => panic!("expected MaxClnum and the first hit, got {:?}", other),
+        }
+        // Nothing new to report
+        assert!(ticker.tick(last, &breakpoints).is_empty());
+    }
+
+    #[tokio::test]
+    async fn large_cfgs_go_out_in_chunks() {
+        let state = state_for(straight_blocks(400).build());
+        let expected = state.db.analyze_cfg(false, false, false).unwrap().to_mermaid();
+        assert!(expected.len() > 2 * CFG_CHUNK_BYTES);
+        let mut client = connect(state).await;
+
+        send(&mut client, cfg_request(false)).await;
+        let mut graph = String::new();
+        loop {
+            match decode(&next_frame(&mut client).await) {
+                ServerMessage::CFGChunk { seq, total, data, .. } => {
+                    assert!(data.len() <= CFG_CHUNK_BYTES);
+                    graph.push_str(&data);
+                    if seq + 1 == total {
                         break;
                     }
                 }
+                other => panic!("expected a CFGChunk, got {:?}", other),
             }
         }
+        assert_eq!(graph, expected);
+    }
+
+    #[tokio::test]
+    async fn no_chunks_follow_a_small_cfg_sent_during_a_large_one() {
+        let db = straight_blocks(400).build();
+        // Only the first block is user code, so its graph is one message
+        db.set_user_ranges(vec![(0x401000, 0x401040)]);
+        let mut client = connect(state_for(db)).await;
+
+        send(&mut client, cfg_request(false)).await;
+        send(&mut client, cfg_request(true)).await;
+        // Chunks sent before the second request was read are fine
+        loop {
+            match decode(&next_frame(&mut client).await) {
+                ServerMessage::CFGChunk { .. } => {}
+                ServerMessage::CFG { graph, .. } => {
+                    assert!(graph.len() <= CFG_CHUNK_BYTES);
+                    break;
+                }
+                other => panic!("expected a CFG, got {:?}", other),
+            }
+        }
+        send(&mut client, ClientMessage::GetServerInfo).await;
+        let reply = decode(&next_frame(&mut client).await);
+        assert!(matches!(reply, ServerMessage::ServerInfo { .. }), "got {:?} after the small CFG", reply);
+    }
+
+    #[tokio::test]
+    async fn replies_come_as_binary_frames_once_negotiated() {
+        let state = state_for(counted_loop(3).build());
+        let registers = state.backend.get_registers_at(2);
+        let mut client = connect(state).await;
+
+        send(&mut client, ClientMessage::QueryState { clnum: 2, memory_addr: None }).await;
+        assert!(matches!(next_frame(&mut client).await, Frame::Text(_)));
+
+        send(&mut client, ClientMessage::Hello { binary_frames: true }).await;
+        send(&mut client, ClientMessage::QueryState { clnum: 2, memory_addr: None }).await;
+        let frame = next_frame(&mut client).await;
+        assert!(matches!(frame, Frame::Binary(_)));
+        match decode(&frame) {
+            ServerMessage::StateUpdate { clnum, registers: received, .. } => {
+                assert_eq!(clnum, 2);
+                assert_eq!(received, registers);
+            }
+            other => panic!("expected a StateUpdate, got {:?}", other),
+        }
+
+        // Small replies stay JSON
+        send(&mut client, ClientMessage::GetServerInfo).await;
+        assert!(matches!(next_frame(&mut client).await, Frame::Text(_)));
     }
 }
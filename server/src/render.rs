@@ -0,0 +1,45 @@
+use std::env;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+// Large graphs take Graphviz a while, but a stuck `dot` must not hold the socket
+const DOT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Renders Graphviz DOT source to SVG with the `dot` binary (KORADAR_DOT
+/// overrides its path).
+pub async fn dot_to_svg(dot: &str) -> Result<String, String> {
+    let program = env::var("KORADAR_DOT").unwrap_or_else(|_| "dot".to_string());
+    let mut child = Command::new(&program)
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("Graphviz '{}' is not installed", program),
+            _ => format!("failed to run '{}': {}", program, e),
+        })?;
+
+    let mut stdin = child.stdin.take().ok_or("no stdin for dot")?;
+    let input = dot.to_string();
+    // Feed stdin concurrently with reading stdout, or a large graph deadlocks
+    // on full pipes
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(input.as_bytes()).await;
+    });
+
+    let output = tokio::time::timeout(DOT_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("'{}' timed out after {}s", program, DOT_TIMEOUT.as_secs()))?
+        .map_err(|e| format!("failed to run '{}': {}", program, e))?;
+    let _ = writer.await;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("'{}' failed: {}", program, stderr.trim()));
+    }
+    String::from_utf8(output.stdout).map_err(|e| format!("'{}' produced invalid UTF-8: {}", program, e))
+}
@@ -1,11 +1,14 @@
 use lazy_static::lazy_static;
+use koradar_protocol::TraceEvent;
 use qemu_plugin_sys::*;
-use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
-use std::net::TcpStream;
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::os::raw::{c_char, c_int, c_uint, c_void};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 // Wrapper for pointers to make them Send+Sync
 struct SyncPtr<T>(*mut T);
@@ -47,67 +50,216 @@ struct qemu_plugin_reg_descriptor_local {
     feature: *const c_char,
 }
 
-#[derive(Serialize)]
-enum TraceEvent {
-    Init {
-        vcpu_index: u32,
-    },
-    InsnExec {
-        vcpu_index: u32,
-        pc: u64,
-        bytes: Vec<u8>,
-        disasm: Option<String>,
-        regs: Vec<u64>, // Add registers
-    },
-    MemAccess {
-        vcpu_index: u32,
-        vaddr: u64,
-        is_store: bool,
-        value: u64, // Not used yet, placeholder
-    },
-    Exit {
-        vcpu_index: u32,
-    },
-}
-
 struct TracerState {
     insn_count: u64,
-    stream: Option<TcpStream>,
+    // Stop emitting events after this many instructions (plugin arg: max_insns=N)
+    max_insns: Option<u64>,
+    // Set once max_insns is hit; all later callbacks are no-ops
+    capped: bool,
+    stream: Option<Box<dyn Write + Send>>,
+    // Unix domain socket path (plugin arg: socket=/path); TCP is used when unset
+    socket_path: Option<String>,
+    // Only record which blocks executed, sent at exit (plugin arg: mode=coverage)
+    coverage_only: bool,
+    // Failed connection attempts so far, when the first one was made and
+    // when the backoff allows the next one
+    connect_attempts: u32,
+    first_connect: Option<Instant>,
+    next_connect: Option<Instant>,
+    // Give up connecting after this many attempts (plugin arg:
+    // connect_attempts=N, 0 for no limit) or this long (connect_timeout=SECS)
+    max_connect_attempts: u32,
+    connect_give_up: Duration,
+    // Set once the server couldn't be reached in time; like `capped`, all
+    // later callbacks are no-ops and QEMU runs untraced
+    gave_up: bool,
+    // User-mode emulation of a single program, whose end is reported
+    user_mode: bool,
+    // Status the program passed to exit/exit_group, once it did
+    exit_code: Option<i64>,
+    // Last signal the program sent itself (abort() raises SIGABRT)
+    raised_signal: Option<i32>,
+}
+
+impl TracerState {
+    // Nothing more will be traced
+    fn stopped(&self) -> bool {
+        self.capped || self.gave_up
+    }
+}
+
+// Where control can go after an instruction, from QEMU's disassembly of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Flow {
+    // Only to the next instruction
+    Next,
+    // To the next instruction or a direct branch/call target
+    Branch(u64),
+    // Anywhere: returns, indirect branches, or no disassembly to tell
+    Any,
+}
+
+impl Flow {
+    fn classify(disas: Option<&str>) -> Flow {
+        let Some(disas) = disas else { return Flow::Any };
+        let mut tokens = disas
+            .split_whitespace()
+            .skip_while(|t| matches!(*t, "rep" | "repe" | "repz" | "repne" | "repnz" | "lock" | "bnd" | "notrack"));
+        let Some(mnemonic) = tokens.next() else { return Flow::Any };
+        let direct = mnemonic.starts_with('j') || mnemonic.starts_with("call") || mnemonic.starts_with("loop") || mnemonic == "xbegin";
+        if direct {
+            return tokens
+                .next()
+                .and_then(|target| target.strip_prefix("0x"))
+                .and_then(|target| u64::from_str_radix(target, 16).ok())
+                .map_or(Flow::Any, Flow::Branch);
+        }
+        if mnemonic.starts_with("ret") || mnemonic.starts_with("iret") || mnemonic.starts_with("sysret") || matches!(mnemonic, "sysexit" | "ud2" | "hlt") {
+            return Flow::Any;
+        }
+        Flow::Next
+    }
+
+    // Whether `next` can follow the instruction at `pc` ending at `fallthrough`
+    // without an asynchronous event. Repeated string instructions execute
+    // again at the same pc.
+    fn allows(self, pc: u64, fallthrough: u64, next: u64) -> bool {
+        next == fallthrough
+            || next == pc
+            || match self {
+                Flow::Next => false,
+                Flow::Branch(target) => next == target,
+                Flow::Any => true,
+            }
+    }
 }
 
+// Backoff between connection attempts: doubles from the initial delay up to the max
+const CONNECT_BACKOFF_INITIAL: Duration = Duration::from_millis(100);
+const CONNECT_BACKOFF_MAX: Duration = Duration::from_secs(5);
+// Per-address TCP connect timeout, so an unreachable host can't block the guest long
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
 lazy_static! {
     static ref STATE: Mutex<TracerState> = Mutex::new(TracerState {
         insn_count: 0,
+        max_insns: None,
+        capped: false,
         stream: None,
+        socket_path: None,
+        coverage_only: false,
+        connect_attempts: 0,
+        first_connect: None,
+        next_connect: None,
+        max_connect_attempts: 10,
+        connect_give_up: Duration::from_secs(30),
+        gave_up: false,
+        user_mode: false,
+        exit_code: None,
+        raised_signal: None,
     });
 
-    // Cache for instruction bytes: PC -> Bytes
-    static ref INSN_CACHE: Mutex<HashMap<u64, Vec<u8>>> = Mutex::new(HashMap::new());
+    // Coverage mode: pcs of the translation blocks that executed
+    static ref BLOCKS: Mutex<HashSet<u64>> = Mutex::new(HashSet::new());
+
+    // Cache for instruction bytes: PC -> (Bytes, whether QEMU provided them)
+    static ref INSN_CACHE: Mutex<HashMap<u64, (Vec<u8>, bool)>> = Mutex::new(HashMap::new());
     // Cache for disassembly: PC -> String
     static ref DISASM_CACHE: Mutex<HashMap<u64, String>> = Mutex::new(HashMap::new());
     
+    // PC -> (address after the instruction, where control can go next)
+    static ref FLOW_CACHE: Mutex<HashMap<u64, (u64, Flow)>> = Mutex::new(HashMap::new());
+
+    // Last traced pc per vcpu
+    static ref LAST_PC: Mutex<HashMap<u32, u64>> = Mutex::new(HashMap::new());
+
     // Register Cache
     static ref REGS: Mutex<Vec<SyncPtr<c_void>>> = Mutex::new(Vec::new());
 }
 
+// Records `pc` as the latest instruction of `vcpu_index`, returning whether it
+// can't have followed the previous one (signal delivery or return, interrupt)
+fn discontinuous(vcpu_index: u32, pc: u64) -> bool {
+    let Some(prev) = LAST_PC.lock().ok().and_then(|mut last| last.insert(vcpu_index, pc)) else {
+        return false;
+    };
+    FLOW_CACHE
+        .lock()
+        .ok()
+        .and_then(|flows| flows.get(&prev).copied())
+        .is_some_and(|(fallthrough, flow)| !flow.allows(prev, fallthrough, pc))
+}
+
 // --- Helper to send events ---
+fn connect(socket_path: Option<&str>) -> Option<Box<dyn Write + Send>> {
+    #[cfg(unix)]
+    if let Some(path) = socket_path {
+        return match UnixStream::connect(path) {
+            Ok(stream) => {
+                tracing::info!("connected to server at unix:{}", path);
+                Some(Box::new(stream))
+            }
+            Err(_) => None,
+        };
+    }
+    #[cfg(not(unix))]
+    let _ = socket_path;
+
+    // Use host.docker.internal for macOS Docker, or localhost for native
+    // (e.g. Linux) as the fallback
+    for addr in ["host.docker.internal:3001", "127.0.0.1:3001"] {
+        if let Some(stream) = connect_tcp(addr) {
+            tracing::info!("connected to server at {}", addr);
+            return Some(Box::new(stream));
+        }
+    }
+    None
+}
+
+fn connect_tcp(addr: &str) -> Option<TcpStream> {
+    addr.to_socket_addrs()
+        .ok()?
+        .find_map(|sock_addr| TcpStream::connect_timeout(&sock_addr, TCP_CONNECT_TIMEOUT).ok())
+}
+
+// Connects unless still backing off from a failed attempt. Once the attempt
+// or time budget is spent, gives up for good so QEMU runs untraced rather
+// than stalling on connects.
+fn try_connect(state: &mut TracerState) -> bool {
+    if state.gave_up || state.next_connect.is_some_and(|at| Instant::now() < at) {
+        return false;
+    }
+    let first = *state.first_connect.get_or_insert_with(Instant::now);
+    if let Some(stream) = connect(state.socket_path.as_deref()) {
+        state.stream = Some(stream);
+        return true;
+    }
+
+    state.connect_attempts += 1;
+    let elapsed = first.elapsed();
+    let out_of_attempts = state.max_connect_attempts != 0 && state.connect_attempts >= state.max_connect_attempts;
+    if out_of_attempts || elapsed >= state.connect_give_up {
+        state.gave_up = true;
+        tracing::warn!(
+            "server unreachable after {} attempts in {:.1}s, running without tracing",
+            state.connect_attempts,
+            elapsed.as_secs_f64()
+        );
+    } else {
+        let backoff = CONNECT_BACKOFF_INITIAL
+            .saturating_mul(1 << (state.connect_attempts - 1).min(16))
+            .min(CONNECT_BACKOFF_MAX);
+        tracing::debug!("connection attempt {} failed, retrying in {:?}", state.connect_attempts, backoff);
+        state.next_connect = Some(Instant::now() + backoff);
+    }
+    false
+}
+
 fn send_event(event: TraceEvent) {
     let mut state = STATE.lock().unwrap();
-    if state.stream.is_none() {
-        // Try to connect on first send
-        // Use host.docker.internal for macOS Docker, or localhost for native
-        let addr = "host.docker.internal:3001";
-        if let Ok(stream) = TcpStream::connect(addr) {
-            state.stream = Some(stream);
-            println!("Koradar Tracer: Connected to server at {}", addr);
-        } else if let Ok(stream) = TcpStream::connect("127.0.0.1:3001") {
-            // Fallback to localhost (e.g. Linux native)
-            state.stream = Some(stream);
-            println!("Koradar Tracer: Connected to server at 127.0.0.1:3001");
-        } else {
-            // Failed to connect
-            return;
-        }
+    // Events sent before the connection is up are dropped
+    if state.stream.is_none() && !try_connect(&mut state) {
+        return;
     }
 
     if let Some(stream) = &mut state.stream {
@@ -121,7 +273,7 @@ fn send_event(event: TraceEvent) {
 // --- Callbacks ---
 
 extern "C" fn vcpu_init(_id: qemu_plugin_id_t, vcpu_index: u32) {
-    println!("Koradar Tracer: vCPU {} initialized", vcpu_index);
+    tracing::debug!("vCPU {} initialized", vcpu_index);
     
     // Initialize registers if not done
     let mut regs = REGS.lock().unwrap();
@@ -134,31 +286,32 @@ extern "C" fn vcpu_init(_id: qemu_plugin_id_t, vcpu_index: u32) {
                 let count = reg_array.len as usize;
                 let data_ptr = reg_array.data as *mut qemu_plugin_reg_descriptor_local;
                 
-                println!("Koradar Tracer: Found {} registers", count);
+                tracing::debug!("found {} registers", count);
                 
                 // Map of name -> handle
                 let mut reg_map = HashMap::new();
 
-                println!("Koradar Tracer: Scanning registers...");
                 for i in 0..count {
                     let desc = &*data_ptr.add(i);
                     let name_c = std::ffi::CStr::from_ptr(desc.name);
                     let name = name_c.to_string_lossy().into_owned();
-                    println!("Koradar Tracer: Reg[{}] = {}", i, name); // DEBUG PRINT
+                    tracing::trace!("reg[{}] = {}", i, name);
                     reg_map.insert(name.to_lowercase(), desc.handle);
                 }
 
-                // Target order: RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, R8-R15
+                // Target order: RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, R8-R15,
+                // then the FS/GS segment bases used for TLS accesses
                 let target_regs = [
                     "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp",
-                    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"
+                    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+                    "fs_base", "gs_base"
                 ];
 
                 for &target in target_regs.iter() {
                     if let Some(&handle) = reg_map.get(target) {
                         regs.push(SyncPtr(handle));
                     } else {
-                        println!("Koradar Tracer: Warning - Register {} not found", target);
+                        tracing::warn!("register {} not found", target);
                         // Push null pointer as placeholder? 
                         // Or handle it in read loop.
                         // Let's push null and check for it.
@@ -173,20 +326,102 @@ extern "C" fn vcpu_init(_id: qemu_plugin_id_t, vcpu_index: u32) {
 }
 
 extern "C" fn vcpu_exit(_id: qemu_plugin_id_t, vcpu_index: u32) {
-    println!("Koradar Tracer: vCPU {} exited", vcpu_index);
+    tracing::debug!("vCPU {} exited", vcpu_index);
     send_event(TraceEvent::Exit { vcpu_index });
 }
 
+// x86_64 syscall numbers of the ways a program ends itself
+const SYS_KILL: i64 = 62;
+const SYS_EXIT: i64 = 60;
+const SYS_TKILL: i64 = 200;
+const SYS_EXIT_GROUP: i64 = 231;
+const SYS_TGKILL: i64 = 234;
+
+// In user mode the guest runs in QEMU's own process, so signals it sends to
+// itself target our pid
+#[allow(clippy::too_many_arguments)]
+extern "C" fn vcpu_syscall(
+    _id: qemu_plugin_id_t,
+    _vcpu_index: c_uint,
+    num: i64,
+    a1: u64,
+    a2: u64,
+    a3: u64,
+    _a4: u64,
+    _a5: u64,
+    _a6: u64,
+    _a7: u64,
+    _a8: u64,
+) {
+    let own = |pid: u64| pid as u32 == std::process::id();
+    let signal = match num {
+        SYS_EXIT | SYS_EXIT_GROUP => {
+            STATE.lock().unwrap().exit_code = Some(a1 as i32 as i64);
+            return;
+        }
+        SYS_KILL | SYS_TKILL if own(a1) => a2,
+        SYS_TGKILL if own(a1) => a3,
+        _ => return,
+    };
+    if signal != 0 {
+        STATE.lock().unwrap().raised_signal = Some(signal as i32);
+    }
+}
+
 extern "C" fn plugin_exit(_id: qemu_plugin_id_t, _data: *mut c_void) {
-    let count = STATE.lock().unwrap().insn_count;
-    println!("Koradar Tracer: Exiting. Total instructions: {}", count);
+    let (count, coverage_only, ending) = {
+        let state = STATE.lock().unwrap();
+        // A capped trace doesn't reach the end, so its last instruction says nothing
+        let ending = (state.user_mode && !state.coverage_only && !state.stopped())
+            .then_some((state.exit_code, state.raised_signal));
+        (state.insn_count, state.coverage_only, ending)
+    };
+    if coverage_only {
+        let mut blocks: Vec<u64> = BLOCKS.lock().unwrap().iter().copied().collect();
+        blocks.sort_unstable();
+        tracing::info!("exiting, {} blocks executed", blocks.len());
+        send_event(TraceEvent::BlockCoverage { blocks });
+    } else {
+        tracing::info!("exiting, traced {} instructions", count);
+    }
+    if let Some((exit_code, signal)) = ending {
+        let signal = match exit_code {
+            Some(code) => {
+                tracing::info!("program exited with status {}", code);
+                None
+            }
+            None => {
+                tracing::warn!("program was killed (signal it raised: {:?})", signal);
+                signal
+            }
+        };
+        send_event(TraceEvent::ProcessEnd { exit_code, signal });
+    }
+}
+
+extern "C" fn vcpu_tb_exec(_vcpu_index: u32, userdata: *mut c_void) {
+    BLOCKS.lock().unwrap().insert(userdata as u64);
 }
 
 extern "C" fn vcpu_insn_exec(vcpu_index: u32, userdata: *mut c_void) {
     let mut state = STATE.lock().unwrap();
+    if state.stopped() {
+        return;
+    }
+    if let Some(max_insns) = state.max_insns.filter(|&max| state.insn_count >= max) {
+        state.capped = true;
+        drop(state);
+        tracing::warn!("reached max_insns={}, no longer tracing", max_insns);
+        send_event(TraceEvent::TraceLimitReached { max_insns });
+        return;
+    }
     state.insn_count += 1;
     let pc = userdata as u64;
     drop(state);
+    let discontinuous = discontinuous(vcpu_index, pc);
+    if discontinuous {
+        tracing::debug!("vcpu {} reached {:#x} asynchronously", vcpu_index, pc);
+    }
 
     // Capture registers (x86_64)
     let mut reg_values = Vec::new();
@@ -227,13 +462,13 @@ extern "C" fn vcpu_insn_exec(vcpu_index: u32, userdata: *mut c_void) {
     #[cfg(not(target_os = "linux"))]
     {
         // Dummy values for non-Linux builds
-        reg_values.resize(16, 0);
+        reg_values.resize(18, 0);
     }
     
     let regs = reg_values;
 
     // Retrieve bytes from cache
-    let (bytes, disasm) = if let Ok(cache) = INSN_CACHE.lock() {
+    let ((bytes, bytes_valid), disasm) = if let Ok(cache) = INSN_CACHE.lock() {
         let b = cache.get(&pc).cloned().unwrap_or_default();
         let d = if let Ok(d_cache) = DISASM_CACHE.lock() {
             d_cache.get(&pc).cloned()
@@ -242,66 +477,133 @@ extern "C" fn vcpu_insn_exec(vcpu_index: u32, userdata: *mut c_void) {
         };
         (b, d)
     } else {
-        (Vec::new(), None)
+        ((Vec::new(), false), None)
     };
 
     send_event(TraceEvent::InsnExec {
         vcpu_index,
         pc,
         bytes,
+        bytes_valid,
         disasm,
         regs,
+        discontinuous,
     });
 }
 
 extern "C" fn vcpu_mem_access(vcpu_index: u32, info: qemu_plugin_meminfo_t, vaddr: u64, _userdata: *mut c_void) {
+    if STATE.lock().unwrap().stopped() {
+        return;
+    }
     let is_store = unsafe { qemu_plugin_mem_is_store(info) };
-    
+    let size = 1u32 << unsafe { qemu_plugin_mem_size_shift(info) };
+
     send_event(TraceEvent::MemAccess {
         vcpu_index,
         vaddr,
         is_store,
-        value: 0, // Value capture requires more callbacks or register inspection
+        value: mem_value(info),
+        size,
     });
 }
 
+// The value loaded or stored, as QEMU reports it after the access. 128-bit
+// (vector) accesses don't fit an event's value and are sent without one.
+fn mem_value(info: qemu_plugin_meminfo_t) -> Option<u64> {
+    let value = unsafe { qemu_plugin_mem_get_value(info) };
+    unsafe {
+        match value.type_ {
+            qemu_plugin_mem_value_type::QEMU_PLUGIN_MEM_VALUE_U8 => Some(value.data.u8_ as u64),
+            qemu_plugin_mem_value_type::QEMU_PLUGIN_MEM_VALUE_U16 => Some(value.data.u16_ as u64),
+            qemu_plugin_mem_value_type::QEMU_PLUGIN_MEM_VALUE_U32 => Some(value.data.u32_ as u64),
+            qemu_plugin_mem_value_type::QEMU_PLUGIN_MEM_VALUE_U64 => Some(value.data.u64_),
+            _ => None,
+        }
+    }
+}
+
+// Guest page size of x86-64, the smallest an instruction's bytes can be split at
+const GUEST_PAGE_SIZE: u64 = 4096;
+
+// Copies the `size` bytes of the instruction at `vaddr`, and whether all of
+// them were available. The host address QEMU gives only covers the guest page
+// the instruction starts in: the next page may be mapped elsewhere on the
+// host, or not at all, so an instruction crossing into it is copied through
+// qemu_plugin_insn_data instead, which reads each page where it is.
+unsafe fn insn_bytes(insn: *mut qemu_plugin_insn, vaddr: u64, size: usize) -> (Vec<u8>, bool) {
+    let mut bytes = vec![0u8; size];
+    let on_first_page = GUEST_PAGE_SIZE - vaddr % GUEST_PAGE_SIZE;
+    if size as u64 <= on_first_page {
+        let haddr = qemu_plugin_insn_haddr(insn);
+        if !haddr.is_null() {
+            std::ptr::copy_nonoverlapping(haddr as *const u8, bytes.as_mut_ptr(), size);
+            return (bytes, true);
+        }
+    }
+    let copied = qemu_plugin_insn_data(insn, bytes.as_mut_ptr() as *mut c_void, size);
+    (bytes, copied == size)
+}
+
 extern "C" fn vcpu_tb_trans(_id: qemu_plugin_id_t, tb: *mut qemu_plugin_tb) {
+    let (gave_up, coverage_only) = {
+        let state = STATE.lock().unwrap();
+        (state.gave_up, state.coverage_only)
+    };
+    // No server to send to: leave newly translated blocks uninstrumented
+    if gave_up {
+        return;
+    }
+    if coverage_only {
+        // One callback per block instead of per instruction and memory access
+        unsafe {
+            qemu_plugin_register_vcpu_tb_exec_cb(
+                tb,
+                Some(vcpu_tb_exec),
+                qemu_plugin_cb_flags::QEMU_PLUGIN_CB_NO_REGS,
+                qemu_plugin_tb_vaddr(tb) as *mut c_void,
+            );
+        }
+        return;
+    }
+
     unsafe {
         let n = qemu_plugin_tb_n_insns(tb);
         for i in 0..n {
             let insn = qemu_plugin_tb_get_insn(tb, i);
             let vaddr = qemu_plugin_insn_vaddr(insn);
 
-            // Extract bytes
+            // The decoded length is authoritative; the bytes themselves may be
+            // unavailable (e.g. code in MMIO), which is reported rather than
+            // guessed at so the server reads them from memory instead
             let size = qemu_plugin_insn_size(insn);
-            let mut bytes = vec![0xAAu8; size];
-            
-            let haddr = qemu_plugin_insn_haddr(insn);
-            let captured;
-
-            if !haddr.is_null() {
-                std::ptr::copy_nonoverlapping(haddr as *const u8, bytes.as_mut_ptr(), size);
-                captured = true;
-            } else {
-                qemu_plugin_insn_data(insn, bytes.as_mut_ptr() as *mut c_void, size);
-                if bytes.iter().all(|&b| b == 0xAA) {
-                    captured = false;
-                } else {
-                    captured = true;
-                }
-            }
-
+            let (bytes, captured) = insn_bytes(insn, vaddr, size);
             if !captured {
-                bytes.clear();
+                tracing::debug!("no bytes for the instruction at {:#x} ({} bytes long)", vaddr, size);
             }
-            
-            if let Ok(mut cache) = INSN_CACHE.lock() {
-                cache.insert(vaddr, bytes);
+
+            let replaced = match INSN_CACHE.lock() {
+                Ok(mut cache) => {
+                    let old = cache.insert(vaddr, (bytes.clone(), captured));
+                    old.is_some_and(|(old, old_captured)| old_captured && captured && old != bytes)
+                }
+                Err(_) => false,
+            };
+            if replaced {
+                // QEMU's disassembly of the old bytes must not be sent for the new ones
+                if let Ok(mut cache) = DISASM_CACHE.lock() {
+                    cache.remove(&vaddr);
+                }
+                tracing::debug!("code at {:#x} changed since its last translation", vaddr);
+                send_event(TraceEvent::CodeInvalidated { pc: vaddr, bytes });
             }
             
             let disas_ptr = qemu_plugin_insn_disas(insn);
-            if !disas_ptr.is_null() {
-                let s = std::ffi::CStr::from_ptr(disas_ptr).to_string_lossy().into_owned();
+            let disas = (!disas_ptr.is_null())
+                .then(|| std::ffi::CStr::from_ptr(disas_ptr).to_string_lossy().into_owned());
+            if let Ok(mut cache) = FLOW_CACHE.lock() {
+                cache.insert(vaddr, (vaddr + size as u64, Flow::classify(disas.as_deref())));
+            }
+            if let Some(s) = disas {
                  if let Ok(mut cache) = DISASM_CACHE.lock() {
                     cache.insert(vaddr, s);
                  }
@@ -330,19 +632,63 @@ extern "C" fn vcpu_tb_trans(_id: qemu_plugin_id_t, tb: *mut qemu_plugin_tb) {
 pub static qemu_plugin_version: c_int = 2;
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn qemu_plugin_install(
     id: qemu_plugin_id_t,
-    _info: *const qemu_info_t,
-    _argc: c_int,
-    _argv: *const *const c_char,
+    info: *const qemu_info_t,
+    argc: c_int,
+    argv: *const *const c_char,
 ) -> c_int {
-    println!("Koradar Tracer: Install");
+    // Logs go to stderr so they don't mix with the traced program's stdout;
+    // RUST_LOG controls verbosity
+    let _ = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .try_init();
+    tracing::info!("Koradar tracer installed");
+    if let Some(info) = unsafe { info.as_ref() } {
+        STATE.lock().unwrap().user_mode = !info.system_emulation;
+    }
+
+    // Plugin arguments: -plugin libkoradar_tracer.so,socket=/tmp/koradar.sock,max_insns=1000000,mode=coverage,
+    // connect_attempts=10,connect_timeout=30
+    for i in 0..argc.max(0) as usize {
+        let arg = unsafe { std::ffi::CStr::from_ptr(*argv.add(i)) }.to_string_lossy();
+        if let Some(path) = arg.strip_prefix("socket=") {
+            STATE.lock().unwrap().socket_path = Some(path.to_string());
+        } else if let Some(n) = arg.strip_prefix("max_insns=") {
+            match n.parse::<u64>() {
+                Ok(n) => STATE.lock().unwrap().max_insns = Some(n),
+                Err(_) => tracing::warn!("ignoring invalid max_insns={}", n),
+            }
+        } else if let Some(mode) = arg.strip_prefix("mode=") {
+            match mode {
+                "coverage" => STATE.lock().unwrap().coverage_only = true,
+                "trace" => {}
+                _ => tracing::warn!("ignoring unknown mode={} (trace, coverage)", mode),
+            }
+        } else if let Some(n) = arg.strip_prefix("connect_attempts=") {
+            match n.parse::<u32>() {
+                Ok(n) => STATE.lock().unwrap().max_connect_attempts = n,
+                Err(_) => tracing::warn!("ignoring invalid connect_attempts={}", n),
+            }
+        } else if let Some(secs) = arg.strip_prefix("connect_timeout=") {
+            match secs.parse::<u64>() {
+                Ok(secs) => STATE.lock().unwrap().connect_give_up = Duration::from_secs(secs),
+                Err(_) => tracing::warn!("ignoring invalid connect_timeout={}", secs),
+            }
+        }
+    }
 
     unsafe {
         qemu_plugin_register_vcpu_init_cb(id, Some(vcpu_init));
         qemu_plugin_register_vcpu_exit_cb(id, Some(vcpu_exit));
         qemu_plugin_register_atexit_cb(id, Some(plugin_exit), std::ptr::null_mut());
         qemu_plugin_register_vcpu_tb_trans_cb(id, Some(vcpu_tb_trans));
+        qemu_plugin_register_vcpu_syscall_cb(id, Some(vcpu_syscall));
     }
 
     0